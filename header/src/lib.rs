@@ -74,6 +74,35 @@ pub mod syscalls {
         Recvmsg,
         GetAddrinfo,
         FreeAddrinfo,
+        Poll,
+        Rename,
+        GetSockName,
+        GetPeerName,
+        Pread,
+        Pwrite,
+        Dup,
+        Dup2,
+        Dup3,
+        Pipe,
+        Pipe2,
+        EpollCreate,
+        EpollCreate1,
+        EpollCtl,
+        EpollWait,
+        Alarm,
+        SetITimer,
+        Openat,
+        Mkdirat,
+        Unlinkat,
+        Fstatat,
+        Symlink,
+        Readlink,
+        Fsync,
+        Fdatasync,
+        Tcgetattr,
+        Tcsetattr,
+        Ioctl,
+        GetRandom,
         LastNR,
     }
 }
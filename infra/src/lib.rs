@@ -32,6 +32,7 @@ pub mod intrusive;
 pub mod list;
 pub mod ringbuffer;
 pub mod spinarc;
+pub mod spsc;
 pub mod string;
 pub mod tinyarc;
 pub mod tinyrwlock;
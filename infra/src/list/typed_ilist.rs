@@ -17,6 +17,11 @@
 // to wrap a value, it's conventional that the value is pinned. This
 // ListHead should be used with smart pointers. It's **NOT**
 // concurrent safe.
+//
+// The adapter `A` tells where the ListHead lives inside a `T`, every
+// node linked to a list must be the field at `A::offset()` of a live
+// `T`, so that `owner` can get back to it. A node must be detached
+// before its owner is moved or dropped.
 
 use crate::intrusive::Adapter;
 use core::{marker::PhantomData, ptr::NonNull};
@@ -97,6 +102,60 @@ impl<T, A: Adapter> Iterator for ListReverseIterator<T, A> {
     }
 }
 
+/// A cursor over the nodes after `head` and before `tail`, which can
+/// detach the node it points to and keep walking. It borrows `head`
+/// mutably, so the list can't be changed behind its back through it.
+pub struct CursorMut<'a, T, A: Adapter> {
+    current: Option<NonNull<ListHead<T, A>>>,
+    tail: Option<NonNull<ListHead<T, A>>>,
+    _head: PhantomData<&'a mut ListHead<T, A>>,
+}
+
+impl<'a, T, A: Adapter> CursorMut<'a, T, A> {
+    pub fn new(head: &'a mut ListHead<T, A>, tail: Option<NonNull<ListHead<T, A>>>) -> Self {
+        let mut cursor = Self {
+            current: None,
+            tail,
+            _head: PhantomData,
+        };
+        cursor.current = cursor.checked(head.next);
+        cursor
+    }
+
+    fn checked(&self, node: Option<NonNull<ListHead<T, A>>>) -> Option<NonNull<ListHead<T, A>>> {
+        if node == self.tail {
+            None
+        } else {
+            node
+        }
+    }
+
+    /// The node the cursor points to, None once past the last one.
+    pub fn current(&self) -> Option<NonNull<ListHead<T, A>>> {
+        self.current
+    }
+
+    pub fn current_owner(&self) -> Option<&T> {
+        self.current.map(|node| unsafe { node.as_ref() }.owner())
+    }
+
+    pub fn move_next(&mut self) {
+        if let Some(node) = self.current {
+            self.current = self.checked(unsafe { node.as_ref().next });
+        }
+    }
+
+    /// Detach the current node and move to the one after it. Returns the
+    /// detached node, whose owner is the caller's to release.
+    pub fn remove_current(&mut self) -> Option<NonNull<ListHead<T, A>>> {
+        let node = self.current?;
+        self.current = self.checked(unsafe { node.as_ref().next });
+        let ok = ListHead::<T, A>::detach(node);
+        assert!(ok);
+        Some(node)
+    }
+}
+
 impl<T, A: Adapter> ListHead<T, A> {
     pub const fn new() -> Self {
         Self::const_new()
@@ -246,4 +305,36 @@ mod tests {
         assert!(a.lh.is_detached());
         assert!(b.lh.is_detached());
     }
+
+    #[test]
+    fn test_cursor_remove_even() {
+        type Ty = ListHead<Foo, OffsetOfLh>;
+        let mut head = Ty::new();
+        let mut nodes: [Foo; 10] = Default::default();
+        for (i, node) in nodes.iter_mut().enumerate() {
+            node.head[0] = i as u8;
+        }
+        for node in nodes.iter().rev() {
+            assert!(Ty::insert_after(&mut head, NonNull::from_ref(&node.lh)));
+        }
+
+        let mut cursor = CursorMut::new(&mut head, None);
+        while let Some(foo) = cursor.current_owner() {
+            if foo.head[0] % 2 == 0 {
+                let node = cursor.remove_current().unwrap();
+                assert!(unsafe { node.as_ref() }.is_detached());
+            } else {
+                cursor.move_next();
+            }
+        }
+        assert!(cursor.current().is_none());
+
+        let ids: Vec<u8> = ListIterator::new(&head, None)
+            .map(|node| unsafe { node.as_ref() }.owner().head[0])
+            .collect();
+        assert_eq!(ids, [1, 3, 5, 7, 9]);
+        for node in nodes.iter().step_by(2) {
+            assert!(node.lh.is_detached());
+        }
+    }
 }
@@ -229,6 +229,16 @@ impl RingBuffer {
         start == end
     }
 
+    // Number of bytes between `start` and `end`.
+    fn used(&self, start: usize, end: usize) -> usize {
+        let len = self.len.load(Ordering::Relaxed);
+        if end >= start {
+            end - start
+        } else {
+            end + len * 2 - start
+        }
+    }
+
     fn wrap(&self, mut n: usize) -> usize {
         let len = self.len.load(Ordering::Relaxed);
 
@@ -365,9 +375,16 @@ impl Writer<'_> {
     }
 
     /// Mark n bytes as written and advance the write index.
+    ///
+    /// `n` must not exceed the total length of the buffers returned by the
+    /// last `push_bufs`/`push_slices`, otherwise unread data is overwritten.
     pub fn push_done(&mut self, n: usize) {
         // trace!("  ringbuf: push {:?}", n);
         let end = self.0.end.load(Ordering::Relaxed);
+        debug_assert!(
+            n <= self.0.capacity() - self.0.used(self.0.start.load(Ordering::Relaxed), end),
+            "ringbuf: pushed more than the free space"
+        );
 
         // Ordering: write `end` last, with Release ordering.
         // The ordering ensures no preceding memory accesses (such as writing
@@ -517,10 +534,17 @@ impl Reader<'_> {
     }
 
     /// Mark n bytes as read and allow advance the read index.
+    ///
+    /// `n` must not exceed the total length of the buffers returned by the
+    /// last `pop_bufs`/`pop_slices`, otherwise the reader passes the writer.
     pub fn pop_done(&mut self, n: usize) {
         // trace!("  ringbuf: pop {:?}", n);
 
         let start = self.0.start.load(Ordering::Relaxed);
+        debug_assert!(
+            n <= self.0.used(start, self.0.end.load(Ordering::Relaxed)),
+            "ringbuf: popped more than the stored data"
+        );
 
         // Ordering: write `start` last, with Release ordering.
         // The ordering ensures no preceding memory accesses (such as reading
@@ -1079,6 +1103,43 @@ mod tests {
         assert!(rb.is_empty());
     }
 
+    #[test]
+    fn test_slices_across_wrap() {
+        let rb = BoxedRingBuffer::new(8);
+        let mut writer = unsafe { rb.writer() };
+        let mut reader = unsafe { rb.reader() };
+        // Move both indexes to the middle so the next push wraps.
+        writer.push_done(5);
+        reader.pop_done(5);
+
+        let data = [1, 2, 3, 4, 5, 6, 7];
+        let [first, second] = writer.push_slices();
+        assert_eq!(first.len(), 3);
+        assert_eq!(second.len(), 5);
+        first.copy_from_slice(&data[..3]);
+        second[..4].copy_from_slice(&data[3..]);
+        writer.push_done(data.len());
+
+        let [first, second] = reader.pop_slices();
+        assert_eq!(first.len(), 3);
+        assert_eq!(second.len(), 4);
+        let read: Vec<u8> = first.iter().chain(second.iter()).copied().collect();
+        assert_eq!(read, data);
+        reader.pop_done(read.len());
+        assert!(rb.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn test_pop_done_past_data() {
+        let rb = BoxedRingBuffer::new(4);
+        let mut writer = unsafe { rb.writer() };
+        writer.push_one(1);
+        let mut reader = unsafe { rb.reader() };
+        reader.pop_done(2);
+    }
+
     #[test]
     fn test_concurrent_boxed_ringbuffer() {
         let rb = Arc::new(BoxedRingBuffer::new(16));
@@ -30,6 +30,29 @@ use core::{
 pub type SpinArc<T> = Arc<RwLock<T>>;
 type Uint = u8;
 
+impl<T> SpinArc<T> {
+    /// Lock without spinning, None if someone else holds the lock.
+    pub fn try_lock(&self) -> Option<WriteGuard<'_, T>> {
+        self.try_write()
+    }
+
+    /// Spin for the lock as long as `keep_trying` returns true, e.g.
+    /// until a tick budget runs out. Callers disabling IRQs should do it
+    /// per attempt, within `keep_trying` and around the returned guard,
+    /// so that ticks keep coming while spinning.
+    pub fn lock_while(&self, mut keep_trying: impl FnMut() -> bool) -> Option<WriteGuard<'_, T>> {
+        loop {
+            if let Some(guard) = self.try_write() {
+                return Some(guard);
+            }
+            if !keep_trying() {
+                return None;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
 // Can be used to implement intrusive list based on fine grained rwlock.
 #[derive(Default, Debug)]
 pub struct IlistNode<T: Sized> {
@@ -430,9 +453,49 @@ impl<T> Ilist<T> {
 mod tests {
     extern crate test;
     use super::*;
-    use std::{collections::HashSet, thread};
+    use std::{
+        collections::HashSet,
+        sync::{mpsc, Barrier},
+        thread,
+    };
     use test::{black_box, Bencher};
 
+    #[test]
+    fn try_lock_contended() {
+        let lock: SpinArc<usize> = Arc::new(RwLock::new(0));
+        let held = std::sync::Arc::new(Barrier::new(2));
+        let (release, released) = mpsc::channel();
+
+        let holder = {
+            let lock = lock.clone();
+            let held = held.clone();
+            thread::spawn(move || {
+                let mut guard = lock.try_lock().unwrap();
+                *guard = 1;
+                held.wait();
+                released.recv().unwrap();
+            })
+        };
+
+        held.wait();
+        assert!(lock.try_lock().is_none());
+        let mut budget = 100;
+        let timed_out = lock.lock_while(|| {
+            budget -= 1;
+            budget > 0
+        });
+        assert!(timed_out.is_none());
+        assert_eq!(budget, 0);
+
+        release.send(()).unwrap();
+        holder.join().unwrap();
+        let mut guard = lock.try_lock().unwrap();
+        assert_eq!(*guard, 1);
+        *guard = 2;
+        drop(guard);
+        assert_eq!(*lock.lock_while(|| false).unwrap(), 2);
+    }
+
     #[test]
     fn threaded_insert_after_many() {
         type Node = IlistNode<usize>;
@@ -0,0 +1,221 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A lock-free queue of `T` with one producer and one consumer, e.g. an
+//! ISR pushing received items and a thread popping them. Both sides are
+//! wait-free.
+
+extern crate alloc;
+use alloc::boxed::Box;
+use core::{
+    cell::UnsafeCell,
+    mem::MaybeUninit,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+pub struct SpscRingBuffer<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    // Count of popped items, only stored by the consumer.
+    head: AtomicUsize,
+    // Count of pushed items, only stored by the producer.
+    tail: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for SpscRingBuffer<T> {}
+unsafe impl<T: Send> Sync for SpscRingBuffer<T> {}
+
+impl<T> SpscRingBuffer<T> {
+    /// Create a queue holding at most `capacity` items, which must be a
+    /// power of two so that slots stay distinct when the counters wrap.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity.is_power_of_two());
+        Self {
+            slots: (0..capacity)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Get the producer of this queue.
+    /// # Safety
+    ///
+    /// - Only one producer can exist at a time.
+    pub unsafe fn producer(&self) -> Producer<'_, T> {
+        Producer(self)
+    }
+
+    /// Get the consumer of this queue.
+    /// # Safety
+    ///
+    /// - Only one consumer can exist at a time.
+    pub unsafe fn consumer(&self) -> Consumer<'_, T> {
+        Consumer(self)
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn len(&self) -> usize {
+        let tail = self.tail.load(Ordering::Acquire);
+        tail.wrapping_sub(self.head.load(Ordering::Acquire))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    fn slot(&self, index: usize) -> *mut MaybeUninit<T> {
+        self.slots[index & (self.slots.len() - 1)].get()
+    }
+}
+
+impl<T> Drop for SpscRingBuffer<T> {
+    fn drop(&mut self) {
+        let tail = *self.tail.get_mut();
+        let mut head = *self.head.get_mut();
+        while head != tail {
+            unsafe { (*self.slot(head)).assume_init_drop() };
+            head = head.wrapping_add(1);
+        }
+    }
+}
+
+pub struct Producer<'a, T>(&'a SpscRingBuffer<T>);
+
+impl<T> Producer<'_, T> {
+    /// Push `val`, or give it back if the queue is full.
+    pub fn push(&mut self, val: T) -> Result<(), T> {
+        // Ordering: the consumer stores `head` after moving the item out,
+        // so Acquire makes sure the slot is free before we overwrite it.
+        let head = self.0.head.load(Ordering::Acquire);
+        let tail = self.0.tail.load(Ordering::Relaxed);
+        if tail.wrapping_sub(head) == self.0.capacity() {
+            return Err(val);
+        }
+        unsafe { (*self.0.slot(tail)).write(val) };
+        // Ordering: Release publishes the item written above along with
+        // the new `tail`.
+        self.0.tail.store(tail.wrapping_add(1), Ordering::Release);
+        Ok(())
+    }
+}
+
+pub struct Consumer<'a, T>(&'a SpscRingBuffer<T>);
+
+impl<T> Consumer<'_, T> {
+    /// Pop the oldest item, if any.
+    pub fn pop(&mut self) -> Option<T> {
+        // Ordering: pairs with the Release store of `tail` in `push`, so
+        // the item is visible once we see it counted.
+        let tail = self.0.tail.load(Ordering::Acquire);
+        let head = self.0.head.load(Ordering::Relaxed);
+        if head == tail {
+            return None;
+        }
+        let val = unsafe { (*self.0.slot(head)).assume_init_read() };
+        // Ordering: Release hands the slot back to the producer only after
+        // the item has been moved out.
+        self.0.head.store(head.wrapping_add(1), Ordering::Release);
+        Some(val)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread, time::Duration};
+
+    #[test]
+    #[should_panic]
+    fn test_capacity_not_power_of_two() {
+        let _ = SpscRingBuffer::<u8>::new(3);
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let queue = SpscRingBuffer::new(2);
+        let mut producer = unsafe { queue.producer() };
+        let mut consumer = unsafe { queue.consumer() };
+        assert_eq!(consumer.pop(), None);
+        assert_eq!(producer.push(1), Ok(()));
+        assert_eq!(producer.push(2), Ok(()));
+        assert!(queue.is_full());
+        assert_eq!(producer.push(3), Err(3));
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(producer.push(3), Ok(()));
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(3));
+        assert_eq!(consumer.pop(), None);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_drop_pending_items() {
+        let item = Arc::new(0);
+        let queue = SpscRingBuffer::new(4);
+        let mut producer = unsafe { queue.producer() };
+        producer.push(item.clone()).unwrap();
+        producer.push(item.clone()).unwrap();
+        assert_eq!(Arc::strong_count(&item), 3);
+        drop(queue);
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[test]
+    fn test_fast_producer_slow_consumer() {
+        const COUNT: usize = 10000;
+        let queue = Arc::new(SpscRingBuffer::new(16));
+        let queue_producer = Arc::clone(&queue);
+
+        let producer = thread::spawn(move || {
+            let mut producer = unsafe { queue_producer.producer() };
+            let mut rejected = 0;
+            for i in 0..COUNT {
+                let mut val = i;
+                while let Err(v) = producer.push(val) {
+                    val = v;
+                    rejected += 1;
+                    core::hint::spin_loop();
+                }
+            }
+            rejected
+        });
+
+        let mut consumer = unsafe { queue.consumer() };
+        let mut expected = 0;
+        while expected < COUNT {
+            match consumer.pop() {
+                Some(val) => {
+                    // Nothing is lost or seen twice.
+                    assert_eq!(val, expected);
+                    expected += 1;
+                    if expected % 1000 == 0 {
+                        thread::sleep(Duration::from_millis(1));
+                    }
+                }
+                None => thread::yield_now(),
+            }
+        }
+        // The producer outran the consumer at some point.
+        assert!(producer.join().unwrap() > 0);
+        assert_eq!(consumer.pop(), None);
+    }
+}
@@ -15,13 +15,14 @@
 extern crate alloc;
 use crate::{
     intrusive::Adapter,
-    list::typed_ilist::{ListHead, ListIterator, ListReverseIterator},
+    list::typed_ilist::{CursorMut, ListHead, ListIterator, ListReverseIterator},
 };
 use alloc::boxed::Box;
 use core::{
     marker::PhantomData,
+    mem::ManuallyDrop,
     ops::Deref,
-    ptr::NonNull,
+    ptr::{self, NonNull},
     sync::atomic::{fence, Ordering},
 };
 
@@ -38,9 +39,11 @@ type AtomicUint = core::sync::atomic::AtomicUsize;
 #[derive(Debug)]
 pub struct TinyArcInner<T: Sized> {
     data: T,
-    // We don't need a large counter as Arc, we don't have weak
-    // counter either.
+    // We don't need a large counter as Arc.
     rc: AtomicUint,
+    // Count of TinyWeak, plus one shared by all TinyArc. The data is
+    // dropped when `rc` reaches 0, the allocation when `weak` does.
+    weak: AtomicUint,
 }
 
 impl<T: Sized> TinyArcInner<T> {
@@ -48,6 +51,7 @@ impl<T: Sized> TinyArcInner<T> {
         Self {
             data,
             rc: AtomicUint::new(1),
+            weak: AtomicUint::new(1),
         }
     }
 
@@ -114,6 +118,19 @@ impl<T> TinyArc<T> {
     pub fn is(&self, other: &Self) -> bool {
         unsafe { Self::get_handle(self) == Self::get_handle(other) }
     }
+
+    pub fn downgrade(this: &Self) -> TinyWeak<T> {
+        let old = unsafe { this.inner.as_ref() }
+            .weak
+            .fetch_add(1, Ordering::Relaxed);
+        assert!(old >= 1);
+        TinyWeak { inner: this.inner }
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    pub fn weak_count(this: &Self) -> usize {
+        unsafe { this.inner.as_ref().weak.load(Ordering::Relaxed) as usize - 1 }
+    }
 }
 
 impl<T: Sized> Clone for TinyArc<T> {
@@ -138,8 +155,9 @@ impl<T: Sized> Drop for TinyArc<T> {
         }
         fence(Ordering::SeqCst);
         // Static data should never reach here.
-        let x = unsafe { Box::from_non_null(self.inner) };
-        drop(x);
+        unsafe { ptr::drop_in_place(&mut (*self.inner.as_ptr()).data) };
+        // Release the weak reference shared by all TinyArc.
+        drop(TinyWeak { inner: self.inner });
     }
 }
 
@@ -157,6 +175,67 @@ impl<T: Sized> Deref for TinyArc<T> {
 unsafe impl<T: Sized> Send for TinyArc<T> {}
 unsafe impl<T: Sized> Sync for TinyArc<T> {}
 
+// A reference which doesn't keep the data alive, e.g. for wait queues
+// which shouldn't own their threads.
+#[derive(Debug)]
+#[repr(transparent)]
+pub struct TinyWeak<T: Sized> {
+    inner: NonNull<TinyArcInner<T>>,
+}
+
+impl<T> TinyWeak<T> {
+    /// Get a TinyArc of the data, None if it has been dropped.
+    pub fn upgrade(&self) -> Option<TinyArc<T>> {
+        let rc = &unsafe { self.inner.as_ref() }.rc;
+        let mut old = rc.load(Ordering::Relaxed);
+        loop {
+            if old == 0 {
+                return None;
+            }
+            match rc.compare_exchange_weak(old, old + 1, Ordering::Acquire, Ordering::Relaxed) {
+                Ok(_) => return Some(TinyArc { inner: self.inner }),
+                Err(current) => old = current,
+            }
+        }
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    pub fn strong_count(&self) -> usize {
+        unsafe { self.inner.as_ref().rc.load(Ordering::Relaxed) as usize }
+    }
+}
+
+impl<T: Sized> Clone for TinyWeak<T> {
+    #[inline]
+    fn clone(&self) -> TinyWeak<T> {
+        let old = unsafe { self.inner.as_ref() }
+            .weak
+            .fetch_add(1, Ordering::Relaxed);
+        assert!(old >= 1);
+        TinyWeak { inner: self.inner }
+    }
+}
+
+impl<T: Sized> Drop for TinyWeak<T> {
+    #[inline]
+    fn drop(&mut self) {
+        let old_val = unsafe { self.inner.as_ref() }
+            .weak
+            .fetch_sub(1, Ordering::Release);
+        if old_val != 1 {
+            return;
+        }
+        fence(Ordering::Acquire);
+        // The data has been dropped by the last TinyArc, only free the
+        // allocation.
+        let x = unsafe { Box::from_raw(self.inner.as_ptr() as *mut ManuallyDrop<TinyArcInner<T>>) };
+        drop(x);
+    }
+}
+
+unsafe impl<T: Sized> Send for TinyWeak<T> {}
+unsafe impl<T: Sized> Sync for TinyWeak<T> {}
+
 // This list is semi-safe for concurrency. Following usage is
 // considered safe if a node might be inserted to several lists:
 // Acquire the lock of the list and then the lock of the node, after
@@ -335,6 +414,37 @@ impl<T, A: Adapter> TinyArcListReverseIterator<T, A> {
     }
 }
 
+// A cursor over a list sharing the ownership of its nodes, removing a
+// node gives that ownership back to the caller.
+pub struct TinyArcCursorMut<'a, T, A: Adapter> {
+    cursor: CursorMut<'a, T, A>,
+}
+
+impl<'a, T, A: Adapter> TinyArcCursorMut<'a, T, A> {
+    pub fn new(head: &'a mut ListHead<T, A>, tail: Option<NonNull<ListHead<T, A>>>) -> Self {
+        Self {
+            cursor: CursorMut::new(head, tail),
+        }
+    }
+
+    pub fn current(&self) -> Option<TinyArc<T>> {
+        let node = self.cursor.current()?;
+        Some(unsafe { TinyArcList::<T, A>::make_arc_from(node.as_ref()) })
+    }
+
+    pub fn move_next(&mut self) {
+        self.cursor.move_next();
+    }
+
+    pub fn remove_current(&mut self) -> Option<TinyArc<T>> {
+        let node = self.cursor.remove_current()?;
+        let arc = unsafe { TinyArcList::<T, A>::make_arc_from(node.as_ref()) };
+        // The list no longer shares ownership of it.
+        unsafe { TinyArc::<T>::decrement_strong_count(&arc) };
+        Some(arc)
+    }
+}
+
 impl<T, A: Adapter> Iterator for TinyArcListIterator<T, A> {
     type Item = TinyArc<T>;
 
@@ -478,6 +588,36 @@ mod tests {
         assert!(l.is_empty());
     }
 
+    #[test]
+    fn test_cursor_remove() {
+        type Ty = TinyArc<Thread>;
+        type CslList = TinyArcList<Thread, OffsetOfCsl>;
+        let mut l = CslList::default();
+        l.init();
+        let threads: Vec<Ty> = (0..10).map(|i| Ty::new(Thread::new(i))).collect();
+        for t in threads.iter() {
+            assert!(l.push_back(t.clone()));
+        }
+
+        let tail = Some(NonNull::from_ref(&l.tail));
+        let mut cursor = TinyArcCursorMut::new(&mut l.head, tail);
+        while let Some(t) = cursor.current() {
+            if t.id % 2 == 0 {
+                let removed = cursor.remove_current().unwrap();
+                assert!(removed.is(&t));
+            } else {
+                cursor.move_next();
+            }
+        }
+        for t in threads.iter() {
+            let linked = if t.id % 2 == 0 { 0 } else { 1 };
+            assert_eq!(Ty::strong_count(t), 1 + linked);
+        }
+        let ids: Vec<usize> = l.iter().map(|t| t.id).collect();
+        assert_eq!(ids, [1, 3, 5, 7, 9]);
+        l.clear();
+    }
+
     #[test]
     fn test_push_and_drop() {
         type Ty = TinyArc<Thread>;
@@ -578,6 +718,59 @@ mod tests {
         });
     }
 
+    struct DropCounter<'a>(&'a core::sync::atomic::AtomicUsize);
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_weak_upgrade() {
+        let t = TinyArc::new(Thread::new(7));
+        let w = TinyArc::downgrade(&t);
+        assert_eq!(TinyArc::weak_count(&t), 1);
+        let u = w.upgrade().unwrap();
+        assert!(u.is(&t));
+        assert_eq!(u.id, 7);
+        assert_eq!(w.strong_count(), 2);
+        drop(u);
+        drop(t);
+        assert_eq!(w.strong_count(), 0);
+        assert!(w.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_weak_outlives_data() {
+        let drops = core::sync::atomic::AtomicUsize::new(0);
+        let t = TinyArc::new(DropCounter(&drops));
+        let w1 = TinyArc::downgrade(&t);
+        let w2 = w1.clone();
+        assert_eq!(TinyArc::weak_count(&t), 2);
+
+        // The data goes with the last TinyArc, the weak ones still see
+        // the counters.
+        drop(t);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+        assert!(w1.upgrade().is_none());
+        drop(w1);
+        assert!(w2.upgrade().is_none());
+        drop(w2);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_weak_dropped_first() {
+        let drops = core::sync::atomic::AtomicUsize::new(0);
+        let t = TinyArc::new(DropCounter(&drops));
+        drop(TinyArc::downgrade(&t));
+        assert_eq!(TinyArc::weak_count(&t), 0);
+        assert_eq!(drops.load(Ordering::Relaxed), 0);
+        drop(t);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
     #[bench]
     fn bench_insert_and_detach_1(b: &mut Bencher) {
         type Ty = TinyArc<Thread>;
@@ -14,9 +14,13 @@
 
 extern crate alloc;
 
-use crate::static_arc;
+use crate::{static_arc, support::DisableInterruptGuard, sync::SpinLock};
 use alloc::alloc::Layout;
-use core::{alloc::GlobalAlloc, ptr};
+use core::{
+    alloc::GlobalAlloc,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
 
 pub mod block;
 #[cfg(any(allocator = "tlsf", allocator = "slab"))]
@@ -39,10 +43,124 @@ static_arc! {
    HEAP(Heap, Heap::new()),
 }
 
+/// A cache which is able to release memory voluntarily when the heap
+/// is under pressure.
+pub trait Shrinker: Sync {
+    /// Release at least `target_bytes` if possible, returns the number
+    /// of bytes actually released. It's invoked on the allocation path,
+    /// so it must not allocate.
+    fn shrink(&self, target_bytes: usize) -> usize;
+}
+
+pub const MAX_SHRINKERS: usize = 8;
+static SHRINKERS: SpinLock<[Option<&'static dyn Shrinker>; MAX_SHRINKERS]> =
+    SpinLock::const_new([None; MAX_SHRINKERS]);
+// Shrinkers are invoked when free memory drops below this watermark.
+// 0 means shrinkers are only invoked when an allocation fails.
+static LOW_WATERMARK: AtomicUsize = AtomicUsize::new(0);
+static SHRINKING: AtomicBool = AtomicBool::new(false);
+static OOM_HANDLER: SpinLock<Option<fn(&Layout)>> = SpinLock::const_new(None);
+static IN_OOM_HANDLER: AtomicBool = AtomicBool::new(false);
+
+/// Register a shrinker. Shrinkers are invoked in order of
+/// registration. Returns false if there is no free slot.
+pub fn register_shrinker(shrinker: &'static dyn Shrinker) -> bool {
+    let mut shrinkers = SHRINKERS.irqsave_lock();
+    for slot in shrinkers.iter_mut() {
+        if slot.is_none() {
+            *slot = Some(shrinker);
+            return true;
+        }
+    }
+    false
+}
+
+pub fn set_low_watermark(bytes: usize) {
+    LOW_WATERMARK.store(bytes, Ordering::Relaxed);
+}
+
+pub fn low_watermark() -> usize {
+    LOW_WATERMARK.load(Ordering::Relaxed)
+}
+
+// Ask registered shrinkers to release `target_bytes`. Returns the
+// number of bytes released.
+fn shrink(target_bytes: usize) -> usize {
+    // Shrinkers release memory via the allocator, don't recurse
+    // into them, nor run them on several cores at the same time.
+    if SHRINKING
+        .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        return 0;
+    }
+    // Don't hold the lock while shrinking.
+    let shrinkers = *SHRINKERS.irqsave_lock();
+    let mut released = 0;
+    for shrinker in shrinkers.iter().flatten() {
+        if released >= target_bytes {
+            break;
+        }
+        released += shrinker.shrink(target_bytes - released);
+    }
+    SHRINKING.store(false, Ordering::Release);
+    released
+}
+
+fn alloc_with_reclaim(layout: Layout) -> Option<NonNull<u8>> {
+    alloc_with_reclaim_in(&HEAP, layout)
+}
+
+fn alloc_with_reclaim_in(heap: &Heap, layout: Layout) -> Option<NonNull<u8>> {
+    let ptr = heap.alloc(layout);
+    let watermark = low_watermark();
+    let free = if watermark == 0 {
+        usize::MAX
+    } else {
+        let info = heap.memory_info();
+        info.total.saturating_sub(info.used)
+    };
+    if ptr.is_some() && free >= watermark {
+        return ptr;
+    }
+    let target = core::cmp::max(watermark.saturating_sub(free), layout.size());
+    let released = shrink(target);
+    if ptr.is_some() {
+        return ptr;
+    }
+    let ptr = if released == 0 {
+        None
+    } else {
+        heap.alloc(layout)
+    };
+    if ptr.is_none() {
+        oom(&layout);
+    }
+    ptr
+}
+
+// Runs the OOM handler before the failed allocation returns null.
+fn oom(layout: &Layout) {
+    let Some(handler) = *OOM_HANDLER.irqsave_lock() else {
+        return;
+    };
+    // An allocation failing in the handler doesn't run it again.
+    if IN_OOM_HANDLER
+        .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+        .is_err()
+    {
+        return;
+    }
+    {
+        let _guard = DisableInterruptGuard::new();
+        handler(layout);
+    }
+    IN_OOM_HANDLER.store(false, Ordering::Release);
+}
+
 unsafe impl GlobalAlloc for KernelAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        HEAP.alloc(layout)
-            .map_or(ptr::null_mut(), |ptr| ptr.as_ptr())
+        alloc_with_reclaim(layout).map_or(ptr::null_mut(), |ptr| ptr.as_ptr())
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
@@ -54,20 +172,35 @@ impl KernelAllocator {
     pub fn memory_info(&self) -> MemoryInfo {
         HEAP.memory_info()
     }
+
+    /// Install `handler` to be called with the layout of any allocation
+    /// about to fail, after shrinkers couldn't help. It runs with local
+    /// interrupts disabled and the heap unlocked, its own allocations
+    /// failing don't call it again. None removes the handler.
+    pub fn set_oom_handler(&self, handler: Option<fn(&Layout)>) {
+        *OOM_HANDLER.irqsave_lock() = handler;
+    }
+
+    #[cfg(allocator = "slab")]
+    pub fn slab_info(&self) -> [SlabInfo; slab::SLAB_CLASSES] {
+        HEAP.slab_info()
+    }
+
+    #[cfg(allocator = "slab")]
+    pub fn heap_stats(&self) -> HeapStats {
+        HEAP.stats()
+    }
 }
 
 mod allocator_api {
     use super::*;
-    use core::{
-        alloc::{AllocError, Allocator},
-        ptr::NonNull,
-    };
+    use core::alloc::{AllocError, Allocator};
 
     unsafe impl Allocator for KernelAllocator {
         fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
             match layout.size() {
                 0 => Ok(NonNull::slice_from_raw_parts(layout.dangling(), 0)),
-                size => HEAP.alloc(layout).map_or(Err(AllocError), |allocation| {
+                size => alloc_with_reclaim(layout).map_or(Err(AllocError), |allocation| {
                     Ok(NonNull::slice_from_raw_parts(allocation, size))
                 }),
             }
@@ -99,6 +232,62 @@ pub fn memory_info() -> MemoryInfo {
     HEAP.memory_info()
 }
 
+/// Blocks of a slab class, in number of blocks.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct SlabInfo {
+    pub block_size: usize,
+    pub free: usize,
+    pub total: usize,
+}
+
+#[cfg(allocator = "slab")]
+pub fn slab_info() -> [SlabInfo; slab::SLAB_CLASSES] {
+    HEAP.slab_info()
+}
+
+/// Allocations and frees served by a size class.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct AllocStats {
+    pub allocs: usize,
+    pub frees: usize,
+}
+
+impl AllocStats {
+    pub const fn new() -> Self {
+        Self {
+            allocs: 0,
+            frees: 0,
+        }
+    }
+
+    /// Number of blocks currently allocated.
+    pub fn live(&self) -> usize {
+        self.allocs.saturating_sub(self.frees)
+    }
+
+    pub(crate) fn on_alloc(&mut self) {
+        self.allocs += 1;
+    }
+
+    pub(crate) fn on_free(&mut self) {
+        self.frees += 1;
+    }
+}
+
+/// Allocation statistics of each slab class, in the order of `slab_info`,
+/// and of the allocator slabs fall back to.
+#[cfg(allocator = "slab")]
+#[derive(Default, Debug, Clone, Copy)]
+pub struct HeapStats {
+    pub slabs: [AllocStats; slab::SLAB_CLASSES],
+    pub system: AllocStats,
+}
+
+#[cfg(allocator = "slab")]
+pub fn heap_stats() -> HeapStats {
+    HEAP.stats()
+}
+
 /// Allocate memory on heap and returns a pointer to it.
 /// If size equals zero, then null mutable raw pointer will be returned.
 // TODO: Make malloc a blocking API, i.e., if the heap lock is
@@ -109,8 +298,7 @@ pub fn malloc(size: usize) -> *mut u8 {
     }
     const ALIGN: usize = core::mem::size_of::<usize>();
     let layout = Layout::from_size_align(size, ALIGN).unwrap();
-    HEAP.alloc(layout)
-        .map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
+    alloc_with_reclaim(layout).map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
 }
 
 /// Free previously allocated memory pointed by ptr.
@@ -152,15 +340,35 @@ pub fn realloc(ptr: *mut u8, newsize: usize) -> *mut u8 {
 /// * `count` - Number of elements to allocate space for.
 /// * `size` - Size of each element.
 pub fn calloc(count: usize, size: usize) -> *mut u8 {
-    let required_size = count * size;
+    let Some(required_size) = count.checked_mul(size) else {
+        return ptr::null_mut();
+    };
     const ALIGN: usize = core::mem::size_of::<usize>();
-    let layout = Layout::from_size_align(required_size, ALIGN).unwrap();
-    if let Some(alloc_ptr) = HEAP.alloc(layout) {
-        unsafe { ptr::write_bytes(alloc_ptr.as_ptr(), 0, required_size) };
-        alloc_ptr.as_ptr()
-    } else {
-        ptr::null_mut()
+    malloc_zeroed_align(required_size, ALIGN)
+}
+
+/// Allocates aligned memory of at least the specified size, with all
+/// bytes initialized to zero.
+///
+/// # Arguments
+///
+/// * `size` - Minimum size of the memory region to allocate.
+/// * `align` - Alignment requirement for the returned memory.
+pub fn malloc_zeroed_align(size: usize, align: usize) -> *mut u8 {
+    if core::intrinsics::unlikely(size == 0) {
+        return ptr::null_mut();
     }
+    let Ok(layout) = Layout::from_size_align(size, align) else {
+        return ptr::null_mut();
+    };
+    let Some(allocation) = alloc_with_reclaim(layout) else {
+        return ptr::null_mut();
+    };
+    // Slab blocks are recycled as they were freed, and memory is not
+    // cleared when it's given to the heap, so no block is known to be
+    // zero and it's always cleared here.
+    unsafe { ptr::write_bytes(allocation.as_ptr(), 0, size) };
+    allocation.as_ptr()
 }
 
 /// Allocates aligned memory of at least the specified size.
@@ -175,8 +383,7 @@ pub fn malloc_align(size: usize, align: usize) -> *mut u8 {
     }
 
     let layout = Layout::from_size_align(size, align).unwrap();
-    HEAP.alloc(layout)
-        .map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
+    alloc_with_reclaim(layout).map_or(ptr::null_mut(), |allocation| allocation.as_ptr())
 }
 
 /// Deallocates memory that was allocated using `malloc_align`.
@@ -254,3 +461,170 @@ mod ffi {
         super::realloc(ptr, newsize)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{vec, vec::Vec};
+    use blueos_test_macro::test;
+
+    struct DummyCache {
+        entries: SpinLock<Vec<Vec<u8>>>,
+        invoked: AtomicUsize,
+    }
+
+    impl Shrinker for DummyCache {
+        fn shrink(&self, target_bytes: usize) -> usize {
+            self.invoked.fetch_add(1, Ordering::Relaxed);
+            let mut entries = self.entries.irqsave_lock();
+            let mut released = 0;
+            while released < target_bytes {
+                let Some(entry) = entries.pop() else {
+                    break;
+                };
+                released += entry.len();
+            }
+            released
+        }
+    }
+
+    static CACHE: DummyCache = DummyCache {
+        entries: SpinLock::const_new(Vec::new()),
+        invoked: AtomicUsize::new(0),
+    };
+
+    // Holds a single allocation of a private heap, which is released
+    // at once, so the global heap isn't exhausted by the test.
+    struct BlockHolder {
+        heap: Heap,
+        block: SpinLock<Option<(usize, Layout)>>,
+        invoked: AtomicUsize,
+    }
+
+    impl Shrinker for BlockHolder {
+        fn shrink(&self, _target_bytes: usize) -> usize {
+            self.invoked.fetch_add(1, Ordering::Relaxed);
+            let Some((ptr, layout)) = self.block.irqsave_lock().take() else {
+                return 0;
+            };
+            unsafe { self.heap.dealloc(ptr as *mut u8, layout) };
+            layout.size()
+        }
+    }
+
+    static BLOCK_HOLDER: BlockHolder = BlockHolder {
+        heap: Heap::new(),
+        block: SpinLock::const_new(None),
+        invoked: AtomicUsize::new(0),
+    };
+
+    static OOM_SIZE: AtomicUsize = AtomicUsize::new(0);
+
+    fn record_oom(layout: &Layout) {
+        OOM_SIZE.store(layout.size(), Ordering::Relaxed);
+    }
+
+    #[test]
+    fn test_calloc_zeroed() {
+        // Slab classes and TLSF
+        let sizes = [8, 24, 100, 200, 300, 700, 2048, 8192];
+        for size in sizes {
+            let ptr = malloc(size);
+            assert!(!ptr.is_null());
+            unsafe { ptr::write_bytes(ptr, 0xa5, size) };
+            free(ptr);
+        }
+        for size in sizes {
+            let ptr = calloc(1, size);
+            assert!(!ptr.is_null());
+            let bytes = unsafe { core::slice::from_raw_parts(ptr, size) };
+            assert!(bytes.iter().all(|&b| b == 0));
+            free(ptr);
+        }
+        assert!(calloc(usize::MAX, 2).is_null());
+    }
+
+    #[cfg(all(allocator = "slab", heap_redzone))]
+    #[test]
+    fn test_heap_redzone() {
+        // Slab and TLSF
+        for size in [24, 4096] {
+            let ptr = malloc(size);
+            assert!(!ptr.is_null());
+            let user = NonNull::new(ptr).unwrap();
+            assert!(unsafe { slab::redzone::verify(user) }.is_ok());
+            // One byte past the end
+            let saved = unsafe { ptr.add(size).read() };
+            unsafe { ptr.add(size).write(!saved) };
+            assert_eq!(unsafe { slab::redzone::verify(user) }, Err(user));
+            unsafe { ptr.add(size).write(saved) };
+            free(ptr);
+        }
+    }
+
+    #[test]
+    fn test_oom_handler() {
+        KernelAllocator.set_oom_handler(Some(record_oom));
+        let size = memory_info().total * 2;
+        assert!(malloc(size).is_null());
+        KernelAllocator.set_oom_handler(None);
+        assert_eq!(OOM_SIZE.load(Ordering::Relaxed), size);
+
+        OOM_SIZE.store(0, Ordering::Relaxed);
+        assert!(malloc(size).is_null());
+        assert_eq!(OOM_SIZE.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_shrinker_below_watermark() {
+        assert!(register_shrinker(&CACHE));
+        let mut entries = Vec::with_capacity(4);
+        for _ in 0..4 {
+            entries.push(vec![0u8; 1024]);
+        }
+        *CACHE.entries.irqsave_lock() = entries;
+
+        let info = memory_info();
+        set_low_watermark(info.total - info.used + 4096);
+        let ptr = malloc(64);
+        set_low_watermark(0);
+        assert!(!ptr.is_null());
+        assert!(CACHE.invoked.load(Ordering::Relaxed) > 0);
+        assert!(CACHE.entries.irqsave_lock().is_empty());
+        free(ptr);
+    }
+
+    #[test]
+    fn test_shrinker_retries_failed_allocation() {
+        const SIZE: usize = 256 * 1024;
+        let region = Layout::from_size_align(SIZE, 4096).unwrap();
+        let start = unsafe { alloc::alloc::alloc_zeroed(region) };
+        assert!(!start.is_null());
+        let heap = &BLOCK_HOLDER.heap;
+        unsafe { heap.init(start as usize, SIZE) };
+        assert!(register_shrinker(&BLOCK_HOLDER));
+
+        // The largest block the private heap hands out
+        let info = heap.memory_info();
+        let mut size = info.total - info.used;
+        let layout = loop {
+            let layout = Layout::from_size_align(size, core::mem::size_of::<usize>()).unwrap();
+            if let Some(ptr) = heap.alloc(layout) {
+                *BLOCK_HOLDER.block.irqsave_lock() = Some((ptr.as_ptr() as usize, layout));
+                break layout;
+            }
+            size -= size / 16 + 1;
+        };
+
+        // Only fits once the holder is shrunk
+        assert!(heap.alloc(layout).is_none());
+        let invoked = BLOCK_HOLDER.invoked.load(Ordering::Relaxed);
+        let ptr = alloc_with_reclaim_in(heap, layout).unwrap();
+        assert_eq!(BLOCK_HOLDER.invoked.load(Ordering::Relaxed), invoked + 1);
+        assert!(BLOCK_HOLDER.block.irqsave_lock().is_none());
+        unsafe {
+            heap.dealloc(ptr.as_ptr(), layout);
+            alloc::alloc::dealloc(start, region);
+        }
+    }
+}
@@ -12,11 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::SlabHeap as Slab;
-use crate::{allocator::MemoryInfo, sync::spinlock::SpinLock};
+#[cfg(heap_redzone)]
+use super::redzone;
+use super::{SlabHeap as Slab, SLAB_CLASSES};
+use crate::{
+    allocator::{HeapStats, MemoryInfo, SlabInfo},
+    sync::spinlock::SpinLock,
+};
 use core::{alloc::Layout, ptr::NonNull};
 
-type SlabHeap = Slab<2, 2, 2, 2, 2>;
+type SlabHeap = Slab<2, 2, 2, 2, 2, 2, 2>;
 pub struct Heap {
     heap: SpinLock<SlabHeap>,
 }
@@ -38,23 +43,46 @@ impl Heap {
 
     // try to allocate memory with the given layout
     pub fn alloc(&self, layout: Layout) -> Option<NonNull<u8>> {
+        #[cfg(heap_redzone)]
+        let (layout, user_layout) = (redzone::outer_layout(&layout)?, layout);
         let mut heap = self.heap.irqsave_lock();
         let ptr = heap.allocate(&layout);
+        #[cfg(heap_redzone)]
+        let ptr = ptr.map(|block| unsafe { redzone::arm(block, &user_layout) });
         ptr
     }
 
     // deallocate the memory pointed by ptr with the given layout
     // Safety: the ptr must be a valid pointer.
     pub unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let ptr = NonNull::new_unchecked(ptr);
+        #[cfg(heap_redzone)]
+        let (ptr, layout) = (
+            redzone::check(ptr).0,
+            redzone::outer_layout(&layout).unwrap(),
+        );
         let mut heap = self.heap.irqsave_lock();
-        heap.deallocate(NonNull::new_unchecked(ptr), &layout);
+        heap.deallocate(ptr, &layout);
     }
 
     // deallocate the memory pointed by ptr with out align
     // Safety: the ptr must be a valid pointer.
     pub unsafe fn deallocate_unknown_align(&self, ptr: *mut u8) {
+        let ptr = NonNull::new_unchecked(ptr);
+        #[cfg(heap_redzone)]
+        let ptr = redzone::check(ptr).0;
         let mut heap = self.heap.irqsave_lock();
-        heap.deallocate_unknown_align(NonNull::new_unchecked(ptr));
+        heap.deallocate_unknown_align(ptr);
+    }
+
+    // Blocks are always moved, so that the red zones follow the new size.
+    #[cfg(heap_redzone)]
+    unsafe fn realloc_redzone(&self, ptr: *mut u8, new_layout: Layout) -> Option<NonNull<u8>> {
+        let (_, size) = redzone::check(NonNull::new_unchecked(ptr));
+        let new_ptr = self.alloc(new_layout)?;
+        core::ptr::copy_nonoverlapping(ptr, new_ptr.as_ptr(), size.min(new_layout.size()));
+        self.deallocate_unknown_align(ptr);
+        Some(new_ptr)
     }
 
     // reallocate memory with the given size and layout
@@ -66,8 +94,13 @@ impl Heap {
         new_size: usize,
     ) -> Option<NonNull<u8>> {
         let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
-        let mut heap = self.heap.irqsave_lock();
-        let new_ptr = heap.reallocate(NonNull::new_unchecked(ptr), &new_layout);
+        #[cfg(heap_redzone)]
+        let new_ptr = self.realloc_redzone(ptr, new_layout);
+        #[cfg(not(heap_redzone))]
+        let new_ptr = {
+            let mut heap = self.heap.irqsave_lock();
+            heap.reallocate(NonNull::new_unchecked(ptr), &new_layout)
+        };
         new_ptr
     }
 
@@ -78,8 +111,16 @@ impl Heap {
         ptr: *mut u8,
         new_size: usize,
     ) -> Option<NonNull<u8>> {
-        let mut heap = self.heap.irqsave_lock();
-        let new_ptr = heap.reallocate_unknown_align(NonNull::new_unchecked(ptr), new_size);
+        #[cfg(heap_redzone)]
+        let new_ptr = self.realloc_redzone(
+            ptr,
+            Layout::from_size_align_unchecked(new_size, core::mem::size_of::<usize>()),
+        );
+        #[cfg(not(heap_redzone))]
+        let new_ptr = {
+            let mut heap = self.heap.irqsave_lock();
+            heap.reallocate_unknown_align(NonNull::new_unchecked(ptr), new_size)
+        };
         new_ptr
     }
 
@@ -92,4 +133,14 @@ impl Heap {
             max_used: heap.maximum(),
         }
     }
+
+    // Free and total blocks of each slab class.
+    pub fn slab_info(&self) -> [SlabInfo; SLAB_CLASSES] {
+        self.heap.irqsave_lock().slab_info()
+    }
+
+    // Allocations and frees of each size class.
+    pub fn stats(&self) -> HeapStats {
+        self.heap.irqsave_lock().stats()
+    }
 }
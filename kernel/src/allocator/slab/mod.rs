@@ -18,17 +18,23 @@
 
 use crate::allocator::{
     block::{used_block_hdr_for_allocation_unknown_align, BlockHdr, SIZE_USED},
-    tlsf,
+    tlsf, AllocStats, HeapStats, SlabInfo,
 };
 use blueos_infra::list::singly_linked_list::SinglyLinkedList;
 use core::{alloc::Layout, mem, ptr, ptr::NonNull};
 use log::{debug, warn};
 
 pub mod heap;
+#[cfg(heap_redzone)]
+pub(crate) mod redzone;
+
+// Classes of 16, 32, 64, 128, 256, 512 and 1024 bytes
+pub const SLAB_CLASSES: usize = 7;
 
 pub struct Slab {
     block_size: usize,
     len: usize,
+    count: usize,
     free_block_list: SinglyLinkedList,
     #[cfg(debug_slab)]
     start_addr: usize,
@@ -42,6 +48,7 @@ impl Slab {
         Slab {
             block_size: 0,
             len: 0,
+            count: 0,
             free_block_list: SinglyLinkedList::new(),
             #[cfg(debug_slab)]
             start_addr: 0,
@@ -63,6 +70,15 @@ impl Slab {
         }
 
         self.len = count;
+        self.count = count;
+    }
+
+    pub fn info(&self) -> SlabInfo {
+        SlabInfo {
+            block_size: self.block_size,
+            free: self.len,
+            total: self.count,
+        }
     }
 
     pub fn allocate(&mut self, _layout: &Layout) -> Option<NonNull<u8>> {
@@ -123,6 +139,8 @@ pub enum HeapAllocator {
     Slab64Bytes,
     Slab128Bytes,
     Slab256Bytes,
+    Slab512Bytes,
+    Slab1024Bytes,
     SystemAllocator,
 }
 
@@ -134,6 +152,8 @@ impl HeapAllocator {
             HeapAllocator::Slab64Bytes => 64,
             HeapAllocator::Slab128Bytes => 128,
             HeapAllocator::Slab256Bytes => 256,
+            HeapAllocator::Slab512Bytes => 512,
+            HeapAllocator::Slab1024Bytes => 1024,
             _ => unreachable!("not a block!"),
         }
     }
@@ -145,12 +165,16 @@ pub struct SlabHeap<
     const SLAB_64: usize,
     const SLAB_128: usize,
     const SLAB_256: usize,
+    const SLAB_512: usize,
+    const SLAB_1024: usize,
 > {
     slab_16_bytes: Slab,
     slab_32_bytes: Slab,
     slab_64_bytes: Slab,
     slab_128_bytes: Slab,
     slab_256_bytes: Slab,
+    slab_512_bytes: Slab,
+    slab_1024_bytes: Slab,
     system_allocator: tlsf::heap::TlsfHeap,
     slab_begin_addr: usize,
     slab_total_size: usize,
@@ -158,6 +182,7 @@ pub struct SlabHeap<
     allocated: usize,
     maximum: usize,
     total: usize,
+    stats: HeapStats,
 }
 
 impl<
@@ -166,13 +191,17 @@ impl<
         const SLAB_64: usize,
         const SLAB_128: usize,
         const SLAB_256: usize,
-    > SlabHeap<SLAB_16, SLAB_32, SLAB_64, SLAB_128, SLAB_256>
+        const SLAB_512: usize,
+        const SLAB_1024: usize,
+    > SlabHeap<SLAB_16, SLAB_32, SLAB_64, SLAB_128, SLAB_256, SLAB_512, SLAB_1024>
 {
     // Constants for slab boundaries
     const SLAB_32_END: usize = SLAB_16 + SLAB_32;
     const SLAB_64_END: usize = Self::SLAB_32_END + SLAB_64;
     const SLAB_128_END: usize = Self::SLAB_64_END + SLAB_128;
     const SLAB_256_END: usize = Self::SLAB_128_END + SLAB_256;
+    const SLAB_512_END: usize = Self::SLAB_256_END + SLAB_512;
+    const SLAB_1024_END: usize = Self::SLAB_512_END + SLAB_1024;
 
     /// Create an empty heap
     pub const fn new() -> Self {
@@ -182,12 +211,18 @@ impl<
             slab_64_bytes: Slab::new(),
             slab_128_bytes: Slab::new(),
             slab_256_bytes: Slab::new(),
+            slab_512_bytes: Slab::new(),
+            slab_1024_bytes: Slab::new(),
             system_allocator: tlsf::heap::TlsfHeap::new(),
             slab_begin_addr: 0,
             slab_total_size: 0,
             allocated: 0,
             maximum: 0,
             total: 0,
+            stats: HeapStats {
+                slabs: [AllocStats::new(); SLAB_CLASSES],
+                system: AllocStats::new(),
+            },
         }
     }
 
@@ -198,7 +233,7 @@ impl<
         self.total = size;
 
         // allocate slabs
-        self.slab_total_size = (SLAB_16 + SLAB_32 + SLAB_64 + SLAB_128 + SLAB_256) * 4096;
+        self.slab_total_size = Self::SLAB_1024_END * 4096;
         assert!(self.slab_total_size < size);
         let slab_layout = Layout::from_size_align(self.slab_total_size, 4096).unwrap();
         let slab_ptr = self.system_allocator.allocate(&slab_layout).unwrap();
@@ -218,6 +253,36 @@ impl<
         self.slab_256_bytes
             .init(start_addr, SLAB_256 << (12 - 8), 256);
         start_addr += SLAB_256 * 4096;
+        self.slab_512_bytes
+            .init(start_addr, SLAB_512 << (12 - 9), 512);
+        start_addr += SLAB_512 * 4096;
+        self.slab_1024_bytes
+            .init(start_addr, SLAB_1024 << (12 - 10), 1024);
+        start_addr += SLAB_1024 * 4096;
+    }
+
+    pub fn slab_info(&self) -> [SlabInfo; SLAB_CLASSES] {
+        [
+            self.slab_16_bytes.info(),
+            self.slab_32_bytes.info(),
+            self.slab_64_bytes.info(),
+            self.slab_128_bytes.info(),
+            self.slab_256_bytes.info(),
+            self.slab_512_bytes.info(),
+            self.slab_1024_bytes.info(),
+        ]
+    }
+
+    /// Allocations and frees of each slab class and of the system allocator.
+    pub fn stats(&self) -> HeapStats {
+        self.stats
+    }
+
+    fn class_stats(&mut self, allocator: HeapAllocator) -> &mut AllocStats {
+        match allocator {
+            HeapAllocator::SystemAllocator => &mut self.stats.system,
+            slab => &mut self.stats.slabs[slab as usize],
+        }
     }
 
     pub fn allocate(&mut self, layout: &Layout) -> Option<NonNull<u8>> {
@@ -261,6 +326,22 @@ impl<
                     if self.slab_256_bytes.len > 0 {
                         ptr = self.slab_256_bytes.allocate(layout);
                         self.allocated += 256;
+                    } else {
+                        current_allocator = HeapAllocator::Slab512Bytes;
+                    }
+                }
+                HeapAllocator::Slab512Bytes => {
+                    if self.slab_512_bytes.len > 0 {
+                        ptr = self.slab_512_bytes.allocate(layout);
+                        self.allocated += 512;
+                    } else {
+                        current_allocator = HeapAllocator::Slab1024Bytes;
+                    }
+                }
+                HeapAllocator::Slab1024Bytes => {
+                    if self.slab_1024_bytes.len > 0 {
+                        ptr = self.slab_1024_bytes.allocate(layout);
+                        self.allocated += 1024;
                     } else {
                         current_allocator = HeapAllocator::SystemAllocator;
                     }
@@ -295,6 +376,7 @@ impl<
         // Update maximum usage
         if ptr.is_some() {
             self.maximum = core::cmp::max(self.maximum, self.allocated);
+            self.class_stats(current_allocator).on_alloc();
         }
 
         ptr
@@ -302,6 +384,7 @@ impl<
 
     pub unsafe fn deallocate(&mut self, ptr: NonNull<u8>, layout: &Layout) -> usize {
         let allocator = self.ptr_to_allocator(ptr.as_ptr() as usize);
+        self.class_stats(allocator).on_free();
         match allocator {
             HeapAllocator::SystemAllocator => {
                 let size = self.system_allocator.deallocate(ptr, layout.align());
@@ -333,11 +416,22 @@ impl<
                 self.allocated -= 256;
                 256
             }
+            HeapAllocator::Slab512Bytes => {
+                self.slab_512_bytes.deallocate(ptr);
+                self.allocated -= 512;
+                512
+            }
+            HeapAllocator::Slab1024Bytes => {
+                self.slab_1024_bytes.deallocate(ptr);
+                self.allocated -= 1024;
+                1024
+            }
         }
     }
 
     pub unsafe fn deallocate_unknown_align(&mut self, ptr: NonNull<u8>) -> usize {
         let allocator = self.ptr_to_allocator(ptr.as_ptr() as usize);
+        self.class_stats(allocator).on_free();
         match allocator {
             HeapAllocator::SystemAllocator => {
                 let size = self.system_allocator.deallocate_unknown_align(ptr);
@@ -369,6 +463,16 @@ impl<
                 self.allocated -= 256;
                 256
             }
+            HeapAllocator::Slab512Bytes => {
+                self.slab_512_bytes.deallocate(ptr);
+                self.allocated -= 512;
+                512
+            }
+            HeapAllocator::Slab1024Bytes => {
+                self.slab_1024_bytes.deallocate(ptr);
+                self.allocated -= 1024;
+                1024
+            }
         }
     }
 
@@ -427,10 +531,10 @@ impl<
     // Finds the appropriate allocator based on layout size and alignment
     //
     // This function implements a best-fit strategy for slab allocation:
-    // - For sizes > 256 bytes, use the system allocator
+    // - For sizes > 1024 bytes, use the system allocator
     // - For smaller sizes, use the smallest slab that can accommodate both size and alignment
     fn layout_to_allocator(size: usize, align: usize) -> HeapAllocator {
-        if size > 256 {
+        if size > 1024 || align > 1024 {
             HeapAllocator::SystemAllocator
         } else if size <= 16 && align <= 16 {
             HeapAllocator::Slab16Bytes
@@ -440,8 +544,12 @@ impl<
             HeapAllocator::Slab64Bytes
         } else if size <= 128 && align <= 128 {
             HeapAllocator::Slab128Bytes
-        } else {
+        } else if size <= 256 && align <= 256 {
             HeapAllocator::Slab256Bytes
+        } else if size <= 512 && align <= 512 {
+            HeapAllocator::Slab512Bytes
+        } else {
+            HeapAllocator::Slab1024Bytes
         }
     }
 
@@ -462,6 +570,10 @@ impl<
             HeapAllocator::Slab128Bytes
         } else if slab_index < Self::SLAB_256_END {
             HeapAllocator::Slab256Bytes
+        } else if slab_index < Self::SLAB_512_END {
+            HeapAllocator::Slab512Bytes
+        } else if slab_index < Self::SLAB_1024_END {
+            HeapAllocator::Slab1024Bytes
         } else {
             HeapAllocator::SystemAllocator
         }
@@ -482,3 +594,82 @@ impl<
         self.total
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::{
+        alloc::{alloc_zeroed, dealloc},
+        vec::Vec,
+    };
+    use blueos_test_macro::test;
+
+    #[test]
+    fn test_slab_512_and_1024() {
+        const SIZE: usize = 64 * 1024;
+        let layout = Layout::from_size_align(SIZE, 4096).unwrap();
+        let start = unsafe { alloc_zeroed(layout) };
+        assert!(!start.is_null());
+        let mut heap = SlabHeap::<1, 1, 1, 1, 1, 2, 2>::new();
+        unsafe { heap.init(start as usize, SIZE) };
+
+        // All of them fit in their own class
+        for (size, block_size, count) in [(300, 512, 16), (700, 1024, 8)] {
+            let object = Layout::from_size_align(size, 8).unwrap();
+            let mut ptrs = Vec::new();
+            for _ in 0..count {
+                let ptr = heap.allocate(&object).unwrap();
+                let allocator = heap.ptr_to_allocator(ptr.as_ptr() as usize);
+                assert!(!matches!(allocator, HeapAllocator::SystemAllocator));
+                assert_eq!(allocator.block_size(), block_size);
+                ptrs.push(ptr);
+            }
+            // The 1024-byte class is the last one before TLSF
+            if block_size == 1024 {
+                let ptr = heap.allocate(&object).unwrap();
+                let allocator = heap.ptr_to_allocator(ptr.as_ptr() as usize);
+                assert!(matches!(allocator, HeapAllocator::SystemAllocator));
+                ptrs.push(ptr);
+            }
+            for ptr in ptrs {
+                unsafe { heap.deallocate(ptr, &object) };
+            }
+        }
+        assert_eq!(heap.slab_info()[5].free, 16);
+        assert_eq!(heap.slab_info()[6].free, 8);
+        unsafe { dealloc(start, layout) };
+    }
+
+    #[test]
+    fn test_heap_stats() {
+        const SIZE: usize = 64 * 1024;
+        let layout = Layout::from_size_align(SIZE, 4096).unwrap();
+        let start = unsafe { alloc_zeroed(layout) };
+        assert!(!start.is_null());
+        let mut heap = SlabHeap::<1, 1, 1, 1, 1, 1, 1>::new();
+        unsafe { heap.init(start as usize, SIZE) };
+
+        let sizes = [8, 24, 100, 200, 300, 700, 2000, 8];
+        let mut ptrs = Vec::new();
+        for size in sizes {
+            let object = Layout::from_size_align(size, 8).unwrap();
+            ptrs.push((heap.allocate(&object).unwrap(), object));
+        }
+        let stats = heap.stats();
+        let live: Vec<usize> = stats.slabs.iter().map(|s| s.live()).collect();
+        assert_eq!(live, [2, 1, 0, 1, 1, 1, 1]);
+        assert_eq!(stats.system.live(), 1);
+
+        for (ptr, object) in ptrs {
+            unsafe { heap.deallocate(ptr, &object) };
+        }
+        let stats = heap.stats();
+        for class in stats.slabs.iter().chain([&stats.system]) {
+            assert_eq!(class.live(), 0);
+            assert_eq!(class.allocs, class.frees);
+        }
+        assert_eq!(stats.slabs[0].allocs, 2);
+        assert_eq!(stats.system.allocs, 1);
+        unsafe { dealloc(start, layout) };
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Red zones around heap blocks, to catch overruns when they are freed.
+//!
+//! A block is laid out as
+//! `[padding | front offset | size | red zone | user region | red zone]`,
+//! the header lets a block be checked without knowing its alignment.
+
+use core::{alloc::Layout, mem, ptr::NonNull};
+
+const REDZONE: usize = 16;
+const MAGIC: u8 = 0xfd;
+const HEADER: usize = 2 * mem::size_of::<usize>();
+
+// Offset of the user region in the block.
+fn front(align: usize) -> usize {
+    (HEADER + REDZONE).next_multiple_of(align)
+}
+
+/// The layout of the block holding an allocation of `layout`.
+pub fn outer_layout(layout: &Layout) -> Option<Layout> {
+    let align = layout.align().max(mem::align_of::<usize>());
+    let size = front(align)
+        .checked_add(layout.size())?
+        .checked_add(REDZONE)?;
+    Layout::from_size_align(size, align).ok()
+}
+
+/// Write the header and the red zones into `block`, returns the user region.
+///
+/// # Safety
+///
+/// `block` must have been allocated with `outer_layout(layout)`.
+pub unsafe fn arm(block: NonNull<u8>, layout: &Layout) -> NonNull<u8> {
+    let align = layout.align().max(mem::align_of::<usize>());
+    let front = front(align);
+    let user = block.as_ptr().add(front);
+    let header = user.sub(REDZONE + HEADER) as *mut usize;
+    header.write_unaligned(front);
+    header.add(1).write_unaligned(layout.size());
+    user.sub(REDZONE).write_bytes(MAGIC, REDZONE);
+    user.add(layout.size()).write_bytes(MAGIC, REDZONE);
+    NonNull::new_unchecked(user)
+}
+
+/// Check the red zones around `user`, returns the block and the size of
+/// the user region, or `user` if any of them has been overwritten.
+///
+/// # Safety
+///
+/// `user` must have been returned by `arm`.
+pub unsafe fn verify(user: NonNull<u8>) -> Result<(NonNull<u8>, usize), NonNull<u8>> {
+    let user_ptr = user.as_ptr();
+    // The header is only trusted if the red zone next to it is intact
+    let before = core::slice::from_raw_parts(user_ptr.sub(REDZONE), REDZONE);
+    if before.iter().any(|&b| b != MAGIC) {
+        return Err(user);
+    }
+    let header = user_ptr.sub(REDZONE + HEADER) as *const usize;
+    let front = header.read_unaligned();
+    let size = header.add(1).read_unaligned();
+    let after = core::slice::from_raw_parts(user_ptr.add(size), REDZONE);
+    if after.iter().any(|&b| b != MAGIC) {
+        return Err(user);
+    }
+    Ok((NonNull::new_unchecked(user_ptr.sub(front)), size))
+}
+
+/// Like `verify`, panics with the offending pointer on corruption.
+///
+/// # Safety
+///
+/// `user` must have been returned by `arm`.
+pub unsafe fn check(user: NonNull<u8>) -> (NonNull<u8>, usize) {
+    match verify(user) {
+        Ok(block) => block,
+        Err(ptr) => panic!("Heap corruption around {:p}", ptr),
+    }
+}
@@ -0,0 +1,44 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use core::ops::Range;
+
+pub const MAX_BACKTRACE_ADDRESSES: usize = 32;
+
+// Walk the AAPCS64 frame records starting at `fp`. Each record is a
+// pair of the caller's fp and the return address. Records must lie in
+// `stack` and move towards its top, so that a corrupted chain can't
+// lead us to read random memory. Returns the number of return
+// addresses written to `addrs`.
+pub(crate) fn unwind(mut fp: usize, stack: Range<usize>, addrs: &mut [usize]) -> usize {
+    let mut n = 0;
+    while n < addrs.len() {
+        if fp % 16 != 0 || fp < stack.start || fp.checked_add(16).is_none_or(|end| end > stack.end)
+        {
+            break;
+        }
+        let record = fp as *const usize;
+        let (next_fp, ret) = unsafe { (record.read_volatile(), record.add(1).read_volatile()) };
+        if ret == 0 {
+            break;
+        }
+        addrs[n] = ret;
+        n += 1;
+        if next_fp <= fp {
+            break;
+        }
+        fp = next_fp;
+    }
+    n
+}
@@ -19,9 +19,11 @@ use crate::{
     support::sideeffect,
     syscalls::{dispatch_syscall, Context as ScContext},
 };
+use blueos_kconfig::NUM_CORES;
 use core::{
     arch::{asm, naked_asm},
     mem::offset_of,
+    ptr::addr_of_mut,
     sync::atomic::{compiler_fence, fence, Ordering},
 };
 use tock_registers::interfaces::Readable;
@@ -173,22 +175,81 @@ extern "C" fn trap_exception(context: &mut Context) -> usize {
     sp
 }
 
-extern "C" fn trap_irq(context: &mut Context) -> usize {
+macro_rules! preemptible_irq_handler {
+    ($name:ident, $handler:path) => {
+        #[naked]
+        unsafe extern "C" fn $name(context: &mut Context) -> usize {
+            naked_asm!(
+                "
+                mov x19, lr
+                mov x20, x0
+                bl {handler}
+                mov sp, x0
+                mov x1, x20
+                mov lr, x19
+                b {might_preempt}
+                ",
+                handler = sym $handler,
+                might_preempt = sym might_preempt,
+            );
+        }
+    };
+}
+
+preemptible_irq_handler!(trap_irq, handle_irq);
+
+preemptible_irq_handler!(trap_fiq, handle_fiq);
+
+// Hooks of the switches decided by IRQs, each is consumed on the stack
+// of the next thread right after the IRQ handler returns.
+static mut PREEMPT_HOOKS: [Option<ContextSwitchHookHolder<'static>>; NUM_CORES] =
+    [const { None }; NUM_CORES];
+
+// The slot of the current core, no reference to the whole array is made.
+fn preempt_hook() -> *mut Option<ContextSwitchHookHolder<'static>> {
+    let hooks = addr_of_mut!(PREEMPT_HOOKS) as *mut Option<ContextSwitchHookHolder<'static>>;
+    // SAFETY: current_cpu_id() is below NUM_CORES.
+    unsafe { hooks.add(super::current_cpu_id()) }
+}
+
+fn preempt_on_return(context: &Context) -> usize {
     let sp = context as *const _ as usize;
+    if !super::take_pending_switch() {
+        return sp;
+    }
+    let Some((to_sp, hook)) = scheduler::preempt_me_and_return_next_sp(sp) else {
+        return sp;
+    };
+    // SAFETY: Local irq is disabled, nothing else touches this core's slot.
+    unsafe { *preempt_hook() = Some(hook) };
+    to_sp
+}
+
+extern "C" fn might_preempt(to: &Context, from: &Context) -> usize {
+    if !core::ptr::eq(to, from) {
+        sideeffect();
+        // SAFETY: Local irq is disabled, nothing else touches this core's slot.
+        let mut hook = unsafe { (*preempt_hook()).take() };
+        assert!(hook.is_some());
+        scheduler::save_context_finish_hook(hook.as_mut());
+    }
+    to as *const _ as usize
+}
+
+extern "C" fn handle_irq(context: &mut Context) -> usize {
     let irq = irq::get_interrupt();
     irq::trigger_irq(irq);
     irq::end_interrupt(irq);
-    sp
+    preempt_on_return(context)
 }
 
-extern "C" fn trap_fiq(context: &mut Context) -> usize {
-    let sp = context as *const _ as usize;
+extern "C" fn handle_fiq(context: &mut Context) -> usize {
     let fiq = irq::get_interrupt();
     if u32::from(fiq) != 1023 {
         irq::trigger_irq(fiq);
     }
     irq::end_interrupt(fiq);
-    sp
+    preempt_on_return(context)
 }
 
 fn show_exception(ec: u64, context: &mut Context) {
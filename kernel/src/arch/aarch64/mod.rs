@@ -14,6 +14,7 @@
 
 // pub(crate) mod asm;
 // pub(crate) mod mmu;
+pub(crate) mod backtrace;
 mod exception;
 #[cfg(not(target_board = "bcm2711"))]
 #[path = "gicv3.rs"]
@@ -27,12 +28,14 @@ pub(crate) mod registers;
 pub(crate) mod vector;
 
 use crate::{arch::registers::mpidr_el1::MPIDR_EL1, scheduler};
+pub use backtrace::MAX_BACKTRACE_ADDRESSES;
+use blueos_kconfig::NUM_CORES;
 use core::{
     fmt,
     mem::offset_of,
     sync::{
         atomic,
-        atomic::{AtomicU8, Ordering},
+        atomic::{AtomicBool, AtomicU8, Ordering},
     },
 };
 use scheduler::ContextSwitchHookHolder;
@@ -286,6 +289,16 @@ impl Context {
         self.x0 = val;
         self
     }
+
+    #[inline]
+    pub(crate) fn frame_pointer(&self) -> usize {
+        self.fp
+    }
+
+    #[inline]
+    pub(crate) fn pc(&self) -> usize {
+        self.elr
+    }
 }
 
 impl fmt::Display for Context {
@@ -436,6 +449,13 @@ pub(crate) extern "C" fn current_sp() -> usize {
     x
 }
 
+#[inline(always)]
+pub(crate) extern "C" fn current_frame_pointer() -> usize {
+    let x: usize;
+    unsafe { core::arch::asm!("mov {}, x29", out(reg) x, options(nostack, nomem)) };
+    x
+}
+
 #[inline]
 pub extern "C" fn disable_local_irq_save() -> usize {
     let old: usize;
@@ -470,8 +490,19 @@ pub extern "C" fn local_irq_enabled() -> bool {
     (x & (1 << 7)) == 0
 }
 
+// Set by `pend_switch_context`, the switch happens when the core
+// returns from its next IRQ, or the current one if it's in an ISR.
+static SWITCH_PENDING: [AtomicBool; NUM_CORES] = [const { AtomicBool::new(false) }; NUM_CORES];
+
+#[inline]
+pub extern "C" fn pend_switch_context() {
+    SWITCH_PENDING[current_cpu_id()].store(true, Ordering::Relaxed);
+}
+
 #[inline]
-pub extern "C" fn pend_switch_context() {}
+fn take_pending_switch() -> bool {
+    SWITCH_PENDING[current_cpu_id()].swap(false, Ordering::Relaxed)
+}
 
 pub fn secondary_cpu_setup(psci_base: u32) {
     atomic::fence(Ordering::SeqCst);
@@ -16,6 +16,7 @@ pub mod cntfrq_el0;
 pub mod cntp_ctl_el0;
 pub mod cntp_tval_el0;
 pub mod cntpct_el0;
+pub mod cntvct_el0;
 pub mod cpacr_el1;
 pub mod daif;
 pub mod esr_el1;
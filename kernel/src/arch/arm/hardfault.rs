@@ -13,6 +13,8 @@
 // limitations under the License.
 
 use super::{xpsr, IsrContext};
+#[cfg(not(armv6m))]
+use crate::{scheduler, thread::Thread};
 use core::fmt;
 use cortex_m::peripheral::SCB;
 
@@ -213,9 +215,34 @@ impl fmt::Display for HardFaultRegs {
     }
 }
 
+// A MemManage fault in the guard region of current thread's stack, or
+// during exception entry stacking, means the thread overflowed its
+// stack.
+#[cfg(not(armv6m))]
+fn check_stack_guard(regs: &HardFaultRegs) {
+    let current = scheduler::current_thread();
+    let Some(guard) = current.stack_guard() else {
+        return;
+    };
+    let stacking = regs.cfsr & (1 << 4) != 0;
+    let in_guard = regs.cfsr & (1 << 7) != 0
+        && (guard..guard + super::mpu::STACK_GUARD_SIZE).contains(&(regs.mmfar as usize));
+    if stacking || in_guard {
+        panic!(
+            "Stack overflow detected in thread 0x{:x}: hit the guard region at 0x{:x}, stack [0x{:x}, 0x{:x})",
+            Thread::id(&current),
+            guard,
+            current.stack_base(),
+            current.stack_base() + current.stack_size(),
+        );
+    }
+}
+
 pub(crate) extern "C" fn panic_on_hardfault(ctx: &IsrContext) {
     super::disable_local_irq();
     let fault_regs: HardFaultRegs = HardFaultRegs::from_scb();
+    #[cfg(not(armv6m))]
+    check_stack_guard(&fault_regs);
     let xpsr = xpsr::read();
     panic!(
         "
@@ -14,6 +14,8 @@
 
 pub(crate) mod hardfault;
 pub(crate) mod irq;
+#[cfg(not(armv6m))]
+pub(crate) mod mpu;
 pub(crate) mod xpsr;
 
 pub(crate) use hardfault::handle_hardfault;
@@ -0,0 +1,89 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Thread stack guard built on the highest numbered MPU region. The
+// region is read-only so that the stack high-water scan still works,
+// while a push into it raises a MemManage fault, which is escalated to
+// HardFault.
+
+const MPU_TYPE: usize = 0xE000_ED90;
+const MPU_CTRL: usize = 0xE000_ED94;
+const MPU_RNR: usize = 0xE000_ED98;
+const MPU_RBAR: usize = 0xE000_ED9C;
+// MPU_RLAR on ARMv8-M.
+const MPU_RASR: usize = 0xE000_EDA0;
+#[cfg(armv8m)]
+const MPU_MAIR0: usize = 0xE000_EDC0;
+
+const CTRL_ENABLE: u32 = 1 << 0;
+const CTRL_PRIVDEFENA: u32 = 1 << 2;
+
+pub(crate) const STACK_GUARD_SIZE: usize = 32;
+
+#[inline]
+fn read(reg: usize) -> u32 {
+    unsafe { (reg as *const u32).read_volatile() }
+}
+
+#[inline]
+fn write(reg: usize, val: u32) {
+    unsafe { (reg as *mut u32).write_volatile(val) }
+}
+
+#[inline]
+fn num_regions() -> u32 {
+    (read(MPU_TYPE) >> 8) & 0xff
+}
+
+#[inline]
+pub(crate) fn is_present() -> bool {
+    num_regions() != 0
+}
+
+#[inline]
+pub(crate) fn stack_guard_base(stack_base: usize) -> usize {
+    stack_base.next_multiple_of(STACK_GUARD_SIZE)
+}
+
+// Arm the guard region at `base`, or disarm it if `base` is None. We
+// assume this is called with local irq disabled.
+pub(crate) fn set_stack_guard(base: Option<usize>) {
+    if !is_present() {
+        return;
+    }
+    write(MPU_RNR, num_regions() - 1);
+    match base {
+        None => write(MPU_RASR, 0),
+        Some(base) => {
+            debug_assert_eq!(base % STACK_GUARD_SIZE, 0);
+            #[cfg(not(armv8m))]
+            {
+                // XN, AP = 0b110 (read-only), SIZE = log2(32) - 1.
+                write(MPU_RBAR, base as u32);
+                write(MPU_RASR, (1 << 28) | (0b110 << 24) | (4 << 1) | 1);
+            }
+            #[cfg(armv8m)]
+            {
+                // AttrIndx 0 is normal write-back memory.
+                write(MPU_MAIR0, (read(MPU_MAIR0) & !0xff) | 0xff);
+                // AP = 0b11 (read-only), XN.
+                write(MPU_RBAR, base as u32 | (0b11 << 1) | 1);
+                write(MPU_RASR, (base + STACK_GUARD_SIZE - 32) as u32 | 1);
+            }
+        }
+    }
+    write(MPU_CTRL, CTRL_ENABLE | CTRL_PRIVDEFENA);
+    cortex_m::asm::dsb();
+    cortex_m::asm::isb();
+}
@@ -257,10 +257,19 @@ pub(crate) extern "C" fn disable_local_irq_save() -> usize {
 
 #[inline]
 pub(crate) extern "C" fn enable_local_irq_restore(old: usize) {
-    unsafe {
-        core::arch::asm!("csrw mstatus, {old}", old = in(reg) old,
-                         options(nostack))
-    };
+    // Only MIE is restored, a trap in between might have changed other
+    // fields of mstatus, e.g. MPP and MPIE.
+    if old & MSTATUS_MIE == 0 {
+        unsafe {
+            core::arch::asm!("csrci mstatus, {bit}", bit = const MSTATUS_MIE,
+                             options(nostack))
+        };
+    } else {
+        unsafe {
+            core::arch::asm!("csrsi mstatus, {bit}", bit = const MSTATUS_MIE,
+                             options(nostack))
+        };
+    }
     compiler_fence(Ordering::SeqCst);
 }
 
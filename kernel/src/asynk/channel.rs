@@ -0,0 +1,177 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A bounded multi-producer single-consumer channel for async tasks.
+//!
+//! The byte ring buffers of infra can't hold arbitrary values, so the
+//! messages are kept in a VecDeque which never grows past the capacity.
+
+use crate::{sync::SpinLock, types::Arc};
+use alloc::{collections::VecDeque, vec::Vec};
+use core::{
+    future::{poll_fn, Future},
+    task::{Poll, Waker},
+};
+
+struct Inner<T> {
+    queue: VecDeque<T>,
+    capacity: usize,
+    senders: usize,
+    receiver_alive: bool,
+    recv_waker: Option<Waker>,
+    // Senders waiting for room
+    send_wakers: Vec<Waker>,
+}
+
+struct Shared<T> {
+    inner: SpinLock<Inner<T>>,
+}
+
+/// The sending half of a channel, it can be cloned.
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// The receiving half of a channel.
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+/// Create a channel holding up to `capacity` messages.
+pub fn bounded<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0);
+    let shared = Arc::new(Shared {
+        inner: SpinLock::new(Inner {
+            queue: VecDeque::with_capacity(capacity),
+            capacity,
+            senders: 1,
+            receiver_alive: true,
+            recv_waker: None,
+            send_wakers: Vec::new(),
+        }),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+impl<T> Sender<T> {
+    /// Send `value`, waiting while the channel is full. Gives the value
+    /// back if the receiver is gone.
+    pub fn send(&self, value: T) -> impl Future<Output = Result<(), T>> + '_ {
+        let mut value = Some(value);
+        poll_fn(move |cx| {
+            let mut inner = self.shared.inner.irqsave_lock();
+            if !inner.receiver_alive {
+                return Poll::Ready(Err(value.take().unwrap()));
+            }
+            if inner.queue.len() == inner.capacity {
+                // Polled again before being woken, e.g. by a select
+                if !inner.send_wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                    inner.send_wakers.push(cx.waker().clone());
+                }
+                return Poll::Pending;
+            }
+            inner.queue.push_back(value.take().unwrap());
+            let waker = inner.recv_waker.take();
+            drop(inner);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+            Poll::Ready(Ok(()))
+        })
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.inner.irqsave_lock().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        let mut inner = self.shared.inner.irqsave_lock();
+        inner.senders -= 1;
+        if inner.senders > 0 {
+            return;
+        }
+        // The receiver sees the end of the channel
+        let waker = inner.recv_waker.take();
+        drop(inner);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Receive the next message, waiting while the channel is empty.
+    /// None once it's empty and all senders are gone.
+    pub fn recv(&mut self) -> impl Future<Output = Option<T>> + '_ {
+        poll_fn(move |cx| {
+            let mut inner = self.shared.inner.irqsave_lock();
+            let Some(value) = inner.queue.pop_front() else {
+                if inner.senders == 0 {
+                    return Poll::Ready(None);
+                }
+                inner.recv_waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            };
+            let wakers = core::mem::take(&mut inner.send_wakers);
+            drop(inner);
+            for waker in wakers {
+                waker.wake();
+            }
+            Poll::Ready(Some(value))
+        })
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        let mut inner = self.shared.inner.irqsave_lock();
+        inner.receiver_alive = false;
+        let wakers = core::mem::take(&mut inner.send_wakers);
+        drop(inner);
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blueos_test_macro::test;
+    use core::task::Context;
+
+    #[test]
+    fn test_send_repolled_while_full() {
+        let (tx, _rx) = bounded(1);
+        assert_eq!(crate::asynk::block_on(tx.send(1)), Ok(()));
+        let mut send = core::pin::pin!(tx.send(2));
+        let mut ctx = Context::from_waker(Waker::noop());
+        for _ in 0..3 {
+            assert!(send.as_mut().poll(&mut ctx).is_pending());
+        }
+        assert_eq!(tx.shared.inner.irqsave_lock().send_wakers.len(), 1);
+    }
+}
@@ -13,25 +13,34 @@
 // limitations under the License.
 
 // Asynk contains a simple executor, however runs fast.
+//
+// Spawned futures are run by the poller thread, block_on() runs the
+// future on the calling thread, which sleeps until the future's waker
+// fires.
 
 extern crate alloc;
 use crate::{
     config::MAX_THREAD_PRIORITY,
     scheduler, static_arc,
     support::ArcBufferingQueue,
-    sync::{atomic_wait, ISpinLock, SpinLockGuard},
+    sync::{atomic_wait, ISpinLock, SpinLock, SpinLockGuard},
     thread::{self, Entry, SystemThreadStorage, ThreadKind, ThreadNode},
+    time::{self, timer::Timer},
     types::{impl_simple_intrusive_adapter, Arc, IlistHead},
 };
-use alloc::boxed::Box;
+use alloc::{boxed::Box, task::Wake};
 use core::{
-    future::Future,
+    future::{poll_fn, Future},
     mem::MaybeUninit,
-    pin::Pin,
-    sync::atomic::{AtomicUsize, Ordering},
-    task::{Context, Poll, Waker},
+    pin::{pin, Pin},
+    ptr,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+    time::Duration,
 };
 
+pub mod channel;
+
 impl_simple_intrusive_adapter!(TaskletNode, Tasklet, node);
 impl_simple_intrusive_adapter!(TaskletLock, Tasklet, lock);
 
@@ -39,7 +48,6 @@ pub struct Tasklet {
     pub node: IlistHead<Tasklet, TaskletNode>,
     lock: ISpinLock<Tasklet, TaskletLock>,
     future: Pin<Box<dyn Future<Output = ()>>>,
-    blocked: Option<ThreadNode>,
 }
 
 impl Tasklet {
@@ -48,7 +56,6 @@ impl Tasklet {
             node: IlistHead::new(),
             future,
             lock: ISpinLock::new(),
-            blocked: None,
         }
     }
 
@@ -84,28 +91,151 @@ fn create_tasklet(future: impl Future<Output = ()> + 'static) -> Arc<Tasklet> {
     Arc::new(Tasklet::new(future))
 }
 
-pub fn block_on(future: impl Future<Output = ()> + Send + 'static) {
-    let t = scheduler::current_thread();
-    let mut task = create_tasklet(future);
-    task.lock().blocked = Some(t.clone());
-    scheduler::suspend_me_with_hook(move || {
-        let ok = t.transfer_state(thread::RUNNING, thread::SUSPENDED);
-        assert!(ok);
-        enqueue_active_tasklet(task);
-        #[cfg(debugging_scheduler)]
-        crate::trace!(
-            "[TH:0x{:x}] is waking up the poller",
-            scheduler::current_thread_id()
-        );
-        wake_poller();
+// Wakes up the thread in block_on(). It's refcounted, a future may keep
+// a clone of the waker after block_on() returns.
+struct ThreadWaker {
+    seq: AtomicUsize,
+}
+
+impl Wake for ThreadWaker {
+    fn wake(self: alloc::sync::Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &alloc::sync::Arc<Self>) {
+        self.seq.fetch_add(1, Ordering::Release);
+        let _ = atomic_wait::atomic_wake(&self.seq, 1);
+    }
+}
+
+/// Run `future` to completion on the current thread. The thread is
+/// suspended while the future is pending, until its waker fires.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let thread_waker = alloc::sync::Arc::new(ThreadWaker {
+        seq: AtomicUsize::new(0),
     });
+    let waker = Waker::from(thread_waker.clone());
+    let mut ctx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        // Sample before polling, so that a wake up in between isn't lost.
+        let seq = thread_waker.seq.load(Ordering::Acquire);
+        if let Poll::Ready(output) = future.as_mut().poll(&mut ctx) {
+            return output;
+        }
+        let _ = atomic_wait::atomic_wait(&thread_waker.seq, seq, None);
+    }
 }
 
-fn wake_poller() {
+pub(crate) fn wake_poller() {
     POLLER_WAKER.fetch_add(1, Ordering::Release);
     atomic_wait::atomic_wake(&POLLER_WAKER, 1);
 }
 
+/// Run both futures concurrently, resolves to both outputs once both
+/// are done.
+pub fn join<A: Future, B: Future>(a: A, b: B) -> impl Future<Output = (A::Output, B::Output)> {
+    let mut a = Box::pin(a);
+    let mut b = Box::pin(b);
+    let mut output_a = None;
+    let mut output_b = None;
+    poll_fn(move |cx| {
+        if output_a.is_none() {
+            if let Poll::Ready(output) = a.as_mut().poll(cx) {
+                output_a = Some(output);
+            }
+        }
+        if output_b.is_none() {
+            if let Poll::Ready(output) = b.as_mut().poll(cx) {
+                output_b = Some(output);
+            }
+        }
+        if output_a.is_some() && output_b.is_some() {
+            Poll::Ready((output_a.take().unwrap(), output_b.take().unwrap()))
+        } else {
+            Poll::Pending
+        }
+    })
+}
+
+// Shared by a Sleep and the callback of its timer, which runs in IRQ.
+struct SleepState {
+    fired: AtomicBool,
+    waker: SpinLock<Option<Waker>>,
+}
+
+/// A future completing once its duration has elapsed, see `sleep`.
+pub struct Sleep {
+    ticks: usize,
+    state: Arc<SleepState>,
+    timer: Option<Arc<Timer>>,
+}
+
+/// Complete after `duration`, without holding a thread meanwhile. The
+/// timer is armed on first poll and stopped if the future is dropped
+/// before it fires.
+pub fn sleep(duration: Duration) -> Sleep {
+    let ms = usize::try_from(duration.as_millis()).unwrap_or(usize::MAX);
+    let mut ticks = time::tick_from_millisecond(ms);
+    if ticks == 0 && !duration.is_zero() {
+        ticks = 1;
+    }
+    Sleep {
+        ticks,
+        state: Arc::new(SleepState {
+            fired: AtomicBool::new(false),
+            waker: SpinLock::new(None),
+        }),
+        timer: None,
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+        if this.ticks == 0 || this.state.fired.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+        *this.state.waker.irqsave_lock() = Some(cx.waker().clone());
+        // It may have fired before the waker is stored.
+        if this.state.fired.load(Ordering::Acquire) {
+            return Poll::Ready(());
+        }
+        if this.timer.is_none() {
+            let state = this.state.clone();
+            let timer = Timer::new_hard_oneshot(
+                this.ticks,
+                Box::new(move || {
+                    state.fired.store(true, Ordering::Release);
+                    let waker = state.waker.irqsave_lock().take();
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }),
+            );
+            timer.start();
+            this.timer = Some(timer);
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(timer) = self.timer.take() {
+            timer.stop();
+        }
+    }
+}
+
+/// Run `future` on the poller thread.
+///
+/// The future may outlive the thread spawning it, e.g. exit_thread()
+/// spawns the exit hook of a thread which is retiring. It must own
+/// everything it uses and must not refer to the stack, the TLS or the
+/// `ThreadNode` of the spawning thread unless it holds a reference to it.
 pub fn spawn(future: impl Future<Output = ()> + Send + 'static) -> Arc<Tasklet> {
     let task = create_tasklet(future);
     enqueue_active_tasklet(task.clone());
@@ -129,15 +259,27 @@ pub fn enqueue_active_tasklet(t: Arc<Tasklet>) {
     );
 }
 
+// Spawned futures are all polled by the poller thread, waking any of
+// them wakes up the poller.
+static POLLER_WAKER_VTABLE: RawWakerVTable = RawWakerVTable::new(
+    |_| poller_raw_waker(),
+    |_| wake_poller(),
+    |_| wake_poller(),
+    |_| {},
+);
+
+fn poller_raw_waker() -> RawWaker {
+    RawWaker::new(ptr::null(), &POLLER_WAKER_VTABLE)
+}
+
 fn poll_inner() {
-    let mut ctx = Context::from_waker(Waker::noop());
+    // SAFETY: The vtable ignores the data pointer.
+    let waker = unsafe { Waker::from_raw(poller_raw_waker()) };
+    let mut ctx = Context::from_waker(&waker);
     let mut w = ASYNC_WORK_QUEUE.advance_active_queue();
     for mut task in w.iter() {
         let mut l = task.lock();
         if let Poll::Ready(()) = l.future.as_mut().poll(&mut ctx) {
-            if let Some(t) = l.blocked.take() {
-                scheduler::queue_ready_thread(thread::SUSPENDED, t);
-            }
             // If we detach the task what ever it's ready or
             // pending, it would be edge-level triggered. Now
             // we're using level-trigger mode conservatively.
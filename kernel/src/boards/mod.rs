@@ -15,29 +15,26 @@
 #[cfg(target_board = "qemu_mps2_an385")]
 mod qemu_mps2_an385;
 #[cfg(target_board = "qemu_mps2_an385")]
-pub(crate) use qemu_mps2_an385::{get_cycles_to_duration, get_cycles_to_ms, get_early_uart, init};
+pub(crate) use qemu_mps2_an385::{get_early_uart, init};
 
 #[cfg(target_board = "qemu_riscv64")]
 mod qemu_riscv64;
 #[cfg(target_board = "qemu_riscv64")]
 pub(crate) use qemu_riscv64::{
-    current_cycles, current_ticks, get_cycles_to_duration, get_cycles_to_ms, get_early_uart,
-    handle_plic_irq, init, set_timeout_after,
+    current_ticks, get_early_uart, handle_plic_irq, init, set_timeout_after, NUM_TICKS_PER_SECOND,
 };
 
 #[cfg(target_board = "qemu_mps3_an547")]
 mod qemu_mps3_an547;
 #[cfg(target_board = "qemu_mps3_an547")]
-pub(crate) use qemu_mps3_an547::{get_cycles_to_duration, get_cycles_to_ms, get_early_uart, init};
+pub(crate) use qemu_mps3_an547::{get_early_uart, init};
 
 #[cfg(target_board = "qemu_virt64_aarch64")]
 mod qemu_virt64_aarch64;
 #[cfg(target_board = "qemu_virt64_aarch64")]
-pub(crate) use qemu_virt64_aarch64::{
-    get_cycles_to_duration, get_cycles_to_ms, get_early_uart, init,
-};
+pub(crate) use qemu_virt64_aarch64::{get_early_uart, init};
 
 #[cfg(target_board = "bcm2711")]
 mod bcm2711;
 #[cfg(target_board = "bcm2711")]
-pub(crate) use bcm2711::{get_cycles_to_duration, get_cycles_to_ms, get_early_uart, init};
+pub(crate) use bcm2711::{get_early_uart, init};
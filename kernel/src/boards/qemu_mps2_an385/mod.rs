@@ -87,14 +87,3 @@ pub(crate) fn init() {
         Err(e) => panic!("Failed to init console: {}", Error::from(e)),
     }
 }
-
-// can SYSTEM_CORE_CLOCK bigger than 1GHz ？
-pub(crate) fn get_cycles_to_duration(cycles: u64) -> core::time::Duration {
-    core::time::Duration::from_nanos(
-        (cycles as f32 * (1_000_000_000f32 / config::SYSTEM_CORE_CLOCK as f32)) as u64,
-    )
-}
-
-pub(crate) fn get_cycles_to_ms(cycles: u64) -> u64 {
-    (cycles as f32 * (1_000_000f32 / config::SYSTEM_CORE_CLOCK as f32)) as u64
-}
@@ -34,7 +34,7 @@ pub(crate) use uart::get_early_uart;
 
 const CLOCK_ADDR: usize = 0x0200_0000;
 const CLOCK_TIME: usize = CLOCK_ADDR + 0xBFF8;
-const NUM_TICKS_PER_SECOND: usize = 10_000_000;
+pub(crate) const NUM_TICKS_PER_SECOND: usize = 10_000_000;
 const NUM_TICKS_PER_TIMER: usize = NUM_TICKS_PER_SECOND / 10;
 const NS_PER_TICK: usize = 1_000_000_000 / NUM_TICKS_PER_SECOND;
 static PLIC: Plic = Plic::new(0x0c00_0000);
@@ -49,17 +49,6 @@ pub fn current_ticks() -> usize {
     unsafe { (CLOCK_TIME as *const usize).read_volatile() }
 }
 
-#[inline]
-pub fn current_cycles() -> usize {
-    let x: usize;
-    unsafe {
-        core::arch::asm!("csrr {}, cycle",
-                         out(reg) x,
-                         options(nostack, nomem))
-    }
-    x
-}
-
 fn set_timecmp(tick: usize) {
     let hart = arch::current_cpu_id();
     unsafe { clock_timecmp_ptr(hart).write_volatile(tick) };
@@ -87,14 +76,6 @@ pub(crate) fn set_timeout_after(ns: usize) {
     set_timecmp(current_ticks() + ns / NS_PER_TICK);
 }
 
-pub(crate) fn get_cycles_to_duration(cycles: u64) -> core::time::Duration {
-    core::time::Duration::from_nanos(cycles)
-}
-
-pub(crate) fn get_cycles_to_ms(cycles: u64) -> u64 {
-    cycles / 1_000
-}
-
 pub(crate) fn ticks_to_duration(ticks: usize) -> core::time::Duration {
     core::time::Duration::from_nanos((ticks * NS_PER_TICK) as u64)
 }
@@ -42,3 +42,9 @@ pub const DEFAULT_STACK_SIZE: usize = 16 << 10;
 pub const DEFAULT_STACK_SIZE: usize = 8 << 10;
 
 pub const SOFT_TIMER_THREAD_PRIORITY: ThreadPriority = 0;
+
+// Number of thread-local storage slots carried by each thread.
+pub const MAX_TLS_KEYS: usize = 8;
+
+// Longest thread name in bytes, longer names are truncated.
+pub const MAX_THREAD_NAME_LEN: usize = 16;
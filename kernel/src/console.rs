@@ -12,8 +12,25 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::devices::console::{get_console, get_early_uart};
-use core::{fmt, str};
+use crate::{
+    devices::console::{get_console, get_early_uart},
+    sync::SpinLock,
+    time,
+};
+use blueos_kconfig::TICKS_PER_SECOND;
+use core::{cmp, fmt, str};
+use semihosting::io::Write;
+
+// Pending output is flushed once it has waited this long.
+const FLUSH_DELAY_TICKS: usize = if TICKS_PER_SECOND >= 100 {
+    TICKS_PER_SECOND / 100
+} else {
+    1
+};
+const SEMIHOSTING_BUFFER_SIZE: usize = 1024;
+
+pub static SEMIHOSTING_CONSOLE: BatchedConsole<SemihostingSink, SEMIHOSTING_BUFFER_SIZE> =
+    BatchedConsole::new(SemihostingSink);
 
 #[macro_export]
 macro_rules! kprintln {
@@ -43,6 +60,25 @@ macro_rules! kearly_println {
     });
 }
 
+#[macro_export]
+macro_rules! batched_print {
+    ($($arg:tt)*) => ({
+        use core::fmt::Write;
+        let mut writer = &$crate::console::SEMIHOSTING_CONSOLE;
+        writer.write_fmt(format_args!($($arg)*)).unwrap();
+    });
+}
+
+#[macro_export]
+macro_rules! batched_println {
+    ($fmt:expr) => ({
+        $crate::batched_print!(concat!($fmt, "\n"));
+    });
+    ($fmt:expr, $($arg:tt)*) => ({
+        $crate::batched_print!(concat!($fmt, "\n"), $($arg)*);
+    });
+}
+
 pub struct Console;
 impl fmt::Write for Console {
     fn write_str(&mut self, s: &str) -> core::fmt::Result {
@@ -59,3 +95,174 @@ impl fmt::Write for EarlyConsole {
         Ok(())
     }
 }
+
+pub trait ConsoleSink: Sync {
+    fn write_bytes(&self, buf: &[u8]);
+}
+
+pub struct SemihostingSink;
+
+impl ConsoleSink for SemihostingSink {
+    fn write_bytes(&self, buf: &[u8]) {
+        if let Ok(mut stdout) = semihosting::io::stdout() {
+            let _ = stdout.write_all(buf);
+        }
+    }
+}
+
+struct BatchBuffer<const N: usize> {
+    len: usize,
+    // Tick at which the oldest pending byte was written.
+    since: usize,
+    data: [u8; N],
+}
+
+/// Accumulates console output and hands it to the sink in large chunks:
+/// when the buffer fills up, on flush(), or once the oldest pending
+/// output has waited for FLUSH_DELAY_TICKS.
+pub struct BatchedConsole<S: ConsoleSink, const N: usize> {
+    sink: S,
+    buf: SpinLock<BatchBuffer<N>>,
+}
+
+impl<S: ConsoleSink, const N: usize> BatchedConsole<S, N> {
+    pub const fn new(sink: S) -> Self {
+        Self {
+            sink,
+            buf: SpinLock::new(BatchBuffer {
+                len: 0,
+                since: 0,
+                data: [0; N],
+            }),
+        }
+    }
+
+    pub fn write(&self, mut bytes: &[u8]) {
+        let mut buf = self.buf.irqsave_lock();
+        let now = time::get_sys_ticks();
+        if Self::is_stale(&buf, now) {
+            self.sink.write_bytes(&buf.data[..buf.len]);
+            buf.len = 0;
+        }
+        while !bytes.is_empty() {
+            let len = buf.len;
+            if len == 0 {
+                buf.since = now;
+            }
+            let n = cmp::min(N - len, bytes.len());
+            buf.data[len..len + n].copy_from_slice(&bytes[..n]);
+            buf.len += n;
+            bytes = &bytes[n..];
+            if buf.len == N {
+                self.sink.write_bytes(&buf.data);
+                buf.len = 0;
+            }
+        }
+    }
+
+    pub fn flush(&self) {
+        let mut buf = self.buf.irqsave_lock();
+        if buf.len > 0 {
+            self.sink.write_bytes(&buf.data[..buf.len]);
+            buf.len = 0;
+        }
+    }
+
+    fn is_stale(buf: &BatchBuffer<N>, now: usize) -> bool {
+        buf.len > 0 && now.saturating_sub(buf.since) >= FLUSH_DELAY_TICKS
+    }
+
+    /// Flush the pending output if it has waited for FLUSH_DELAY_TICKS.
+    /// The sink might block, so it's called by the idle threads rather
+    /// than on ticks. Writes flush stale output as well.
+    pub fn flush_stale(&self, now: usize) {
+        let mut buf = self.buf.irqsave_lock();
+        if Self::is_stale(&buf, now) {
+            self.sink.write_bytes(&buf.data[..buf.len]);
+            buf.len = 0;
+        }
+    }
+
+    /// Flush whatever is pending before panic output. The buffer is
+    /// skipped if it is locked, since the lock owner might be the
+    /// panicking context itself.
+    pub fn flush_on_panic(&self) {
+        if let Some(mut buf) = self.buf.try_irqsave_lock() {
+            if buf.len > 0 {
+                self.sink.write_bytes(&buf.data[..buf.len]);
+                buf.len = 0;
+            }
+        }
+    }
+}
+
+impl<S: ConsoleSink, const N: usize> fmt::Write for &BatchedConsole<S, N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blueos_test_macro::test;
+    use core::{
+        fmt::Write,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    struct CountingSink {
+        calls: AtomicUsize,
+        bytes: AtomicUsize,
+    }
+
+    impl ConsoleSink for CountingSink {
+        fn write_bytes(&self, buf: &[u8]) {
+            self.calls.fetch_add(1, Ordering::Relaxed);
+            self.bytes.fetch_add(buf.len(), Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn test_batched_console_flush() {
+        const PRINTS: usize = 200;
+        let console = BatchedConsole::<_, 256>::new(CountingSink {
+            calls: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+        });
+        let mut writer = &console;
+        for i in 0..PRINTS {
+            writeln!(writer, "line {:03}", i).unwrap();
+        }
+        console.flush();
+        // Each print is 9 bytes, 256 bytes are written per call.
+        let calls = console.sink.calls.load(Ordering::Relaxed);
+        assert_eq!(console.sink.bytes.load(Ordering::Relaxed), PRINTS * 9);
+        assert_eq!(calls, (PRINTS * 9).div_ceil(256));
+        assert!(calls * 20 < PRINTS);
+    }
+
+    #[test]
+    fn test_batched_console_flush_stale() {
+        let console = BatchedConsole::<_, 256>::new(CountingSink {
+            calls: AtomicUsize::new(0),
+            bytes: AtomicUsize::new(0),
+        });
+        console.write(b"pending");
+        let since = console.buf.irqsave_lock().since;
+        console.flush_stale(since);
+        assert_eq!(console.sink.calls.load(Ordering::Relaxed), 0);
+        console.flush_stale(since + FLUSH_DELAY_TICKS);
+        assert_eq!(console.sink.calls.load(Ordering::Relaxed), 1);
+        assert_eq!(console.sink.bytes.load(Ordering::Relaxed), 7);
+
+        // A write after the delay flushes what was pending before it.
+        console.write(b"old");
+        console.buf.irqsave_lock().since -= FLUSH_DELAY_TICKS;
+        console.write(b"new");
+        assert_eq!(console.sink.calls.load(Ordering::Relaxed), 2);
+        assert_eq!(console.sink.bytes.load(Ordering::Relaxed), 10);
+        assert_eq!(console.buf.irqsave_lock().len, 3);
+    }
+}
@@ -0,0 +1,403 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Write-back cache of block device sectors.
+//!
+//! Sectors are read from the driver on the first access and written back
+//! when they are evicted or the cache is flushed. Sequential reads load
+//! the sectors they miss and a window of following sectors in one driver
+//! call.
+
+use super::BlockDriverOps;
+use alloc::{boxed::Box, vec, vec::Vec};
+use core::{cmp::min, ops::Range};
+use virtio_drivers::device::blk::SECTOR_SIZE;
+
+struct CachedSector {
+    sector: usize,
+    data: Box<[u8; SECTOR_SIZE]>,
+    dirty: bool,
+}
+
+pub(crate) struct BlockCache {
+    // In LRU order, the least recently used sector comes first
+    sectors: Vec<CachedSector>,
+    capacity: usize,
+    // Max number of sectors read ahead, 0 disables read-ahead
+    window: usize,
+    // Byte position right after the last read
+    last_end: Option<u64>,
+}
+
+impl BlockCache {
+    pub fn new(capacity: usize, window: usize) -> Self {
+        assert!(capacity > 0);
+        Self {
+            sectors: Vec::with_capacity(capacity),
+            capacity,
+            window,
+            last_end: None,
+        }
+    }
+
+    fn contains(&self, sector: usize) -> bool {
+        self.sectors.iter().any(|s| s.sector == sector)
+    }
+
+    // Makes `sector` the most recently used, returns false if it isn't cached.
+    fn touch(&mut self, sector: usize) -> bool {
+        let Some(index) = self.sectors.iter().position(|s| s.sector == sector) else {
+            return false;
+        };
+        let cached = self.sectors.remove(index);
+        self.sectors.push(cached);
+        true
+    }
+
+    fn insert<E>(
+        &mut self,
+        driver: &mut dyn BlockDriverOps<Error = E>,
+        sector: usize,
+        data: Box<[u8; SECTOR_SIZE]>,
+    ) -> Result<(), E> {
+        if self.sectors.len() == self.capacity {
+            self.evict(driver)?;
+        }
+        self.sectors.push(CachedSector {
+            sector,
+            data,
+            dirty: false,
+        });
+        Ok(())
+    }
+
+    // Sectors to be overwritten entirely are not read from the driver.
+    fn get<E>(
+        &mut self,
+        driver: &mut dyn BlockDriverOps<Error = E>,
+        sector: usize,
+        load: bool,
+    ) -> Result<&mut CachedSector, E> {
+        if !self.touch(sector) {
+            let mut data = Box::new([0u8; SECTOR_SIZE]);
+            if load {
+                driver.read_blocks(sector, &mut data[..])?;
+            }
+            self.insert(driver, sector, data)?;
+        }
+        Ok(self.sectors.last_mut().unwrap())
+    }
+
+    fn evict<E>(&mut self, driver: &mut dyn BlockDriverOps<Error = E>) -> Result<(), E> {
+        let victim = &self.sectors[0];
+        if victim.dirty {
+            driver.write_blocks(victim.sector, &victim.data[..])?;
+        }
+        self.sectors.remove(0);
+        Ok(())
+    }
+
+    /// Record a read of `len` bytes at byte position `pos`, called before
+    /// reading the sectors it covers. If it continues the previous read and
+    /// misses the cache, the uncached sectors from the first miss on, up to
+    /// `window` sectors past the read, are loaded in one driver call. It
+    /// never evicts sectors of the read itself, and is skipped for reads
+    /// larger than the cache.
+    pub fn read_ahead<E>(
+        &mut self,
+        driver: &mut dyn BlockDriverOps<Error = E>,
+        pos: u64,
+        len: usize,
+    ) -> Result<(), E> {
+        let sequential = self.last_end == Some(pos);
+        self.last_end = Some(pos + len as u64);
+        if !sequential || len == 0 {
+            return Ok(());
+        }
+        let first = (pos / SECTOR_SIZE as u64) as usize;
+        let last = ((pos + len as u64 - 1) / SECTOR_SIZE as u64) as usize;
+        if last - first >= self.capacity {
+            return Ok(());
+        }
+        let Some(start) = (first..=last).find(|&sector| !self.contains(sector)) else {
+            return Ok(());
+        };
+        // The cached sectors of the read go last in LRU order, so that only
+        // older ones make room for the loaded sectors.
+        for sector in first..start {
+            self.touch(sector);
+        }
+        // Never read past the device, nor more than the cache holds
+        let end = min(last + 1 + self.window, driver.capacity() as usize);
+        let end = min(end, first + self.capacity);
+        let count = (start..end)
+            .position(|sector| self.contains(sector))
+            .unwrap_or(end - start);
+
+        let mut buf = vec![0u8; count * SECTOR_SIZE];
+        driver.read_blocks(start, &mut buf)?;
+        for (i, chunk) in buf.chunks_exact(SECTOR_SIZE).enumerate() {
+            let mut data = Box::new([0u8; SECTOR_SIZE]);
+            data.copy_from_slice(chunk);
+            self.insert(driver, start + i, data)?;
+        }
+        Ok(())
+    }
+
+    /// Read `buf.len()` bytes at `offset` of `sector`, the range must not
+    /// cross the sector.
+    pub fn read<E>(
+        &mut self,
+        driver: &mut dyn BlockDriverOps<Error = E>,
+        sector: usize,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> Result<(), E> {
+        let cached = self.get(driver, sector, true)?;
+        buf.copy_from_slice(&cached.data[offset..offset + buf.len()]);
+        Ok(())
+    }
+
+    /// Write `buf` at `offset` of `sector`, the range must not cross the
+    /// sector. It reaches the driver on eviction or `flush`.
+    pub fn write<E>(
+        &mut self,
+        driver: &mut dyn BlockDriverOps<Error = E>,
+        sector: usize,
+        offset: usize,
+        buf: &[u8],
+    ) -> Result<(), E> {
+        let whole_sector = offset == 0 && buf.len() == SECTOR_SIZE;
+        let cached = self.get(driver, sector, !whole_sector)?;
+        cached.data[offset..offset + buf.len()].copy_from_slice(buf);
+        cached.dirty = true;
+        Ok(())
+    }
+
+    /// Write all dirty sectors back to the driver.
+    pub fn flush<E>(&mut self, driver: &mut dyn BlockDriverOps<Error = E>) -> Result<(), E> {
+        self.flush_range(driver, 0..usize::MAX)
+    }
+
+    /// Write the dirty sectors in `range` back to the driver.
+    pub fn flush_range<E>(
+        &mut self,
+        driver: &mut dyn BlockDriverOps<Error = E>,
+        range: Range<usize>,
+    ) -> Result<(), E> {
+        for cached in self
+            .sectors
+            .iter_mut()
+            .filter(|s| s.dirty && range.contains(&s.sector))
+        {
+            driver.write_blocks(cached.sector, &cached.data[..])?;
+            cached.dirty = false;
+        }
+        Ok(())
+    }
+
+    /// Drop the sectors in `range` without writing them back, they are
+    /// about to be overwritten on the device.
+    pub fn discard_range(&mut self, range: Range<usize>) {
+        self.sectors.retain(|s| !range.contains(&s.sector));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::block::ErrorType;
+    use alloc::{vec, vec::Vec};
+    use blueos_test_macro::test;
+    use embedded_io::ErrorKind;
+
+    // In memory disk counting the I/Os reaching it
+    struct MockDriver {
+        disk: Vec<u8>,
+        reads: usize,
+        writes: usize,
+    }
+
+    impl MockDriver {
+        fn new(sectors: usize) -> Self {
+            Self {
+                disk: vec![0u8; sectors * SECTOR_SIZE],
+                reads: 0,
+                writes: 0,
+            }
+        }
+    }
+
+    impl ErrorType for MockDriver {
+        type Error = ErrorKind;
+    }
+
+    impl BlockDriverOps for MockDriver {
+        fn capacity(&self) -> u64 {
+            (self.disk.len() / SECTOR_SIZE) as u64
+        }
+
+        fn sector_size(&self) -> u16 {
+            SECTOR_SIZE as u16
+        }
+
+        fn read_blocks(&mut self, block_id: usize, buf: &mut [u8]) -> Result<(), ErrorKind> {
+            self.reads += 1;
+            let start = block_id * SECTOR_SIZE;
+            buf.copy_from_slice(&self.disk[start..start + buf.len()]);
+            Ok(())
+        }
+
+        fn write_blocks(&mut self, block_id: usize, buf: &[u8]) -> Result<(), ErrorKind> {
+            self.writes += 1;
+            let start = block_id * SECTOR_SIZE;
+            self.disk[start..start + buf.len()].copy_from_slice(buf);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), ErrorKind> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_read_after_cached_write() {
+        let mut driver = MockDriver::new(8);
+        let mut cache = BlockCache::new(4, 0);
+
+        // A partial write reads the sector once
+        cache.write(&mut driver, 1, 10, b"hello").unwrap();
+        assert_eq!((driver.reads, driver.writes), (1, 0));
+        let mut buf = [0u8; 5];
+        cache.read(&mut driver, 1, 10, &mut buf).unwrap();
+        assert_eq!(&buf, b"hello");
+        cache.write(&mut driver, 1, 12, b"LL").unwrap();
+        cache.read(&mut driver, 1, 10, &mut buf).unwrap();
+        assert_eq!(&buf, b"heLLo");
+        assert_eq!((driver.reads, driver.writes), (1, 0));
+
+        // A whole sector is not read before being written
+        cache.write(&mut driver, 2, 0, &[7u8; SECTOR_SIZE]).unwrap();
+        assert_eq!((driver.reads, driver.writes), (1, 0));
+        assert!(driver.disk.iter().all(|&b| b == 0));
+
+        cache.flush(&mut driver).unwrap();
+        assert_eq!(driver.writes, 2);
+        assert_eq!(&driver.disk[SECTOR_SIZE + 10..SECTOR_SIZE + 15], b"heLLo");
+        assert_eq!(driver.disk[2 * SECTOR_SIZE], 7);
+        // Nothing is dirty any more
+        cache.flush(&mut driver).unwrap();
+        assert_eq!(driver.writes, 2);
+    }
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut driver = MockDriver::new(8);
+        let mut cache = BlockCache::new(2, 0);
+        let mut buf = [0u8; 1];
+
+        cache.write(&mut driver, 0, 0, b"a").unwrap();
+        cache.read(&mut driver, 1, 0, &mut buf).unwrap();
+        // Sector 0 becomes the most recently used
+        cache.read(&mut driver, 0, 0, &mut buf).unwrap();
+        assert_eq!(&buf, b"a");
+        assert_eq!((driver.reads, driver.writes), (2, 0));
+
+        // Sector 1 is clean, it's dropped without writing
+        cache.read(&mut driver, 2, 0, &mut buf).unwrap();
+        assert_eq!((driver.reads, driver.writes), (3, 0));
+
+        // Dirty sector 0 is written back on eviction
+        cache.read(&mut driver, 3, 0, &mut buf).unwrap();
+        assert_eq!((driver.reads, driver.writes), (4, 1));
+        assert_eq!(driver.disk[0], b'a');
+
+        // And read again on the next access
+        cache.read(&mut driver, 0, 0, &mut buf).unwrap();
+        assert_eq!(&buf, b"a");
+        assert_eq!(driver.reads, 5);
+    }
+
+    // Read the disk sequentially in `chunk` bytes, returns the driver reads
+    fn sequential_reads(sectors: usize, window: usize, chunk: usize) -> usize {
+        let mut driver = MockDriver::new(sectors);
+        for (i, b) in driver.disk.iter_mut().enumerate() {
+            *b = (i / SECTOR_SIZE) as u8;
+        }
+        let mut cache = BlockCache::new(8, window);
+        let mut buf = vec![0u8; chunk];
+        let mut pos = 0;
+        while pos < sectors * SECTOR_SIZE {
+            let sector = pos / SECTOR_SIZE;
+            let offset = pos % SECTOR_SIZE;
+            cache.read_ahead(&mut driver, pos as u64, chunk).unwrap();
+            cache.read(&mut driver, sector, offset, &mut buf).unwrap();
+            assert!(buf.iter().all(|&b| b == sector as u8));
+            pos += chunk;
+        }
+        driver.reads
+    }
+
+    #[test]
+    fn test_read_ahead() {
+        assert_eq!(sequential_reads(16, 0, 64), 16);
+        // The first read isn't sequential, then 5 sectors per call
+        assert_eq!(sequential_reads(16, 4, 64), 4);
+        // Bounded by the cache capacity
+        assert_eq!(sequential_reads(16, 32, 64), 3);
+        // Never past the device
+        assert_eq!(sequential_reads(3, 4, SECTOR_SIZE), 2);
+    }
+
+    #[test]
+    fn test_read_ahead_random_access() {
+        let mut driver = MockDriver::new(16);
+        let mut cache = BlockCache::new(8, 4);
+        let mut buf = [0u8; 16];
+        for sector in [3, 9, 1, 12] {
+            let pos = (sector * SECTOR_SIZE) as u64;
+            cache.read_ahead(&mut driver, pos, buf.len()).unwrap();
+            cache.read(&mut driver, sector, 0, &mut buf).unwrap();
+        }
+        assert_eq!(driver.reads, 4);
+        assert_eq!(cache.sectors.len(), 4);
+    }
+
+    #[test]
+    fn test_read_ahead_keeps_current_read() {
+        let mut driver = MockDriver::new(16);
+        let mut cache = BlockCache::new(4, 4);
+        let mut buf = [0u8; SECTOR_SIZE];
+        cache.read_ahead(&mut driver, 0, SECTOR_SIZE).unwrap();
+        // Sector 1 is the least recently used one
+        for sector in [1, 10, 11, 12] {
+            cache.read(&mut driver, sector, 0, &mut buf).unwrap();
+        }
+        assert_eq!(driver.reads, 4);
+
+        // Reads sectors 1 and 2, only 2 to 4 are loaded
+        let pos = SECTOR_SIZE as u64;
+        cache.read_ahead(&mut driver, pos, 2 * SECTOR_SIZE).unwrap();
+        assert_eq!(driver.reads, 5);
+        for sector in 1..=4 {
+            cache.read(&mut driver, sector, 0, &mut buf).unwrap();
+        }
+        assert_eq!(driver.reads, 5);
+
+        // Larger than the cache
+        let pos = 3 * SECTOR_SIZE as u64;
+        cache.read_ahead(&mut driver, pos, 5 * SECTOR_SIZE).unwrap();
+        assert_eq!(driver.reads, 5);
+    }
+}
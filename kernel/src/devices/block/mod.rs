@@ -16,17 +16,41 @@ use crate::{
     devices::{virtio::VirtioHal, Device, DeviceClass, DeviceId, DeviceManager},
     sync::SpinLock,
 };
-use alloc::{string::String, sync::Arc, vec};
-use core::cmp::min;
-use embedded_io::{Error as IOError, ErrorKind};
+use alloc::{collections::BTreeMap, string::String, sync::Arc, vec, vec::Vec};
+use blueos_kconfig::{BLOCK_CACHE_SECTORS, BLOCK_READ_AHEAD_SECTORS};
+use cache::BlockCache;
+use core::{
+    cmp::min,
+    ffi::{c_int, c_ulong},
+};
+use embedded_io::ErrorKind;
+use request::{CompletionMap, RequestIo};
 use virtio_drivers::{
-    device::blk::{VirtIOBlk, SECTOR_SIZE},
+    device::blk::{BlkReq, BlkResp, VirtIOBlk, SECTOR_SIZE},
     transport::SomeTransport,
     Hal,
 };
 
+mod cache;
+mod request;
+
+pub use request::BlockRequest;
+
 pub const VIRTUAL_STORAGE_NAME: &str = "virt-storage";
 
+// Device requests of block devices, numbered as on Linux.
+// Capacity in 512-byte sectors, as an unsigned long.
+pub const BLKGETSIZE: u32 = 0x1260;
+// Write back cached data.
+pub const BLKFLSBUF: u32 = 0x1261;
+// Sector size, as an int.
+pub const BLKSSZGET: u32 = 0x1268;
+// Capacity in bytes, as a u64.
+#[cfg(target_pointer_width = "64")]
+pub const BLKGETSIZE64: u32 = 0x8008_1272;
+#[cfg(target_pointer_width = "32")]
+pub const BLKGETSIZE64: u32 = 0x8004_1272;
+
 #[derive(Debug, Clone, Eq, PartialEq, thiserror::Error)]
 pub enum BlockError<T> {
     #[error("Error from the drviver: {0}")]
@@ -68,6 +92,75 @@ pub trait BlockDriverOps: Send + Sync + ErrorType {
     fn write_blocks(&mut self, block_id: usize, buf: &[u8]) -> Result<(), Self::Error>;
     /// Requests the device to flush any pending writes to storage.
     fn flush(&mut self) -> Result<(), Self::Error>;
+
+    /// Submits a read of one or more blocks without waiting for the device,
+    /// returns the token identifying the request. Drivers without a request
+    /// queue complete the request right away and return `None`.
+    ///
+    /// # Safety
+    ///
+    /// `req`, `buf` and `resp` must not be moved nor accessed until the
+    /// request is completed by `complete_read`.
+    unsafe fn submit_read(
+        &mut self,
+        block_id: usize,
+        _req: &mut BlkReq,
+        buf: &mut [u8],
+        _resp: &mut BlkResp,
+    ) -> Result<Option<u16>, Self::Error> {
+        self.read_blocks(block_id, buf).map(|_| None)
+    }
+
+    /// Submits a write of one or more blocks without waiting for the device.
+    ///
+    /// # Safety
+    ///
+    /// Same as `submit_read`, until the request is completed by
+    /// `complete_write`.
+    unsafe fn submit_write(
+        &mut self,
+        block_id: usize,
+        _req: &mut BlkReq,
+        buf: &[u8],
+        _resp: &mut BlkResp,
+    ) -> Result<Option<u16>, Self::Error> {
+        self.write_blocks(block_id, buf).map(|_| None)
+    }
+
+    /// Gets the token of the next request finished by the device.
+    fn peek_used(&mut self) -> Option<u16> {
+        None
+    }
+
+    /// Completes the read request of `token` once it's used.
+    ///
+    /// # Safety
+    ///
+    /// The arguments must be the ones the request was submitted with.
+    unsafe fn complete_read(
+        &mut self,
+        _token: u16,
+        _req: &BlkReq,
+        _buf: &mut [u8],
+        _resp: &mut BlkResp,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Completes the write request of `token` once it's used.
+    ///
+    /// # Safety
+    ///
+    /// The arguments must be the ones the request was submitted with.
+    unsafe fn complete_write(
+        &mut self,
+        _token: u16,
+        _req: &BlkReq,
+        _buf: &[u8],
+        _resp: &mut BlkResp,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 impl<H: Hal> ErrorType for VirtIOBlk<H, SomeTransport<'static>> {
@@ -103,6 +196,62 @@ impl<H: Hal> BlockDriverOps for VirtIOBlk<H, SomeTransport<'static>> {
     fn sector_size(&self) -> u16 {
         SECTOR_SIZE.try_into().unwrap()
     }
+
+    unsafe fn submit_read(
+        &mut self,
+        block_id: usize,
+        req: &mut BlkReq,
+        buf: &mut [u8],
+        resp: &mut BlkResp,
+    ) -> Result<Option<u16>, Self::Error> {
+        match self.read_blocks_nb(block_id, req, buf, resp) {
+            Ok(token) => Ok(Some(token)),
+            Err(error) => Err(BlockError::Driver(error)),
+        }
+    }
+
+    unsafe fn submit_write(
+        &mut self,
+        block_id: usize,
+        req: &mut BlkReq,
+        buf: &[u8],
+        resp: &mut BlkResp,
+    ) -> Result<Option<u16>, Self::Error> {
+        match self.write_blocks_nb(block_id, req, buf, resp) {
+            Ok(token) => Ok(Some(token)),
+            Err(error) => Err(BlockError::Driver(error)),
+        }
+    }
+
+    fn peek_used(&mut self) -> Option<u16> {
+        self.peek_used()
+    }
+
+    unsafe fn complete_read(
+        &mut self,
+        token: u16,
+        req: &BlkReq,
+        buf: &mut [u8],
+        resp: &mut BlkResp,
+    ) -> Result<(), Self::Error> {
+        match self.complete_read_blocks_nb(token, req, buf, resp) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(BlockError::Driver(error)),
+        }
+    }
+
+    unsafe fn complete_write(
+        &mut self,
+        token: u16,
+        req: &BlkReq,
+        buf: &[u8],
+        resp: &mut BlkResp,
+    ) -> Result<(), Self::Error> {
+        match self.complete_write_blocks_nb(token, req, buf, resp) {
+            Ok(_) => Ok(()),
+            Err(error) => Err(BlockError::Driver(error)),
+        }
+    }
 }
 
 pub fn init_virtio_block(
@@ -114,6 +263,10 @@ pub fn init_virtio_block(
 
 pub struct Block<E: embedded_io::Error, const SECTOR_SIZE: usize> {
     driver: Arc<SpinLock<dyn BlockDriverOps<Error = E>>>,
+    // Locked before `driver`
+    cache: SpinLock<BlockCache>,
+    // Asynchronous requests in flight, locked after `driver`
+    requests: Arc<CompletionMap>,
     name: String,
     total_size: u64, // in bytes
 }
@@ -126,13 +279,84 @@ impl<E: embedded_io::Error> Block<E, SECTOR_SIZE> {
         };
         Block {
             driver,
+            cache: SpinLock::new(BlockCache::new(
+                BLOCK_CACHE_SECTORS,
+                BLOCK_READ_AHEAD_SECTORS,
+            )),
+            requests: Arc::new(SpinLock::new(BTreeMap::new())),
             name: String::from(name),
             total_size,
         }
     }
 }
 
-impl<E: embedded_io::Error> Device for Block<E, SECTOR_SIZE> {
+impl<E: embedded_io::Error + 'static> Block<E, SECTOR_SIZE> {
+    fn check_range(&self, sector: usize, count: usize) -> Result<(), ErrorKind> {
+        let sectors = self.total_size / SECTOR_SIZE as u64;
+        match sector.checked_add(count) {
+            Some(end) if count > 0 && end as u64 <= sectors => Ok(()),
+            _ => Err(ErrorKind::InvalidInput),
+        }
+    }
+
+    /// Read `count` sectors from `sector`, the driver isn't locked while
+    /// the device processes the request. Cached sectors are written back
+    /// first.
+    pub fn read_async(&self, sector: usize, count: usize) -> BlockRequest<E> {
+        let prepared = self.check_range(sector, count).and_then(|_| {
+            let mut io = RequestIo::new(&self.driver, &self.requests);
+            self.cache
+                .lock()
+                .flush_range(&mut io, sector..sector + count)
+        });
+        match prepared {
+            Ok(()) => BlockRequest::submit(
+                self.driver.clone(),
+                self.requests.clone(),
+                sector,
+                vec![0u8; count * SECTOR_SIZE],
+                false,
+            ),
+            Err(error) => BlockRequest::error(self.driver.clone(), self.requests.clone(), error),
+        }
+    }
+
+    /// Write `buf` to the sectors from `sector`, its length must be a
+    /// multiple of the sector size. Cached sectors are dropped first.
+    pub fn write_async(&self, sector: usize, buf: Vec<u8>) -> BlockRequest<E> {
+        let count = buf.len() / SECTOR_SIZE;
+        let prepared = if buf.len() % SECTOR_SIZE != 0 {
+            Err(ErrorKind::InvalidInput)
+        } else {
+            self.check_range(sector, count)
+        };
+        match prepared {
+            Ok(()) => {
+                self.cache.lock().discard_range(sector..sector + count);
+                BlockRequest::submit(
+                    self.driver.clone(),
+                    self.requests.clone(),
+                    sector,
+                    buf,
+                    true,
+                )
+            }
+            Err(error) => BlockRequest::error(self.driver.clone(), self.requests.clone(), error),
+        }
+    }
+
+    /// Synchronous version of `read_async`.
+    pub fn read_sectors(&self, sector: usize, count: usize) -> Result<Vec<u8>, ErrorKind> {
+        request::block_on(self.read_async(sector, count))
+    }
+
+    /// Synchronous version of `write_async`.
+    pub fn write_sectors(&self, sector: usize, buf: Vec<u8>) -> Result<(), ErrorKind> {
+        request::block_on(self.write_async(sector, buf)).map(|_| ())
+    }
+}
+
+impl<E: embedded_io::Error + 'static> Device for Block<E, SECTOR_SIZE> {
     fn name(&self) -> String {
         self.name.clone()
     }
@@ -148,81 +372,34 @@ impl<E: embedded_io::Error> Device for Block<E, SECTOR_SIZE> {
     fn read(&self, pos: u64, buf: &mut [u8], _is_nonblocking: bool) -> Result<usize, ErrorKind> {
         // TODO: handle nonblocking read
         let max_read = min(buf.len() as u64, self.total_size.saturating_sub(pos)) as usize;
-        if max_read == 0 {
-            return Ok(0);
+        let mut cache = self.cache.lock();
+        let mut io = RequestIo::new(&self.driver, &self.requests);
+        cache.read_ahead(&mut io, pos, max_read)?;
+        let mut done = 0;
+        while done < max_read {
+            let cur_pos = pos + done as u64;
+            let sector = (cur_pos / SECTOR_SIZE as u64) as usize;
+            let sector_offset = (cur_pos % SECTOR_SIZE as u64) as usize;
+            let size = min(SECTOR_SIZE - sector_offset, max_read - done);
+            cache.read(&mut io, sector, sector_offset, &mut buf[done..done + size])?;
+            done += size;
         }
-        // Calculate starting sector and offset
-        let start_sector = (pos / SECTOR_SIZE as u64) as usize;
-        let sector_offset = (pos % SECTOR_SIZE as u64) as usize;
-        let sectors_coverred = (sector_offset + max_read).div_ceil(SECTOR_SIZE);
-        let mut sector_buf = vec![0u8; sectors_coverred * SECTOR_SIZE];
-        self.driver
-            .lock()
-            .read_blocks(start_sector, &mut sector_buf)
-            .map_err(|e| IOError::kind(&e))?;
-        // Copy to output buffer
-        buf[..max_read].copy_from_slice(&sector_buf[sector_offset..sector_offset + max_read]);
         Ok(max_read)
     }
 
     fn write(&self, pos: u64, buf: &[u8], _is_nonblocking: bool) -> Result<usize, ErrorKind> {
         // TODO: handle nonblocking write
         let total_write_size = min(buf.len() as u64, self.total_size.saturating_sub(pos)) as usize;
-        if total_write_size == 0 {
-            return Ok(0);
-        }
-        let mut data = &buf[..total_write_size];
-        let mut start_sector = (pos / SECTOR_SIZE as u64) as usize;
-        let sector_offset = (pos % SECTOR_SIZE as u64) as usize;
-
-        // 1. Write first sector
-        let mut write_size = min(SECTOR_SIZE - sector_offset, total_write_size);
-        let mut sector_buf = [0u8; SECTOR_SIZE];
-        if sector_offset != 0 || write_size != SECTOR_SIZE {
-            // If the content to be written cannot completely cover the sector, it needs to be read out first
-            self.driver
-                .lock()
-                .read_blocks(start_sector, &mut sector_buf)
-                .map_err(|e| IOError::kind(&e))?;
-        }
-        // Update the parts that need to be modified
-        sector_buf[sector_offset..sector_offset + write_size].copy_from_slice(&data[..write_size]);
-        // Write back to the modified sectors
-        self.driver
-            .lock()
-            .write_blocks(start_sector, &sector_buf)
-            .map_err(|e| IOError::kind(&e))?;
-        data = &data[write_size..];
-        start_sector += 1;
-        // 2. Write continuous sectors
-        let continuous_sectors = data.len() / SECTOR_SIZE;
-        if continuous_sectors != 0 {
-            write_size = SECTOR_SIZE * continuous_sectors;
-            let mut sector_buf = vec![0u8; write_size];
-            sector_buf[..write_size].copy_from_slice(&data[..write_size]);
-            // Write back to the modified sectors
-            self.driver
-                .lock()
-                .write_blocks(start_sector, &sector_buf)
-                .map_err(|e| IOError::kind(&e))?;
-            data = &data[write_size..];
-            start_sector += continuous_sectors;
-        }
-        // 3. Write last sector
-        write_size = data.len();
-        if write_size > 0 {
-            let mut sector_buf = [0u8; SECTOR_SIZE];
-            self.driver
-                .lock()
-                .read_blocks(start_sector, &mut sector_buf)
-                .map_err(|e| IOError::kind(&e))?;
-            // Update the parts that need to be modified
-            sector_buf[..write_size].copy_from_slice(&data[..write_size]);
-            // Write back to the modified sectors
-            self.driver
-                .lock()
-                .write_blocks(start_sector, &sector_buf)
-                .map_err(|e| IOError::kind(&e))?;
+        let mut cache = self.cache.lock();
+        let mut io = RequestIo::new(&self.driver, &self.requests);
+        let mut done = 0;
+        while done < total_write_size {
+            let cur_pos = pos + done as u64;
+            let sector = (cur_pos / SECTOR_SIZE as u64) as usize;
+            let sector_offset = (cur_pos % SECTOR_SIZE as u64) as usize;
+            let size = min(SECTOR_SIZE - sector_offset, total_write_size - done);
+            cache.write(&mut io, sector, sector_offset, &buf[done..done + size])?;
+            done += size;
         }
         Ok(total_write_size)
     }
@@ -237,21 +414,269 @@ impl<E: embedded_io::Error> Device for Block<E, SECTOR_SIZE> {
         Ok(driver.sector_size())
     }
 
-    fn sync(&self) -> Result<(), ErrorKind> {
-        let mut driver = self.driver.lock();
-        match driver.flush() {
-            Ok(_) => Ok(()),
-            Err(error) => Err(embedded_io::Error::kind(&error)),
+    fn ioctl(&self, request: u32, arg: usize) -> Result<(), ErrorKind> {
+        match request {
+            BLKFLSBUF => self.sync(),
+            BLKGETSIZE | BLKSSZGET | BLKGETSIZE64 if arg == 0 => Err(ErrorKind::InvalidInput),
+            BLKGETSIZE => {
+                let sectors = self.total_size / 512;
+                unsafe { (arg as *mut c_ulong).write(sectors as c_ulong) };
+                Ok(())
+            }
+            BLKSSZGET => {
+                let sector_size = self.sector_size()?;
+                unsafe { (arg as *mut c_int).write(sector_size as c_int) };
+                Ok(())
+            }
+            BLKGETSIZE64 => {
+                unsafe { (arg as *mut u64).write(self.total_size) };
+                Ok(())
+            }
+            _ => Err(ErrorKind::Unsupported),
         }
     }
+
+    fn sync(&self) -> Result<(), ErrorKind> {
+        let mut io = RequestIo::new(&self.driver, &self.requests);
+        self.cache.lock().flush(&mut io)?;
+        io.flush()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use alloc::collections::VecDeque;
     use blueos_test_macro::test;
+    use core::{
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll, Waker},
+    };
     use semihosting::println;
 
+    // In memory disk with a request queue, the data is transferred when a
+    // request is completed
+    struct QueuedDriver {
+        disk: Vec<u8>,
+        // Token and first block of the requests
+        submitted: Vec<(u16, usize)>,
+        used: VecDeque<(u16, usize)>,
+        next_token: u16,
+        // Requests are used as soon as they are submitted
+        immediate: bool,
+    }
+
+    impl QueuedDriver {
+        fn new(sectors: usize, immediate: bool) -> Self {
+            let mut disk = vec![0u8; sectors * SECTOR_SIZE];
+            for (i, b) in disk.iter_mut().enumerate() {
+                *b = (i / SECTOR_SIZE) as u8;
+            }
+            Self {
+                disk,
+                submitted: Vec::new(),
+                used: VecDeque::new(),
+                next_token: 0,
+                immediate,
+            }
+        }
+
+        fn submit(&mut self, block_id: usize) -> u16 {
+            let token = self.next_token;
+            self.next_token += 1;
+            if self.immediate {
+                self.used.push_back((token, block_id));
+            } else {
+                self.submitted.push((token, block_id));
+            }
+            token
+        }
+
+        // The device finishes the requests in reverse order
+        fn finish(&mut self) {
+            while let Some(request) = self.submitted.pop() {
+                self.used.push_back(request);
+            }
+        }
+
+        fn pop_used(&mut self, token: u16) -> usize {
+            let (used, block_id) = self.used.pop_front().unwrap();
+            assert_eq!(used, token);
+            block_id * SECTOR_SIZE
+        }
+    }
+
+    impl ErrorType for QueuedDriver {
+        type Error = ErrorKind;
+    }
+
+    impl BlockDriverOps for QueuedDriver {
+        fn capacity(&self) -> u64 {
+            (self.disk.len() / SECTOR_SIZE) as u64
+        }
+
+        fn sector_size(&self) -> u16 {
+            SECTOR_SIZE as u16
+        }
+
+        fn read_blocks(&mut self, block_id: usize, buf: &mut [u8]) -> Result<(), ErrorKind> {
+            let start = block_id * SECTOR_SIZE;
+            buf.copy_from_slice(&self.disk[start..start + buf.len()]);
+            Ok(())
+        }
+
+        fn write_blocks(&mut self, block_id: usize, buf: &[u8]) -> Result<(), ErrorKind> {
+            let start = block_id * SECTOR_SIZE;
+            self.disk[start..start + buf.len()].copy_from_slice(buf);
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), ErrorKind> {
+            Ok(())
+        }
+
+        unsafe fn submit_read(
+            &mut self,
+            block_id: usize,
+            _req: &mut BlkReq,
+            _buf: &mut [u8],
+            _resp: &mut BlkResp,
+        ) -> Result<Option<u16>, ErrorKind> {
+            Ok(Some(self.submit(block_id)))
+        }
+
+        unsafe fn submit_write(
+            &mut self,
+            block_id: usize,
+            _req: &mut BlkReq,
+            _buf: &[u8],
+            _resp: &mut BlkResp,
+        ) -> Result<Option<u16>, ErrorKind> {
+            Ok(Some(self.submit(block_id)))
+        }
+
+        fn peek_used(&mut self) -> Option<u16> {
+            self.used.front().map(|(token, _)| *token)
+        }
+
+        unsafe fn complete_read(
+            &mut self,
+            token: u16,
+            _req: &BlkReq,
+            buf: &mut [u8],
+            _resp: &mut BlkResp,
+        ) -> Result<(), ErrorKind> {
+            let start = self.pop_used(token);
+            buf.copy_from_slice(&self.disk[start..start + buf.len()]);
+            Ok(())
+        }
+
+        unsafe fn complete_write(
+            &mut self,
+            token: u16,
+            _req: &BlkReq,
+            buf: &[u8],
+            _resp: &mut BlkResp,
+        ) -> Result<(), ErrorKind> {
+            let start = self.pop_used(token);
+            self.disk[start..start + buf.len()].copy_from_slice(buf);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_block_async_overlapped_reads() {
+        let driver = Arc::new(SpinLock::new(QueuedDriver::new(16, false)));
+        let block = Block::new("queued-block", driver.clone());
+        let mut ctx = Context::from_waker(Waker::noop());
+
+        let mut first = block.read_async(1, 2);
+        let mut second = block.read_async(6, 1);
+        assert!(Pin::new(&mut first).poll(&mut ctx).is_pending());
+        assert!(Pin::new(&mut second).poll(&mut ctx).is_pending());
+
+        // Polling the first request completes both
+        driver.lock().finish();
+        let Poll::Ready(Ok(buf)) = Pin::new(&mut first).poll(&mut ctx) else {
+            panic!("the first read is not done");
+        };
+        assert!(driver.lock().used.is_empty());
+        assert!(buf[..SECTOR_SIZE].iter().all(|&b| b == 1));
+        assert!(buf[SECTOR_SIZE..].iter().all(|&b| b == 2));
+        let Poll::Ready(Ok(buf)) = Pin::new(&mut second).poll(&mut ctx) else {
+            panic!("the second read is not done");
+        };
+        assert_eq!(buf, vec![6u8; SECTOR_SIZE]);
+        assert!(block.requests.lock().is_empty());
+
+        // A dropped request is removed once completed
+        drop(block.read_async(3, 1));
+        assert_eq!(block.requests.lock().len(), 1);
+        driver.lock().finish();
+        // The cache reads through requests as well
+        driver.lock().immediate = true;
+        let mut buf = [0u8; 1];
+        assert_eq!(block.read(0, &mut buf, false), Ok(1));
+        assert!(block.requests.lock().is_empty());
+    }
+
+    #[test]
+    fn test_block_sync_sectors() {
+        let block = Block::new(
+            "queued-block",
+            Arc::new(SpinLock::new(QueuedDriver::new(16, true))),
+        );
+
+        // Cached writes are seen by the uncached path
+        let pos = 2 * SECTOR_SIZE as u64 + 10;
+        assert_eq!(block.write(pos, b"cached", false), Ok(6));
+        let buf = block.read_sectors(2, 1).unwrap();
+        assert_eq!(&buf[10..16], b"cached");
+
+        // And the other way around
+        block.write_sectors(2, vec![5u8; SECTOR_SIZE]).unwrap();
+        let mut buf = [0u8; 6];
+        assert_eq!(block.read(pos, &mut buf, false), Ok(6));
+        assert_eq!(buf, [5u8; 6]);
+
+        assert_eq!(
+            block.write_sectors(2, vec![0u8; SECTOR_SIZE / 2]),
+            Err(ErrorKind::InvalidInput)
+        );
+        assert_eq!(block.read_sectors(15, 2), Err(ErrorKind::InvalidInput));
+        assert_eq!(block.read_sectors(0, 0), Err(ErrorKind::InvalidInput));
+    }
+
+    #[test]
+    fn test_block_ioctl_capacity() {
+        let block = Block::new(
+            "queued-block",
+            Arc::new(SpinLock::new(QueuedDriver::new(16, true))),
+        );
+        let mut sectors: c_ulong = 0;
+        assert_eq!(
+            block.ioctl(BLKGETSIZE, &mut sectors as *mut c_ulong as usize),
+            Ok(())
+        );
+        assert_eq!(sectors as u64, block.capacity().unwrap());
+        let mut size = 0u64;
+        assert_eq!(
+            block.ioctl(BLKGETSIZE64, &mut size as *mut u64 as usize),
+            Ok(())
+        );
+        assert_eq!(size, 16 * SECTOR_SIZE as u64);
+        let mut sector_size: c_int = 0;
+        assert_eq!(
+            block.ioctl(BLKSSZGET, &mut sector_size as *mut c_int as usize),
+            Ok(())
+        );
+        assert_eq!(sector_size, SECTOR_SIZE as c_int);
+
+        assert_eq!(block.ioctl(BLKGETSIZE, 0), Err(ErrorKind::InvalidInput));
+        assert_eq!(block.ioctl(0x5401, 0), Err(ErrorKind::Unsupported));
+    }
+
     fn test_virtio_block_read_write(write_size: usize, pos: usize) {
         let block_device = DeviceManager::get().get_block_device(VIRTUAL_STORAGE_NAME);
         if let Some(block_device) = block_device {
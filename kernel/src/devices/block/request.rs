@@ -0,0 +1,289 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Asynchronous block requests.
+//!
+//! A request is submitted to the driver's queue and the driver lock is
+//! released right away. Requests in flight are kept in a completion map
+//! keyed by the driver's token, so whichever request is polled first
+//! completes every request the device has finished, in any order.
+
+use super::{BlockDriverOps, ErrorType};
+use crate::{asynk, sync::SpinLock};
+use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec, vec::Vec};
+use core::{
+    future::{poll_fn, Future},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use embedded_io::{Error as IOError, ErrorKind};
+use virtio_drivers::device::blk::{BlkReq, BlkResp};
+
+pub(super) struct Request {
+    // Boxed, the device accesses them until the request is completed
+    req: Box<BlkReq>,
+    resp: Box<BlkResp>,
+    buf: Vec<u8>,
+    write: bool,
+    result: Option<Result<(), ErrorKind>>,
+    // The future was dropped before the request completed
+    abandoned: bool,
+}
+
+impl Request {
+    fn new(buf: Vec<u8>, write: bool) -> Self {
+        Self {
+            req: Box::default(),
+            resp: Box::default(),
+            buf,
+            write,
+            result: None,
+            abandoned: false,
+        }
+    }
+
+    // Returns the token, or None if the driver completed it synchronously.
+    fn submit<E: IOError>(
+        &mut self,
+        driver: &mut dyn BlockDriverOps<Error = E>,
+        sector: usize,
+    ) -> Result<Option<u16>, ErrorKind> {
+        // SAFETY: The buffers are owned by the request, which stays in the
+        // completion map until the request is completed.
+        let token = unsafe {
+            if self.write {
+                driver.submit_write(sector, &mut self.req, &self.buf, &mut self.resp)
+            } else {
+                driver.submit_read(sector, &mut self.req, &mut self.buf, &mut self.resp)
+            }
+        };
+        token.map_err(|e| e.kind())
+    }
+
+    fn complete<E: IOError>(&mut self, driver: &mut dyn BlockDriverOps<Error = E>, token: u16) {
+        // SAFETY: Same buffers as submitted with `token`.
+        let result = unsafe {
+            if self.write {
+                driver.complete_write(token, &self.req, &self.buf, &mut self.resp)
+            } else {
+                driver.complete_read(token, &self.req, &mut self.buf, &mut self.resp)
+            }
+        };
+        self.result = Some(result.map_err(|e| e.kind()));
+    }
+}
+
+pub(super) type CompletionMap = SpinLock<BTreeMap<u16, Request>>;
+
+/// Complete the requests finished by the device, whichever future they
+/// belong to.
+pub(super) fn reap<E: IOError>(
+    driver: &mut dyn BlockDriverOps<Error = E>,
+    requests: &mut BTreeMap<u16, Request>,
+) {
+    while let Some(used) = driver.peek_used() {
+        let Some(request) = requests.get_mut(&used) else {
+            break;
+        };
+        request.complete(driver, used);
+        if request.abandoned {
+            requests.remove(&used);
+        }
+    }
+}
+
+enum State {
+    Done(Option<Result<Vec<u8>, ErrorKind>>),
+    InFlight(u16),
+}
+
+/// A read or write in flight, resolves to the buffer of the request.
+pub struct BlockRequest<E: IOError + 'static> {
+    driver: Arc<SpinLock<dyn BlockDriverOps<Error = E>>>,
+    // Locked after `driver`
+    requests: Arc<CompletionMap>,
+    state: State,
+}
+
+impl<E: IOError + 'static> BlockRequest<E> {
+    pub(super) fn submit(
+        driver: Arc<SpinLock<dyn BlockDriverOps<Error = E>>>,
+        requests: Arc<CompletionMap>,
+        sector: usize,
+        buf: Vec<u8>,
+        write: bool,
+    ) -> Self {
+        let mut request = Request::new(buf, write);
+        let state = {
+            let mut locked = driver.lock();
+            match request.submit(&mut *locked, sector) {
+                Ok(Some(token)) => {
+                    // Inserted before unlocking the driver, so that no one
+                    // could see the token completed without its request.
+                    requests.lock().insert(token, request);
+                    State::InFlight(token)
+                }
+                Ok(None) => State::Done(Some(Ok(request.buf))),
+                Err(e) => State::Done(Some(Err(e))),
+            }
+        };
+        Self {
+            driver,
+            requests,
+            state,
+        }
+    }
+
+    pub(super) fn error(
+        driver: Arc<SpinLock<dyn BlockDriverOps<Error = E>>>,
+        requests: Arc<CompletionMap>,
+        error: ErrorKind,
+    ) -> Self {
+        Self {
+            driver,
+            requests,
+            state: State::Done(Some(Err(error))),
+        }
+    }
+}
+
+impl<E: IOError + 'static> Future for BlockRequest<E> {
+    type Output = Result<Vec<u8>, ErrorKind>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let token = match &mut this.state {
+            State::Done(result) => return Poll::Ready(result.take().unwrap()),
+            State::InFlight(token) => *token,
+        };
+        let mut driver = this.driver.lock();
+        let mut requests = this.requests.lock();
+        reap(&mut *driver, &mut requests);
+        if requests.get(&token).is_some_and(|r| r.result.is_some()) {
+            let request = requests.remove(&token).unwrap();
+            let result = request.result.unwrap().map(|_| request.buf);
+            this.state = State::Done(None);
+            return Poll::Ready(result);
+        }
+        drop(requests);
+        drop(driver);
+        // The block device has no completion interrupt yet, so ask to be
+        // polled again until it's done.
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+impl<E: IOError + 'static> Drop for BlockRequest<E> {
+    fn drop(&mut self) {
+        let State::InFlight(token) = self.state else {
+            return;
+        };
+        let mut requests = self.requests.lock();
+        let Some(request) = requests.get_mut(&token) else {
+            return;
+        };
+        if request.result.is_some() {
+            requests.remove(&token);
+        } else {
+            // The device still owns the buffers, it's removed once completed.
+            request.abandoned = true;
+        }
+    }
+}
+
+/// Run `request` on the current thread and wait for it.
+pub(super) fn block_on<E: IOError + 'static>(
+    request: BlockRequest<E>,
+) -> Result<Vec<u8>, ErrorKind> {
+    asynk::block_on(request)
+}
+
+/// Flush the device once every request in flight is completed. The
+/// driver's flush waits for the next request the device finishes, which
+/// must be its own.
+pub(super) fn flush<E: IOError + 'static>(
+    driver: &SpinLock<dyn BlockDriverOps<Error = E>>,
+    requests: &CompletionMap,
+) -> Result<(), ErrorKind> {
+    asynk::block_on(poll_fn(|cx| {
+        let mut driver = driver.lock();
+        let mut requests = requests.lock();
+        reap(&mut *driver, &mut requests);
+        if requests.values().any(|r| r.result.is_none()) {
+            drop(requests);
+            drop(driver);
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        // Nothing can be submitted while the driver is locked.
+        drop(requests);
+        Poll::Ready(driver.flush().map_err(|e| e.kind()))
+    }))
+}
+
+/// The driver as seen by the cache, each I/O is a request waited for
+/// on the current thread. The driver is only locked to submit and to
+/// complete requests.
+pub(super) struct RequestIo<'a, E: IOError + 'static> {
+    driver: &'a Arc<SpinLock<dyn BlockDriverOps<Error = E>>>,
+    requests: &'a Arc<CompletionMap>,
+}
+
+impl<'a, E: IOError + 'static> RequestIo<'a, E> {
+    pub(super) fn new(
+        driver: &'a Arc<SpinLock<dyn BlockDriverOps<Error = E>>>,
+        requests: &'a Arc<CompletionMap>,
+    ) -> Self {
+        Self { driver, requests }
+    }
+
+    fn request(&self, sector: usize, buf: Vec<u8>, write: bool) -> BlockRequest<E> {
+        BlockRequest::submit(
+            self.driver.clone(),
+            self.requests.clone(),
+            sector,
+            buf,
+            write,
+        )
+    }
+}
+
+impl<E: IOError + 'static> ErrorType for RequestIo<'_, E> {
+    type Error = ErrorKind;
+}
+
+impl<E: IOError + 'static> BlockDriverOps for RequestIo<'_, E> {
+    fn capacity(&self) -> u64 {
+        self.driver.lock().capacity()
+    }
+
+    fn sector_size(&self) -> u16 {
+        self.driver.lock().sector_size()
+    }
+
+    fn read_blocks(&mut self, block_id: usize, buf: &mut [u8]) -> Result<(), ErrorKind> {
+        let data = block_on(self.request(block_id, vec![0u8; buf.len()], false))?;
+        buf.copy_from_slice(&data);
+        Ok(())
+    }
+
+    fn write_blocks(&mut self, block_id: usize, buf: &[u8]) -> Result<(), ErrorKind> {
+        block_on(self.request(block_id, buf.to_vec(), true)).map(|_| ())
+    }
+
+    fn flush(&mut self) -> Result<(), ErrorKind> {
+        flush(self.driver, self.requests)
+    }
+}
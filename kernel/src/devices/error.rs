@@ -67,8 +67,9 @@ impl From<ErrorKind> for Error {
             // An operation could not be completed, because it failed
             // to allocate enough memory.
             ErrorKind::OutOfMemory => -ENOMEM,
-            // An attempted write could not write any data.
-            ErrorKind::WriteZero => -EIO,
+            // An attempted write could not write any data, e.g. the
+            // device is full.
+            ErrorKind::WriteZero => -ENOSPC,
             _ => -EIO,
         };
         Error::from_errno(code)
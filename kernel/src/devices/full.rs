@@ -0,0 +1,78 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::devices::{Device, DeviceClass, DeviceId, DeviceManager};
+use alloc::{string::String, sync::Arc};
+use embedded_io::ErrorKind;
+
+pub struct Full;
+
+impl Full {
+    pub fn register() -> Result<(), ErrorKind> {
+        let full = Arc::new(Full);
+        DeviceManager::get().register_device(String::from("full"), full)
+    }
+}
+
+impl Device for Full {
+    fn name(&self) -> String {
+        String::from("full")
+    }
+
+    fn class(&self) -> DeviceClass {
+        DeviceClass::Char
+    }
+
+    fn id(&self) -> DeviceId {
+        DeviceId::new(1, 7)
+    }
+
+    fn read(&self, _pos: u64, buf: &mut [u8], _is_blocking: bool) -> Result<usize, ErrorKind> {
+        // Fill buffer with zeros
+        buf.fill(0);
+        Ok(buf.len())
+    }
+
+    fn write(&self, _pos: u64, buf: &[u8], _is_blocking: bool) -> Result<usize, ErrorKind> {
+        // There's never space left, which is ENOSPC for the writer
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        Err(ErrorKind::WriteZero)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blueos_test_macro::test;
+
+    #[test]
+    fn test_full_device_read() {
+        let full = Full;
+        let mut buffer = [1u8; 10];
+
+        let result = full.read(0, &mut buffer, true);
+        assert_eq!(result, Ok(buffer.len()));
+        assert!(buffer.iter().all(|&x| x == 0));
+    }
+
+    #[test]
+    fn test_full_device_write() {
+        let full = Full;
+
+        assert_eq!(full.write(0, &[1u8, 2, 3], true), Err(ErrorKind::WriteZero));
+        assert_eq!(full.write(0, &[], true), Ok(0));
+    }
+}
@@ -12,24 +12,29 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::error::Error;
+use crate::{
+    error::Error,
+    vfs::poll::{PollEvents, PollTable},
+};
 use alloc::{collections::BTreeMap, string::String, sync::Arc};
 use core::{
     fmt::Debug,
-    sync::atomic::{AtomicU32, Ordering},
+    sync::atomic::{AtomicBool, AtomicU32, Ordering},
 };
 use embedded_io::ErrorKind;
 use libc::*;
-use spin::{Once, RwLock as SpinRwLock};
+use spin::{Mutex as SpinMutex, Once, RwLock as SpinRwLock};
 #[cfg(virtio)]
 pub mod block;
 pub mod console;
 pub(crate) mod dumb;
 mod error;
+mod full;
 pub(crate) mod net;
 mod null;
 #[cfg(target_arch = "riscv64")]
 pub(crate) mod plic;
+pub(crate) mod random;
 pub mod tty;
 #[cfg(virtio)]
 pub mod virtio;
@@ -200,6 +205,16 @@ pub trait Device: Send + Sync {
     fn sync(&self) -> Result<(), ErrorKind> {
         Err(ErrorKind::Unsupported)
     }
+    /// Devices which may block on read or write should report the ready
+    /// events and notify the registered waiters.
+    fn poll(&self, events: PollEvents, table: &mut PollTable) -> PollEvents {
+        events & (PollEvents::POLLIN | PollEvents::POLLOUT)
+    }
+    /// False once the device is unregistered. Files still open on it
+    /// fail with ENODEV.
+    fn is_online(&self) -> bool {
+        true
+    }
 }
 
 impl Debug for dyn Device {
@@ -212,12 +227,138 @@ impl Debug for dyn Device {
     }
 }
 
+// Whether a device is registered and how many files have it open. Both
+// are checked and updated together, so an open racing with unregister
+// either keeps the device or fails.
+struct OpenState {
+    opens: u32,
+    online: bool,
+}
+
+// What the device manager keeps of a device. Once unregistered, the
+// device is dropped when its last open file is closed.
+struct Registered {
+    name: String,
+    class: DeviceClass,
+    id: DeviceId,
+    dev: SpinRwLock<Option<Arc<dyn Device>>>,
+    state: SpinMutex<OpenState>,
+}
+
+impl Registered {
+    fn new(dev: Arc<dyn Device>) -> Self {
+        Self {
+            name: dev.name(),
+            class: dev.class(),
+            id: dev.id(),
+            dev: SpinRwLock::new(Some(dev)),
+            state: SpinMutex::new(OpenState {
+                opens: 0,
+                online: true,
+            }),
+        }
+    }
+
+    // Operations get their own reference, so the device outlives them
+    // even if it's unregistered meanwhile.
+    fn device(&self) -> Result<Arc<dyn Device>, ErrorKind> {
+        self.dev.read().clone().ok_or(ErrorKind::NotFound)
+    }
+
+    fn unregister(&self) {
+        let mut state = self.state.lock();
+        state.online = false;
+        if state.opens == 0 {
+            self.dev.write().take();
+        }
+    }
+
+    // Drop an open, the last one of an unregistered device drops it.
+    fn release(&self) {
+        let mut state = self.state.lock();
+        state.opens = state.opens.saturating_sub(1);
+        if state.opens == 0 && !state.online {
+            self.dev.write().take();
+        }
+    }
+}
+
+impl Device for Registered {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn class(&self) -> DeviceClass {
+        self.class
+    }
+
+    fn id(&self) -> DeviceId {
+        self.id
+    }
+
+    fn open(&self) -> Result<(), ErrorKind> {
+        {
+            let mut state = self.state.lock();
+            if !state.online {
+                return Err(ErrorKind::NotFound);
+            }
+            state.opens += 1;
+        }
+        let res = self.device().and_then(|dev| dev.open());
+        if res.is_err() {
+            self.release();
+        }
+        res
+    }
+
+    fn close(&self) -> Result<(), ErrorKind> {
+        let res = self.device().and_then(|dev| dev.close());
+        self.release();
+        res
+    }
+
+    fn read(&self, pos: u64, buf: &mut [u8], is_nonblocking: bool) -> Result<usize, ErrorKind> {
+        self.device()?.read(pos, buf, is_nonblocking)
+    }
+
+    fn write(&self, pos: u64, buf: &[u8], is_nonblocking: bool) -> Result<usize, ErrorKind> {
+        self.device()?.write(pos, buf, is_nonblocking)
+    }
+
+    fn ioctl(&self, request: u32, arg: usize) -> Result<(), ErrorKind> {
+        self.device()?.ioctl(request, arg)
+    }
+
+    fn capacity(&self) -> Result<u64, ErrorKind> {
+        self.device()?.capacity()
+    }
+
+    fn sector_size(&self) -> Result<u16, ErrorKind> {
+        self.device()?.sector_size()
+    }
+
+    fn sync(&self) -> Result<(), ErrorKind> {
+        self.device()?.sync()
+    }
+
+    fn poll(&self, events: PollEvents, table: &mut PollTable) -> PollEvents {
+        match self.device() {
+            Ok(dev) => dev.poll(events, table),
+            Err(_) => PollEvents::POLLERR,
+        }
+    }
+
+    fn is_online(&self) -> bool {
+        self.state.lock().online
+    }
+}
+
 static DEVICE_MANAGER: Once<DeviceManager> = Once::new();
 
 pub struct DeviceManager {
-    pub char_devices: SpinRwLock<BTreeMap<String, Arc<dyn Device>>>,
-    pub block_devices: SpinRwLock<BTreeMap<String, Arc<dyn Device>>>,
-    pub misc_devices: SpinRwLock<BTreeMap<String, Arc<dyn Device>>>,
+    char_devices: SpinRwLock<BTreeMap<String, Arc<Registered>>>,
+    block_devices: SpinRwLock<BTreeMap<String, Arc<Registered>>>,
+    misc_devices: SpinRwLock<BTreeMap<String, Arc<Registered>>>,
 }
 
 impl DeviceManager {
@@ -240,7 +381,8 @@ impl DeviceManager {
     }
 
     pub fn register_device(&self, name: String, dev: Arc<dyn Device>) -> Result<(), ErrorKind> {
-        match dev.class() {
+        let dev = Arc::new(Registered::new(dev));
+        match dev.class {
             DeviceClass::Char => {
                 let mut devices = self.char_devices.write();
                 devices
@@ -263,16 +405,39 @@ impl DeviceManager {
         Ok(())
     }
 
+    /// Remove the device `name`. Files still open on it fail with ENODEV
+    /// from now on, and the device is dropped once they're all closed.
+    pub fn unregister_device(&self, name: &str) -> Result<(), ErrorKind> {
+        let dev = self
+            .char_devices
+            .write()
+            .remove(name)
+            .or_else(|| self.block_devices.write().remove(name))
+            .or_else(|| self.misc_devices.write().remove(name))
+            .ok_or(ErrorKind::NotFound)?;
+        dev.unregister();
+        Ok(())
+    }
+
     pub fn get_block_device(&self, str: &str) -> Option<Arc<dyn Device>> {
-        self.block_devices.read().get(str).cloned()
+        self.block_devices
+            .read()
+            .get(str)
+            .map(|dev| dev.clone() as Arc<dyn Device>)
     }
 
     pub fn get_char_device(&self, str: &str) -> Option<Arc<dyn Device>> {
-        self.char_devices.read().get(str).cloned()
+        self.char_devices
+            .read()
+            .get(str)
+            .map(|dev| dev.clone() as Arc<dyn Device>)
     }
 
     pub fn get_misc_device(&self, str: &str) -> Option<Arc<dyn Device>> {
-        self.misc_devices.read().get(str).cloned()
+        self.misc_devices
+            .read()
+            .get(str)
+            .map(|dev| dev.clone() as Arc<dyn Device>)
     }
 
     pub fn foreach<F>(&self, callback: F) -> Result<(), Error>
@@ -282,19 +447,19 @@ impl DeviceManager {
         {
             let char_devices = self.char_devices.read();
             for (name, device) in char_devices.iter() {
-                callback(name, device.clone())?
+                callback(name, device.clone() as Arc<dyn Device>)?
             }
         }
         {
             let block_devices = self.block_devices.read();
             for (name, device) in block_devices.iter() {
-                callback(name, device.clone())?
+                callback(name, device.clone() as Arc<dyn Device>)?
             }
         }
         {
             let misc_devices = self.misc_devices.read();
             for (name, device) in misc_devices.iter() {
-                callback(name, device.clone())?
+                callback(name, device.clone() as Arc<dyn Device>)?
             }
         }
         Ok(())
@@ -304,12 +469,15 @@ impl DeviceManager {
 pub fn init() -> Result<(), Error> {
     null::Null::register().map_err(Error::from)?;
     zero::Zero::register().map_err(Error::from)?;
+    full::Full::register().map_err(Error::from)?;
+    random::Random::register().map_err(Error::from)?;
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::vfs::{inode_mode::InodeMode, path, syscalls as vfs_syscalls};
     use blueos_test_macro::test;
 
     #[test]
@@ -366,4 +534,92 @@ mod tests {
             assert_eq!(device_id.minor(), 0xFFFFFFFFFFF); // Should be truncated to 44 bits
         }
     }
+
+    struct Probe {
+        dropped: Arc<AtomicBool>,
+    }
+
+    impl Drop for Probe {
+        fn drop(&mut self) {
+            self.dropped.store(true, Ordering::Relaxed);
+        }
+    }
+
+    impl Device for Probe {
+        fn name(&self) -> String {
+            String::from("probe")
+        }
+        fn class(&self) -> DeviceClass {
+            DeviceClass::Char
+        }
+        fn id(&self) -> DeviceId {
+            DeviceId::new(241, 0)
+        }
+        fn read(&self, _pos: u64, buf: &mut [u8], _is_blocking: bool) -> Result<usize, ErrorKind> {
+            buf.fill(0);
+            Ok(buf.len())
+        }
+        fn write(&self, _pos: u64, buf: &[u8], _is_blocking: bool) -> Result<usize, ErrorKind> {
+            Ok(buf.len())
+        }
+    }
+
+    #[test]
+    fn test_unregister_device() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let manager = DeviceManager::get();
+        manager
+            .register_device(
+                String::from("probe"),
+                Arc::new(Probe {
+                    dropped: dropped.clone(),
+                }),
+            )
+            .unwrap();
+        path::lookup_path("/dev")
+            .unwrap()
+            .create_device(
+                "probe",
+                InodeMode::from_bits_truncate(0o666),
+                manager.get_char_device("probe").unwrap(),
+            )
+            .unwrap();
+
+        let mut buf = [1u8; 4];
+        let fd = vfs_syscalls::open(c"/dev/probe".as_ptr(), O_RDWR, 0);
+        assert!(fd >= 0);
+        assert_eq!(vfs_syscalls::read(fd, buf.as_mut_ptr(), buf.len()), 4);
+
+        manager.unregister_device("probe").unwrap();
+        assert!(manager.get_char_device("probe").is_none());
+        assert_eq!(manager.unregister_device("probe"), Err(ErrorKind::NotFound));
+        assert_eq!(
+            vfs_syscalls::open(c"/dev/probe".as_ptr(), O_RDWR, 0),
+            -ENODEV
+        );
+        assert_eq!(
+            vfs_syscalls::read(fd, buf.as_mut_ptr(), buf.len()),
+            -ENODEV as isize
+        );
+        assert!(!dropped.load(Ordering::Relaxed));
+
+        assert_eq!(vfs_syscalls::close(fd), 0);
+        assert!(dropped.load(Ordering::Relaxed));
+        assert_eq!(vfs_syscalls::unlink(c"/dev/probe".as_ptr()), 0);
+    }
+
+    #[test]
+    fn test_open_after_unregister() {
+        let dropped = Arc::new(AtomicBool::new(false));
+        let registered = Registered::new(Arc::new(Probe {
+            dropped: dropped.clone(),
+        }));
+        assert_eq!(registered.open(), Ok(()));
+        registered.unregister();
+        assert!(!registered.is_online());
+        assert_eq!(registered.open(), Err(ErrorKind::NotFound));
+        assert!(!dropped.load(Ordering::Relaxed));
+        assert_eq!(registered.close(), Ok(()));
+        assert!(dropped.load(Ordering::Relaxed));
+    }
 }
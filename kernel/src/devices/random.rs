@@ -0,0 +1,322 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{
+    devices::{Device, DeviceClass, DeviceId, DeviceManager},
+    sync::SpinLock,
+    time,
+};
+use alloc::{string::String, sync::Arc};
+use core::{ffi::c_uint, slice};
+use embedded_io::ErrorKind;
+use spin::Once;
+
+/// Random number generator of the board, e.g. a TRNG peripheral.
+pub trait HwRng: Send + Sync {
+    fn fill(&self, buf: &mut [u8]) -> Result<(), ErrorKind>;
+}
+
+static HW_RNG: Once<&'static dyn HwRng> = Once::new();
+
+/// Make `rng` the source of /dev/random, /dev/urandom and getrandom().
+/// Boards without one get bytes of a ChaCha20 CSPRNG seeded from the
+/// jitter of the cycle counter.
+pub fn set_hw_rng(rng: &'static dyn HwRng) {
+    HW_RNG.call_once(|| rng);
+}
+
+// "expand 32-byte k"
+const SIGMA: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+// A block of the ChaCha20 keystream (RFC 8439), with the original
+// 64-bit counter and nonce.
+fn chacha20_block(key: &[u32; 8], counter: u64, nonce: u64) -> [u8; 64] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&SIGMA);
+    state[4..12].copy_from_slice(key);
+    state[12] = counter as u32;
+    state[13] = (counter >> 32) as u32;
+    state[14] = nonce as u32;
+    state[15] = (nonce >> 32) as u32;
+
+    let mut x = state;
+    for _ in 0..10 {
+        quarter_round(&mut x, 0, 4, 8, 12);
+        quarter_round(&mut x, 1, 5, 9, 13);
+        quarter_round(&mut x, 2, 6, 10, 14);
+        quarter_round(&mut x, 3, 7, 11, 15);
+        quarter_round(&mut x, 0, 5, 10, 15);
+        quarter_round(&mut x, 1, 6, 11, 12);
+        quarter_round(&mut x, 2, 7, 8, 13);
+        quarter_round(&mut x, 3, 4, 9, 14);
+    }
+
+    let mut out = [0u8; 64];
+    for (i, chunk) in out.chunks_exact_mut(4).enumerate() {
+        chunk.copy_from_slice(&x[i].wrapping_add(state[i]).to_le_bytes());
+    }
+    out
+}
+
+fn key_from_bytes(bytes: &[u8]) -> [u32; 8] {
+    let mut key = [0u32; 8];
+    for (word, chunk) in key.iter_mut().zip(bytes.chunks_exact(4)) {
+        *word = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+    }
+    key
+}
+
+// How long a short loop takes varies with caches, interrupts and bus
+// contention, the low bits of the cycle counter around it are the seed.
+fn jitter_seed() -> [u32; 8] {
+    let mut pool = [0u32; 16];
+    let mut prev = time::get_sys_cycles();
+    for i in 0..256 {
+        let mut x = prev;
+        for _ in 0..(prev & 0x3f) {
+            x = core::hint::black_box(x.rotate_left(7) ^ 0x9e37_79b9_7f4a_7c15);
+        }
+        let now = time::get_sys_cycles();
+        let sample = now.wrapping_sub(prev) ^ x;
+        pool[i % 16] = pool[i % 16].rotate_left(5) ^ sample as u32 ^ (sample >> 32) as u32;
+        prev = now;
+    }
+    // Condense the pool through ChaCha20 itself.
+    let key: [u32; 8] = pool[..8].try_into().unwrap();
+    let nonce = ((pool[8] as u64) << 32) | pool[9] as u64;
+    key_from_bytes(&chacha20_block(&key, pool[10] as u64, nonce)[..32])
+}
+
+// ChaCha20 with fast key erasure: the start of each keystream becomes
+// the next key, so the state never reveals bytes handed out before.
+// The cycle counter of each fill is its nonce.
+struct Prng {
+    key: [u32; 8],
+    seeded: bool,
+}
+
+impl Prng {
+    fn fill(&mut self, buf: &mut [u8]) {
+        if !self.seeded {
+            self.key = jitter_seed();
+            self.seeded = true;
+        }
+        let nonce = time::get_sys_cycles();
+        let first = chacha20_block(&self.key, 0, nonce);
+        let (next_key, extra) = first.split_at(32);
+        let n = buf.len().min(extra.len());
+        buf[..n].copy_from_slice(&extra[..n]);
+        for (counter, chunk) in buf[n..].chunks_mut(64).enumerate() {
+            let block = chacha20_block(&self.key, counter as u64 + 1, nonce);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+        self.key = key_from_bytes(next_key);
+    }
+}
+
+static PRNG: SpinLock<Prng> = SpinLock::new(Prng {
+    key: [0; 8],
+    seeded: false,
+});
+
+// Bytes generated with the PRNG locked, so large reads don't keep
+// interrupts disabled for long.
+const PRNG_CHUNK: usize = 256;
+
+/// Fill `buf` with random bytes, from the board RNG if there is one.
+pub fn fill_random(buf: &mut [u8]) {
+    if let Some(rng) = HW_RNG.get() {
+        if rng.fill(buf).is_ok() {
+            return;
+        }
+    }
+    for chunk in buf.chunks_mut(PRNG_CHUNK) {
+        PRNG.irqsave_lock().fill(chunk);
+    }
+}
+
+// Flags of getrandom(), numbered as on Linux.
+const GRND_NONBLOCK: c_uint = 0x1;
+const GRND_RANDOM: c_uint = 0x2;
+const GRND_INSECURE: c_uint = 0x4;
+// Most bytes a call returns, as on Linux.
+const MAX_GETRANDOM: usize = (32 << 20) - 1;
+
+/// Fill `buf` with up to `buflen` random bytes. There's always
+/// randomness available, so it never blocks and the flags are only
+/// validated.
+pub fn getrandom(buf: *mut u8, buflen: usize, flags: c_uint) -> isize {
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0
+        || flags & (GRND_RANDOM | GRND_INSECURE) == GRND_RANDOM | GRND_INSECURE
+    {
+        return -libc::EINVAL as isize;
+    }
+    if buflen == 0 {
+        return 0;
+    }
+    if buf.is_null() {
+        return -libc::EFAULT as isize;
+    }
+    let buflen = buflen.min(MAX_GETRANDOM);
+    let buf = unsafe { slice::from_raw_parts_mut(buf, buflen) };
+    fill_random(buf);
+    buflen as isize
+}
+
+/// /dev/random and /dev/urandom, which never block.
+pub struct Random {
+    urandom: bool,
+}
+
+impl Random {
+    pub fn register() -> Result<(), ErrorKind> {
+        let manager = DeviceManager::get();
+        manager.register_device(String::from("random"), Arc::new(Random { urandom: false }))?;
+        manager.register_device(String::from("urandom"), Arc::new(Random { urandom: true }))
+    }
+}
+
+impl Device for Random {
+    fn name(&self) -> String {
+        if self.urandom {
+            String::from("urandom")
+        } else {
+            String::from("random")
+        }
+    }
+
+    fn class(&self) -> DeviceClass {
+        DeviceClass::Char
+    }
+
+    fn id(&self) -> DeviceId {
+        DeviceId::new(1, if self.urandom { 9 } else { 8 })
+    }
+
+    fn read(&self, _pos: u64, buf: &mut [u8], _is_blocking: bool) -> Result<usize, ErrorKind> {
+        fill_random(buf);
+        Ok(buf.len())
+    }
+
+    fn write(&self, _pos: u64, buf: &[u8], _is_blocking: bool) -> Result<usize, ErrorKind> {
+        // Always succeed, but discard the data
+        Ok(buf.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blueos_test_macro::test;
+
+    #[test]
+    fn test_random_device_read() {
+        let random = Random { urandom: true };
+        let mut first = [0u8; 16];
+        let mut second = [0u8; 16];
+
+        assert_eq!(random.read(0, &mut first, true), Ok(first.len()));
+        assert_eq!(random.read(0, &mut second, true), Ok(second.len()));
+        assert!(first.iter().any(|&x| x != first[0]));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_random_fill_chunks() {
+        let mut buffer = [0u8; 3 * PRNG_CHUNK];
+        assert_eq!(
+            getrandom(buffer.as_mut_ptr(), buffer.len(), 0),
+            buffer.len() as isize
+        );
+        // Every chunk is drawn with a fresh key.
+        assert_ne!(buffer[..PRNG_CHUNK], buffer[PRNG_CHUNK..2 * PRNG_CHUNK]);
+        assert_ne!(buffer[PRNG_CHUNK..2 * PRNG_CHUNK], buffer[2 * PRNG_CHUNK..]);
+    }
+
+    #[test]
+    fn test_random_device_read_odd_length() {
+        let random = Random { urandom: false };
+        let mut buffer = [0u8; 13];
+        assert_eq!(random.read(0, &mut buffer, true), Ok(buffer.len()));
+    }
+
+    #[test]
+    fn test_chacha20_block() {
+        // RFC 8439 2.3.2, its 32-bit counter and 96-bit nonce laid out
+        // as our 64-bit ones.
+        let key = key_from_bytes(&core::array::from_fn::<u8, 32, _>(|i| i as u8));
+        let block = chacha20_block(&key, 0x0900_0000_0000_0001, 0x4a00_0000);
+        assert_eq!(
+            block[..16],
+            [
+                0x10, 0xf1, 0xe7, 0xe4, 0xd1, 0x3b, 0x59, 0x15, 0x50, 0x0f, 0xdd, 0x1f, 0xa3, 0x20,
+                0x71, 0xc4
+            ]
+        );
+        assert_eq!(
+            block[48..],
+            [
+                0xb5, 0x12, 0x9c, 0xd1, 0xde, 0x16, 0x4e, 0xb9, 0xcb, 0xd0, 0x83, 0xe8, 0xa2, 0x50,
+                0x3c, 0x4e
+            ]
+        );
+    }
+
+    #[test]
+    fn test_getrandom() {
+        // Longer than a block, to go past the first one.
+        let mut first = [0u8; 100];
+        let mut second = [0u8; 100];
+        assert_eq!(getrandom(first.as_mut_ptr(), first.len(), 0), 100);
+        assert_eq!(
+            getrandom(second.as_mut_ptr(), second.len(), GRND_NONBLOCK),
+            100
+        );
+        assert_ne!(first, second);
+        assert!(first[64..].iter().any(|&x| x != 0));
+
+        let mut prng = Prng {
+            key: [0; 8],
+            seeded: false,
+        };
+        prng.fill(&mut first);
+        prng.fill(&mut second);
+        assert_ne!(first, second);
+
+        assert_eq!(getrandom(first.as_mut_ptr(), 0, 0), 0);
+        assert_eq!(
+            getrandom(core::ptr::null_mut(), 1, 0),
+            -libc::EFAULT as isize
+        );
+        assert_eq!(
+            getrandom(first.as_mut_ptr(), 1, GRND_RANDOM | GRND_INSECURE),
+            -libc::EINVAL as isize
+        );
+        assert_eq!(
+            getrandom(first.as_mut_ptr(), 1, 0x8),
+            -libc::EINVAL as isize
+        );
+    }
+}
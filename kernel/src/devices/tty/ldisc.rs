@@ -0,0 +1,302 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::devices::tty::termios::{CcIndex, Iflags, Lflags, Termios};
+use alloc::vec::Vec;
+use blueos_infra::ringbuffer::BoxedRingBuffer;
+
+/// Max length of a line being edited, further input is dropped.
+pub const MAX_CANON: usize = 512;
+/// Size of the input readers haven't consumed yet. Received bytes are
+/// dropped while it's full, a complete line waits until it fits and
+/// input received meanwhile is dropped.
+pub const INPUT_SIZE: usize = 2 * MAX_CANON;
+
+const BACKSPACE: u8 = 0x08;
+const ERASE_SEQ: &[u8] = b"\x08 \x08";
+
+/// Turns the bytes received by a terminal into the input of its
+/// readers. In canonical mode, input is edited a line at a time and
+/// only complete lines become readable. Otherwise every byte is readable
+/// as soon as it's received.
+#[derive(Debug)]
+pub struct LineDiscipline {
+    // The line being edited in canonical mode.
+    line: Vec<u8>,
+    // `line` is complete, including its terminator, and waits for room
+    // in `ready`.
+    pending: bool,
+    // Input readers may consume. It's only accessed through `&mut self`,
+    // so there's never more than one reader or writer.
+    ready: BoxedRingBuffer,
+}
+
+impl Default for LineDiscipline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LineDiscipline {
+    pub fn new() -> Self {
+        Self {
+            line: Vec::new(),
+            pending: false,
+            ready: BoxedRingBuffer::new(INPUT_SIZE),
+        }
+    }
+
+    // Free space of the input.
+    fn room(&self) -> usize {
+        self.ready.capacity() - self.available()
+    }
+
+    fn push_ready(ready: &BoxedRingBuffer, bytes: &[u8]) {
+        let mut writer = unsafe { ready.writer() };
+        let mut count = 0;
+        for slice in writer.push_slices() {
+            let n = slice.len().min(bytes.len() - count);
+            slice[..n].copy_from_slice(&bytes[count..count + n]);
+            count += n;
+        }
+        writer.push_done(count);
+    }
+
+    // Make the pending line readable once it fits.
+    fn commit_line(&mut self) {
+        if self.room() < self.line.len() {
+            return;
+        }
+        Self::push_ready(&self.ready, &self.line);
+        self.line.clear();
+        self.pending = false;
+    }
+
+    /// Process a received byte, `echo` is called with what should be
+    /// echoed back to the terminal.
+    pub fn receive(&mut self, mut ch: u8, termios: &Termios, echo: &mut impl FnMut(&[u8])) {
+        if ch == b'\r' {
+            if termios.iflag.contains(Iflags::IGNCR) {
+                return;
+            }
+            if termios.iflag.contains(Iflags::ICRNL) {
+                ch = b'\n';
+            }
+        } else if ch == b'\n' && termios.iflag.contains(Iflags::INLCR) {
+            ch = b'\r';
+        }
+
+        let echo_on = termios.lflag.contains(Lflags::ECHO);
+        if !termios.lflag.contains(Lflags::ICANON) {
+            if self.ready.is_full() {
+                return;
+            }
+            Self::push_ready(&self.ready, &[ch]);
+            if echo_on {
+                echo(&[ch]);
+            }
+            return;
+        }
+
+        if self.pending {
+            return;
+        }
+        let cc = &termios.cc;
+        // Terminals send either DEL or BS for the backspace key.
+        if ch == cc[CcIndex::Verase as usize] || ch == BACKSPACE {
+            if self.line.pop().is_some() && echo_on {
+                echo(ERASE_SEQ);
+            }
+        } else if ch == cc[CcIndex::Vkill as usize] {
+            if echo_on {
+                for _ in 0..self.line.len() {
+                    echo(ERASE_SEQ);
+                }
+            }
+            self.line.clear();
+        } else if ch == b'\n' || (ch != 0 && ch == cc[CcIndex::Veol as usize]) {
+            self.line.push(ch);
+            self.pending = true;
+            self.commit_line();
+            if echo_on || termios.lflag.contains(Lflags::ECHONL) {
+                echo(&[ch]);
+            }
+        } else if self.line.len() < MAX_CANON {
+            self.line.push(ch);
+            if echo_on {
+                echo(&[ch]);
+            }
+        }
+    }
+
+    /// Move readable input to `buf`. In canonical mode at most one line
+    /// is read at a time.
+    pub fn read(&mut self, buf: &mut [u8], canonical: bool) -> usize {
+        let mut reader = unsafe { self.ready.reader() };
+        let mut count = 0;
+        for slice in reader.pop_slices() {
+            let mut n = slice.len().min(buf.len() - count);
+            if canonical {
+                if let Some(end) = slice[..n].iter().position(|&ch| ch == b'\n') {
+                    n = end + 1;
+                    buf[count..count + n].copy_from_slice(&slice[..n]);
+                    count += n;
+                    break;
+                }
+            }
+            buf[count..count + n].copy_from_slice(&slice[..n]);
+            count += n;
+        }
+        reader.pop_done(count);
+        if self.pending {
+            self.commit_line();
+        }
+        count
+    }
+
+    /// Number of bytes readers may consume.
+    pub fn available(&self) -> usize {
+        // Looking at the slices doesn't pop anything.
+        let mut reader = unsafe { self.ready.reader() };
+        reader.pop_slices().iter().map(|s| s.len()).sum()
+    }
+
+    pub fn line(&self) -> &[u8] {
+        &self.line
+    }
+
+    /// Replace the line being edited, e.g. by one from the history. A
+    /// complete line waiting for room is kept.
+    pub fn set_line(&mut self, line: &[u8]) {
+        if self.pending {
+            return;
+        }
+        self.line.clear();
+        self.line
+            .extend_from_slice(&line[..line.len().min(MAX_CANON)]);
+    }
+
+    /// Discard all pending input.
+    pub fn flush(&mut self) {
+        self.line.clear();
+        self.pending = false;
+        let mut reader = unsafe { self.ready.reader() };
+        let n = reader.pop_slices().iter().map(|s| s.len()).sum();
+        reader.pop_done(n);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use blueos_test_macro::test;
+
+    fn feed(ldisc: &mut LineDiscipline, input: &[u8], termios: &Termios) -> Vec<u8> {
+        let mut echoed = Vec::new();
+        for &ch in input {
+            ldisc.receive(ch, termios, &mut |s: &[u8]| echoed.extend_from_slice(s));
+        }
+        echoed
+    }
+
+    #[test]
+    fn test_canonical_line_editing() {
+        let termios = Termios::default();
+        let mut ldisc = LineDiscipline::new();
+        let echoed = feed(&mut ldisc, b"ab\x08c", &termios);
+        assert_eq!(echoed, b"ab\x08 \x08c");
+        // Nothing is readable until the line is complete.
+        assert_eq!(ldisc.available(), 0);
+        feed(&mut ldisc, b"\n", &termios);
+
+        let mut buf = [0u8; 16];
+        let n = ldisc.read(&mut buf, true);
+        assert_eq!(&buf[..n], b"ac\n");
+    }
+
+    #[test]
+    fn test_canonical_reads_one_line() {
+        let termios = Termios::default();
+        let mut ldisc = LineDiscipline::new();
+        // ICRNL maps the carriage return to a newline.
+        feed(&mut ldisc, b"one\rtwo\n", &termios);
+        let mut buf = [0u8; 16];
+        let n = ldisc.read(&mut buf, true);
+        assert_eq!(&buf[..n], b"one\n");
+        let n = ldisc.read(&mut buf, true);
+        assert_eq!(&buf[..n], b"two\n");
+        assert_eq!(ldisc.read(&mut buf, true), 0);
+    }
+
+    #[test]
+    fn test_raw_mode() {
+        let mut termios = Termios::default();
+        termios.lflag.remove(Lflags::ICANON | Lflags::ECHO);
+        termios.iflag.remove(Iflags::ICRNL);
+        let mut ldisc = LineDiscipline::new();
+        let echoed = feed(&mut ldisc, b"ab\x7f\r", &termios);
+        assert!(echoed.is_empty());
+        assert_eq!(ldisc.available(), 4);
+
+        let mut buf = vec![0u8; 2];
+        assert_eq!(ldisc.read(&mut buf, false), 2);
+        assert_eq!(&buf[..], b"ab");
+        assert_eq!(ldisc.read(&mut buf, false), 2);
+        assert_eq!(&buf[..], b"\x7f\r");
+    }
+
+    #[test]
+    fn test_canonical_kill_line() {
+        let termios = Termios::default();
+        let mut ldisc = LineDiscipline::new();
+        // Ctrl-U wipes "junk" from the terminal, then DEL erases 'b'.
+        let echoed = feed(&mut ldisc, b"junk\x15ab\x7fc\n", &termios);
+        assert_eq!(
+            echoed,
+            b"junk\x08 \x08\x08 \x08\x08 \x08\x08 \x08ab\x08 \x08c\n"
+        );
+
+        let mut buf = [0u8; 16];
+        let n = ldisc.read(&mut buf, true);
+        assert_eq!(&buf[..n], b"ac\n");
+        assert_eq!(ldisc.available(), 0);
+    }
+
+    #[test]
+    fn test_input_full() {
+        let mut termios = Termios::default();
+        termios.lflag.remove(Lflags::ECHO);
+        let mut ldisc = LineDiscipline::new();
+        let line = [b'x'; MAX_CANON - 1];
+        feed(&mut ldisc, &line, &termios);
+        feed(&mut ldisc, b"\n", &termios);
+        feed(&mut ldisc, &line, &termios);
+        feed(&mut ldisc, b"\n", &termios);
+        assert_eq!(ldisc.available(), INPUT_SIZE);
+
+        // The next line waits until a reader makes room, input received
+        // meanwhile is dropped.
+        feed(&mut ldisc, b"y\nz", &termios);
+        assert_eq!(ldisc.line(), b"y\n");
+        assert_eq!(ldisc.available(), INPUT_SIZE);
+        let mut buf = [0u8; MAX_CANON];
+        assert_eq!(ldisc.read(&mut buf, true), MAX_CANON);
+        assert!(ldisc.line().is_empty());
+        assert_eq!(ldisc.available(), MAX_CANON + 2);
+        assert_eq!(ldisc.read(&mut buf, true), MAX_CANON);
+        assert_eq!(ldisc.read(&mut buf, true), 2);
+        assert_eq!(&buf[..2], b"y\n");
+    }
+}
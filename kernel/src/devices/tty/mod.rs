@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub mod ldisc;
 pub mod n_tty;
 pub mod serial;
 pub mod termios;
@@ -12,12 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::devices::{
-    tty::{
-        serial,
-        termios::{CcIndex, Iflags},
+use crate::{
+    devices::{
+        tty::{
+            ldisc::LineDiscipline,
+            serial::{self, SerialError},
+            termios::{CcIndex, Lflags, Termios, TCGETS, TCSETS, TCSETSF, TCSETSW, TIOCSTI},
+        },
+        Device, DeviceClass, DeviceId,
     },
-    Device, DeviceClass, DeviceId,
+    time,
+    vfs::poll::{PollEvents, PollTable},
 };
 use alloc::{collections::VecDeque, string::String, sync::Arc};
 use core::sync::atomic::{AtomicUsize, Ordering};
@@ -34,8 +39,8 @@ enum SpecKey {
 
 pub struct Tty {
     serial: Arc<Serial>,
-    line_buf: Mutex<[u8; 512]>,
-    cursor: AtomicUsize,
+    termios: Mutex<Termios>,
+    ldisc: Mutex<LineDiscipline>,
     history: Mutex<VecDeque<String>>,
     history_cursor: AtomicUsize,
     spec_key: Mutex<Option<SpecKey>>,
@@ -45,9 +50,9 @@ impl Tty {
     pub fn init(serial: Arc<Serial>) -> &'static Arc<Tty> {
         TTY.call_once(|| {
             Arc::new(Self {
+                termios: Mutex::new(serial.termios),
                 serial,
-                line_buf: Mutex::new([0u8; 512]),
-                cursor: AtomicUsize::new(0),
+                ldisc: Mutex::new(LineDiscipline::new()),
                 history: Mutex::new(VecDeque::with_capacity(5)),
                 history_cursor: AtomicUsize::new(0),
                 spec_key: Mutex::new(None),
@@ -70,11 +75,136 @@ impl Tty {
         self.history.lock().get(index).cloned()
     }
 
-    fn clear_line(&self, pos: u64, is_blocking: bool) -> Result<(), ErrorKind> {
-        self.serial.write(pos, b"\r", is_blocking)?;
-        self.serial.write(pos, b"\x1b[2K", is_blocking)?;
+    fn clear_line(&self, pos: u64, is_nonblocking: bool) -> Result<(), ErrorKind> {
+        self.serial.write(pos, b"\r", is_nonblocking)?;
+        self.serial.write(pos, b"\x1b[2K", is_nonblocking)?;
         Ok(())
     }
+
+    // Load the line picked by the last up or down key and echo it.
+    fn recall_history(&self, pos: u64) -> Result<(), ErrorKind> {
+        let Some(key) = self.spec_key.lock().take() else {
+            return Ok(());
+        };
+        let history_cursor = self.history_cursor.load(Ordering::Relaxed);
+        let mut ldisc = self.ldisc.lock();
+        match key {
+            SpecKey::Up => {
+                if let Some(hist_cmd) = self.get_history(history_cursor) {
+                    ldisc.set_line(hist_cmd.as_bytes());
+                    self.history_cursor
+                        .store(history_cursor + 1, Ordering::Relaxed);
+                }
+            }
+            SpecKey::Down => {
+                if history_cursor != 0 {
+                    if let Some(hist_cmd) = self.get_history(history_cursor - 1) {
+                        ldisc.set_line(hist_cmd.as_bytes());
+                    }
+                    self.history_cursor
+                        .store(history_cursor - 1, Ordering::Relaxed);
+                } else {
+                    ldisc.set_line(&[]);
+                }
+            }
+        }
+        self.serial.write(pos, ldisc.line(), false)?;
+        Ok(())
+    }
+
+    // Pass received bytes to the line discipline, echoing them to the
+    // serial.
+    fn receive(&self, pos: u64, input: &[u8], termios: &Termios) {
+        let mut ldisc = self.ldisc.lock();
+        for &ch in input {
+            ldisc.receive(ch, termios, &mut |s: &[u8]| {
+                let _ = self.serial.write(pos, s, false);
+            });
+        }
+    }
+
+    fn read_canonical(
+        &self,
+        pos: u64,
+        buf: &mut [u8],
+        is_nonblocking: bool,
+        termios: &Termios,
+    ) -> Result<usize, ErrorKind> {
+        self.recall_history(pos)?;
+        let mut temp_buf = [0u8; 512];
+        loop {
+            let n = self.ldisc.lock().read(buf, true);
+            if n > 0 {
+                if n > 1 && buf[n - 1] == b'\n' {
+                    self.add_history(&String::from_utf8_lossy(&buf[..n - 1]));
+                }
+                return Ok(n);
+            }
+
+            let nbytes = self.serial.read(pos, &mut temp_buf, is_nonblocking)?;
+            if nbytes == 0 {
+                return Ok(0);
+            }
+            let mut i = 0;
+            while i < nbytes {
+                // get commandline history
+                // up key  : 0x1b 0x5b 0x41
+                // down key: 0x1b 0x5b 0x42
+                if temp_buf[i] == 0x1b && i + 2 < nbytes && temp_buf[i + 1] == 0x5b {
+                    let key = match temp_buf[i + 2] {
+                        0x41 => SpecKey::Up,
+                        0x42 => SpecKey::Down,
+                        _ => {
+                            i += 3;
+                            continue;
+                        }
+                    };
+                    *self.spec_key.lock() = Some(key);
+                    self.clear_line(pos, false)?;
+                    buf[0] = b'\n';
+                    return Ok(1);
+                }
+                self.receive(pos, &temp_buf[i..i + 1], termios);
+                i += 1;
+            }
+        }
+    }
+
+    // Out of canonical mode, VMIN is the number of bytes to wait for and
+    // VTIME, in tenths of a second, how long to wait for the next one.
+    // Without VMIN, VTIME bounds the wait for the first byte, and reads
+    // don't block if neither is set.
+    fn read_raw(
+        &self,
+        pos: u64,
+        buf: &mut [u8],
+        is_nonblocking: bool,
+        termios: &Termios,
+    ) -> Result<usize, ErrorKind> {
+        let vmin = termios.cc[CcIndex::Vmin as usize] as usize;
+        let vtime = termios.cc[CcIndex::Vtime as usize] as usize;
+        let mut count = self.ldisc.lock().read(buf, false);
+        let mut temp_buf = [0u8; 64];
+        while count < buf.len() && count < vmin.max(1) {
+            let res = if is_nonblocking || (vmin == 0 && vtime == 0) {
+                self.serial.read(pos, &mut temp_buf, true)
+            } else if vtime > 0 && (vmin == 0 || count > 0) {
+                self.serial
+                    .read_timeout(&mut temp_buf, time::tick_from_millisecond(vtime * 100))
+                    .map_err(ErrorKind::from)
+            } else {
+                self.serial.read(pos, &mut temp_buf, false)
+            };
+            let nbytes = match res {
+                Ok(0) | Err(ErrorKind::TimedOut) => break,
+                Ok(n) => n,
+                Err(e) => return Err(e),
+            };
+            self.receive(pos, &temp_buf[..nbytes], termios);
+            count += self.ldisc.lock().read(&mut buf[count..], false);
+        }
+        Ok(count)
+    }
 }
 
 impl Device for Tty {
@@ -98,115 +228,56 @@ impl Device for Tty {
         self.serial.close()
     }
 
-    fn read(&self, _pos: u64, buf: &mut [u8], is_blocking: bool) -> Result<usize, ErrorKind> {
-        let mut line_buf = self.line_buf.lock();
-        // handle special characters
-        if let Some(key) = &*self.spec_key.lock() {
-            let history_cursor = self.history_cursor.load(Ordering::Relaxed);
-            match key {
-                SpecKey::Up => {
-                    if history_cursor < self.history.lock().len() {
-                        if let Some(hist_cmd) = self.get_history(history_cursor) {
-                            line_buf[..hist_cmd.len()].copy_from_slice(hist_cmd.as_bytes());
-                            self.cursor.store(hist_cmd.len(), Ordering::Relaxed);
-                            self.serial.write(_pos, hist_cmd.as_bytes(), false)?;
-                            self.history_cursor
-                                .store(history_cursor + 1, Ordering::Relaxed);
-                        }
-                    }
-                }
-                SpecKey::Down => {
-                    if history_cursor != 0 {
-                        if let Some(hist_cmd) = self.get_history(history_cursor - 1) {
-                            line_buf[..hist_cmd.len()].copy_from_slice(hist_cmd.as_bytes());
-                            self.cursor.store(hist_cmd.len(), Ordering::Relaxed);
-                            self.serial.write(_pos, hist_cmd.as_bytes(), false)?;
-                        }
-                        self.history_cursor
-                            .store(history_cursor - 1, Ordering::Relaxed);
-                    } else {
-                        line_buf.fill(0);
-                        self.cursor.store(0, Ordering::Relaxed);
-                        self.history_cursor.store(0, Ordering::Relaxed);
-                    }
-                }
-            }
+    fn poll(&self, events: PollEvents, table: &mut PollTable) -> PollEvents {
+        let mut revents = self.serial.poll(events, table);
+        if self.ldisc.lock().available() > 0 {
+            revents |= PollEvents::POLLIN & events;
         }
-        *self.spec_key.lock() = None;
-        // normal character
-        loop {
-            let mut temp_buf = [0u8; 512];
-            let nbytes = self.serial.read(_pos, &mut temp_buf, is_blocking).unwrap();
-            let mut i = 0;
-            while i < nbytes {
-                let ch = temp_buf[i];
-                let cursor = self.cursor.load(Ordering::Relaxed);
-                if self.serial.termios.iflag.contains(Iflags::ICRNL) && ch == b'\r' {
-                    let _ = self.serial.write(_pos, b"\n", false);
-                    line_buf[cursor] = b'\n';
-                    buf[..cursor + 1].copy_from_slice(&line_buf[..cursor + 1]);
-                    let command = String::from_utf8_lossy(&line_buf[..cursor]).into_owned();
-                    if !command.is_empty() {
-                        self.add_history(&command);
-                    }
-                    line_buf.fill(0);
-                    self.cursor.store(0, Ordering::Relaxed);
-                    return Ok(cursor + 1);
-                }
-                if self.serial.termios.cc[CcIndex::Verase as usize] == ch {
-                    if cursor > 0 {
-                        let backspace_seq = [8u8, b' ', 8u8];
-                        let _ = self.serial.write(_pos, &backspace_seq, false);
-                        let _ = self.cursor.fetch_sub(1, Ordering::Relaxed);
-                        line_buf[cursor - 1] = 0;
-                    }
-                    i += 1;
-                    continue;
-                }
-
-                if self.serial.termios.cc[CcIndex::Vkill as usize] == ch {
-                    line_buf.fill(0);
-                    self.cursor.store(0, Ordering::Relaxed);
-                    i += 1;
-                    continue;
-                }
+        revents
+    }
 
-                // get commandline history
-                // up key  : 0x1b 0x5b 0x41
-                // down key: 0x1b 0x5b 0x42
-                if ch == 0x1b && i <= temp_buf.len() - 3 && temp_buf[i + 1] == 0x5b {
-                    match temp_buf[i + 2] {
-                        0x41 => {
-                            *self.spec_key.lock() = Some(SpecKey::Up);
-                            self.clear_line(_pos, false)?;
-                            buf[0] = b'\n';
-                            return Ok(1);
-                        }
-                        0x42 => {
-                            *self.spec_key.lock() = Some(SpecKey::Down);
-                            self.clear_line(_pos, false)?;
-                            buf[0] = b'\n';
-                            return Ok(1);
-                        }
-                        _ => {
-                            i += 3;
-                            continue;
-                        }
-                    }
-                }
-                i += 1;
-                line_buf[cursor] = ch;
-                let _ = self.cursor.fetch_add(1, Ordering::Relaxed);
-                let _ = self.serial.write(_pos, &[ch], false);
-            }
+    fn read(&self, pos: u64, buf: &mut [u8], is_nonblocking: bool) -> Result<usize, ErrorKind> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let termios = *self.termios.lock();
+        if termios.lflag.contains(Lflags::ICANON) {
+            self.read_canonical(pos, buf, is_nonblocking, &termios)
+        } else {
+            self.read_raw(pos, buf, is_nonblocking, &termios)
         }
     }
 
-    fn write(&self, _pos: u64, buf: &[u8], is_blocking: bool) -> Result<usize, ErrorKind> {
-        self.serial.write(_pos, buf, is_blocking)
+    fn write(&self, pos: u64, buf: &[u8], is_nonblocking: bool) -> Result<usize, ErrorKind> {
+        self.serial.write(pos, buf, is_nonblocking)
     }
 
+    // Only the line discipline settings of the termios take effect, the
+    // serial keeps the configuration it's set up with.
     fn ioctl(&self, request: u32, arg: usize) -> Result<(), ErrorKind> {
-        self.serial.ioctl(request, arg)
+        match request {
+            TCGETS => {
+                unsafe { (arg as *mut Termios).write(*self.termios.lock()) };
+                Ok(())
+            }
+            TCSETS | TCSETSW | TCSETSF => {
+                let termios = unsafe { (arg as *const Termios).read() };
+                if request != TCSETS {
+                    self.serial.drain().map_err(ErrorKind::from)?;
+                }
+                if request == TCSETSF {
+                    self.ldisc.lock().flush();
+                }
+                *self.termios.lock() = termios;
+                Ok(())
+            }
+            TIOCSTI => {
+                let ch = unsafe { (arg as *const u8).read() };
+                let termios = *self.termios.lock();
+                self.receive(0, &[ch], &termios);
+                Ok(())
+            }
+            _ => self.serial.ioctl(request, arg),
+        }
     }
 }
@@ -19,6 +19,7 @@ use crate::{
         atomic_wait::{atomic_wait, atomic_wake},
         spinlock::SpinLock,
     },
+    vfs::poll::{PollEvents, PollQueue, PollTable},
 };
 use alloc::{format, string::String, sync::Arc};
 use blueos_infra::ringbuffer::BoxedRingBuffer;
@@ -131,6 +132,7 @@ pub struct Serial {
     pub termios: Termios,
     rx_fifo: SerialRxFifo,
     tx_fifo: SerialTxFifo,
+    poll_queue: Arc<PollQueue>,
     pub uart_ops: Arc<SpinLock<dyn UartOps>>,
 }
 
@@ -142,6 +144,7 @@ impl Serial {
             termios,
             rx_fifo: SerialRxFifo::new(SERIAL_RX_FIFO_SIZE.max(SERIAL_RX_FIFO_MIN_SIZE)),
             tx_fifo: SerialTxFifo::new(SERIAL_TX_FIFO_SIZE.max(SERIAL_TX_FIFO_MIN_SIZE)),
+            poll_queue: Arc::new(PollQueue::new()),
             uart_ops,
         }
     }
@@ -168,7 +171,12 @@ impl Serial {
         Ok(())
     }
 
-    fn fifo_rx(&self, buf: &mut [u8], is_nonblocking: bool) -> Result<usize, SerialError> {
+    fn fifo_rx(
+        &self,
+        buf: &mut [u8],
+        is_nonblocking: bool,
+        timeout: Option<usize>,
+    ) -> Result<usize, SerialError> {
         let len = buf.len();
         let mut count = 0;
         let mut reader = unsafe { self.rx_fifo.rb.reader() };
@@ -188,7 +196,8 @@ impl Serial {
             if !is_nonblocking {
                 // if the available data is less than the requested data, wait for data
                 if n == 0 {
-                    atomic_wait(&self.rx_fifo.futex, 0, None).map_err(|_| SerialError::TimedOut)?;
+                    atomic_wait(&self.rx_fifo.futex, 0, timeout)
+                        .map_err(|_| SerialError::TimedOut)?;
                 } else {
                     break;
                 }
@@ -243,6 +252,27 @@ impl Serial {
         Ok(count)
     }
 
+    /// Wait until everything written is passed to the UART. The fifo is
+    /// checked again every tick, in case its last bytes are sent between
+    /// the check and the wait.
+    pub fn drain(&self) -> Result<(), SerialError> {
+        while !self.tx_fifo.rb.is_empty() && !irq::is_in_irq() {
+            self.uart_ops.irqsave_lock().set_tx_interrupt(true);
+            let _ = self.xmitchars();
+            if self.tx_fifo.rb.is_empty() {
+                break;
+            }
+            let _ = atomic_wait(&self.tx_fifo.futex, 0, Some(1));
+        }
+        Ok(())
+    }
+
+    /// Blocking read which gives up with `SerialError::TimedOut` if no
+    /// data is received within `timeout` ticks.
+    pub fn read_timeout(&self, buf: &mut [u8], timeout: usize) -> Result<usize, SerialError> {
+        self.fifo_rx(buf, false, Some(timeout))
+    }
+
     /// this Function is called from the UART interrupt handler
     /// when an interrupt is received indicating that there is more space in the
     /// transmit FIFO
@@ -268,8 +298,8 @@ impl Serial {
         }
 
         if nbytes > 0 {
-            // TODO: add notify for poll/select
             let _ = atomic_wake(&self.tx_fifo.futex, 1);
+            self.poll_queue.notify();
         }
 
         Ok(nbytes)
@@ -296,9 +326,9 @@ impl Serial {
             }
         }
 
-        // TODO: add notify for poll/select
         if nbytes > 0 {
             let _ = atomic_wake(&self.rx_fifo.futex, 1);
+            self.poll_queue.notify();
         }
 
         Ok(nbytes)
@@ -347,7 +377,8 @@ impl Device for Serial {
     }
 
     fn read(&self, _pos: u64, buf: &mut [u8], is_nonblocking: bool) -> Result<usize, ErrorKind> {
-        self.fifo_rx(buf, is_nonblocking).map_err(|e| e.into())
+        self.fifo_rx(buf, is_nonblocking, None)
+            .map_err(|e| e.into())
     }
 
     fn write(&self, _pos: u64, buf: &[u8], is_nonblocking: bool) -> Result<usize, ErrorKind> {
@@ -358,4 +389,16 @@ impl Device for Serial {
         let mut uart_ops = self.uart_ops.irqsave_lock();
         uart_ops.ioctl(request, arg).map_err(|e| e.into())
     }
+
+    fn poll(&self, events: PollEvents, table: &mut PollTable) -> PollEvents {
+        table.register(&self.poll_queue);
+        let mut revents = PollEvents::empty();
+        if !self.rx_fifo.rb.is_empty() {
+            revents |= PollEvents::POLLIN;
+        }
+        if !self.tx_fifo.rb.is_full() {
+            revents |= PollEvents::POLLOUT;
+        }
+        revents & events
+    }
 }
@@ -14,6 +14,21 @@
 
 use bitflags::bitflags;
 
+// Device requests of terminals, numbered as on Linux.
+pub const TCGETS: u32 = 0x5401;
+pub const TCSETS: u32 = 0x5402;
+// Same as TCSETS, after the output is drained.
+pub const TCSETSW: u32 = 0x5403;
+// Same as TCSETSW, also discarding pending input.
+pub const TCSETSF: u32 = 0x5404;
+// Insert a byte into the input, as if it was received.
+pub const TIOCSTI: u32 = 0x5412;
+
+// Optional actions of tcsetattr(), added to TCSETS.
+pub const TCSANOW: i32 = 0;
+pub const TCSADRAIN: i32 = 1;
+pub const TCSAFLUSH: i32 = 2;
+
 /// Termios flags, see: https://pubs.opengroup.org/onlinepubs/9699919799/basedefs/termios.h.html.
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
@@ -33,6 +33,7 @@ pub mod code {
     pub const ENOENT: super::Error = super::Error(-libc::ENOENT);
     pub const ENODEV: super::Error = super::Error(-libc::ENODEV);
     pub const EPERM: super::Error = super::Error(-libc::EPERM);
+    pub const ESRCH: super::Error = super::Error(-libc::ESRCH);
     pub const EAGAIN: super::Error = super::Error(-libc::EAGAIN);
     pub const EBADF: super::Error = super::Error(-libc::EBADF);
     pub const EEXIST: super::Error = super::Error(-libc::EEXIST);
@@ -42,11 +43,13 @@ pub mod code {
     pub const ENAMETOOLONG: super::Error = super::Error(-libc::ENAMETOOLONG);
     pub const EACCES: super::Error = super::Error(-libc::EACCES);
     pub const ESPIPE: super::Error = super::Error(-libc::ESPIPE);
+    pub const EPIPE: super::Error = super::Error(-libc::EPIPE);
     pub const EOVERFLOW: super::Error = super::Error(-libc::EOVERFLOW);
     pub const ELOOP: super::Error = super::Error(-libc::ELOOP);
     pub const EXDEV: super::Error = super::Error(-libc::EXDEV);
     pub const EILSEQ: super::Error = super::Error(-libc::EILSEQ);
     pub const ENOTSUP: super::Error = super::Error(-libc::ENOTSUP);
+    pub const ENOTTY: super::Error = super::Error(-libc::ENOTTY);
 }
 
 const UNKNOW_STR: &CStr = c"EUNKNOW ";
@@ -63,6 +66,7 @@ const EINTR_STR: &CStr = c"Interrupted system call  ";
 const EINVAL_STR: &CStr = c"Invalid argument  ";
 const ENOENT_STR: &CStr = c"No such file or directory  ";
 const EPERM_STR: &CStr = c"Operation not permitted  ";
+const ESRCH_STR: &CStr = c"No such process  ";
 const ENODEV_STR: &CStr = c"No Such Device  ";
 const EAGAIN_STR: &CStr = c"Try again  ";
 const EBADF_STR: &CStr = c"File descriptor in bad state  ";
@@ -72,11 +76,13 @@ const EISDIR_STR: &CStr = c"Is a directory ";
 const ENOTEMPTY_STR: &CStr = c"Directory not empty ";
 const ENAMETOOLONG_STR: &CStr = c"File name too long";
 const ESPIPE_STR: &CStr = c"Invalid seek";
+const EPIPE_STR: &CStr = c"Broken pipe";
 const EOVERFLOW_STR: &CStr = c"Value too large to be stored in data type";
 const ELOOP_STR: &CStr = c"Too many symbolic links encountered";
 const EXDEV_STR: &CStr = c"Cross-device link";
 const EILSEQ_STR: &CStr = c"Invalid data";
 const ENOTSUP_STR: &CStr = c"Not supported";
+const ENOTTY_STR: &CStr = c"Inappropriate ioctl for device";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
@@ -107,6 +113,7 @@ impl Error {
             code::EINVAL => EINVAL_STR,
             code::ENOENT => ENOENT_STR,
             code::EPERM => EPERM_STR,
+            code::ESRCH => ESRCH_STR,
             code::EAGAIN => EAGAIN_STR,
             code::EBADF => EBADF_STR,
             code::EEXIST => EEXIST_STR,
@@ -116,11 +123,13 @@ impl Error {
             code::ENODEV => ENODEV_STR,
             code::ENAMETOOLONG => ENAMETOOLONG_STR,
             code::ESPIPE => ESPIPE_STR,
+            code::EPIPE => EPIPE_STR,
             code::EOVERFLOW => EOVERFLOW_STR,
             code::ELOOP => ELOOP_STR,
             code::EXDEV => EXDEV_STR,
             code::EILSEQ => EILSEQ_STR,
             code::ENOTSUP => ENOTSUP_STR,
+            code::ENOTTY => ENOTTY_STR,
             _ => UNKNOW_STR,
         }
     }
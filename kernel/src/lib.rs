@@ -76,7 +76,7 @@ pub mod asynk;
 pub(crate) mod boards;
 pub(crate) mod boot;
 pub(crate) mod config;
-pub(crate) mod console;
+pub mod console;
 #[cfg(coverage)]
 pub mod coverage;
 pub(crate) mod devices;
@@ -89,7 +89,7 @@ pub mod support;
 pub mod sync;
 pub mod syscall_handlers;
 pub mod thread;
-pub(crate) mod time;
+pub mod time;
 pub mod types;
 pub mod vfs;
 
@@ -108,14 +108,14 @@ macro_rules! trace {
         let dig = $crate::support::DisableInterruptGuard::new();
         let l = $crate::TRACER.lock();
         #[cfg(target_pointer_width="32")]
-        semihosting::eprint!("[C:{:02} SP:0x{:08x}] ",
-                             $crate::arch::current_cpu_id(),
-                             $crate::arch::current_sp());
+        $crate::batched_print!("[C:{:02} SP:0x{:08x}] ",
+                               $crate::arch::current_cpu_id(),
+                               $crate::arch::current_sp());
         #[cfg(target_pointer_width="64")]
-        semihosting::eprint!("[C:{:02} SP:0x{:016x}] ",
-                             $crate::arch::current_cpu_id(),
-                             $crate::arch::current_sp());
-        semihosting::eprintln!($($tt)*);
+        $crate::batched_print!("[C:{:02} SP:0x{:016x}] ",
+                               $crate::arch::current_cpu_id(),
+                               $crate::arch::current_sp());
+        $crate::batched_println!($($tt)*);
         drop(l);
         drop(dig);
     }};
@@ -134,7 +134,7 @@ mod tests {
     use core::{
         mem::MaybeUninit,
         panic::PanicInfo,
-        sync::atomic::{AtomicUsize, Ordering},
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
     };
     use spin::Mutex;
     use thread::{Entry, SystemThreadStorage, Thread, ThreadKind, ThreadNode};
@@ -234,6 +234,7 @@ mod tests {
     #[panic_handler]
     fn oops(info: &PanicInfo) -> ! {
         let _guard = DisableInterruptGuard::new();
+        console::SEMIHOSTING_CONSOLE.flush_on_panic();
         semihosting::println!("{}", info);
         semihosting::println!("Oops: {}", info.message());
         loop {}
@@ -254,6 +255,23 @@ mod tests {
         assert_eq!(*r, 1);
     }
 
+    #[test]
+    fn test_spinarc_lock_for() {
+        use sync::spinlock::SpinArcLockFor;
+        let lock: types::SpinArc<usize> = types::Arc::new(types::RwLock::new(0));
+        let held = lock.try_lock().unwrap();
+        assert!(lock.lock_for(2).is_none());
+        assert!(arch::local_irq_enabled());
+        drop(held);
+
+        let mut w = lock.lock_for(2).unwrap();
+        assert!(!arch::local_irq_enabled());
+        *w = 1;
+        drop(w);
+        assert!(arch::local_irq_enabled());
+        assert_eq!(*lock.try_lock().unwrap(), 1);
+    }
+
     #[test]
     fn test_spinlock_loop() {
         let lock = sync::spinlock::SpinLock::new(0);
@@ -271,7 +289,7 @@ mod tests {
         }
     }
 
-    #[cfg(cortex_m)]
+    #[cfg(any(cortex_m, target_arch = "riscv64"))]
     #[test]
     fn test_sys_tick() {
         let tick = time::get_sys_ticks();
@@ -283,11 +301,64 @@ mod tests {
         assert!(tick2 - tick <= 11);
     }
 
+    #[test]
+    fn test_tick_from_millisecond_saturates() {
+        let ticks = time::tick_from_millisecond(usize::MAX);
+        assert!(ticks >= time::tick_from_millisecond(usize::MAX / 2));
+    }
+
+    // Every core is kept busy by a CPU-bound thread, so once woken up
+    // the higher priority thread only runs if the timer IRQ preempts one
+    // of them.
+    #[cfg(all(target_arch = "aarch64", robin_scheduler))]
+    #[test]
+    fn test_irq_preemption() {
+        static STOP: AtomicBool = AtomicBool::new(false);
+        static MAX_LATENCY: AtomicUsize = AtomicUsize::new(0);
+        let priority = scheduler::current_thread().priority();
+        for _i in 0..NUM_CORES {
+            thread::Builder::new(Entry::Closure(alloc::boxed::Box::new(|| {
+                while !STOP.load(Ordering::Acquire) {
+                    core::hint::spin_loop();
+                }
+            })))
+            .set_priority(priority)
+            .start();
+        }
+        thread::Builder::new(Entry::Closure(alloc::boxed::Box::new(|| {
+            for _i in 0..8 {
+                let start = time::get_sys_ticks();
+                scheduler::suspend_me_for(1);
+                let latency = time::get_sys_ticks() - start;
+                MAX_LATENCY.fetch_max(latency, Ordering::Relaxed);
+            }
+            STOP.store(true, Ordering::Release);
+        })))
+        .set_priority(priority - 1)
+        .start();
+        while !STOP.load(Ordering::Acquire) {
+            scheduler::yield_me();
+        }
+        assert!(MAX_LATENCY.load(Ordering::Relaxed) <= blueos_kconfig::ROBIN_SLICE as usize);
+    }
+
     #[test]
     fn test_local_irq() {
         assert!(arch::local_irq_enabled());
     }
 
+    #[test]
+    fn test_local_irq_save_nested() {
+        let outer = arch::disable_local_irq_save();
+        assert!(!arch::local_irq_enabled());
+        let inner = arch::disable_local_irq_save();
+        arch::enable_local_irq_restore(inner);
+        // Still disabled, the outer section hasn't ended yet.
+        assert!(!arch::local_irq_enabled());
+        arch::enable_local_irq_restore(outer);
+        assert!(arch::local_irq_enabled());
+    }
+
     #[test]
     fn stress_trap() {
         #[cfg(target_pointer_width = "32")]
@@ -453,7 +524,6 @@ mod tests {
         assert_eq!(a - b, 0);
     }
 
-    // FIXME: We still have chance falling into deadlock, TBI.
     #[test]
     fn stress_async_basic() {
         let n = 1024;
@@ -462,6 +532,91 @@ mod tests {
         }
     }
 
+    // Pending a few times, waking itself up each time.
+    struct YieldTimes(usize);
+
+    impl core::future::Future for YieldTimes {
+        type Output = usize;
+
+        fn poll(
+            mut self: core::pin::Pin<&mut Self>,
+            cx: &mut core::task::Context<'_>,
+        ) -> core::task::Poll<usize> {
+            if self.0 == 0 {
+                return core::task::Poll::Ready(42);
+            }
+            self.0 -= 1;
+            cx.waker().wake_by_ref();
+            core::task::Poll::Pending
+        }
+    }
+
+    #[test]
+    fn test_async_sleep_join() {
+        let ms = 100;
+        let start = time::get_sys_ticks();
+        asynk::block_on(asynk::join(
+            asynk::sleep(core::time::Duration::from_millis(ms)),
+            asynk::sleep(core::time::Duration::from_millis(ms)),
+        ));
+        let elapsed = time::get_sys_ticks() - start;
+        let ticks = time::tick_from_millisecond(ms as usize);
+        // Slept concurrently
+        assert!(elapsed >= ticks);
+        assert!(elapsed < 2 * ticks);
+
+        // Dropped before firing, the timer is stopped and never wakes.
+        struct FlagWaker(AtomicBool);
+        impl alloc::task::Wake for FlagWaker {
+            fn wake(self: alloc::sync::Arc<Self>) {
+                self.0.store(true, Ordering::Release);
+            }
+        }
+        let flag = alloc::sync::Arc::new(FlagWaker(AtomicBool::new(false)));
+        let waker = core::task::Waker::from(flag.clone());
+        let mut ctx = core::task::Context::from_waker(&waker);
+        {
+            let mut sleep = core::pin::pin!(asynk::sleep(core::time::Duration::from_millis(ms)));
+            assert!(core::future::Future::poll(sleep.as_mut(), &mut ctx).is_pending());
+        }
+        scheduler::suspend_me_for(2 * ticks);
+        assert!(!flag.0.load(Ordering::Acquire));
+
+        // Durations beyond the tick range saturate instead of wrapping.
+        let mut sleep = core::pin::pin!(asynk::sleep(core::time::Duration::MAX));
+        assert!(core::future::Future::poll(sleep.as_mut(), &mut ctx).is_pending());
+    }
+
+    #[test]
+    fn test_async_channel() {
+        let (tx, mut rx) = asynk::channel::bounded(4);
+        let producer = async move {
+            for i in 0..100usize {
+                assert!(tx.send(i).await.is_ok());
+            }
+        };
+        let consumer = async move {
+            let mut expected = 0;
+            while let Some(i) = rx.recv().await {
+                assert_eq!(i, expected);
+                expected += 1;
+            }
+            expected
+        };
+        let ((), received) = asynk::block_on(asynk::join(producer, consumer));
+        assert_eq!(received, 100);
+
+        let (tx, rx) = asynk::channel::bounded(1);
+        drop(rx);
+        assert_eq!(asynk::block_on(tx.send(7)), Err(7));
+    }
+
+    #[test]
+    fn test_block_on_pending() {
+        assert_eq!(asynk::block_on(YieldTimes(3)), 42);
+        assert_eq!(asynk::block_on(async { YieldTimes(1).await + 1 }), 43);
+    }
+
     #[inline(never)]
     pub fn kernel_unittest_runner(tests: &[&dyn Fn()]) {
         let t = scheduler::current_thread();
@@ -476,6 +631,7 @@ mod tests {
         for test in tests {
             test();
         }
+        console::SEMIHOSTING_CONSOLE.flush();
         semihosting::println!(
             "After test, thread 0x{:x}, heap status: {:?}, sp: 0x{:x}",
             Thread::id(&t),
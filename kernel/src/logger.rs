@@ -13,11 +13,28 @@
 // limitations under the License.
 
 use crate::{
-    arch, kprintln, scheduler, sync::SpinLock, thread::Thread, time::tick_get_millisecond,
+    arch,
+    error::{code, Error},
+    kprintln, scheduler,
+    sync::SpinLock,
+    time::tick_get_millisecond,
+};
+use alloc::{string::String, vec::Vec};
+use core::{
+    cmp, fmt,
+    sync::atomic::{AtomicBool, Ordering},
 };
 use log::{LevelFilter, Metadata, Record};
 
 static LOGGER_MUTEX: SpinLock<()> = SpinLock::new(());
+static LOGGER: Logger = Logger {};
+static FILTERS: SpinLock<LevelFilters> = SpinLock::new(LevelFilters {
+    default: LevelFilter::Info,
+    modules: Vec::new(),
+});
+// Whether FILTERS has any module level. Without one, every record is
+// checked against log::max_level() without taking the lock.
+static HAS_MODULE_LEVELS: AtomicBool = AtomicBool::new(false);
 
 struct Logger;
 
@@ -29,31 +46,130 @@ pub enum LogLevel {
     Error,
 }
 
+impl core::str::FromStr for LogLevel {
+    type Err = Error;
+
+    // Accepts level names in any case, e.g. "debug" or "WARN".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let levels = [
+            ("trace", LogLevel::Trace),
+            ("debug", LogLevel::Debug),
+            ("info", LogLevel::Info),
+            ("warn", LogLevel::Warn),
+            ("error", LogLevel::Error),
+        ];
+        levels
+            .into_iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+            .map(|(_, level)| level)
+            .ok_or(code::EINVAL)
+    }
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Trace => LevelFilter::Trace,
+            LogLevel::Debug => LevelFilter::Debug,
+            LogLevel::Info => LevelFilter::Info,
+            LogLevel::Warn => LevelFilter::Warn,
+            LogLevel::Error => LevelFilter::Error,
+        }
+    }
+}
+
+struct LevelFilters {
+    // Level of modules without a filter of their own.
+    default: LevelFilter,
+    // Keyed by module path prefix, the longest matching prefix wins.
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl LevelFilters {
+    fn level_of(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(module, _)| is_in_module(target, module))
+            .max_by_key(|(module, _)| module.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    // The log crate drops records above log::max_level() before they
+    // reach the backend, so it must admit the most verbose filter.
+    fn update_max_level(&self) {
+        let max = self
+            .modules
+            .iter()
+            .fold(self.default, |max, (_, level)| cmp::max(max, *level));
+        log::set_max_level(max);
+        HAS_MODULE_LEVELS.store(!self.modules.is_empty(), Ordering::Release);
+    }
+}
+
+// "blueos::net" covers "blueos::net" and "blueos::net::tcp", but not
+// "blueos::network".
+fn is_in_module(target: &str, module: &str) -> bool {
+    target
+        .strip_prefix(module)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
 ///set max log level
 pub fn set_max_level(level: LogLevel) {
-    match level {
-        LogLevel::Trace => log::set_max_level(LevelFilter::Trace),
-        LogLevel::Debug => log::set_max_level(LevelFilter::Debug),
-        LogLevel::Info => log::set_max_level(LevelFilter::Info),
-        LogLevel::Warn => log::set_max_level(LevelFilter::Warn),
-        LogLevel::Error => log::set_max_level(LevelFilter::Error),
+    let mut filters = FILTERS.irqsave_lock();
+    filters.default = level.into();
+    filters.update_max_level();
+}
+
+/// Max log level which is not overridden by any module.
+pub fn max_level() -> LevelFilter {
+    FILTERS.irqsave_lock().default
+}
+
+/// Set the level of the modules under `module`, overriding the max
+/// log level.
+pub fn set_module_level(module: &str, level: LogLevel) {
+    let entry = (String::from(module), level.into());
+    let mut filters = FILTERS.irqsave_lock();
+    match filters.modules.iter_mut().find(|(m, _)| *m == entry.0) {
+        Some(filter) => filter.1 = entry.1,
+        None => filters.modules.push(entry),
+    }
+    filters.update_max_level();
+}
+
+pub fn clear_module_level(module: &str) {
+    let mut filters = FILTERS.irqsave_lock();
+    filters.modules.retain(|(m, _)| m != module);
+    filters.update_max_level();
+}
+
+/// Write the default level and the per-module levels, one per line.
+pub fn write_levels(w: &mut impl fmt::Write) -> fmt::Result {
+    let filters = FILTERS.irqsave_lock();
+    writeln!(w, "default {}", filters.default)?;
+    for (module, level) in filters.modules.iter() {
+        writeln!(w, "{} {}", module, level)?;
     }
+    Ok(())
 }
 
 /// log init
 pub fn logger_init() {
-    static LOGGER: Logger = Logger {};
     #[cfg(debug)]
-    log::set_max_level(LevelFilter::Trace);
+    set_max_level(LogLevel::Trace);
     #[cfg(release)]
-    log::set_max_level(LevelFilter::Warn);
+    set_max_level(LogLevel::Warn);
     log::set_logger(&LOGGER).unwrap();
 }
 
 ///impl log for Logger
 impl log::Log for Logger {
     fn enabled(&self, metadata: &Metadata) -> bool {
-        metadata.level() <= log::max_level()
+        if !HAS_MODULE_LEVELS.load(Ordering::Acquire) {
+            return metadata.level() <= log::max_level();
+        }
+        metadata.level() <= FILTERS.irqsave_lock().level_of(metadata.target())
     }
 
     fn log(&self, record: &Record) {
@@ -76,3 +192,62 @@ impl log::Log for Logger {
 
     fn flush(&self) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blueos_test_macro::test;
+    use log::{Level, Log, MetadataBuilder};
+
+    fn is_enabled(level: Level, target: &str) -> bool {
+        LOGGER.enabled(&MetadataBuilder::new().level(level).target(target).build())
+    }
+
+    #[test]
+    fn test_module_level_filter() {
+        let default = FILTERS.irqsave_lock().default;
+        set_max_level(LogLevel::Info);
+        assert!(!HAS_MODULE_LEVELS.load(Ordering::Relaxed));
+        assert!(!is_enabled(Level::Debug, "blueos::net"));
+        set_module_level("blueos::net", LogLevel::Debug);
+        assert!(HAS_MODULE_LEVELS.load(Ordering::Relaxed));
+        assert!(log::max_level() >= LevelFilter::Debug);
+
+        assert!(is_enabled(Level::Debug, "blueos::net"));
+        assert!(is_enabled(Level::Debug, "blueos::net::tcp"));
+        assert!(!is_enabled(Level::Trace, "blueos::net::tcp"));
+        assert!(!is_enabled(Level::Debug, "blueos::network"));
+        assert!(!is_enabled(Level::Debug, "blueos::vfs"));
+        assert!(is_enabled(Level::Info, "blueos::vfs"));
+
+        // A more specific module takes precedence.
+        set_module_level("blueos::net::tcp", LogLevel::Warn);
+        assert!(!is_enabled(Level::Info, "blueos::net::tcp"));
+        assert!(is_enabled(Level::Debug, "blueos::net::udp"));
+
+        let mut levels = String::new();
+        write_levels(&mut levels).unwrap();
+        assert_eq!(
+            levels,
+            "default INFO\nblueos::net DEBUG\nblueos::net::tcp WARN\n"
+        );
+
+        clear_module_level("blueos::net");
+        clear_module_level("blueos::net::tcp");
+        assert!(!HAS_MODULE_LEVELS.load(Ordering::Relaxed));
+        assert!(!is_enabled(Level::Debug, "blueos::net"));
+        assert_eq!(log::max_level(), LevelFilter::Info);
+
+        let mut filters = FILTERS.irqsave_lock();
+        filters.default = default;
+        filters.update_max_level();
+    }
+
+    #[test]
+    fn test_parse_log_level() {
+        assert!(matches!("debug".parse::<LogLevel>(), Ok(LogLevel::Debug)));
+        assert!(matches!("WARN".parse::<LogLevel>(), Ok(LogLevel::Warn)));
+        assert!(matches!("verbose".parse::<LogLevel>(), Err(code::EINVAL)));
+        assert!("".parse::<LogLevel>().is_err());
+    }
+}
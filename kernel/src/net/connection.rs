@@ -17,26 +17,33 @@ use crate::{
     net::{
         connection_err::ConnectionError,
         net_manager::NetworkManager,
-        port_generator::PORT_GENERATOR,
+        port_generator::{PortReuse, PORT_GENERATOR},
         socket::{
-            socket_err::SocketError, FnRecv, FnRecvWithEndpoint, FnSend, FnSendMsg, PosixSocket,
+            socket_err::SocketError, FnEndpoint, FnRecv, FnRecvWithEndpoint, FnSend, FnSendMsg,
+            PosixSocket,
         },
         SocketDomain, SocketFd, SocketProtocol, SocketResult, SocketType,
     },
     scheduler::{self, yield_me},
     sync::atomic_wait as futex,
     thread::Thread,
+    time,
+    vfs::poll::{PollEvents, PollQueue, PollTable},
 };
-use alloc::{boxed::Box, rc::Rc, sync::Arc};
+use alloc::{boxed::Box, format, rc::Rc, sync::Arc};
 use core::{
     cell::RefCell,
     net::SocketAddr,
-    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
     time::Duration,
 };
 use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
 use spin::Mutex;
 
+// Same defaults as Linux, in seconds
+const DEFAULT_KEEP_IDLE: u32 = 7200;
+const DEFAULT_KEEP_INTVL: u32 = 75;
+
 // For posix syscalls
 pub type ConnectionResult = Result<usize, ConnectionError>;
 
@@ -50,7 +57,16 @@ pub struct Connection {
     is_nonblocking: AtomicBool, // default io mode is blocking, use O_NONBLOCK to set non-blocking
     recv_timeout: Mutex<Option<Duration>>, // block indefinitely as default
     send_timeout: Mutex<Option<Duration>>, // block indefinitely as default
-    ipc_reply: Arc<OperationIPCReply>,
+    reuse_addr: AtomicBool,     // ref to libc::SO_REUSEADDR
+    reuse_port: AtomicBool,     // ref to libc::SO_REUSEPORT
+    no_delay: AtomicBool,       // ref to libc::TCP_NODELAY
+    keep_alive: AtomicBool,     // ref to libc::SO_KEEPALIVE
+    keep_idle: AtomicU32,       // ref to libc::TCP_KEEPIDLE, in seconds
+    keep_intvl: AtomicU32,      // ref to libc::TCP_KEEPINTVL, in seconds
+    is_listening: AtomicBool,
+    // Replaced once a timed out request is abandoned, see `queue_and_wait_for`
+    ipc_reply: Mutex<Arc<OperationIPCReply>>,
+    poll_queue: Arc<PollQueue>,
 }
 
 impl Connection {
@@ -70,7 +86,15 @@ impl Connection {
             is_nonblocking: AtomicBool::new(false),
             recv_timeout: Mutex::new(None),
             send_timeout: Mutex::new(None),
-            ipc_reply: Arc::new(OperationIPCReply::new()),
+            reuse_addr: AtomicBool::new(false),
+            reuse_port: AtomicBool::new(false),
+            no_delay: AtomicBool::new(false),
+            keep_alive: AtomicBool::new(false),
+            keep_idle: AtomicU32::new(DEFAULT_KEEP_IDLE),
+            keep_intvl: AtomicU32::new(DEFAULT_KEEP_INTVL),
+            is_listening: AtomicBool::new(false),
+            ipc_reply: Mutex::new(Arc::new(OperationIPCReply::new())),
+            poll_queue: Arc::new(PollQueue::new()),
         }
     }
 
@@ -79,17 +103,18 @@ impl Connection {
     }
 
     pub fn create(&mut self) -> ConnectionResult {
+        let ipc_reply = self.ipc_reply.lock().clone();
         let create_task = Operation::Create {
             socket_fd: self.socket_fd,
             socket_domain: self.socket_domain,
             socket_type: self.socket_type,
             socket_protocol: self.socket_protocol,
-            ipc_reply: self.ipc_reply.clone(),
+            ipc_reply: ipc_reply.clone(),
         };
 
         log::debug!("[Socket {}] Create request queued", self.socket_fd);
 
-        self.ipc_reply.queue_and_wait(create_task)
+        ipc_reply.queue_and_wait(create_task)
     }
 
     pub fn bind(&self, local_endpoint: IpEndpoint) -> ConnectionResult {
@@ -111,7 +136,11 @@ impl Connection {
                     self.socket_type,
                     SocketType::SockStream | SocketType::SockDgram
                 ) {
-                    PORT_GENERATOR.acquire_port(self.socket_type, local_endpoint.port)?
+                    PORT_GENERATOR.acquire_port_with_reuse(
+                        self.socket_type,
+                        local_endpoint.port,
+                        self.port_reuse(),
+                    )?
                 } else {
                     local_endpoint.port
                 };
@@ -121,16 +150,17 @@ impl Connection {
                 local_endpoint
             };
 
+            let ipc_reply = self.ipc_reply.lock().clone();
             let bind_task = Operation::Bind {
                 socket_fd: self.socket_fd,
                 local_endpoint,
-                ipc_reply: self.ipc_reply.clone(),
+                ipc_reply: ipc_reply.clone(),
             };
 
             log::debug!("[Socket {}] Bind request queued", self.socket_fd);
 
             // Wait for network stack response and return directly
-            self.ipc_reply.queue_and_wait(bind_task)
+            ipc_reply.queue_and_wait(bind_task)
         } else {
             Err(ConnectionError::UnsupportedSocketType(self.socket_type))
         }
@@ -142,16 +172,19 @@ impl Connection {
             None => return Err(ConnectionError::LockFail("local endpoint".into())),
         };
 
+        let ipc_reply = self.ipc_reply.lock().clone();
         let listen_task = Operation::Listen {
             socket_fd: self.socket_fd,
             local_endpoint,
-            ipc_reply: self.ipc_reply.clone(),
+            ipc_reply: ipc_reply.clone(),
         };
 
         log::debug!("[Socket {}] Listen request queued", self.socket_fd);
 
+        self.is_listening.store(true, Ordering::Release);
+
         // Wait for network stack response and return directly
-        self.ipc_reply.queue_and_wait(listen_task)
+        ipc_reply.queue_and_wait(listen_task)
     }
 
     pub fn connect(&self, remote_endpoint: IpEndpoint) -> ConnectionResult {
@@ -168,80 +201,115 @@ impl Connection {
             }
         };
 
+        let ipc_reply = self.ipc_reply.lock().clone();
         let connect_task = Operation::Connect {
             socket_fd: self.socket_fd,
             remote_endpoint,
             local_port,
             is_nonblocking: self.is_nonblocking.load(Ordering::Acquire),
-            ipc_reply: self.ipc_reply.clone(),
+            ipc_reply: ipc_reply.clone(),
         };
 
         self.remote_endpoint.lock().replace(remote_endpoint);
 
         log::debug!("[Socket {}] Connect request queued", self.socket_fd);
 
-        self.ipc_reply.queue_and_wait(connect_task)
+        ipc_reply.queue_and_wait(connect_task)
+    }
+
+    // Take the pending error : ref to libc::SO_ERROR, returns 0 if there is none
+    pub fn take_error(&self) -> ConnectionResult {
+        let ipc_reply = self.ipc_reply.lock().clone();
+        let take_error_task = Operation::TakeError {
+            socket_fd: self.socket_fd,
+            ipc_reply: ipc_reply.clone(),
+        };
+
+        log::debug!("[Socket {}] TakeError request queued", self.socket_fd);
+
+        ipc_reply.queue_and_wait(take_error_task)
     }
 
     pub fn shutdown(&self) -> ConnectionResult {
         // Construct shutdown request with cloned response channel
+        let ipc_reply = self.ipc_reply.lock().clone();
         let shutdown_task = Operation::Shutdown {
             socket_fd: self.socket_fd,
-            ipc_reply: self.ipc_reply.clone(),
+            ipc_reply: ipc_reply.clone(),
         };
 
         // Log successful request submission
         log::debug!("[Socket {}] Shutdown request queued", self.socket_fd);
 
         // Await and return final shutdown status from network stack
-        self.ipc_reply.queue_and_wait(shutdown_task)
+        ipc_reply.queue_and_wait(shutdown_task)
+    }
+
+    pub fn shutdown_direction(&self, read: bool, write: bool) -> ConnectionResult {
+        let ipc_reply = self.ipc_reply.lock().clone();
+        let shutdown_task = Operation::ShutdownDirection {
+            socket_fd: self.socket_fd,
+            read,
+            write,
+            ipc_reply: ipc_reply.clone(),
+        };
+
+        log::debug!(
+            "[Socket {}] ShutdownDirection request queued",
+            self.socket_fd
+        );
+
+        ipc_reply.queue_and_wait(shutdown_task)
     }
 
     pub fn recv(&self, f: FnRecv) -> ConnectionResult {
         // Construct receive request with buffer ownership transfer
+        let ipc_reply = self.ipc_reply.lock().clone();
         let recv_task = Operation::Recv {
             socket_fd: self.socket_fd,
             f,
             is_nonblocking: self.is_nonblocking.load(Ordering::Acquire),
-            ipc_reply: self.ipc_reply.clone(),
+            ipc_reply: ipc_reply.clone(),
         };
 
         // Log successful request submission
         log::debug!("[Socket {}] Recv request queued", self.socket_fd);
 
         // Wait for network stack response and convert result
-        self.ipc_reply.queue_and_wait(recv_task)
+        self.queue_and_wait_for(&ipc_reply, recv_task, *self.recv_timeout.lock())
     }
 
     pub fn recvfrom(&self, f: FnRecvWithEndpoint) -> ConnectionResult {
         // Construct receive request with buffer ownership transfer
+        let ipc_reply = self.ipc_reply.lock().clone();
         let recv_task = Operation::RecvFrom {
             socket_fd: self.socket_fd,
             f,
             is_nonblocking: self.is_nonblocking.load(Ordering::Acquire),
-            ipc_reply: self.ipc_reply.clone(),
+            ipc_reply: ipc_reply.clone(),
         };
 
         // Log successful request submission
         log::debug!("[Socket {}] RecvFrom request queued", self.socket_fd);
 
         // Wait for network stack response and convert result
-        self.ipc_reply.queue_and_wait(recv_task)
+        self.queue_and_wait_for(&ipc_reply, recv_task, *self.recv_timeout.lock())
     }
 
     pub fn send(&self, f: FnSend, _flag: i32) -> ConnectionResult {
         // Construct send request with buffer reference
+        let ipc_reply = self.ipc_reply.lock().clone();
         let send_task = Operation::Send {
             socket_fd: self.socket_fd,
             f,
             is_nonblocking: self.is_nonblocking.load(Ordering::Acquire),
-            ipc_reply: self.ipc_reply.clone(),
+            ipc_reply: ipc_reply.clone(),
         };
 
         // Log successful request submission
         log::debug!("[Socket {}] Send request queued", self.socket_fd);
 
-        self.ipc_reply.queue_and_wait(send_task)
+        self.queue_and_wait_for(&ipc_reply, send_task, *self.send_timeout.lock())
     }
 
     pub fn sendto(
@@ -268,13 +336,14 @@ impl Connection {
         };
 
         // Construct send request with buffer reference
+        let ipc_reply = self.ipc_reply.lock().clone();
         let sendto_task = Operation::SendTo {
             socket_fd: self.socket_fd,
             remote_endpoint,
             local_port,
             buffer: message,
             is_nonblocking: self.is_nonblocking.load(Ordering::Acquire),
-            ipc_reply: self.ipc_reply.clone(),
+            ipc_reply: ipc_reply.clone(),
         };
 
         // Log successful request submission
@@ -284,7 +353,7 @@ impl Connection {
             message.len()
         );
 
-        self.ipc_reply.queue_and_wait(sendto_task)
+        self.queue_and_wait_for(&ipc_reply, sendto_task, *self.send_timeout.lock())
     }
 
     // ICMP/ICMPv6 only now
@@ -296,6 +365,7 @@ impl Connection {
         f: FnSendMsg,
     ) -> ConnectionResult {
         // Construct send request with buffer reference
+        let ipc_reply = self.ipc_reply.lock().clone();
         let sendmsg_task = Operation::SendMsg {
             socket_fd: self.socket_fd,
             remote_endpoint,
@@ -303,38 +373,250 @@ impl Connection {
             packet_len,
             f,
             is_nonblocking: self.is_nonblocking.load(Ordering::Acquire),
-            ipc_reply: self.ipc_reply.clone(),
+            ipc_reply: ipc_reply.clone(),
         };
 
         // Log successful request submission
         log::debug!("[Socket {}] SendMsg request queued", self.socket_fd);
 
-        self.ipc_reply.queue_and_wait(sendmsg_task)
+        self.queue_and_wait_for(&ipc_reply, sendmsg_task, *self.send_timeout.lock())
     }
 
     pub fn recvmsg(&self, f: FnRecvWithEndpoint) -> ConnectionResult {
-        // Construct send request with buffer reference
-        let sendmsg_task = Operation::RecvMsg {
+        // Construct recv request with buffer reference
+        let ipc_reply = self.ipc_reply.lock().clone();
+        let recvmsg_task = Operation::RecvMsg {
             socket_fd: self.socket_fd,
             f,
             is_nonblocking: self.is_nonblocking.load(Ordering::Acquire),
-            ipc_reply: self.ipc_reply.clone(),
+            ipc_reply: ipc_reply.clone(),
         };
 
         // Log successful request submission
         log::debug!("[Socket {}] RecvMsg request queued", self.socket_fd);
 
-        self.ipc_reply.queue_and_wait(sendmsg_task)
+        self.queue_and_wait_for(&ipc_reply, recvmsg_task, *self.recv_timeout.lock())
+    }
+
+    pub fn getsockname(&self, f: FnEndpoint) -> ConnectionResult {
+        // A tcp socket tells the network stack its port on listen() or connect(),
+        // before that report the one reserved by bind()
+        let local_endpoint = *self.local_endpoint.lock();
+        let f: FnEndpoint = Box::new(move |endpoint: IpEndpoint| match local_endpoint {
+            Some(local) if endpoint.port == 0 => f(IpEndpoint::new(
+                local.addr.unwrap_or(endpoint.addr),
+                local.port,
+            )),
+            _ => f(endpoint),
+        });
+
+        let ipc_reply = self.ipc_reply.lock().clone();
+        let getsockname_task = Operation::GetSockName {
+            socket_fd: self.socket_fd,
+            f,
+            ipc_reply: ipc_reply.clone(),
+        };
+
+        log::debug!("[Socket {}] GetSockName request queued", self.socket_fd);
+
+        ipc_reply.queue_and_wait(getsockname_task)
+    }
+
+    pub fn getpeername(&self, f: FnEndpoint) -> ConnectionResult {
+        // Neither connected nor accepted a connection
+        if self.remote_endpoint.lock().is_none() && !self.is_listening.load(Ordering::Acquire) {
+            return Err(ConnectionError::PosixError(code::ENOTCONN));
+        }
+
+        let ipc_reply = self.ipc_reply.lock().clone();
+        let getpeername_task = Operation::GetPeerName {
+            socket_fd: self.socket_fd,
+            f,
+            ipc_reply: ipc_reply.clone(),
+        };
+
+        log::debug!("[Socket {}] GetPeerName request queued", self.socket_fd);
+
+        ipc_reply.queue_and_wait(getpeername_task)
+    }
+
+    pub fn join_multicast_group(
+        &self,
+        group: IpAddress,
+        interface_addr: IpAddress,
+    ) -> ConnectionResult {
+        self.multicast(group, interface_addr, true)
+    }
+
+    pub fn leave_multicast_group(
+        &self,
+        group: IpAddress,
+        interface_addr: IpAddress,
+    ) -> ConnectionResult {
+        self.multicast(group, interface_addr, false)
+    }
+
+    fn multicast(
+        &self,
+        group: IpAddress,
+        interface_addr: IpAddress,
+        join: bool,
+    ) -> ConnectionResult {
+        let ipc_reply = self.ipc_reply.lock().clone();
+        let multicast_task = Operation::Multicast {
+            socket_fd: self.socket_fd,
+            group,
+            interface_addr,
+            join,
+            ipc_reply: ipc_reply.clone(),
+        };
+
+        log::debug!("[Socket {}] Multicast request queued", self.socket_fd);
+
+        ipc_reply.queue_and_wait(multicast_task)
+    }
+
+    pub fn poll(&self, events: PollEvents, table: &mut PollTable) -> PollEvents {
+        table.register(&self.poll_queue);
+
+        let ipc_reply = self.ipc_reply.lock().clone();
+        let poll_task = Operation::Poll {
+            socket_fd: self.socket_fd,
+            events,
+            poll_queue: self.poll_queue.clone(),
+            ipc_reply: ipc_reply.clone(),
+        };
+
+        log::debug!("[Socket {}] Poll request queued", self.socket_fd);
+
+        match ipc_reply.queue_and_wait(poll_task) {
+            Ok(revents) => PollEvents::from_bits_truncate(revents as i16),
+            Err(e) => {
+                log::debug!("[Socket {}] Poll fail {}", self.socket_fd, e);
+                PollEvents::POLLERR
+            }
+        }
+    }
+
+    // Queue a send/recv request which gives up with EAGAIN once `timeout` elapses.
+    // The network stack may still hold an abandoned request, so the following
+    // requests are answered through a new reply channel.
+    fn queue_and_wait_for(
+        &self,
+        ipc_reply: &Arc<OperationIPCReply>,
+        task: Operation,
+        timeout: Option<Duration>,
+    ) -> ConnectionResult {
+        let result = ipc_reply.queue_and_wait_for(task, timeout);
+        if matches!(result, Err(ConnectionError::PosixError(code::EAGAIN))) {
+            *self.ipc_reply.lock() = Arc::new(OperationIPCReply::new());
+        }
+        result
     }
 
-    // Set recv timeout : ref to libc::SO_RCVTIMEO
+    // Set recv timeout : ref to libc::SO_RCVTIMEO, zero means blocking indefinitely
     pub fn set_recv_timeout(&self, timeout: Duration) {
-        self.recv_timeout.lock().replace(timeout);
+        *self.recv_timeout.lock() = Some(timeout).filter(|t| !t.is_zero());
     }
 
-    // Set send timeout : ref to libc::SO_SNDTIMEO
+    // Set send timeout : ref to libc::SO_SNDTIMEO, zero means blocking indefinitely
     pub fn set_send_timeout(&self, timeout: Duration) {
-        self.send_timeout.lock().replace(timeout);
+        *self.send_timeout.lock() = Some(timeout).filter(|t| !t.is_zero());
+    }
+
+    // Set address reuse : ref to libc::SO_REUSEADDR, takes effect on the next bind
+    pub fn set_reuse_addr(&self, reuse: bool) {
+        self.reuse_addr.store(reuse, Ordering::Release);
+    }
+
+    // Set port reuse : ref to libc::SO_REUSEPORT, takes effect on the next bind
+    pub fn set_reuse_port(&self, reuse: bool) {
+        self.reuse_port.store(reuse, Ordering::Release);
+    }
+
+    pub fn get_reuse_addr(&self) -> bool {
+        self.reuse_addr.load(Ordering::Acquire)
+    }
+
+    pub fn get_reuse_port(&self) -> bool {
+        self.reuse_port.load(Ordering::Acquire)
+    }
+
+    // Set no delay : ref to libc::TCP_NODELAY, disables Nagle's algorithm
+    pub fn set_no_delay(&self, no_delay: bool) -> ConnectionResult {
+        let ipc_reply = self.ipc_reply.lock().clone();
+        let set_no_delay_task = Operation::SetNoDelay {
+            socket_fd: self.socket_fd,
+            no_delay,
+            ipc_reply: ipc_reply.clone(),
+        };
+
+        log::debug!("[Socket {}] SetNoDelay request queued", self.socket_fd);
+
+        let result = ipc_reply.queue_and_wait(set_no_delay_task)?;
+        self.no_delay.store(no_delay, Ordering::Release);
+        Ok(result)
+    }
+
+    pub fn get_no_delay(&self) -> bool {
+        self.no_delay.load(Ordering::Acquire)
+    }
+
+    // smoltcp probes at a single interval, so the connection is probed
+    // every `idle` seconds once idle. TCP_KEEPINTVL is only reported back.
+    fn apply_keep_alive(&self, enabled: bool, idle: u32) -> ConnectionResult {
+        // Other sockets just keep the option, like Linux does
+        if self.socket_type != SocketType::SockStream {
+            return Ok(0);
+        }
+        let ipc_reply = self.ipc_reply.lock().clone();
+        let set_keep_alive_task = Operation::SetKeepAlive {
+            socket_fd: self.socket_fd,
+            interval: enabled.then(|| Duration::from_secs(idle as u64)),
+            ipc_reply: ipc_reply.clone(),
+        };
+
+        log::debug!("[Socket {}] SetKeepAlive request queued", self.socket_fd);
+
+        ipc_reply.queue_and_wait(set_keep_alive_task)
+    }
+
+    // Set keep alive : ref to libc::SO_KEEPALIVE
+    pub fn set_keep_alive(&self, enabled: bool) -> ConnectionResult {
+        let result = self.apply_keep_alive(enabled, self.get_keep_idle())?;
+        self.keep_alive.store(enabled, Ordering::Release);
+        Ok(result)
+    }
+
+    pub fn get_keep_alive(&self) -> bool {
+        self.keep_alive.load(Ordering::Acquire)
+    }
+
+    // Set keep idle : ref to libc::TCP_KEEPIDLE
+    pub fn set_keep_idle(&self, secs: u32) -> ConnectionResult {
+        let result = self.apply_keep_alive(self.get_keep_alive(), secs)?;
+        self.keep_idle.store(secs, Ordering::Release);
+        Ok(result)
+    }
+
+    pub fn get_keep_idle(&self) -> u32 {
+        self.keep_idle.load(Ordering::Acquire)
+    }
+
+    // Set keep interval : ref to libc::TCP_KEEPINTVL
+    pub fn set_keep_intvl(&self, secs: u32) {
+        self.keep_intvl.store(secs, Ordering::Release);
+    }
+
+    pub fn get_keep_intvl(&self) -> u32 {
+        self.keep_intvl.load(Ordering::Acquire)
+    }
+
+    fn port_reuse(&self) -> PortReuse {
+        PortReuse {
+            reuse_addr: self.get_reuse_addr(),
+            reuse_port: self.get_reuse_port(),
+        }
     }
 
     // Get recv timeout : ref to libc::SO_RCVTIMEO
@@ -383,11 +665,23 @@ impl Connection {
         if let Some(posix_socket) = network_manager.borrow_mut().get_posix_socket(socket_fd) {
             if posix_socket.borrow().is_shutdown() {
                 log::debug!("Socket {} already shutdown", socket_fd);
+                if ipc_reply.begin_consume() {
+                    ipc_reply.wakeup_client(
+                        Err(SocketError::InvalidState("Socket is shutdown".into())),
+                        socket_fd,
+                    );
+                }
                 return;
             }
 
-            if let Some(result) = f(posix_socket) {
-                ipc_reply.wakeup_client(result, socket_fd);
+            if !ipc_reply.begin_consume() {
+                log::debug!("Socket {} request is cancelled by client", socket_fd);
+                return;
+            }
+
+            match f(posix_socket) {
+                Some(result) => ipc_reply.wakeup_client(result, socket_fd),
+                None => ipc_reply.end_consume(),
             }
         } else {
             ipc_reply.wakeup_client(Err(SocketError::InvalidSocketFd(socket_fd)), socket_fd);
@@ -457,7 +751,22 @@ impl Connection {
                         |posix_socket| {
                             let mut posix_socket = posix_socket.borrow_mut();
 
-                            Some(posix_socket.connect(remote_endpoint, local_port, is_nonblocking))
+                            let result = posix_socket.connect(
+                                remote_endpoint,
+                                local_port,
+                                is_nonblocking,
+                                ipc_reply.clone(),
+                            );
+
+                            if let Err(SocketError::WouldBlock) = result.as_ref() {
+                                log::debug!(
+                                    "[Connection] handle Connect socket_fd={} , wait for handshake",
+                                    socket_fd,
+                                );
+                                None
+                            } else {
+                                Some(result)
+                            }
                         },
                     );
                 }
@@ -478,6 +787,27 @@ impl Connection {
                         },
                     );
                 }
+                Operation::ShutdownDirection {
+                    socket_fd,
+                    read,
+                    write,
+                    ipc_reply,
+                } => {
+                    log::debug!(
+                        "[Connection] handle ShutdownDirection socket_fd={}",
+                        socket_fd
+                    );
+
+                    Connection::with_posix_socket(
+                        network_manager.clone(),
+                        socket_fd,
+                        ipc_reply.clone(),
+                        |posix_socket| {
+                            let mut posix_socket = posix_socket.borrow_mut();
+                            Some(posix_socket.shutdown_direction(read, write))
+                        },
+                    );
+                }
                 Operation::Send {
                     socket_fd,
                     f,
@@ -673,6 +1003,138 @@ impl Connection {
                         },
                     );
                 }
+                Operation::GetSockName {
+                    socket_fd,
+                    f,
+                    ipc_reply,
+                } => {
+                    log::debug!("[Connection] handle GetSockName socket_fd={}", socket_fd);
+
+                    Connection::with_posix_socket(
+                        network_manager.clone(),
+                        socket_fd,
+                        ipc_reply.clone(),
+                        |posix_socket| {
+                            let mut posix_socket = posix_socket.borrow_mut();
+                            Some(posix_socket.getsockname(f))
+                        },
+                    );
+                }
+                Operation::GetPeerName {
+                    socket_fd,
+                    f,
+                    ipc_reply,
+                } => {
+                    log::debug!("[Connection] handle GetPeerName socket_fd={}", socket_fd);
+
+                    Connection::with_posix_socket(
+                        network_manager.clone(),
+                        socket_fd,
+                        ipc_reply.clone(),
+                        |posix_socket| {
+                            let mut posix_socket = posix_socket.borrow_mut();
+                            Some(posix_socket.getpeername(f))
+                        },
+                    );
+                }
+                Operation::SetNoDelay {
+                    socket_fd,
+                    no_delay,
+                    ipc_reply,
+                } => {
+                    log::debug!("[Connection] handle SetNoDelay socket_fd={}", socket_fd);
+
+                    Connection::with_posix_socket(
+                        network_manager.clone(),
+                        socket_fd,
+                        ipc_reply.clone(),
+                        |posix_socket| {
+                            let mut posix_socket = posix_socket.borrow_mut();
+                            Some(posix_socket.set_nagle_enabled(!no_delay))
+                        },
+                    );
+                }
+                Operation::TakeError {
+                    socket_fd,
+                    ipc_reply,
+                } => {
+                    log::debug!("[Connection] handle TakeError socket_fd={}", socket_fd);
+
+                    Connection::with_posix_socket(
+                        network_manager.clone(),
+                        socket_fd,
+                        ipc_reply.clone(),
+                        |posix_socket| {
+                            let mut posix_socket = posix_socket.borrow_mut();
+                            Some(posix_socket.take_error())
+                        },
+                    );
+                }
+                Operation::SetKeepAlive {
+                    socket_fd,
+                    interval,
+                    ipc_reply,
+                } => {
+                    log::debug!("[Connection] handle SetKeepAlive socket_fd={}", socket_fd);
+
+                    Connection::with_posix_socket(
+                        network_manager.clone(),
+                        socket_fd,
+                        ipc_reply.clone(),
+                        |posix_socket| {
+                            let mut posix_socket = posix_socket.borrow_mut();
+                            Some(posix_socket.set_keep_alive(interval))
+                        },
+                    );
+                }
+                Operation::Multicast {
+                    socket_fd,
+                    group,
+                    interface_addr,
+                    join,
+                    ipc_reply,
+                } => {
+                    log::debug!("[Connection] handle Multicast socket_fd={}", socket_fd);
+
+                    let interface = network_manager.borrow().find_interface(interface_addr);
+                    Connection::with_posix_socket(
+                        network_manager.clone(),
+                        socket_fd,
+                        ipc_reply.clone(),
+                        |posix_socket| {
+                            let mut posix_socket = posix_socket.borrow_mut();
+                            let Some(interface) = interface else {
+                                return Some(Err(SocketError::PosixError(
+                                    -libc::ENODEV,
+                                    format!("no interface owns {}", interface_addr),
+                                )));
+                            };
+                            if join {
+                                Some(posix_socket.join_multicast_group(interface, group))
+                            } else {
+                                Some(posix_socket.leave_multicast_group(interface, group))
+                            }
+                        },
+                    );
+                }
+                Operation::Poll {
+                    socket_fd,
+                    events,
+                    poll_queue,
+                    ipc_reply,
+                } => {
+                    log::debug!("[Connection] handle Poll socket_fd={}", socket_fd);
+
+                    Connection::with_posix_socket(
+                        network_manager.clone(),
+                        socket_fd,
+                        ipc_reply.clone(),
+                        |posix_socket| {
+                            let mut posix_socket = posix_socket.borrow_mut();
+                            Some(posix_socket.poll(events, poll_queue))
+                        },
+                    );
+                }
                 Operation::Bind {
                     socket_fd,
                     local_endpoint,
@@ -712,9 +1174,17 @@ impl Connection {
 
 impl Drop for Connection {
     fn drop(&mut self) {
-        // Release local port
+        // Release local port, ports of tcp connections are kept in TIME_WAIT.
+        // Connection states are not tracked here, so any listening or connected
+        // tcp socket is treated as having one.
         if let Some(local_port) = *self.local_endpoint.lock() {
-            let _ = PORT_GENERATOR.release_port(self.socket_type, local_port.port);
+            let had_connection =
+                self.is_listening.load(Ordering::Acquire) || self.remote_endpoint.lock().is_some();
+            if self.socket_type == SocketType::SockStream && had_connection {
+                let _ = PORT_GENERATOR.release_port_to_time_wait(self.socket_type, local_port.port);
+            } else {
+                let _ = PORT_GENERATOR.release_port(self.socket_type, local_port.port);
+            }
         }
     }
 }
@@ -731,6 +1201,10 @@ const IPC_REPLY_TIMEOUT: usize = 1_000;
 const STATE_IDLE: usize = 0;
 const STATE_WAITING_FOR_CONSUME: usize = 1;
 const STATE_AFTER_CONSUME: usize = 2;
+// Network stack is running the request, the client can't cancel it now
+const STATE_CONSUMING: usize = 3;
+// Client gave up waiting, pending requests are dropped by the network stack
+const STATE_CANCELLED: usize = 4;
 
 pub struct OperationIPCReply {
     reply_result: Mutex<Option<OperationResult>>,
@@ -746,6 +1220,10 @@ impl OperationIPCReply {
     }
 
     fn queue_and_wait(&self, task: Operation) -> ConnectionResult {
+        self.queue_and_wait_for(task, None)
+    }
+
+    fn queue_and_wait_for(&self, task: Operation, timeout: Option<Duration>) -> ConnectionResult {
         // Must store before enqueue, our connection suppose to be only one thread can write at one time
         while self.reply_futex.load(Ordering::Acquire) != STATE_IDLE {
             yield_me();
@@ -763,10 +1241,15 @@ impl OperationIPCReply {
             ConnectionError::NetStackQueueFull
         })?;
 
-        self.queue_and_wait_timeout(IPC_REPLY_TIMEOUT)
+        let deadline = timeout.map(|timeout| {
+            let ms = usize::try_from(timeout.as_millis()).unwrap_or(usize::MAX);
+            let ticks = time::tick_from_millisecond(ms);
+            time::get_sys_ticks().saturating_add(core::cmp::max(ticks, 1))
+        });
+        self.queue_and_wait_timeout(IPC_REPLY_TIMEOUT, deadline)
     }
 
-    fn queue_and_wait_timeout(&self, timeout: usize) -> ConnectionResult {
+    fn queue_and_wait_timeout(&self, timeout: usize, deadline: Option<usize>) -> ConnectionResult {
         let t = scheduler::current_thread();
         log::debug!(
             "[Thread ID 0x{:x}] futex::atomic_wait for addr=0x{:x} begin!",
@@ -775,24 +1258,41 @@ impl OperationIPCReply {
         );
 
         // wait for consume
-        if self.reply_futex.load(Ordering::Acquire) == STATE_WAITING_FOR_CONSUME {
-            // TODO add timeout
-            if let Err(e) = futex::atomic_wait(&self.reply_futex, STATE_WAITING_FOR_CONSUME, None) {
-                match e {
-                    code::EAGAIN => {
-                        // task finish before wait , don't need to wait anymore, continue
-                        log::error!("Unknown error from EAGAIN");
-                    }
-                    code::ETIMEDOUT => {
-                        // TODO futex wait timeout
-                        log::error!("Unknown error from ETIMEDOUT");
-                    }
-                    _ => {
-                        log::error!("Unknown error from futex::atomic_wait");
-                        // unknown state, user may try again , restore state
-                        self.reply_futex.store(STATE_IDLE, Ordering::Release);
-                        return Err(ConnectionError::PosixError(code::EINTR));
+        loop {
+            let state = self.reply_futex.load(Ordering::Acquire);
+            if (state != STATE_WAITING_FOR_CONSUME && state != STATE_CONSUMING)
+                || self.reply_result.lock().is_some()
+            {
+                break;
+            }
+
+            let ticks = match deadline {
+                Some(deadline) => {
+                    let now = time::get_sys_ticks();
+                    if now >= deadline {
+                        if self.cancel() {
+                            log::debug!(
+                                "[Thread ID 0x{:x}] request timed out, cancelled",
+                                Thread::id(&t)
+                            );
+                            return Err(ConnectionError::PosixError(code::EAGAIN));
+                        }
+                        // Reply is ready
+                        break;
                     }
+                    Some(deadline - now)
+                }
+                None => None,
+            };
+
+            match futex::atomic_wait(&self.reply_futex, state, ticks) {
+                // woken up, state changed or deadline reached, check again
+                Ok(()) | Err(code::EAGAIN) | Err(code::ETIMEDOUT) => {}
+                Err(_) => {
+                    log::error!("Unknown error from futex::atomic_wait");
+                    // unknown state, user may try again , restore state
+                    self.reply_futex.store(STATE_IDLE, Ordering::Release);
+                    return Err(ConnectionError::PosixError(code::EINTR));
                 }
             }
         }
@@ -809,6 +1309,43 @@ impl OperationIPCReply {
         }
     }
 
+    // Abandon the pending request, fails if the reply is already produced.
+    fn cancel(&self) -> bool {
+        loop {
+            match self.reply_futex.compare_exchange(
+                STATE_WAITING_FOR_CONSUME,
+                STATE_CANCELLED,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => return true,
+                Err(STATE_CONSUMING) => {
+                    // Network stack is running the request, wait for it to finish or block again
+                    let _ = futex::atomic_wait(&self.reply_futex, STATE_CONSUMING, None);
+                }
+                Err(_) => return false,
+            }
+        }
+    }
+
+    fn begin_consume(&self) -> bool {
+        self.reply_futex
+            .compare_exchange(
+                STATE_WAITING_FOR_CONSUME,
+                STATE_CONSUMING,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            )
+            .is_ok()
+    }
+
+    // Request would block, it's requeued by the socket waker later
+    fn end_consume(&self) {
+        self.reply_futex
+            .store(STATE_WAITING_FOR_CONSUME, Ordering::Release);
+        let _ = futex::atomic_wake(&self.reply_futex, 1);
+    }
+
     fn do_wakeup_client(&self, result: OperationResult) {
         self.reply_result.lock().replace(result);
 
@@ -868,6 +1405,14 @@ pub enum Operation {
         ipc_reply: Arc<OperationIPCReply>,
     },
 
+    /// Shut down the receive and/or the send direction, ref to shutdown()
+    ShutdownDirection {
+        socket_fd: SocketFd,
+        read: bool,
+        write: bool,
+        ipc_reply: Arc<OperationIPCReply>,
+    },
+
     /// Send data
     /// only support for connection-mode socket like tcp now
     Send {
@@ -921,6 +1466,57 @@ pub enum Operation {
         local_endpoint: IpListenEndpoint,
         ipc_reply: Arc<OperationIPCReply>,
     },
+
+    /// Local endpoint, ref to getsockname()
+    GetSockName {
+        socket_fd: SocketFd,
+        f: FnEndpoint,
+        ipc_reply: Arc<OperationIPCReply>,
+    },
+
+    /// Remote endpoint, ref to getpeername()
+    GetPeerName {
+        socket_fd: SocketFd,
+        f: FnEndpoint,
+        ipc_reply: Arc<OperationIPCReply>,
+    },
+
+    /// Enable or disable Nagle's algorithm of a TCP socket
+    SetNoDelay {
+        socket_fd: SocketFd,
+        no_delay: bool,
+        ipc_reply: Arc<OperationIPCReply>,
+    },
+
+    /// Take the pending error of a socket
+    TakeError {
+        socket_fd: SocketFd,
+        ipc_reply: Arc<OperationIPCReply>,
+    },
+
+    /// Set the keep-alive interval of a TCP socket, `None` disables it
+    SetKeepAlive {
+        socket_fd: SocketFd,
+        interval: Option<Duration>,
+        ipc_reply: Arc<OperationIPCReply>,
+    },
+
+    /// Join or leave a multicast group on the interface owning `interface_addr`
+    Multicast {
+        socket_fd: SocketFd,
+        group: IpAddress,
+        interface_addr: IpAddress,
+        join: bool,
+        ipc_reply: Arc<OperationIPCReply>,
+    },
+
+    /// Query readiness for poll()
+    Poll {
+        socket_fd: SocketFd,
+        events: PollEvents,
+        poll_queue: Arc<PollQueue>,
+        ipc_reply: Arc<OperationIPCReply>,
+    },
 }
 
 #[cfg(test)]
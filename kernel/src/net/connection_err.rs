@@ -53,6 +53,23 @@ pub enum ConnectionError {
     SocketOperationError(SocketError),
 }
 
+impl ConnectionError {
+    /// The negative errno reported to the caller of the socket API.
+    pub fn to_errno(&self) -> i32 {
+        match self {
+            Self::Timeout(_) => -libc::ETIMEDOUT,
+            Self::NetStackQueueFull => -libc::ENOBUFS,
+            Self::LockFail(_) => -libc::EBUSY,
+            Self::UnsupportedSocketType(_) => -libc::EOPNOTSUPP,
+            Self::NoAvailableDynamicPort => -libc::EADDRNOTAVAIL,
+            Self::PortInUse(_) => -libc::EADDRINUSE,
+            Self::PortOutOfRange(..) => -libc::EINVAL,
+            Self::PosixError(err) => err.to_errno(),
+            Self::SocketOperationError(err) => err.to_errno(),
+        }
+    }
+}
+
 impl From<SocketError> for ConnectionError {
     fn from(err: SocketError) -> Self {
         Self::SocketOperationError(err)
@@ -55,6 +55,16 @@ impl TryFrom<c_int> for SocketDomain {
     }
 }
 
+impl SocketDomain {
+    // Wildcard address of the domain, the name of a socket not bound yet
+    pub fn unspecified_address(self) -> IpAddress {
+        match self {
+            SocketDomain::AfInet => IpAddress::Ipv4(core::net::Ipv4Addr::UNSPECIFIED),
+            SocketDomain::AfInet6 => IpAddress::Ipv6(core::net::Ipv6Addr::UNSPECIFIED),
+        }
+    }
+}
+
 impl From<SocketDomain> for c_int {
     fn from(socket_domain: SocketDomain) -> c_int {
         match socket_domain {
@@ -80,14 +90,14 @@ impl SocketDomain {
         option_len: *mut libc::socklen_t,
     ) -> Result<(), c_int> {
         if option_len.is_null() || option_value.is_null() {
-            return Err(-1);
+            return Err(-libc::EFAULT);
         }
 
         let user_len = unsafe { *option_len };
         let actual_len = core::mem::size_of::<libc::c_int>() as libc::socklen_t;
 
         if user_len < actual_len {
-            return Err(-1);
+            return Err(-libc::EINVAL);
         }
 
         let option_value = option_value as *mut c_int;
@@ -138,14 +148,14 @@ impl SocketType {
         option_len: *mut libc::socklen_t,
     ) -> Result<(), c_int> {
         if option_len.is_null() || option_value.is_null() {
-            return Err(-1);
+            return Err(-libc::EFAULT);
         }
 
         let user_len = unsafe { *option_len };
         let actual_len = core::mem::size_of::<libc::c_int>() as libc::socklen_t;
 
         if user_len < actual_len {
-            return Err(-1);
+            return Err(-libc::EINVAL);
         }
 
         let option_value = option_value as *mut c_int;
@@ -217,14 +227,14 @@ impl SocketProtocol {
         option_len: *mut libc::socklen_t,
     ) -> Result<(), c_int> {
         if option_len.is_null() || option_value.is_null() {
-            return Err(-1);
+            return Err(-libc::EFAULT);
         }
 
         let user_len = unsafe { *option_len };
         let actual_len = core::mem::size_of::<libc::c_int>() as libc::socklen_t;
 
         if user_len < actual_len {
-            return Err(-1);
+            return Err(-libc::EINVAL);
         }
 
         let option_value = option_value as *mut c_int;
@@ -338,6 +348,11 @@ impl Timeval {
             Some(&*(ptr as *const Self))
         }
     }
+
+    /// Neither field is negative and tv_usec is below one second.
+    pub fn is_valid(&self) -> bool {
+        self.tv_sec >= 0 && (0..1_000_000).contains(&self.tv_usec)
+    }
 }
 
 crate::static_assert!(size_of::<Timeval>() == size_of::<libc::timeval>());
@@ -356,7 +371,7 @@ impl From<Duration> for Timeval {
 
 impl From<&Timeval> for Duration {
     fn from(timeval: &Timeval) -> Self {
-        Duration::new(timeval.tv_sec as u64, timeval.tv_usec as u32)
+        Duration::from_secs(timeval.tv_sec as u64) + Duration::from_micros(timeval.tv_usec as u64)
     }
 }
 
@@ -436,15 +451,11 @@ impl SocketMsghdr {
                 continue;
             }
 
-            // copy into iov
-            let buffer_len = iov.iov_len as usize;
-            let copy_len = remaining.len().min(buffer_len);
-
+            // copy into iov, only the part filled by the payload is touched
+            let copy_len = remaining.len().min(iov.iov_len as usize);
             let dst =
-                unsafe { core::slice::from_raw_parts_mut(iov.iov_base.cast::<u8>(), buffer_len) }; // TODO copy_len to buffer_len
-            let (dst_part, src_part) = (&mut dst[..copy_len], &remaining[..copy_len]);
-
-            dst_part.copy_from_slice(src_part);
+                unsafe { core::slice::from_raw_parts_mut(iov.iov_base.cast::<u8>(), copy_len) };
+            dst.copy_from_slice(&remaining[..copy_len]);
 
             remaining = &remaining[copy_len..];
             total_copied += copy_len;
@@ -506,99 +517,63 @@ impl SocketMsghdr {
     }
 
     pub fn parse_icmp_identifier(&self) -> Option<u16> {
-        if self.msg_iovlen == 0 {
-            return None;
-        }
-
-        let first = unsafe { &*self.msg_iov };
-        if first.iov_len < 1 {
-            return None;
-        }
-
-        let icmp_type = unsafe { *(first.iov_base as *const u8) };
+        // Type, code, checksum and identifier, wherever the iovecs split them
+        let mut header = [0u8; 6];
+        let len = Self::gather_to_buffer(self.msg_iov, self.msg_iovlen as usize, &mut header);
 
         // Check ICMP Message Type
-        if !IDENTIFIER_TYPES.contains(&icmp_type) {
+        if len == 0 || !IDENTIFIER_TYPES.contains(&header[0]) {
             return None;
         }
-
-        let mut offset = 0;
-        let mut high_byte = None;
-
-        for i in 0..self.msg_iovlen {
-            let vec = unsafe { &*self.msg_iov.add(i) };
-            let data = unsafe {
-                core::slice::from_raw_parts(vec.iov_base as *const u8, vec.iov_len as usize)
-            };
-
-            if data.is_empty() {
-                continue;
-            }
-
-            let start = 4usize.saturating_sub(offset);
-            let end = 6usize.saturating_sub(offset);
-
-            match () {
-                _ if start < data.len() && end <= data.len() => {
-                    return Some(u16::from_be_bytes([data[start], data[start + 1]]))
-                }
-
-                _ if start < data.len() => high_byte = Some(data[start]),
-
-                _ if !data.is_empty() && high_byte.is_some() => {
-                    return Some(u16::from_be_bytes([high_byte.take().unwrap(), data[0]]))
-                }
-
-                _ => {}
-            }
-
-            offset += data.len();
+        if len < header.len() {
+            return None;
         }
 
-        None
+        Some(u16::from_be_bytes([header[4], header[5]]))
     }
 }
 
+/// Write `endpoint` to a user address buffer of `*socklen_ptr` bytes. The
+/// address is truncated if the buffer is too small, and `*socklen_ptr` is
+/// set to the full length of the address.
 pub fn write_to_sockaddr(
     endpoint: IpEndpoint,
     sockaddr_ptr: *mut libc::sockaddr,
     socklen_ptr: *mut libc::socklen_t,
 ) {
-    if socklen_ptr as usize >= core::mem::size_of::<libc::sockaddr>() {
-        match endpoint.addr {
-            IpAddress::Ipv4(ipv4) => unsafe {
-                let addr_len = core::mem::size_of::<libc::sockaddr_in>();
-                let sockaddr_ptr = sockaddr_ptr.cast::<libc::sockaddr_in>();
-                // addr
-                (*sockaddr_ptr).sin_len = addr_len as u8;
-                (*sockaddr_ptr).sin_family = libc::AF_INET as libc::sa_family_t;
-                (*sockaddr_ptr).sin_port = u16::from_be(endpoint.port);
-                (*sockaddr_ptr).sin_addr.s_addr = u32::from_ne_bytes(ipv4.octets());
-                let addr_ptr = sockaddr_ptr
-                    .cast::<u8>()
-                    .add(core::mem::offset_of!(libc::sockaddr_in, sin_zero));
-                core::ptr::write_bytes(addr_ptr, 0, 6);
-
-                // addr len
-                *socklen_ptr = addr_len as libc::socklen_t;
-            },
-            IpAddress::Ipv6(ipv6) => unsafe {
-                let addr_len = core::mem::size_of::<libc::sockaddr_in6>();
-                let sockaddr_ptr = sockaddr_ptr.cast::<libc::sockaddr_in6>();
-
-                // addr
-                (*sockaddr_ptr).sin6_len = addr_len as u8;
-                (*sockaddr_ptr).sin6_family = libc::AF_INET6 as libc::sa_family_t;
-                (*sockaddr_ptr).sin6_port = u16::from_be(endpoint.port);
-                (*sockaddr_ptr).sin6_flowinfo = 0;
-                (*sockaddr_ptr).sin6_addr.s6_addr = ipv6.octets();
-                (*sockaddr_ptr).sin6_vport = 0;
-                (*sockaddr_ptr).sin6_scope_id = 0;
-
-                // addr len
-                *socklen_ptr = addr_len as libc::socklen_t;
-            },
+    if sockaddr_ptr.is_null() || socklen_ptr.is_null() {
+        return;
+    }
+    // Large enough for both families
+    let mut storage: libc::sockaddr_in6 = unsafe { core::mem::zeroed() };
+    let addr_len = match endpoint.addr {
+        IpAddress::Ipv4(ipv4) => {
+            let addr_len = core::mem::size_of::<libc::sockaddr_in>();
+            let addr = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+            addr.sin_len = addr_len as u8;
+            addr.sin_family = libc::AF_INET as libc::sa_family_t;
+            addr.sin_port = u16::from_be(endpoint.port);
+            addr.sin_addr.s_addr = u32::from_ne_bytes(ipv4.octets());
+            addr_len
         }
+        IpAddress::Ipv6(ipv6) => {
+            let addr_len = core::mem::size_of::<libc::sockaddr_in6>();
+            storage.sin6_len = addr_len as u8;
+            storage.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            storage.sin6_port = u16::from_be(endpoint.port);
+            storage.sin6_addr.s6_addr = ipv6.octets();
+            addr_len
+        }
+    };
+
+    unsafe {
+        let copy_len = core::cmp::min(*socklen_ptr as usize, addr_len);
+        core::ptr::copy_nonoverlapping(
+            &storage as *const _ as *const u8,
+            sockaddr_ptr.cast::<u8>(),
+            copy_len,
+        );
+        *socklen_ptr = addr_len as libc::socklen_t;
     }
 }
 
@@ -606,6 +581,7 @@ pub fn write_to_sockaddr(
 mod tests {
     use super::*;
     use blueos_test_macro::test;
+    use connection_err::ConnectionError;
     use core::mem::size_of;
 
     #[test]
@@ -621,6 +597,36 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_timeval_is_valid() {
+        let timeval = |tv_sec, tv_usec| Timeval { tv_sec, tv_usec };
+        assert!(timeval(0, 0).is_valid());
+        assert!(timeval(5, 999_999).is_valid());
+        assert!(!timeval(-1, 0).is_valid());
+        assert!(!timeval(0, -1).is_valid());
+        assert!(!timeval(0, 1_000_000).is_valid());
+    }
+
+    #[test]
+    fn test_connection_error_errno() {
+        assert_eq!(
+            ConnectionError::PosixError(crate::error::code::EAGAIN).to_errno(),
+            -libc::EAGAIN
+        );
+        assert_eq!(
+            ConnectionError::from(SocketError::InvalidHandle).to_errno(),
+            -libc::ENOTCONN
+        );
+        assert_eq!(
+            ConnectionError::from(SocketError::PosixError(-libc::EPIPE, "".into())).to_errno(),
+            -libc::EPIPE
+        );
+        assert_eq!(
+            ConnectionError::NetStackQueueFull.to_errno(),
+            -libc::ENOBUFS
+        );
+    }
+
     #[test]
     fn try_from_invalid_value() {
         assert!(matches!(
@@ -665,7 +671,7 @@ mod tests {
 
         let result = domain.write_to_ptr(core::ptr::null_mut(), &mut len as *mut libc::socklen_t);
 
-        assert_eq!(result, Err(-1));
+        assert_eq!(result, Err(-libc::EFAULT));
     }
 
     #[test]
@@ -679,6 +685,101 @@ mod tests {
             &mut insufficient_len as *mut libc::socklen_t,
         );
 
-        assert_eq!(result, Err(-1));
+        assert_eq!(result, Err(-libc::EINVAL));
+    }
+
+    fn msghdr_of(iov: &mut [libc::iovec]) -> SocketMsghdr {
+        SocketMsghdr {
+            msg_name: core::ptr::null_mut(),
+            msg_namelen: 0,
+            msg_iov: iov.as_mut_ptr(),
+            msg_iovlen: iov.len() as libc::size_t,
+            msg_control: core::ptr::null_mut(),
+            msg_controllen: 0,
+            msg_flags: 0,
+        }
+    }
+
+    // Parse the identifier of `packet` split into iovecs at `splits`.
+    fn parse_split(packet: &mut [u8], splits: &[usize]) -> Option<u16> {
+        let base = packet.as_mut_ptr();
+        let mut iov = alloc::vec::Vec::new();
+        let mut start = 0;
+        for &end in splits.iter().chain(core::iter::once(&packet.len())) {
+            iov.push(libc::iovec {
+                iov_base: unsafe { base.add(start) } as *mut c_void,
+                iov_len: end - start,
+            });
+            start = end;
+        }
+        msghdr_of(&mut iov).parse_icmp_identifier()
+    }
+
+    #[test]
+    fn scatter_from_buffer_short_payload() {
+        let mut first = [0xffu8; 8];
+        let mut second = [0xffu8; 8];
+        let mut iov = [
+            libc::iovec {
+                iov_base: first.as_mut_ptr() as *mut c_void,
+                iov_len: first.len(),
+            },
+            libc::iovec {
+                iov_base: second.as_mut_ptr() as *mut c_void,
+                iov_len: second.len(),
+            },
+        ];
+        let mut msghdr = msghdr_of(&mut iov);
+        assert_eq!(msghdr.scatter_from_buffer(b"abc"), 3);
+        assert_eq!(&first[..4], b"abc\xff");
+        assert_eq!(second, [0xffu8; 8]);
+        assert_eq!(msghdr_of(&mut iov).scatter_from_buffer(b""), 0);
+    }
+
+    #[test]
+    fn scatter_from_buffer_null_iovec() {
+        let mut first = [0u8; 2];
+        let mut last = [0u8; 4];
+        let mut iov = [
+            libc::iovec {
+                iov_base: first.as_mut_ptr() as *mut c_void,
+                iov_len: first.len(),
+            },
+            libc::iovec {
+                iov_base: core::ptr::null_mut(),
+                iov_len: 16,
+            },
+            libc::iovec {
+                iov_base: last.as_mut_ptr() as *mut c_void,
+                iov_len: last.len(),
+            },
+        ];
+        assert_eq!(msghdr_of(&mut iov).scatter_from_buffer(b"abcdefgh"), 6);
+        assert_eq!(&first, b"ab");
+        assert_eq!(&last, b"cdef");
+    }
+
+    #[test]
+    fn parse_icmp_identifier_split() {
+        // Echo request, identifier 0x1234, sequence 1
+        let mut packet = [8u8, 0, 0, 0, 0x12, 0x34, 0, 1];
+        assert_eq!(parse_split(&mut packet, &[]), Some(0x1234));
+        assert_eq!(parse_split(&mut packet, &[4]), Some(0x1234));
+        assert_eq!(parse_split(&mut packet, &[5]), Some(0x1234));
+        assert_eq!(parse_split(&mut packet, &[6]), Some(0x1234));
+        // Three tiny iovecs, one of them empty
+        assert_eq!(parse_split(&mut packet, &[3, 3, 5]), Some(0x1234));
+        assert_eq!(parse_split(&mut packet, &[1, 4, 5]), Some(0x1234));
+    }
+
+    #[test]
+    fn parse_icmp_identifier_invalid() {
+        // Destination unreachable has no identifier
+        let mut packet = [3u8, 0, 0, 0, 0x12, 0x34, 0, 1];
+        assert_eq!(parse_split(&mut packet, &[5]), None);
+        // Too short
+        let mut packet = [8u8, 0, 0, 0, 0x12];
+        assert_eq!(parse_split(&mut packet, &[4]), None);
+        assert_eq!(msghdr_of(&mut []).parse_icmp_identifier(), None);
     }
 }
@@ -17,9 +17,9 @@ use core::{
     fmt::{self, Display},
 };
 
-use alloc::{rc::Rc, string::String};
+use alloc::{rc::Rc, string::String, vec::Vec};
 use smoltcp::{
-    iface::{Interface, PollResult, SocketHandle, SocketSet},
+    iface::{Interface, MulticastError, PollResult, SocketHandle, SocketSet},
     phy::Loopback,
     socket::AnySocket,
     time::{Duration, Instant},
@@ -43,6 +43,8 @@ pub struct NetInterface<'a> {
     smoltcp_device: Rc<RefCell<NetDevice>>,
     smoltcp_interface: Rc<RefCell<Interface>>,
     smoltcp_socket_sets: Rc<RefCell<SocketSet<'a>>>,
+    // Joined multicast groups and the number of sockets in each
+    multicast_groups: Vec<(IpAddress, usize)>,
 }
 
 impl<'a> NetInterface<'a> {
@@ -57,6 +59,7 @@ impl<'a> NetInterface<'a> {
             smoltcp_device: smoltcp_enum_device,
             smoltcp_interface: interface,
             smoltcp_socket_sets: socket_sets,
+            multicast_groups: Vec::new(),
         }
     }
 
@@ -91,6 +94,34 @@ impl<'a> NetInterface<'a> {
             .any(|cidr| cidr.contains_addr(&remote_addr))
     }
 
+    /// Sockets share the membership of the interface, it's only left
+    /// after the last socket in the group leaves.
+    pub fn join_multicast_group(&mut self, group: IpAddress) -> Result<(), MulticastError> {
+        if let Some((_, count)) = self.multicast_groups.iter_mut().find(|(g, _)| *g == group) {
+            *count += 1;
+            return Ok(());
+        }
+        self.smoltcp_interface
+            .borrow_mut()
+            .join_multicast_group(group)?;
+        self.multicast_groups.push((group, 1));
+        Ok(())
+    }
+
+    pub fn leave_multicast_group(&mut self, group: IpAddress) -> Result<(), MulticastError> {
+        let Some(index) = self.multicast_groups.iter().position(|(g, _)| *g == group) else {
+            return Ok(());
+        };
+        self.multicast_groups[index].1 -= 1;
+        if self.multicast_groups[index].1 == 0 {
+            self.multicast_groups.remove(index);
+            self.smoltcp_interface
+                .borrow_mut()
+                .leave_multicast_group(group)?;
+        }
+        Ok(())
+    }
+
     pub fn poll(&mut self, timestamp: Instant) -> PollResult {
         match &mut *self.smoltcp_device.borrow_mut() {
             NetDevice::Loopback(loopback) => self.smoltcp_interface.borrow_mut().poll(
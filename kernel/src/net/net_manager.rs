@@ -149,6 +149,18 @@ where
         }
     }
 
+    /// Find the interface owning `addr`, an unspecified address means the
+    /// default interface.
+    pub fn find_interface(&self, addr: IpAddress) -> Option<Rc<RefCell<NetInterface<'a>>>> {
+        if addr.is_unspecified() {
+            return self.default_interface.clone();
+        }
+        self.net_interfaces
+            .iter()
+            .find(|dev| dev.borrow().contains_addr(addr))
+            .cloned()
+    }
+
     pub fn bind_smoltcp_interface(&self, socket_fd: SocketFd, binding_addr: IpAddress) {
         if let Some(socket) = self.socket_maps.get(&socket_fd) {
             self.net_interfaces
@@ -302,6 +314,7 @@ static mut NETWORK_STACK: NetworkStack = NetworkStack {
 
 pub(crate) fn init() {
     let t = ThreadBuilder::new(Entry::C(net_stack_main_loop))
+        .set_name("net_stack")
         .set_stack(Stack::Raw {
             base: unsafe { NETWORK_STACK.rep.as_ptr() } as usize,
             size: NETWORK_STACK_SIZE,
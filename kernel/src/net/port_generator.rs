@@ -14,11 +14,14 @@
 
 //! port_generator.rs
 //! A port generator , manage dynamic ports
-use alloc::collections::BTreeSet;
+use alloc::collections::BTreeMap;
 use core::sync::atomic::{AtomicU16, Ordering};
 use spin::Mutex;
 
-use crate::net::{connection_err::ConnectionError, SocketType};
+use crate::{
+    net::{connection_err::ConnectionError, SocketType},
+    time,
+};
 
 const SYSTEM_PORT_MIN: u16 = 0;
 const SYSTEM_PORT_MAX: u16 = 1023;
@@ -26,6 +29,41 @@ const USER_PORT_MIN: u16 = 1024;
 const USER_PORT_MAX: u16 = 49151;
 const EPHEMERAL_PORT_MIN: u16 = 49152;
 const EPHEMERAL_PORT_MAX: u16 = 65535;
+// 2 * MSL, a closed tcp port can't be bound again without SO_REUSEADDR in the meantime
+const TIME_WAIT_MS: usize = 60_000;
+
+/// Address reuse options of the socket asking for a port, ref to
+/// libc::SO_REUSEADDR and libc::SO_REUSEPORT
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PortReuse {
+    pub reuse_addr: bool,
+    pub reuse_port: bool,
+}
+
+struct PortEntry {
+    // Sockets bound to the port, more than one only if all of them set SO_REUSEPORT
+    users: usize,
+    reuse_port: bool,
+    // Set once the last user is gone and the port is in TIME_WAIT
+    time_wait_deadline: Option<usize>,
+}
+
+impl PortEntry {
+    fn new(reuse: PortReuse) -> Self {
+        Self {
+            users: 1,
+            reuse_port: reuse.reuse_port,
+            time_wait_deadline: None,
+        }
+    }
+
+    fn is_free(&self) -> bool {
+        match self.time_wait_deadline {
+            Some(deadline) => self.users == 0 && time::get_sys_ticks() >= deadline,
+            None => self.users == 0,
+        }
+    }
+}
 
 // A simple local port generator with port allocation and release
 // Port Number Ranges
@@ -36,7 +74,9 @@ const EPHEMERAL_PORT_MAX: u16 = 65535;
 // Dynamic port range as defined in RFC6335
 pub struct PortGenerator {
     ephemeral_counter: AtomicU16,
-    allocated_ports: Mutex<BTreeSet<(u16, SocketType)>>,
+    // Ports handed out for port 0, [lo, hi]
+    ephemeral_range: Mutex<(u16, u16)>,
+    allocated_ports: Mutex<BTreeMap<(u16, SocketType), PortEntry>>,
 }
 
 /// Initialize the global port generator
@@ -46,21 +86,53 @@ impl PortGenerator {
     pub const fn new() -> Self {
         PortGenerator {
             ephemeral_counter: AtomicU16::new(EPHEMERAL_PORT_MIN),
-            allocated_ports: Mutex::new(BTreeSet::new()),
+            ephemeral_range: Mutex::new((EPHEMERAL_PORT_MIN, EPHEMERAL_PORT_MAX)),
+            allocated_ports: Mutex::new(BTreeMap::new()),
         }
     }
 
+    /// Sets the range `[lo, hi]` ephemeral ports are allocated from,
+    /// ports already allocated are not affected
+    pub fn set_ephemeral_range(&self, lo: u16, hi: u16) -> Result<(), ConnectionError> {
+        if lo == 0 || lo > hi {
+            return Err(ConnectionError::PortOutOfRange(lo, "invalid range".into()));
+        }
+        *self.ephemeral_range.lock() = (lo, hi);
+        self.ephemeral_counter.store(lo, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn ephemeral_range(&self) -> (u16, u16) {
+        *self.ephemeral_range.lock()
+    }
+
+    /// Allocates an ephemeral port, None if all ports of the range are in use
+    pub fn try_alloc(&self, socket_type: SocketType) -> Option<u16> {
+        self.allocate_ephemeral_port(socket_type, PortReuse::default())
+            .ok()
+    }
+
     /// Acquires port for the specified protocol
     pub fn acquire_port(
         &self,
         socket_type: SocketType,
         requested_port: u16,
+    ) -> Result<u16, ConnectionError> {
+        self.acquire_port_with_reuse(socket_type, requested_port, PortReuse::default())
+    }
+
+    /// Acquires port for the specified protocol, honoring the address reuse options
+    pub fn acquire_port_with_reuse(
+        &self,
+        socket_type: SocketType,
+        requested_port: u16,
+        reuse: PortReuse,
     ) -> Result<u16, ConnectionError> {
         if requested_port == 0 {
             // allocate from dynamic port range
-            self.allocate_ephemeral_port(socket_type)
+            self.allocate_ephemeral_port(socket_type, reuse)
         } else {
-            self.allocate_specific_port(socket_type, requested_port)
+            self.allocate_specific_port(socket_type, requested_port, reuse)
         }
     }
 
@@ -69,6 +141,7 @@ impl PortGenerator {
         &self,
         socket_type: SocketType,
         requested_port: u16,
+        reuse: PortReuse,
     ) -> Result<u16, ConnectionError> {
         if (SYSTEM_PORT_MIN..=SYSTEM_PORT_MAX).contains(&requested_port) {
             log::warn!("Warning: acquiring a system port");
@@ -85,28 +158,54 @@ impl PortGenerator {
         }
 
         let mut ports = self.allocated_ports.lock();
-        if ports.insert((requested_port, socket_type)) {
-            Ok(requested_port)
-        } else {
-            Err(ConnectionError::PortInUse(requested_port))
+        let Some(entry) = ports.get_mut(&(requested_port, socket_type)) else {
+            ports.insert((requested_port, socket_type), PortEntry::new(reuse));
+            return Ok(requested_port);
+        };
+
+        // SO_REUSEADDR allows to bind a port still in TIME_WAIT
+        if entry.is_free() || (entry.users == 0 && reuse.reuse_addr) {
+            *entry = PortEntry::new(reuse);
+            return Ok(requested_port);
         }
+
+        // SO_REUSEPORT allows to share a port if every socket on it sets it
+        if entry.users > 0 && entry.reuse_port && reuse.reuse_port {
+            entry.users += 1;
+            return Ok(requested_port);
+        }
+
+        Err(ConnectionError::PortInUse(requested_port))
     }
 
-    // Allocates an ephemeral port using RFC6335 dynamic port range
-    fn allocate_ephemeral_port(&self, socket_type: SocketType) -> Result<u16, ConnectionError> {
+    // Allocates an ephemeral port from the configured range, which is the
+    // RFC6335 dynamic port range by default. Ports bound explicitly are skipped.
+    fn allocate_ephemeral_port(
+        &self,
+        socket_type: SocketType,
+        reuse: PortReuse,
+    ) -> Result<u16, ConnectionError> {
+        let (lo, hi) = self.ephemeral_range();
         let mut ports = self.allocated_ports.lock();
 
         // Linear scan through ephemeral range (RFC6335 section 4.2)
-        for _ in EPHEMERAL_PORT_MIN..=EPHEMERAL_PORT_MAX {
+        for _ in lo..=hi {
             let candidate = self
                 .ephemeral_counter
                 .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |prev| {
-                    Some(next_ephemeral(prev))
+                    Some(next_ephemeral(prev, lo, hi))
                 })
                 .expect("Atomic port counter should never fail");
+            // The range may have changed since the counter was updated
+            if !(lo..=hi).contains(&candidate) {
+                continue;
+            }
 
-            if !ports.contains(&(candidate, socket_type)) {
-                ports.insert((candidate, socket_type));
+            let is_free = ports
+                .get(&(candidate, socket_type))
+                .map_or(true, |entry| entry.is_free());
+            if is_free {
+                ports.insert((candidate, socket_type), PortEntry::new(reuse));
                 return Ok(candidate);
             }
         }
@@ -115,15 +214,40 @@ impl PortGenerator {
     }
 
     pub fn release_port(&self, socket_type: SocketType, port: u16) -> bool {
+        self.release(socket_type, port, false)
+    }
+
+    /// Releases a port of a closed tcp connection, it's kept in TIME_WAIT
+    /// once the last socket on it is gone
+    pub fn release_port_to_time_wait(&self, socket_type: SocketType, port: u16) -> bool {
+        self.release(socket_type, port, true)
+    }
+
+    fn release(&self, socket_type: SocketType, port: u16, time_wait: bool) -> bool {
         let mut ports = self.allocated_ports.lock();
-        ports.remove(&(port, socket_type))
+        let Some(entry) = ports.get_mut(&(port, socket_type)) else {
+            return false;
+        };
+        if entry.users == 0 {
+            return false;
+        }
+        entry.users -= 1;
+        if entry.users == 0 {
+            if time_wait {
+                entry.time_wait_deadline =
+                    Some(time::get_sys_ticks() + time::tick_from_millisecond(TIME_WAIT_MS));
+            } else {
+                ports.remove(&(port, socket_type));
+            }
+        }
+        true
     }
 }
 
 // Circular increment within ephemeral port range
-fn next_ephemeral(current: u16) -> u16 {
-    if current == EPHEMERAL_PORT_MAX {
-        EPHEMERAL_PORT_MIN
+fn next_ephemeral(current: u16, lo: u16, hi: u16) -> u16 {
+    if current >= hi || current < lo {
+        lo
     } else {
         current + 1
     }
@@ -150,4 +274,72 @@ mod tests {
             .acquire_port(SocketType::SockStream, specific_port)
             .is_ok());
     }
+
+    #[test]
+    fn test_port_reuse() {
+        let port_gen = PortGenerator::new();
+        let port = 8081;
+        let reuse_addr = PortReuse {
+            reuse_addr: true,
+            reuse_port: false,
+        };
+        let reuse_port = PortReuse {
+            reuse_addr: false,
+            reuse_port: true,
+        };
+        port_gen.acquire_port(SocketType::SockStream, port).unwrap();
+        assert!(port_gen.release_port_to_time_wait(SocketType::SockStream, port));
+        // TIME_WAIT
+        assert!(port_gen.acquire_port(SocketType::SockStream, port).is_err());
+        assert!(port_gen
+            .acquire_port_with_reuse(SocketType::SockStream, port, reuse_port)
+            .is_err());
+        assert_eq!(
+            port_gen.acquire_port_with_reuse(SocketType::SockStream, port, reuse_addr),
+            Ok(port)
+        );
+        // In use, not in TIME_WAIT
+        assert!(port_gen
+            .acquire_port_with_reuse(SocketType::SockStream, port, reuse_addr)
+            .is_err());
+        assert!(port_gen.release_port(SocketType::SockStream, port));
+
+        port_gen
+            .acquire_port_with_reuse(SocketType::SockDgram, port, reuse_port)
+            .unwrap();
+        assert!(port_gen
+            .acquire_port_with_reuse(SocketType::SockDgram, port, reuse_port)
+            .is_ok());
+        assert!(port_gen.acquire_port(SocketType::SockDgram, port).is_err());
+        assert!(port_gen.release_port(SocketType::SockDgram, port));
+        assert!(port_gen.release_port(SocketType::SockDgram, port));
+        assert!(!port_gen.release_port(SocketType::SockDgram, port));
+    }
+
+    #[test]
+    fn test_ephemeral_range_exhausted() {
+        let port_gen = PortGenerator::new();
+        assert!(port_gen.set_ephemeral_range(2002, 2000).is_err());
+        assert!(port_gen.set_ephemeral_range(2000, 2003).is_ok());
+        // Ports bound explicitly are skipped
+        assert_eq!(port_gen.acquire_port(SocketType::SockDgram, 2001), Ok(2001));
+
+        let mut ports = [
+            port_gen.try_alloc(SocketType::SockDgram).unwrap(),
+            port_gen.try_alloc(SocketType::SockDgram).unwrap(),
+            port_gen.try_alloc(SocketType::SockDgram).unwrap(),
+        ];
+        ports.sort();
+        assert_eq!(ports, [2000, 2002, 2003]);
+        assert_eq!(port_gen.try_alloc(SocketType::SockDgram), None);
+        assert_eq!(
+            port_gen.acquire_port(SocketType::SockDgram, 0),
+            Err(ConnectionError::NoAvailableDynamicPort)
+        );
+        // Other protocols have their own ports
+        assert!(port_gen.try_alloc(SocketType::SockStream).is_some());
+
+        assert!(port_gen.release_port(SocketType::SockDgram, 2002));
+        assert_eq!(port_gen.try_alloc(SocketType::SockDgram), Some(2002));
+    }
 }
@@ -12,15 +12,19 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::net::{
-    connection::{Operation, OperationIPCReply},
-    net_interface::NetInterface,
-    net_manager::NetworkManager,
-    socket::{
-        socket_err::SocketError, socket_waker, FnRecv, FnRecvWithEndpoint, FnSend, FnSendMsg,
-        PosixSocket,
+use crate::{
+    net::{
+        connection::{Operation, OperationIPCReply},
+        net_interface::NetInterface,
+        net_manager::NetworkManager,
+        socket::{
+            socket_err::SocketError,
+            socket_waker::{self, SocketWakers},
+            FnRecv, FnRecvWithEndpoint, FnSend, FnSendMsg, PosixSocket,
+        },
+        SocketFd, SocketResult, SocketType,
     },
-    SocketFd, SocketResult, SocketType,
+    vfs::poll::{PollEvents, PollQueue},
 };
 use alloc::{boxed::Box, rc::Rc, sync::Arc, vec};
 use core::{
@@ -32,9 +36,13 @@ use smoltcp::{
     socket::icmp,
     wire::{IpEndpoint, IpListenEndpoint},
 };
+const ICMP_RX_PACKETS: usize = 4;
+
 pub struct IcmpSocket<'a> {
     socket_fd: SocketFd,
     is_shutdown: Rc<Cell<bool>>,
+    recv_wakers: SocketWakers,
+    send_wakers: SocketWakers,
     network_manager: Rc<RefCell<NetworkManager<'a>>>,
     smoltcp_socket_handle: Option<SocketHandle>,
     smoltcp_interface: Option<Rc<RefCell<NetInterface<'a>>>>,
@@ -49,6 +57,8 @@ where
         Self {
             socket_fd,
             is_shutdown: Rc::new(is_shutdown),
+            recv_wakers: SocketWakers::default(),
+            send_wakers: SocketWakers::default(),
             network_manager,
             smoltcp_socket_handle: None,
             smoltcp_interface: None,
@@ -66,10 +76,13 @@ where
             None => return None,
         };
 
-        // Create smoltcp icmp::socket
+        // Create smoltcp icmp::socket, the rx buffer holds a few packets since
+        // echo requests to a local address come back along with their replies.
         let icmp_socket = {
-            let icmp_rx_buffer =
-                icmp::PacketBuffer::new(vec![icmp::PacketMetadata::EMPTY], vec![0; 256]);
+            let icmp_rx_buffer = icmp::PacketBuffer::new(
+                vec![icmp::PacketMetadata::EMPTY; ICMP_RX_PACKETS],
+                vec![0; ICMP_RX_PACKETS * 256],
+            );
             let icmp_tx_buffer =
                 icmp::PacketBuffer::new(vec![icmp::PacketMetadata::EMPTY], vec![0; 256]);
             icmp::Socket::new(icmp_rx_buffer, icmp_tx_buffer)
@@ -142,6 +155,7 @@ impl PosixSocket for IcmpSocket<'static> {
         _remote_endpoint: IpEndpoint,
         _local_port: u16,
         _is_nonblocking: bool,
+        _ipc_reply: Arc<OperationIPCReply>,
     ) -> SocketResult {
         Err(SocketError::UnsupportedSocketTypeForOperation(
             SocketType::SockRaw,
@@ -203,6 +217,7 @@ impl PosixSocket for IcmpSocket<'static> {
 
         let socket_fd = self.socket_fd;
         let is_shutdown = self.is_shutdown.clone();
+        let send_wakers = self.send_wakers.clone();
         self.with(|socket, _| {
             if !socket.is_open() {
                 match identifer {
@@ -243,7 +258,7 @@ impl PosixSocket for IcmpSocket<'static> {
                             socket_operation,
                             is_shutdown,
                         );
-                        socket.register_send_waker(&waker);
+                        socket.register_send_waker(&send_wakers.register_operation(waker));
                         log::debug!(
                             "icmp socket not ready for send_queue={:?}",
                             socket.send_queue()
@@ -283,6 +298,7 @@ impl PosixSocket for IcmpSocket<'static> {
     ) -> SocketResult {
         let socket_fd = self.socket_fd;
         let is_shutdown = self.is_shutdown.clone();
+        let recv_wakers = self.recv_wakers.clone();
         self.with(|socket, _| {
             match socket.can_recv() {
                 true => socket
@@ -303,7 +319,7 @@ impl PosixSocket for IcmpSocket<'static> {
                             socket_operation,
                             is_shutdown,
                         );
-                        socket.register_recv_waker(&waker);
+                        socket.register_recv_waker(&recv_wakers.register_operation(waker));
                         log::debug!(
                             "no data for icmp recvmsg recv_queue={:?}",
                             socket.recv_queue()
@@ -325,14 +341,11 @@ impl PosixSocket for IcmpSocket<'static> {
 
     fn recvfrom(
         &mut self,
-        _f: FnRecvWithEndpoint,
-        _is_nonblocking: bool,
+        f: FnRecvWithEndpoint,
+        is_nonblocking: bool,
         ipc_reply: Arc<OperationIPCReply>,
     ) -> SocketResult {
-        Err(SocketError::UnsupportedSocketTypeForOperation(
-            SocketType::SockRaw,
-            "use recvmsg() instead".into(),
-        ))
+        self.recvmsg(f, is_nonblocking, ipc_reply)
     }
 
     fn shutdown(&self) -> SocketResult {
@@ -377,4 +390,31 @@ impl PosixSocket for IcmpSocket<'static> {
     fn is_shutdown(&self) -> bool {
         self.is_shutdown.get()
     }
+
+    fn poll(&mut self, events: PollEvents, poll_queue: Arc<PollQueue>) -> SocketResult {
+        // The socket is created on first bind() or sendmsg(), which never blocks
+        if self.smoltcp_socket_handle.is_none() {
+            return Ok(PollEvents::POLLOUT.bits() as usize);
+        }
+
+        let recv_wakers = self.recv_wakers.clone();
+        let send_wakers = self.send_wakers.clone();
+        self.with(|socket, _| {
+            let mut revents = PollEvents::empty();
+            if socket.can_recv() {
+                revents |= PollEvents::POLLIN;
+            }
+            if socket.can_send() {
+                revents |= PollEvents::POLLOUT;
+            }
+
+            if events.contains(PollEvents::POLLIN) {
+                socket.register_recv_waker(&recv_wakers.register_poll(poll_queue.clone()));
+            }
+            if events.contains(PollEvents::POLLOUT) {
+                socket.register_send_waker(&send_wakers.register_poll(poll_queue));
+            }
+            Ok(revents.bits() as usize)
+        })
+    }
 }
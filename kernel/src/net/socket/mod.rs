@@ -14,14 +14,17 @@
 
 use smoltcp::wire::{IpAddress, IpEndpoint, IpListenEndpoint};
 
-use crate::net::{
-    connection::{Operation, OperationIPCReply, OperationResult},
-    net_interface::NetInterface,
-    socket::socket_err::SocketError,
-    SocketResult,
+use crate::{
+    net::{
+        connection::{Operation, OperationIPCReply, OperationResult},
+        net_interface::NetInterface,
+        socket::socket_err::SocketError,
+        SocketResult,
+    },
+    vfs::poll::{PollEvents, PollQueue},
 };
 use alloc::{boxed::Box, rc::Rc, sync::Arc};
-use core::{cell::RefCell, net::SocketAddr};
+use core::{cell::RefCell, net::SocketAddr, time::Duration};
 
 pub mod icmp;
 pub mod socket_err;
@@ -33,6 +36,7 @@ pub(crate) type FnSend = Box<dyn FnOnce(&mut [u8]) -> (usize, usize) + Send>;
 pub(crate) type FnSendMsg = Box<dyn FnOnce(&mut [u8]) -> usize + Send>;
 pub(crate) type FnRecv = Box<dyn FnOnce(&mut [u8]) -> (usize, usize) + Send>;
 pub(crate) type FnRecvWithEndpoint = Box<dyn FnOnce(&[u8], IpEndpoint) -> usize + Send>;
+pub(crate) type FnEndpoint = Box<dyn FnOnce(IpEndpoint) + Send>;
 
 pub trait PosixSocket {
     // smoltcp need to bind socket with interface
@@ -47,6 +51,7 @@ pub trait PosixSocket {
         remote_endpoint: IpEndpoint,
         local_port: u16,
         is_nonblocking: bool,
+        ipc_reply: Arc<OperationIPCReply>,
     ) -> SocketResult;
 
     fn listen(&mut self, local_endpoint: IpListenEndpoint) -> SocketResult;
@@ -100,11 +105,69 @@ pub trait PosixSocket {
         ipc_reply: Arc<OperationIPCReply>,
     ) -> SocketResult;
 
-    fn getsockname(&mut self, f: Box<dyn FnOnce(IpEndpoint) + Send>) -> SocketResult;
+    fn getsockname(&mut self, f: FnEndpoint) -> SocketResult;
+
+    // Fails with ENOTCONN if the socket has no peer.
+    fn getpeername(&mut self, f: FnEndpoint) -> SocketResult;
 
-    fn getpeername(&mut self, f: Box<dyn FnOnce(IpEndpoint) + Send>) -> SocketResult;
+    // Only datagram sockets may join multicast groups.
+    fn join_multicast_group(
+        &mut self,
+        _interface: Rc<RefCell<NetInterface<'static>>>,
+        _group: IpAddress,
+    ) -> SocketResult {
+        Err(SocketError::PosixError(
+            -libc::ENOPROTOOPT,
+            "multicast is only supported on UDP sockets".into(),
+        ))
+    }
+
+    fn leave_multicast_group(
+        &mut self,
+        _interface: Rc<RefCell<NetInterface<'static>>>,
+        _group: IpAddress,
+    ) -> SocketResult {
+        Err(SocketError::PosixError(
+            -libc::ENOPROTOOPT,
+            "multicast is only supported on UDP sockets".into(),
+        ))
+    }
+
+    // Only stream sockets use Nagle's algorithm.
+    fn set_nagle_enabled(&mut self, _enabled: bool) -> SocketResult {
+        Err(SocketError::PosixError(
+            -libc::ENOPROTOOPT,
+            "Nagle's algorithm is only used by TCP sockets".into(),
+        ))
+    }
+
+    // Pending error of the socket, cleared once read : ref to libc::SO_ERROR
+    fn take_error(&mut self) -> SocketResult {
+        Ok(0)
+    }
+
+    // Only stream sockets send keep-alive probes, `None` disables them.
+    fn set_keep_alive(&mut self, _interval: Option<Duration>) -> SocketResult {
+        Err(SocketError::PosixError(
+            -libc::ENOPROTOOPT,
+            "Keep-alive is only used by TCP sockets".into(),
+        ))
+    }
 
     fn shutdown(&self) -> SocketResult;
 
+    // Shut down the receive and/or the send direction, the socket stays
+    // open : ref to posix shutdown()
+    fn shutdown_direction(&mut self, _read: bool, _write: bool) -> SocketResult {
+        Err(SocketError::PosixError(
+            -libc::ENOTCONN,
+            "Only connected TCP sockets can be shut down by direction".into(),
+        ))
+    }
+
     fn is_shutdown(&self) -> bool;
+
+    // Returns the bits of ready `PollEvents`, `poll_queue` is notified once
+    // the socket becomes ready if none of `events` is ready now.
+    fn poll(&mut self, events: PollEvents, poll_queue: Arc<PollQueue>) -> SocketResult;
 }
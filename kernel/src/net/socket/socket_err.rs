@@ -89,6 +89,34 @@ pub enum SocketError {
     SmoltcpIcmpRecvError(smoltcp::socket::icmp::RecvError),
 }
 
+impl SocketError {
+    /// The negative errno reported to the caller of the socket API.
+    pub fn to_errno(&self) -> i32 {
+        match self {
+            Self::TryAgain | Self::WouldBlock => -libc::EAGAIN,
+            Self::PosixError(errno, _) => *errno,
+            Self::InvalidSocketFd(_) => -libc::EBADF,
+            Self::InvalidHandle | Self::SmoltcpTcpRecvError(_) => -libc::ENOTCONN,
+            Self::InvalidState(_)
+            | Self::InvalidParam(..)
+            | Self::SmoltcpTcpListenError(_)
+            | Self::SmoltcpUdpBindError(_)
+            | Self::SmoltcpIcmpBindError(_) => -libc::EINVAL,
+            Self::InterfaceNoAvailable => -libc::ENETUNREACH,
+            Self::UnsupportedSocketTypeForOperation(..) => -libc::EOPNOTSUPP,
+            Self::UnsupportedSocketDomain(_) => -libc::EAFNOSUPPORT,
+            Self::UnsupportedSocketType(_) => -libc::EPROTOTYPE,
+            Self::UnsupportedSocketProtocol(_) => -libc::EPROTONOSUPPORT,
+            Self::CreateSmoltcpSocketFail
+            | Self::SmoltcpUdpSendError(_)
+            | Self::SmoltcpIcmpSendError(_) => -libc::ENOBUFS,
+            Self::SmoltcpTcpConnectError(_) => -libc::EADDRNOTAVAIL,
+            Self::SmoltcpTcpSendError(_) => -libc::EPIPE,
+            Self::SmoltcpUdpRecvError(_) | Self::SmoltcpIcmpRecvError(_) => -libc::EAGAIN,
+        }
+    }
+}
+
 impl From<smoltcp::socket::tcp::ListenError> for SocketError {
     fn from(err: smoltcp::socket::tcp::ListenError) -> Self {
         Self::SmoltcpTcpListenError(err)
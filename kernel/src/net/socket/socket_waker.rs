@@ -12,14 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use alloc::{rc::Rc, string::String};
+use alloc::{rc::Rc, string::String, sync::Arc, vec::Vec};
 use core::{
     cell::{Cell, RefCell},
     task::{RawWaker, RawWakerVTable, Waker},
 };
-use spin::Mutex;
 
-use crate::net::connection::{Operation, NETSTACK_QUEUE};
+use crate::{
+    net::connection::{Operation, NETSTACK_QUEUE},
+    vfs::poll::PollQueue,
+};
 
 pub struct SocketWaker {
     name: String,
@@ -95,3 +97,127 @@ pub fn create_closure_waker(
     let raw_waker = RawWaker::new(Rc::into_raw(rc) as *const (), closure_waker_vtable());
     unsafe { Waker::from_raw(raw_waker) }
 }
+
+// Wakers of one direction of a socket. smoltcp keeps a single waker per
+// direction, so the one registered there fans out to the pending blocking
+// operation and to the poll queues of every poller, instead of one of them
+// replacing the other.
+#[derive(Clone, Default)]
+pub struct SocketWakers(Rc<RefCell<WakerList>>);
+
+#[derive(Default)]
+struct WakerList {
+    operation: Option<Waker>,
+    poll_queues: Vec<Arc<PollQueue>>,
+}
+
+impl SocketWakers {
+    // Replaces the waker of the pending blocking operation, returns the one to
+    // register on the smoltcp socket.
+    pub fn register_operation(&self, waker: Waker) -> Waker {
+        self.0.borrow_mut().operation = Some(waker);
+        self.waker()
+    }
+
+    // Notifies threads blocked in poll() on the socket, returns the waker to
+    // register on the smoltcp socket.
+    pub fn register_poll(&self, poll_queue: Arc<PollQueue>) -> Waker {
+        let mut list = self.0.borrow_mut();
+        if !list.poll_queues.iter().any(|q| Arc::ptr_eq(q, &poll_queue)) {
+            list.poll_queues.push(poll_queue);
+        }
+        drop(list);
+        self.waker()
+    }
+
+    // smoltcp drops its waker once woken, so everyone is woken once and has
+    // to register again.
+    fn wake(&self) {
+        let (operation, poll_queues) = {
+            let mut list = self.0.borrow_mut();
+            (
+                list.operation.take(),
+                core::mem::take(&mut list.poll_queues),
+            )
+        };
+        if let Some(operation) = operation {
+            operation.wake();
+        }
+        for poll_queue in poll_queues {
+            poll_queue.notify();
+        }
+    }
+
+    fn waker(&self) -> Waker {
+        let raw_waker = RawWaker::new(
+            Rc::into_raw(self.0.clone()) as *const (),
+            fanout_waker_vtable(),
+        );
+        unsafe { Waker::from_raw(raw_waker) }
+    }
+}
+
+fn fanout_waker_vtable() -> &'static RawWakerVTable {
+    &RawWakerVTable::new(
+        |data| {
+            let rc = unsafe { Rc::from_raw(data as *const RefCell<WakerList>) };
+            let cloned = rc.clone();
+            core::mem::forget(rc);
+            RawWaker::new(Rc::into_raw(cloned) as *const (), fanout_waker_vtable())
+        },
+        |data| {
+            let rc = unsafe { Rc::from_raw(data as *const RefCell<WakerList>) };
+            SocketWakers(rc).wake();
+        },
+        |data| {
+            let rc = unsafe { Rc::from_raw(data as *const RefCell<WakerList>) };
+            let wakers = SocketWakers(rc);
+            wakers.wake();
+            core::mem::forget(wakers);
+        },
+        |data| {
+            unsafe { Rc::from_raw(data as *const RefCell<WakerList>) };
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::poll::PollTable;
+    use alloc::boxed::Box;
+    use blueos_test_macro::test;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_socket_wakers_fan_out() {
+        static POLLED: AtomicUsize = AtomicUsize::new(0);
+        static OPERATED: AtomicUsize = AtomicUsize::new(0);
+        let poll_queue = Arc::new(PollQueue::new());
+        let mut poll_table = PollTable::with_callback(Box::new(|| {
+            POLLED.fetch_add(1, Ordering::Relaxed);
+        }));
+        poll_table.register(&poll_queue);
+        let operation_queue = Arc::new(PollQueue::new());
+        let mut operation_table = PollTable::with_callback(Box::new(|| {
+            OPERATED.fetch_add(1, Ordering::Relaxed);
+        }));
+        operation_table.register(&operation_queue);
+
+        let wakers = SocketWakers::default();
+        // Stands in for the waker of a blocking recv()
+        let operation = SocketWakers::default().register_poll(operation_queue.clone());
+        drop(wakers.register_operation(operation));
+        // A poller doesn't replace the operation, and is notified once
+        drop(wakers.register_poll(poll_queue.clone()));
+        let waker = wakers.register_poll(poll_queue.clone());
+        waker.wake();
+        assert_eq!(POLLED.load(Ordering::Relaxed), 1);
+        assert_eq!(OPERATED.load(Ordering::Relaxed), 1);
+
+        // Both have to register again
+        wakers.waker().wake();
+        assert_eq!(POLLED.load(Ordering::Relaxed), 1);
+        assert_eq!(OPERATED.load(Ordering::Relaxed), 1);
+    }
+}
@@ -12,35 +12,50 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::net::{
-    connection::{Operation, OperationIPCReply, OperationResult},
-    net_interface::NetInterface,
-    net_manager::NetworkManager,
-    port_generator::PORT_GENERATOR,
-    socket::{
-        socket_err::SocketError, socket_waker, FnRecv, FnRecvWithEndpoint, FnSend, FnSendMsg,
-        PosixSocket,
+use crate::{
+    net::{
+        connection::{Operation, OperationIPCReply, OperationResult},
+        net_interface::NetInterface,
+        net_manager::NetworkManager,
+        port_generator::PORT_GENERATOR,
+        socket::{
+            socket_err::SocketError,
+            socket_waker::{self, SocketWakers},
+            FnRecv, FnRecvWithEndpoint, FnSend, FnSendMsg, PosixSocket,
+        },
+        SocketDomain, SocketFd, SocketProtocol, SocketResult, SocketType,
     },
-    SocketDomain, SocketFd, SocketProtocol, SocketResult, SocketType,
+    vfs::poll::{PollEvents, PollQueue},
 };
 use alloc::{boxed::Box, format, rc::Rc, sync::Arc, vec};
 use core::{
     cell::{Cell, RefCell},
-    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    net::SocketAddr,
     sync::atomic::AtomicUsize,
+    time::Duration,
 };
 use smoltcp::{
     iface::{Interface, SocketHandle, SocketSet},
     socket::tcp::{self, State},
-    wire::{IpAddress, IpEndpoint, IpListenEndpoint},
+    wire::{IpEndpoint, IpListenEndpoint},
 };
 pub struct TcpSocket<'a> {
     socket_fd: SocketFd,
     socket_domain: SocketDomain,
     is_shutdown: Rc<Cell<bool>>,
+    // Woken by the smoltcp socket
+    recv_wakers: SocketWakers,
+    send_wakers: SocketWakers,
     network_manager: Rc<RefCell<NetworkManager<'a>>>,
     smoltcp_socket_handle: Option<SocketHandle>,
     smoltcp_interface: Option<Rc<RefCell<NetInterface<'a>>>>,
+    nagle_enabled: bool,
+    keep_alive: Option<Duration>,
+    // A connect() is in progress
+    connecting: bool,
+    // Directions shut down by shutdown()
+    read_shutdown: bool,
+    write_shutdown: bool,
 }
 
 impl<'a> TcpSocket<'a>
@@ -58,9 +73,16 @@ where
             socket_fd,
             socket_domain,
             is_shutdown: Rc::new(is_shutdown),
+            recv_wakers: SocketWakers::default(),
+            send_wakers: SocketWakers::default(),
             network_manager,
             smoltcp_socket_handle: None,
             smoltcp_interface: None,
+            nagle_enabled: true,
+            keep_alive: None,
+            connecting: false,
+            read_shutdown: false,
+            write_shutdown: false,
         }
     }
 
@@ -73,7 +95,10 @@ where
         let tcp_socket = {
             let tcp_rx_buffer = tcp::SocketBuffer::new(vec![0; 1024]);
             let tcp_tx_buffer = tcp::SocketBuffer::new(vec![0; 1024]);
-            tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer)
+            let mut tcp_socket = tcp::Socket::new(tcp_rx_buffer, tcp_tx_buffer);
+            tcp_socket.set_nagle_enabled(self.nagle_enabled);
+            tcp_socket.set_keep_alive(self.keep_alive.map(Into::into));
+            tcp_socket
         };
 
         // Save socket handle
@@ -86,6 +111,23 @@ where
         }
     }
 
+    // A pending connect() is over once the handshake leaves SynSent and
+    // SynReceived. A refused one stays pending until reported by SO_ERROR.
+    fn update_connecting(&mut self) {
+        if !self.connecting || self.smoltcp_socket_handle.is_none() {
+            return;
+        }
+        let pending = self.with(|socket, _| {
+            Ok(matches!(
+                socket.state(),
+                State::SynSent | State::SynReceived | State::Closed
+            ) as usize)
+        });
+        if pending == Ok(0) {
+            self.connecting = false;
+        }
+    }
+
     pub fn with<F>(&mut self, f: F) -> SocketResult
     where
         F: FnOnce(&mut tcp::Socket<'a>, &mut Interface) -> SocketResult,
@@ -128,26 +170,88 @@ impl PosixSocket for TcpSocket<'static> {
         }
     }
 
-    // TCP connect() : TCP Client side method, create smoltcp socket for tcp client
+    // TCP connect() : TCP Client side method, create smoltcp socket for tcp client.
+    // A blocking connect is queued again on state changes until the handshake ends.
     fn connect(
         &mut self,
         remote_endpoint: IpEndpoint,
         local_port: u16,
-        _is_nonblocking: bool,
+        is_nonblocking: bool,
+        ipc_reply: Arc<OperationIPCReply>,
     ) -> SocketResult {
-        // Create smoltcp socket
-        let socket_handle = match self.create_smoltcp_socket() {
-            Some(handle) => handle,
-            None => return Err(SocketError::CreateSmoltcpSocketFail),
-        };
+        let in_progress = self.connecting;
+        if !in_progress {
+            if self.smoltcp_socket_handle.is_some()
+                && self.with(|socket, _| Ok(socket.may_send() as usize))? != 0
+            {
+                return Err(SocketError::PosixError(
+                    -libc::EISCONN,
+                    "Socket is already connected".into(),
+                ));
+            }
 
-        self.with(|socket, interface| {
-            // match socket type
-            socket
-                .connect(interface.context(), remote_endpoint, local_port)
-                .map(|_| 0)
-                .map_err(SocketError::SmoltcpTcpConnectError)
-        })
+            // Create smoltcp socket
+            if self.create_smoltcp_socket().is_none() {
+                return Err(SocketError::CreateSmoltcpSocketFail);
+            }
+
+            self.with(|socket, interface| {
+                socket
+                    .connect(interface.context(), remote_endpoint, local_port)
+                    .map(|_| 0)
+                    .map_err(SocketError::SmoltcpTcpConnectError)
+            })?;
+            self.connecting = true;
+        }
+
+        let socket_fd = self.socket_fd;
+        let is_shutdown = self.is_shutdown.clone();
+        let send_wakers = self.send_wakers.clone();
+        let result = self.with(|socket, _| match socket.state() {
+            State::SynSent | State::SynReceived => {
+                if is_nonblocking {
+                    let errno = if in_progress {
+                        -libc::EALREADY
+                    } else {
+                        -libc::EINPROGRESS
+                    };
+                    return Err(SocketError::PosixError(
+                        errno,
+                        "Connection is in progress".into(),
+                    ));
+                }
+                let wait_operation = Operation::Connect {
+                    socket_fd,
+                    remote_endpoint,
+                    local_port,
+                    is_nonblocking,
+                    ipc_reply,
+                };
+                let waker = socket_waker::create_closure_waker(
+                    "TCP Connect()".into(),
+                    Some(wait_operation),
+                    is_shutdown,
+                );
+                socket.register_send_waker(&send_wakers.register_operation(waker));
+                Err(SocketError::WouldBlock)
+            }
+            // Reset by the peer, nobody is listening
+            State::Closed => Err(SocketError::PosixError(
+                -libc::ECONNREFUSED,
+                "Connection refused".into(),
+            )),
+            _ => Ok(0),
+        });
+
+        // Still in progress unless it's done or failed
+        self.connecting = match &result {
+            Err(SocketError::WouldBlock) => true,
+            Err(SocketError::PosixError(errno, _)) => {
+                *errno == -libc::EINPROGRESS || *errno == -libc::EALREADY
+            }
+            _ => false,
+        };
+        result
     }
 
     fn listen(&mut self, local_endpoint: IpListenEndpoint) -> SocketResult {
@@ -176,8 +280,16 @@ impl PosixSocket for TcpSocket<'static> {
         is_nonblocking: bool,
         ipc_reply: Arc<OperationIPCReply>,
     ) -> SocketResult {
+        self.update_connecting();
+        if self.write_shutdown {
+            return Err(SocketError::PosixError(
+                -libc::EPIPE,
+                "Tcp socket is shut down for writing".into(),
+            ));
+        }
         let socket_fd = self.socket_fd;
         let is_shutdown = self.is_shutdown.clone();
+        let send_wakers = self.send_wakers.clone();
 
         self.with(|socket, _| {
             if socket.can_send() {
@@ -203,7 +315,7 @@ impl PosixSocket for TcpSocket<'static> {
                             socket_operation,
                             is_shutdown,
                         );
-                        socket.register_send_waker(&waker);
+                        socket.register_send_waker(&send_wakers.register_operation(waker));
                         log::debug!(
                             "tcp socket not ready for send={:?}, send_queue={:?}",
                             socket.state(),
@@ -258,16 +370,28 @@ impl PosixSocket for TcpSocket<'static> {
         is_nonblocking: bool,
         ipc_reply: Arc<OperationIPCReply>,
     ) -> SocketResult {
+        self.update_connecting();
         let socket_fd = self.socket_fd;
         let is_shutdown = self.is_shutdown.clone();
+        let recv_wakers = self.recv_wakers.clone();
+        let read_shutdown = self.read_shutdown;
 
         self.with(|socket, _| {
+            // Data arriving after shutdown(SHUT_RD) is discarded
+            if read_shutdown {
+                discard_received(socket);
+                return Ok(0);
+            }
             if socket.can_recv() {
                 return socket.recv(f).map_err(SocketError::SmoltcpTcpRecvError);
             }
 
             match socket.state() {
-                State::Closed | State::CloseWait => {
+                State::Closed
+                | State::CloseWait
+                | State::Closing
+                | State::TimeWait
+                | State::LastAck => {
                     let msg = format!(
                         "TCP state[{}]: closed by server, returning 0 to indicate EOF",
                         socket.state()
@@ -275,8 +399,14 @@ impl PosixSocket for TcpSocket<'static> {
                     log::debug!("{}", msg);
                     Ok(0)
                 }
-                State::SynSent | State::SynReceived | State::Established | State::Listen => {
+                State::SynSent
+                | State::SynReceived
+                | State::Established
+                | State::Listen
+                | State::FinWait1
+                | State::FinWait2 => {
                     // FIXME: Treating Listen state as Established temporarily, since accept() is not implemented yet
+                    // FinWait states are half-closed by shutdown(SHUT_WR), the peer may still send
                     if is_nonblocking {
                         // O_NONBLOCK is set, so return immediately without blocking
                         Err(SocketError::TryAgain)
@@ -293,7 +423,7 @@ impl PosixSocket for TcpSocket<'static> {
                             socket_operation,
                             is_shutdown,
                         );
-                        socket.register_recv_waker(&recv_waker);
+                        socket.register_recv_waker(&recv_wakers.register_operation(recv_waker));
                         log::debug!(
                             "TCP state[{:?}]: no data for recv, recv_queue={:?}",
                             socket.state(),
@@ -302,12 +432,6 @@ impl PosixSocket for TcpSocket<'static> {
                         Err(SocketError::WouldBlock)
                     }
                 }
-                _ => {
-                    // States like FinWait1, FinWait2, or LastAck are unexpected for recv() here
-                    let msg = format!("TCP state[{}]: invalid state for recv()", socket.state());
-                    log::debug!("{}", msg);
-                    Err(SocketError::InvalidState(msg))
-                }
             }
         })
     }
@@ -336,6 +460,43 @@ impl PosixSocket for TcpSocket<'static> {
         ))
     }
 
+    fn set_nagle_enabled(&mut self, enabled: bool) -> SocketResult {
+        self.nagle_enabled = enabled;
+        // Applied on creation if there is no smoltcp socket yet
+        if self.smoltcp_socket_handle.is_none() {
+            return Ok(0);
+        }
+        self.with(|socket, _| {
+            socket.set_nagle_enabled(enabled);
+            Ok(0)
+        })
+    }
+
+    fn take_error(&mut self) -> SocketResult {
+        self.update_connecting();
+        if !self.connecting {
+            return Ok(0);
+        }
+        let refused = self.with(|socket, _| Ok((socket.state() == State::Closed) as usize))?;
+        if refused == 0 {
+            return Ok(0);
+        }
+        self.connecting = false;
+        Ok(libc::ECONNREFUSED as usize)
+    }
+
+    fn set_keep_alive(&mut self, interval: Option<Duration>) -> SocketResult {
+        self.keep_alive = interval;
+        // Applied on creation if there is no smoltcp socket yet
+        if self.smoltcp_socket_handle.is_none() {
+            return Ok(0);
+        }
+        self.with(|socket, _| {
+            socket.set_keep_alive(interval.map(Into::into));
+            Ok(0)
+        })
+    }
+
     fn shutdown(&self) -> SocketResult {
         self.is_shutdown.set(true);
 
@@ -361,11 +522,45 @@ impl PosixSocket for TcpSocket<'static> {
         }
     }
 
+    fn shutdown_direction(&mut self, read: bool, write: bool) -> SocketResult {
+        self.update_connecting();
+        if self.smoltcp_socket_handle.is_none() {
+            return Err(SocketError::PosixError(
+                -libc::ENOTCONN,
+                "Tcp socket is not connected".into(),
+            ));
+        }
+        self.with(|socket, _| match socket.state() {
+            State::Closed | State::Listen | State::SynSent => Err(SocketError::PosixError(
+                -libc::ENOTCONN,
+                "Tcp socket is not connected".into(),
+            )),
+            _ => {
+                if write {
+                    // Sends FIN once the queued data is sent, receiving goes on
+                    socket.close();
+                }
+                if read {
+                    discard_received(socket);
+                }
+                Ok(0)
+            }
+        })?;
+        self.read_shutdown |= read;
+        self.write_shutdown |= write;
+        Ok(0)
+    }
+
     fn getsockname(
         &mut self,
         f: Box<dyn FnOnce(smoltcp::wire::IpEndpoint) + Send>,
     ) -> SocketResult {
         let socket_domain = self.socket_domain;
+        // Not bound yet
+        if self.smoltcp_socket_handle.is_none() {
+            f(IpEndpoint::new(socket_domain.unspecified_address(), 0));
+            return Ok(0);
+        }
         self.with(|socket, _| {
             let local_endpoint = socket.local_endpoint();
             match local_endpoint {
@@ -374,10 +569,7 @@ impl PosixSocket for TcpSocket<'static> {
                     // Ref to posix getsocketname, still return Ok(0)
                     // If the socket has not been bound to a local name,
                     //  the value stored in the object pointed to by address is unspecified.
-                    let address = match socket_domain {
-                        SocketDomain::AfInet => IpAddress::Ipv4(Ipv4Addr::UNSPECIFIED),
-                        SocketDomain::AfInet6 => IpAddress::Ipv6(Ipv6Addr::UNSPECIFIED),
-                    };
+                    let address = socket_domain.unspecified_address();
                     f(IpEndpoint::new(address, 0))
                 }
             };
@@ -389,34 +581,77 @@ impl PosixSocket for TcpSocket<'static> {
         &mut self,
         f: Box<dyn FnOnce(smoltcp::wire::IpEndpoint) + Send>,
     ) -> SocketResult {
-        let socket_domain = self.socket_domain;
-        self.with(|socket, _| {
-            let remote_endpoint = socket.remote_endpoint();
-            match remote_endpoint {
-                Some(endpoint) => f(endpoint),
-                None => {
-                    if socket.state() == tcp::State::Closed {
-                        return Err(SocketError::PosixError(
-                            -libc::ENOTCONN,
-                            "Tcp socket is no connected".into(),
-                        ));
-                    } else {
-                        // Ref to posix getsocketname, still return Ok(0)
-                        // If the protocol permits connections by unbound clients,
-                        //  and the peer is not bound, then the value stored in the object pointed to by address is unspecified.
-                        let address = match socket_domain {
-                            SocketDomain::AfInet => IpAddress::Ipv4(Ipv4Addr::UNSPECIFIED),
-                            SocketDomain::AfInet6 => IpAddress::Ipv6(Ipv6Addr::UNSPECIFIED),
-                        };
-                        f(IpEndpoint::new(address, 0));
-                    }
-                }
+        self.with(|socket, _| match socket.remote_endpoint() {
+            Some(endpoint) => {
+                f(endpoint);
+                Ok(0)
             }
-            Ok(0)
+            // Closed, or still listening for a peer
+            None => Err(SocketError::PosixError(
+                -libc::ENOTCONN,
+                "Tcp socket is no connected".into(),
+            )),
         })
     }
 
     fn is_shutdown(&self) -> bool {
         self.is_shutdown.get()
     }
+
+    fn poll(&mut self, events: PollEvents, poll_queue: Arc<PollQueue>) -> SocketResult {
+        // Neither bound nor connected
+        if self.smoltcp_socket_handle.is_none() {
+            return Ok(PollEvents::POLLHUP.bits() as usize);
+        }
+
+        self.update_connecting();
+        let connecting = self.connecting;
+        let recv_wakers = self.recv_wakers.clone();
+        let send_wakers = self.send_wakers.clone();
+        self.with(|socket, _| {
+            let mut revents = PollEvents::empty();
+            // recv() returns EOF immediately in these states
+            if socket.can_recv()
+                || matches!(
+                    socket.state(),
+                    State::Closed
+                        | State::CloseWait
+                        | State::Closing
+                        | State::TimeWait
+                        | State::LastAck
+                )
+            {
+                revents |= PollEvents::POLLIN;
+            }
+            if socket.can_send() {
+                revents |= PollEvents::POLLOUT;
+            }
+            if socket.state() == State::Closed {
+                revents |= PollEvents::POLLHUP;
+                // The pending connect() failed, see SO_ERROR
+                if connecting {
+                    revents |= PollEvents::POLLERR;
+                }
+            }
+
+            // Registered even when ready, an edge-triggered epoll relies on
+            // being told of the next readiness change. A pending blocking
+            // operation keeps its own waker.
+            if events.contains(PollEvents::POLLIN) {
+                socket.register_recv_waker(&recv_wakers.register_poll(poll_queue.clone()));
+            }
+            if events.contains(PollEvents::POLLOUT) {
+                socket.register_send_waker(&send_wakers.register_poll(poll_queue));
+            }
+            Ok(revents.bits() as usize)
+        })
+    }
+}
+
+fn discard_received(socket: &mut tcp::Socket) {
+    while socket.can_recv() {
+        if socket.recv(|buffer| (buffer.len(), ())).is_err() {
+            break;
+        }
+    }
 }
@@ -12,20 +12,24 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::net::{
-    connection::{Operation, OperationIPCReply, OperationResult},
-    net_interface::NetInterface,
-    net_manager::NetworkManager,
-    socket::{
-        socket_err::SocketError, socket_waker, FnRecv, FnRecvWithEndpoint, FnSend, FnSendMsg,
-        PosixSocket,
+use crate::{
+    net::{
+        connection::{Operation, OperationIPCReply, OperationResult},
+        net_interface::NetInterface,
+        net_manager::NetworkManager,
+        socket::{
+            socket_err::SocketError,
+            socket_waker::{self, SocketWakers},
+            FnRecv, FnRecvWithEndpoint, FnSend, FnSendMsg, PosixSocket,
+        },
+        SocketDomain, SocketFd, SocketProtocol, SocketResult, SocketType,
     },
-    SocketDomain, SocketFd, SocketProtocol, SocketResult, SocketType,
+    vfs::poll::{PollEvents, PollQueue},
 };
-use alloc::{boxed::Box, format, rc::Rc, sync::Arc, vec};
+use alloc::{boxed::Box, format, rc::Rc, sync::Arc, vec, vec::Vec};
 use core::{
     cell::{Cell, RefCell},
-    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    net::SocketAddr,
     sync::atomic::AtomicUsize,
 };
 use smoltcp::{
@@ -37,9 +41,12 @@ pub struct UdpSocket<'a> {
     socket_fd: SocketFd,
     socket_domain: SocketDomain,
     is_shutdown: Rc<Cell<bool>>,
+    recv_wakers: SocketWakers,
+    send_wakers: SocketWakers,
     network_manager: Rc<RefCell<NetworkManager<'a>>>,
     smoltcp_socket_handle: Option<SocketHandle>,
     smoltcp_interface: Option<Rc<RefCell<NetInterface<'a>>>>,
+    multicast_groups: RefCell<Vec<(Rc<RefCell<NetInterface<'a>>>, IpAddress)>>,
 }
 
 impl<'a> UdpSocket<'a>
@@ -57,9 +64,12 @@ where
             socket_fd,
             socket_domain,
             is_shutdown: Rc::new(is_shutdown),
+            recv_wakers: SocketWakers::default(),
+            send_wakers: SocketWakers::default(),
             network_manager,
             smoltcp_socket_handle: None,
             smoltcp_interface: None,
+            multicast_groups: RefCell::new(Vec::new()),
         }
     }
 
@@ -144,6 +154,7 @@ impl PosixSocket for UdpSocket<'static> {
         _remote_endpoint: IpEndpoint,
         _local_port: u16,
         _is_nonblocking: bool,
+        _ipc_reply: Arc<OperationIPCReply>,
     ) -> SocketResult {
         Err(SocketError::UnsupportedSocketTypeForOperation(
             SocketType::SockDgram,
@@ -186,6 +197,7 @@ impl PosixSocket for UdpSocket<'static> {
         }
         let socket_fd = self.socket_fd;
         let is_shutdown = self.is_shutdown.clone();
+        let send_wakers = self.send_wakers.clone();
 
         self.with(|socket, _| {
             // Bind to local port while socket is not bound
@@ -225,7 +237,7 @@ impl PosixSocket for UdpSocket<'static> {
                             socket_operation,
                             is_shutdown,
                         );
-                        socket.register_send_waker(&waker);
+                        socket.register_send_waker(&send_wakers.register_operation(waker));
                         log::debug!(
                             "blocking : udp socket not ready for send_queue={:?}",
                             socket.send_queue()
@@ -292,6 +304,7 @@ impl PosixSocket for UdpSocket<'static> {
     ) -> SocketResult {
         let socket_fd = self.socket_fd;
         let is_shutdown = self.is_shutdown.clone();
+        let recv_wakers = self.recv_wakers.clone();
 
         self.with(|socket, _| {
             match socket.can_recv() {
@@ -314,7 +327,7 @@ impl PosixSocket for UdpSocket<'static> {
                             socket_operation,
                             is_shutdown,
                         );
-                        socket.register_recv_waker(&waker);
+                        socket.register_recv_waker(&recv_wakers.register_operation(waker));
                         log::debug!(
                             "blocking : no data for udp recvfrom recv_queue={:?}",
                             socket.recv_queue()
@@ -334,9 +347,61 @@ impl PosixSocket for UdpSocket<'static> {
         })
     }
 
+    fn join_multicast_group(
+        &mut self,
+        interface: Rc<RefCell<NetInterface<'static>>>,
+        group: IpAddress,
+    ) -> SocketResult {
+        let mut multicast_groups = self.multicast_groups.borrow_mut();
+        if multicast_groups
+            .iter()
+            .any(|(i, g)| Rc::ptr_eq(i, &interface) && *g == group)
+        {
+            return Err(SocketError::PosixError(
+                -libc::EADDRINUSE,
+                format!("already joined {}", group),
+            ));
+        }
+        interface
+            .borrow_mut()
+            .join_multicast_group(group)
+            .map_err(|e| SocketError::PosixError(-libc::ENOBUFS, format!("{:?}", e)))?;
+        multicast_groups.push((interface, group));
+        Ok(0)
+    }
+
+    fn leave_multicast_group(
+        &mut self,
+        interface: Rc<RefCell<NetInterface<'static>>>,
+        group: IpAddress,
+    ) -> SocketResult {
+        let mut multicast_groups = self.multicast_groups.borrow_mut();
+        let Some(index) = multicast_groups
+            .iter()
+            .position(|(i, g)| Rc::ptr_eq(i, &interface) && *g == group)
+        else {
+            return Err(SocketError::PosixError(
+                -libc::EADDRNOTAVAIL,
+                format!("not a member of {}", group),
+            ));
+        };
+        multicast_groups.remove(index);
+        interface
+            .borrow_mut()
+            .leave_multicast_group(group)
+            .map_err(|e| SocketError::PosixError(-libc::EINVAL, format!("{:?}", e)))?;
+        Ok(0)
+    }
+
     fn shutdown(&self) -> SocketResult {
         self.is_shutdown.set(true);
 
+        for (interface, group) in self.multicast_groups.borrow_mut().drain(..) {
+            if let Err(e) = interface.borrow_mut().leave_multicast_group(group) {
+                log::warn!("Leave multicast group {} fail {:?}", group, e);
+            }
+        }
+
         if let Some(interface) = &self.smoltcp_interface {
             let mut interface = interface.borrow_mut();
             let socket_sets = interface.socket_sets_mut();
@@ -361,6 +426,11 @@ impl PosixSocket for UdpSocket<'static> {
 
     fn getsockname(&mut self, f: Box<dyn FnOnce(IpEndpoint) + Send>) -> SocketResult {
         let socket_domain = self.socket_domain;
+        // Not bound yet
+        if self.smoltcp_socket_handle.is_none() {
+            f(IpEndpoint::new(socket_domain.unspecified_address(), 0));
+            return Ok(0);
+        }
         self.with(|socket, _| {
             // Try to get endpoint from socket
             let local_endpoint = socket.endpoint();
@@ -368,10 +438,7 @@ impl PosixSocket for UdpSocket<'static> {
                 Some(address) => address,
                 None => {
                     // None means binding to any address
-                    match socket_domain {
-                        SocketDomain::AfInet => IpAddress::Ipv4(Ipv4Addr::UNSPECIFIED),
-                        SocketDomain::AfInet6 => IpAddress::Ipv6(Ipv6Addr::UNSPECIFIED),
-                    }
+                    socket_domain.unspecified_address()
                 }
             };
             f(IpEndpoint::new(address, local_endpoint.port));
@@ -380,13 +447,41 @@ impl PosixSocket for UdpSocket<'static> {
     }
 
     fn getpeername(&mut self, f: Box<dyn FnOnce(IpEndpoint) + Send>) -> SocketResult {
-        Err(SocketError::UnsupportedSocketTypeForOperation(
-            SocketType::SockDgram,
-            "UDP socket do not support getpeername()".into(),
+        // connect() is not supported for UDP, so there is never a peer
+        Err(SocketError::PosixError(
+            -libc::ENOTCONN,
+            "UDP socket is not connected".into(),
         ))
     }
 
     fn is_shutdown(&self) -> bool {
         self.is_shutdown.get()
     }
+
+    fn poll(&mut self, events: PollEvents, poll_queue: Arc<PollQueue>) -> SocketResult {
+        // The socket is created on first bind() or sendto(), which never blocks
+        if self.smoltcp_socket_handle.is_none() {
+            return Ok(PollEvents::POLLOUT.bits() as usize);
+        }
+
+        let recv_wakers = self.recv_wakers.clone();
+        let send_wakers = self.send_wakers.clone();
+        self.with(|socket, _| {
+            let mut revents = PollEvents::empty();
+            if socket.can_recv() {
+                revents |= PollEvents::POLLIN;
+            }
+            if socket.can_send() {
+                revents |= PollEvents::POLLOUT;
+            }
+
+            if events.contains(PollEvents::POLLIN) {
+                socket.register_recv_waker(&recv_wakers.register_poll(poll_queue.clone()));
+            }
+            if events.contains(PollEvents::POLLOUT) {
+                socket.register_send_waker(&send_wakers.register_poll(poll_queue));
+            }
+            Ok(revents.bits() as usize)
+        })
+    }
 }
@@ -17,8 +17,8 @@
 use crate::{
     error::{self, code},
     net::{
-        self, connection::Connection, SocketAddress, SocketDomain, SocketMsghdr, SocketProtocol,
-        SocketType, Timeval,
+        self, connection::Connection, connection_err::ConnectionError, SocketAddress, SocketDomain,
+        SocketMsghdr, SocketProtocol, SocketType, Timeval,
     },
     vfs::{alloc_sock_fd, free_sock_fd, get_sock_by_fd, sock_attach_to_fd},
 };
@@ -35,6 +35,53 @@ use smoltcp::wire::{IpAddress, IpEndpoint};
 use spin::rwlock::RwLock;
 
 const ONE_ELEMENT: usize = 1;
+// Upper bound of TCP_KEEPIDLE and TCP_KEEPINTVL in seconds, same as Linux
+const MAX_TCP_KEEP_SECS: c_int = 32767;
+
+// Blocking send/recv which timed out reports EAGAIN
+fn io_error(err: ConnectionError) -> c_ssize_t {
+    err.to_errno() as c_ssize_t
+}
+
+// Boolean socket options are passed as an int
+// Returns the group and the interface address of an ip_mreq
+unsafe fn read_ip_mreq(
+    option_value: *const c_void,
+    option_len: libc::socklen_t,
+) -> Option<(IpAddress, IpAddress)> {
+    if option_value.is_null() || (option_len as usize) < size_of::<libc::ip_mreq>() {
+        return None;
+    }
+    let mreq = &*(option_value as *const libc::ip_mreq);
+    let group = IpAddress::Ipv4(Ipv4Addr::from(mreq.imr_multiaddr.s_addr.to_ne_bytes()));
+    let interface_addr = IpAddress::Ipv4(Ipv4Addr::from(mreq.imr_interface.s_addr.to_ne_bytes()));
+    Some((group, interface_addr))
+}
+
+unsafe fn read_int_option(
+    option_value: *const c_void,
+    option_len: libc::socklen_t,
+) -> Option<c_int> {
+    if option_value.is_null() || (option_len as usize) < size_of::<c_int>() {
+        return None;
+    }
+    Some(*(option_value as *const c_int))
+}
+
+fn write_int_option(
+    value: c_int,
+    option_value: *mut c_void,
+    option_len: *mut libc::socklen_t,
+) -> c_int {
+    if unsafe { (*option_len as usize) < size_of::<c_int>() } {
+        return -libc::EINVAL;
+    }
+    unsafe {
+        *(option_value as *mut c_int) = value;
+        *option_len = size_of::<c_int>() as libc::socklen_t;
+    }
+    0
+}
 
 pub fn socket(domain: c_int, type_: c_int, protocol_: c_int) -> c_int {
     let Ok(socket_domain) = SocketDomain::try_from(domain) else {
@@ -61,18 +108,22 @@ pub fn socket(domain: c_int, type_: c_int, protocol_: c_int) -> c_int {
     }
 
     let socket = alloc_sock_fd(flags);
+    if socket < 0 {
+        return socket;
+    }
     let mut connection = Connection::new(socket, socket_domain, socket_type, socket_protocol);
 
     connection.set_is_nonblocking((type_ & libc::SO_NONBLOCK) != 0);
 
     if let Err(e) = connection.create() {
         log::warn!("Failed to create socket: {:?}", e);
-        free_sock_fd(socket);
-        return -1;
+        let _ = free_sock_fd(socket);
+        return e.to_errno();
     }
     if let Err(e) = sock_attach_to_fd(socket, Arc::new(connection)) {
         log::error!("sock_attach_to_fd socket fd={} error: {}", socket, e);
-        -1
+        let _ = free_sock_fd(socket);
+        e.to_errno()
     } else {
         socket
     }
@@ -97,7 +148,7 @@ pub fn listen(socket: c_int, backlog: c_int) -> c_int {
         log::warn!("fd={}: socket is unbound", socket);
         return -libc::EDESTADDRREQ;
     }
-    connection.listen().map(|_| 0).unwrap_or(-1)
+    connection.listen().map_or_else(endpoint_error, |_| 0)
 }
 
 pub fn send(socket: c_int, buffer: *const c_void, length: c_size_t, flags: c_int) -> c_ssize_t {
@@ -120,8 +171,11 @@ pub fn send(socket: c_int, buffer: *const c_void, length: c_size_t, flags: c_int
         return -libc::ENOTCONN as c_ssize_t;
     }
 
-    if buffer.is_null() || length == 0 {
-        return -1;
+    if length == 0 {
+        return 0;
+    }
+    if buffer.is_null() {
+        return -libc::EFAULT as c_ssize_t;
     }
 
     let buffer = buffer as *const u8;
@@ -137,8 +191,12 @@ pub fn send(socket: c_int, buffer: *const c_void, length: c_size_t, flags: c_int
 
     connection
         .send(f, flags)
-        .map(|send_sizes| send_sizes.try_into().unwrap_or(-1))
-        .unwrap_or(-1)
+        .map(|send_sizes| {
+            send_sizes
+                .try_into()
+                .unwrap_or(-libc::EOVERFLOW as c_ssize_t)
+        })
+        .unwrap_or_else(io_error)
 }
 
 pub fn sendto(
@@ -157,14 +215,31 @@ pub fn sendto(
     };
 
     if connection.socket_type() == SocketType::SockStream
-        || connection.socket_type() == SocketType::SockRaw
+        || (connection.socket_type() == SocketType::SockRaw && !is_icmp(&connection))
     {
         log::warn!("fd={}: socket protocol does not support sendto()", socket);
         return -libc::EOPNOTSUPP as c_ssize_t;
     }
 
-    if message.is_null() || length == 0 {
-        return -1;
+    if length == 0 {
+        return 0;
+    }
+    if message.is_null() {
+        return -libc::EFAULT as c_ssize_t;
+    }
+
+    // An ICMP message is sent as a single iovec, the echo identifier is parsed from it
+    if connection.socket_type() == SocketType::SockRaw {
+        let mut iov = libc::iovec {
+            iov_base: message as *mut c_void,
+            iov_len: length,
+        };
+        let mut msghdr: libc::msghdr = unsafe { core::mem::zeroed() };
+        msghdr.msg_name = dest_addr as *mut c_void;
+        msghdr.msg_namelen = dest_len;
+        msghdr.msg_iov = &mut iov;
+        msghdr.msg_iovlen = 1;
+        return sendmsg(socket, &msghdr, flags);
     }
 
     let Some(socket_addr) = (unsafe { SocketAddress::from_ptr(dest_addr, dest_len) }) else {
@@ -181,8 +256,17 @@ pub fn sendto(
 
     connection
         .sendto(buf, flags, remote_endpoint)
-        .map(|send_sizes| send_sizes.try_into().unwrap_or(-1))
-        .unwrap_or(-1)
+        .map(|send_sizes| {
+            send_sizes
+                .try_into()
+                .unwrap_or(-libc::EOVERFLOW as c_ssize_t)
+        })
+        .unwrap_or_else(io_error)
+}
+
+fn is_icmp(connection: &Connection) -> bool {
+    connection.socket_protocol() == SocketProtocol::Icmp
+        || connection.socket_protocol() == SocketProtocol::Icmpv6
 }
 
 pub fn sendmsg(socket: c_int, message: *const libc::msghdr, flags: c_int) -> c_ssize_t {
@@ -232,8 +316,12 @@ pub fn sendmsg(socket: c_int, message: *const libc::msghdr, flags: c_int) -> c_s
 
     connection
         .sendmsg(remote_endpoint, identifer, packet_len, send_payload)
-        .map(|send_sizes| send_sizes.try_into().unwrap_or(-1))
-        .unwrap_or(-1)
+        .map(|send_sizes| {
+            send_sizes
+                .try_into()
+                .unwrap_or(-libc::EOVERFLOW as c_ssize_t)
+        })
+        .unwrap_or_else(io_error)
 }
 
 pub fn recv(socket: c_int, buffer: *mut c_void, length: c_size_t, flags: c_int) -> c_ssize_t {
@@ -259,8 +347,11 @@ pub fn recv(socket: c_int, buffer: *mut c_void, length: c_size_t, flags: c_int)
         return -libc::ENOTCONN as c_ssize_t;
     }
 
-    if buffer.is_null() || length == 0 {
-        return -1;
+    if length == 0 {
+        return 0;
+    }
+    if buffer.is_null() {
+        return -libc::EFAULT as c_ssize_t;
     }
     let buffer = buffer as *mut u8;
     #[allow(unused_mut)]
@@ -277,9 +368,11 @@ pub fn recv(socket: c_int, buffer: *mut c_void, length: c_size_t, flags: c_int)
         .recv(f)
         .map(|recv_sized| {
             log::debug!("[Posix] recv msg recv_sized={}", recv_sized);
-            recv_sized.try_into().unwrap_or(-1)
+            recv_sized
+                .try_into()
+                .unwrap_or(-libc::EOVERFLOW as c_ssize_t)
         })
-        .unwrap_or(-1)
+        .unwrap_or_else(io_error)
 }
 
 pub fn recvmsg(socket: c_int, message: *mut libc::msghdr, flags: c_int) -> c_ssize_t {
@@ -321,8 +414,12 @@ pub fn recvmsg(socket: c_int, message: *mut libc::msghdr, flags: c_int) -> c_ssi
 
     connection
         .recvmsg(recv_payload)
-        .map(|recv_sized| recv_sized.try_into().unwrap_or(-1))
-        .unwrap_or(-1)
+        .map(|recv_sized| {
+            recv_sized
+                .try_into()
+                .unwrap_or(-libc::EOVERFLOW as c_ssize_t)
+        })
+        .unwrap_or_else(io_error)
 }
 
 pub fn recvfrom(
@@ -341,14 +438,17 @@ pub fn recvfrom(
     };
 
     if connection.socket_type() == SocketType::SockStream
-        || connection.socket_type() == SocketType::SockRaw
+        || (connection.socket_type() == SocketType::SockRaw && !is_icmp(&connection))
     {
         log::warn!("fd={}: socket protocol does not support recvfrom()", socket);
         return -libc::EOPNOTSUPP as c_ssize_t;
     }
 
-    if buffer.is_null() || length == 0 {
-        return -1;
+    if length == 0 {
+        return 0;
+    }
+    if buffer.is_null() {
+        return -libc::EFAULT as c_ssize_t;
     }
     let buffer = buffer as *mut u8;
     #[allow(unused_mut)]
@@ -378,8 +478,12 @@ pub fn recvfrom(
 
     connection
         .recvfrom(recv_payload)
-        .map(|recv_sized| recv_sized.try_into().unwrap_or(-1))
-        .unwrap_or(-1)
+        .map(|recv_sized| {
+            recv_sized
+                .try_into()
+                .unwrap_or(-libc::EOVERFLOW as c_ssize_t)
+        })
+        .unwrap_or_else(io_error)
 }
 
 pub fn connect(
@@ -404,7 +508,10 @@ pub fn connect(
         return -libc::EADDRNOTAVAIL;
     };
 
-    connection.connect(remote_endpoint).map(|_| 0).unwrap_or(-1)
+    connection
+        .connect(remote_endpoint)
+        .map(|_| 0)
+        .unwrap_or_else(endpoint_error)
 }
 
 pub fn bind(socket: c_int, address: *const libc::sockaddr, address_len: libc::socklen_t) -> c_int {
@@ -430,11 +537,13 @@ pub fn bind(socket: c_int, address: *const libc::sockaddr, address_len: libc::so
         return -libc::EADDRNOTAVAIL;
     };
 
-    connection
-        .bind(local_endpoint)
-        .map(|_| 0)
-        .map_err(|e| log::debug!("bind fail {:#?}", e))
-        .unwrap_or(-1)
+    match connection.bind(local_endpoint) {
+        Ok(_) => 0,
+        Err(e) => {
+            log::debug!("bind fail {:#?}", e);
+            e.to_errno()
+        }
+    }
 }
 
 pub fn setsockopt(
@@ -453,30 +562,106 @@ pub fn setsockopt(
 
     // option_name suppose to contain only one option
     if level == libc::SOL_SOCKET {
-        if (option_name & libc::SO_RCVTIMEO) != 0 {
+        if option_name == libc::SO_RCVTIMEO {
             return match unsafe { Timeval::from_ptr(option_value, option_len) } {
-                Some(timeval) => {
+                Some(timeval) if timeval.is_valid() => {
                     connection.set_recv_timeout(Duration::from(timeval));
                     0
                 }
-                None => -1,
+                _ => -libc::EINVAL,
             };
         }
 
-        if (option_name & libc::SO_SNDTIMEO) != 0 {
+        if option_name == libc::SO_SNDTIMEO {
             return match unsafe { Timeval::from_ptr(option_value, option_len) } {
-                Some(timeval) => {
+                Some(timeval) if timeval.is_valid() => {
                     connection.set_send_timeout(Duration::from(timeval));
                     0
                 }
-                None => -1,
+                _ => -libc::EINVAL,
+            };
+        }
+
+        if option_name == libc::SO_REUSEADDR {
+            return match unsafe { read_int_option(option_value, option_len) } {
+                Some(value) => {
+                    connection.set_reuse_addr(value != 0);
+                    0
+                }
+                None => -libc::EINVAL,
+            };
+        }
+
+        if option_name == libc::SO_REUSEPORT {
+            return match unsafe { read_int_option(option_value, option_len) } {
+                Some(value) => {
+                    connection.set_reuse_port(value != 0);
+                    0
+                }
+                None => -libc::EINVAL,
+            };
+        }
+
+        if option_name == libc::SO_KEEPALIVE {
+            let Some(value) = (unsafe { read_int_option(option_value, option_len) }) else {
+                return -libc::EINVAL;
+            };
+            return match connection.set_keep_alive(value != 0) {
+                Ok(_) => 0,
+                Err(e) => endpoint_error(e),
             };
         }
 
         // The specified option is invalid at the specified socket level.
         -libc::EINVAL
+    } else if level == libc::IPPROTO_TCP && option_name == libc::TCP_NODELAY {
+        let Some(value) = (unsafe { read_int_option(option_value, option_len) }) else {
+            return -libc::EINVAL;
+        };
+        match connection.set_no_delay(value != 0) {
+            Ok(_) => 0,
+            Err(e) => endpoint_error(e),
+        }
+    } else if level == libc::IPPROTO_TCP
+        && (option_name == libc::TCP_KEEPIDLE || option_name == libc::TCP_KEEPINTVL)
+    {
+        if connection.socket_type() != SocketType::SockStream {
+            return -libc::ENOPROTOOPT;
+        }
+        let Some(secs) = (unsafe { read_int_option(option_value, option_len) })
+            .filter(|secs| (1..=MAX_TCP_KEEP_SECS).contains(secs))
+        else {
+            return -libc::EINVAL;
+        };
+        if option_name == libc::TCP_KEEPINTVL {
+            connection.set_keep_intvl(secs as u32);
+            return 0;
+        }
+        match connection.set_keep_idle(secs as u32) {
+            Ok(_) => 0,
+            Err(e) => endpoint_error(e),
+        }
+    } else if level == libc::IPPROTO_IP
+        && (option_name == libc::IP_ADD_MEMBERSHIP || option_name == libc::IP_DROP_MEMBERSHIP)
+    {
+        let Some((group, interface_addr)) = (unsafe { read_ip_mreq(option_value, option_len) })
+        else {
+            return -libc::EINVAL;
+        };
+        if !group.is_multicast() {
+            return -libc::EINVAL;
+        }
+        let result = if option_name == libc::IP_ADD_MEMBERSHIP {
+            connection.join_multicast_group(group, interface_addr)
+        } else {
+            connection.leave_multicast_group(group, interface_addr)
+        };
+        match result {
+            Ok(_) => 0,
+            Err(e) => endpoint_error(e),
+        }
     } else {
-        // Do not support level other than SOL_SOCKET, like TCP...
+        // Options of other levels are not supported yet.
         // The option is not supported by the protocol.
         -libc::ENOPROTOOPT
     }
@@ -500,7 +685,7 @@ pub fn getsockopt(
         return -libc::EINVAL;
     }
     if level == libc::SOL_SOCKET {
-        if (option_name & libc::SO_RCVTIMEO) != 0 {
+        if option_name == libc::SO_RCVTIMEO {
             let timeval = Timeval::from(connection.get_recv_timeout());
             unsafe {
                 core::ptr::copy_nonoverlapping(&timeval, option_value as *mut Timeval, ONE_ELEMENT);
@@ -509,7 +694,7 @@ pub fn getsockopt(
             return 0;
         }
 
-        if (option_name & libc::SO_SNDTIMEO) != 0 {
+        if option_name == libc::SO_SNDTIMEO {
             let timeval = Timeval::from(connection.get_send_timeout());
             unsafe {
                 core::ptr::copy_nonoverlapping(&timeval, option_value as *mut Timeval, ONE_ELEMENT);
@@ -518,44 +703,84 @@ pub fn getsockopt(
             return 0;
         }
 
-        if (option_name & libc::SO_DOMAIN) != 0 {
+        if option_name == libc::SO_REUSEADDR {
+            return write_int_option(
+                connection.get_reuse_addr() as c_int,
+                option_value,
+                option_len,
+            );
+        }
+
+        if option_name == libc::SO_REUSEPORT {
+            return write_int_option(
+                connection.get_reuse_port() as c_int,
+                option_value,
+                option_len,
+            );
+        }
+
+        if option_name == libc::SO_ERROR {
+            return match connection.take_error() {
+                Ok(errno) => write_int_option(errno as c_int, option_value, option_len),
+                Err(e) => endpoint_error(e),
+            };
+        }
+
+        if option_name == libc::SO_KEEPALIVE {
+            return write_int_option(
+                connection.get_keep_alive() as c_int,
+                option_value,
+                option_len,
+            );
+        }
+
+        if option_name == libc::SO_DOMAIN {
             return connection
                 .socket_domain()
                 .write_to_ptr(option_value, option_len)
-                .map(|()| 0)
-                .unwrap_or(-1);
+                .map_or_else(|errno| errno, |()| 0);
         }
 
-        if (option_name & libc::SO_PROTOCOL) != 0 {
+        if option_name == libc::SO_PROTOCOL {
             return connection
                 .socket_protocol()
                 .into_ptr(option_value, option_len)
-                .map(|()| 0)
-                .unwrap_or(-1);
+                .map_or_else(|errno| errno, |()| 0);
         }
 
-        if (option_name & libc::SO_TYPE) != 0 {
+        if option_name == libc::SO_TYPE {
             return connection
                 .socket_type()
                 .write_to_ptr(option_value, option_len)
-                .map(|()| 0)
-                .unwrap_or(-1);
-        }
-
-        // TODO
-        if (option_name & libc::SO_SNDBUF) != 0 {
-            return -1;
+                .map_or_else(|errno| errno, |()| 0);
         }
 
         // TODO
-        if (option_name & libc::SO_RCVBUF) != 0 {
-            return -1;
+        if option_name == libc::SO_SNDBUF || option_name == libc::SO_RCVBUF {
+            return -libc::ENOPROTOOPT;
         }
 
         // The specified option is invalid at the specified socket level.
         -libc::EINVAL
+    } else if level == libc::IPPROTO_TCP && option_name == libc::TCP_NODELAY {
+        if connection.socket_type() != SocketType::SockStream {
+            return -libc::ENOPROTOOPT;
+        }
+        write_int_option(connection.get_no_delay() as c_int, option_value, option_len)
+    } else if level == libc::IPPROTO_TCP
+        && (option_name == libc::TCP_KEEPIDLE || option_name == libc::TCP_KEEPINTVL)
+    {
+        if connection.socket_type() != SocketType::SockStream {
+            return -libc::ENOPROTOOPT;
+        }
+        let secs = if option_name == libc::TCP_KEEPIDLE {
+            connection.get_keep_idle()
+        } else {
+            connection.get_keep_intvl()
+        };
+        write_int_option(secs as c_int, option_value, option_len)
     } else {
-        // Do not support level other than SOL_SOCKET, like TCP...
+        // Options of other levels are not supported yet.
         // The option is not supported by the protocol.
         -libc::ENOPROTOOPT
     }
@@ -580,12 +805,87 @@ pub fn accept(
 pub fn shutdown(socket: c_int, how: c_int) -> c_int {
     log::debug!("fd={}: Shutting down (how={})", socket, how);
 
+    let (read, write) = match how {
+        libc::SHUT_RD => (true, false),
+        libc::SHUT_WR => (false, true),
+        libc::SHUT_RDWR => (true, true),
+        _ => return -libc::EINVAL,
+    };
+
+    let Ok(connection) = get_sock_by_fd(socket) else {
+        log::error!("fd={}: not a valid file descriptor", socket);
+        return -libc::EBADF;
+    };
+    // The fd is left open, it's released by close()
+    connection
+        .shutdown_direction(read, write)
+        .map(|_| 0)
+        .unwrap_or_else(endpoint_error)
+}
+
+// The address is truncated to the buffer length by write_to_sockaddr
+fn check_sockaddr_buffer(
+    address: *mut libc::sockaddr,
+    address_len: *mut libc::socklen_t,
+) -> Result<(), c_int> {
+    if address.is_null() || address_len.is_null() {
+        return Err(-libc::EFAULT);
+    }
+    Ok(())
+}
+
+fn endpoint_error(err: ConnectionError) -> c_int {
+    err.to_errno()
+}
+
+pub fn getsockname(
+    socket: c_int,
+    address: *mut libc::sockaddr,
+    address_len: *mut libc::socklen_t,
+) -> c_int {
+    log::debug!("fd={}: getsockname", socket);
+
     let Ok(connection) = get_sock_by_fd(socket) else {
         log::error!("fd={}: not a valid file descriptor", socket);
         return -libc::EBADF;
     };
-    free_sock_fd(socket);
-    connection.shutdown().map(|_| 0).unwrap_or(-1)
+
+    if let Err(errno) = check_sockaddr_buffer(address, address_len) {
+        return errno;
+    }
+    let (address_ref, address_len_ref) = unsafe { (&mut *address, &mut *address_len) };
+
+    connection
+        .getsockname(Box::new(move |endpoint: IpEndpoint| {
+            net::write_to_sockaddr(endpoint, address_ref, address_len_ref);
+        }))
+        .map(|_| 0)
+        .unwrap_or_else(endpoint_error)
+}
+
+pub fn getpeername(
+    socket: c_int,
+    address: *mut libc::sockaddr,
+    address_len: *mut libc::socklen_t,
+) -> c_int {
+    log::debug!("fd={}: getpeername", socket);
+
+    let Ok(connection) = get_sock_by_fd(socket) else {
+        log::error!("fd={}: not a valid file descriptor", socket);
+        return -libc::EBADF;
+    };
+
+    if let Err(errno) = check_sockaddr_buffer(address, address_len) {
+        return errno;
+    }
+    let (address_ref, address_len_ref) = unsafe { (&mut *address, &mut *address_len) };
+
+    connection
+        .getpeername(Box::new(move |endpoint: IpEndpoint| {
+            net::write_to_sockaddr(endpoint, address_ref, address_len_ref);
+        }))
+        .map(|_| 0)
+        .unwrap_or_else(endpoint_error)
 }
 
 pub fn getaddrinfo(
@@ -141,9 +141,14 @@ pub(crate) extern "C" fn save_context_finish_hook(hook: Option<&mut ContextSwitc
             next.priority(),
         );
 
-        let cycles = time::get_sys_cycles();
+        let cycles = time::clocksource::current().read_cycles();
         old.lock().increment_cycles(cycles);
         next.lock().set_start_cycles(cycles);
+        if let Err(e) = old.check_stack_canary() {
+            panic!("[TH:0x{:x}] {}", Thread::id(&old), e);
+        }
+        #[cfg(all(target_arch = "arm", not(armv6m)))]
+        arch::mpu::set_stack_guard(next.stack_guard());
     }
     compiler_fence(Ordering::SeqCst);
     if let Some(t) = ready_thread {
@@ -180,6 +185,7 @@ pub(crate) extern "C" fn save_context_finish_hook(hook: Option<&mut ContextSwitc
                 Entry::Posix(f, arg) => f(arg),
             }
         };
+        thread::signal::disarm_itimer(&t);
         GlobalQueueVisitor::remove(&t);
         let ok = t.transfer_state(thread::RUNNING, thread::RETIRED);
         assert!(ok);
@@ -218,15 +224,39 @@ pub(crate) extern "C" fn yield_me_and_return_next_sp(old_sp: usize) -> usize {
     to_sp
 }
 
+// Used by archs switching context on return from an IRQ, e.g. aarch64.
+// It assumes current thread's context is already saved at `old_sp`.
+// Unlike `yield_me_and_return_next_sp`, current thread is only queued
+// by `save_context_finish_hook` with the returned hook, which must run
+// on the next thread's stack, since another core might resume current
+// thread as soon as it's queued.
+pub(crate) fn preempt_me_and_return_next_sp(
+    old_sp: usize,
+) -> Option<(usize, ContextSwitchHookHolder<'static>)> {
+    assert!(!arch::local_irq_enabled());
+    let old = current_thread();
+    if !old.is_preemptable() {
+        return None;
+    }
+    let next = next_ready_thread()?;
+    let to_sp = next.saved_sp();
+    old.lock().set_saved_sp(old_sp);
+    let mut hook_holder = ContextSwitchHookHolder::new(next);
+    if Thread::id(&old) == Thread::id(idle::current_idle_thread()) {
+        // We should never put idle thread to ready queue.
+        let ok = old.transfer_state(thread::RUNNING, thread::READY);
+        assert!(ok);
+    } else {
+        hook_holder.set_ready_thread(old);
+    }
+    Some((to_sp, hook_holder))
+}
+
 pub fn retire_me() -> ! {
     let next = next_ready_thread().map_or_else(|| idle::current_idle_thread().clone(), |v| v);
     let to_sp = next.saved_sp();
 
     let old = current_thread();
-    #[cfg(procfs)]
-    {
-        let _ = crate::vfs::trace_thread_close(old.clone());
-    }
     // FIXME: Some WaitQueue might still share the ownership of
     // the `old`, shall we record which WaitQueue the `old`
     // belongs to? Weak reference might not help to reduce memory
@@ -415,6 +445,7 @@ pub(crate) extern "C" fn schedule() -> ! {
     arch::enable_local_irq();
     assert!(arch::local_irq_enabled());
     loop {
+        crate::console::SEMIHOSTING_CONSOLE.flush_stale(time::get_sys_ticks());
         yield_me();
     }
 }
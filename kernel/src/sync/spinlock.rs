@@ -14,7 +14,8 @@
 
 use crate::{
     support::DisableInterruptGuard,
-    types::{IRwLock, IntrusiveAdapter, RwLock, RwLockWriteGuard},
+    time,
+    types::{IRwLock, IntrusiveAdapter, RwLock, RwLockWriteGuard, SpinArc},
 };
 use core::{
     ops::{Deref, DerefMut},
@@ -88,6 +89,36 @@ impl<T: ?Sized> SpinLock<T> {
         }
     }
 
+    /// Like `irqsave_lock`, but gives up after spinning for `ticks`
+    /// system ticks. IRQs are only disabled during each attempt, so the
+    /// ticks keep coming.
+    pub fn irqsave_lock_for(&self, ticks: usize) -> Option<SpinLockGuard<'_, T>> {
+        let start = time::get_sys_ticks();
+        loop {
+            if let Some(l) = self.try_irqsave_lock() {
+                return Some(l);
+            }
+            if time::get_sys_ticks().wrapping_sub(start) >= ticks {
+                return None;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Like `lock`, but gives up after spinning for `ticks` system ticks.
+    pub fn lock_for(&self, ticks: usize) -> Option<SpinLockGuard<'_, T>> {
+        let start = time::get_sys_ticks();
+        loop {
+            if let Some(l) = self.try_lock() {
+                return Some(l);
+            }
+            if time::get_sys_ticks().wrapping_sub(start) >= ticks {
+                return None;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
     pub fn try_lock(&self) -> Option<SpinLockGuard<'_, T>> {
         let mutex_guard = self.lock.try_write()?;
         Some(SpinLockGuard {
@@ -116,6 +147,34 @@ impl<'a, T: 'a + ?Sized> SpinLockGuard<'a, T> {
     }
 }
 
+/// Bounded locking of a `SpinArc`, holding it with IRQs disabled.
+pub trait SpinArcLockFor<T> {
+    /// Give up after spinning for `ticks` system ticks. IRQs are only
+    /// disabled during each attempt, so the ticks keep coming.
+    fn lock_for(&self, ticks: usize) -> Option<SpinLockGuard<'_, T>>;
+}
+
+impl<T> SpinArcLockFor<T> for SpinArc<T> {
+    fn lock_for(&self, ticks: usize) -> Option<SpinLockGuard<'_, T>> {
+        let start = time::get_sys_ticks();
+        let mut irq_guard = Some(DisableInterruptGuard::new());
+        compiler_fence(Ordering::SeqCst);
+        let mutex_guard = self.lock_while(|| {
+            irq_guard = None;
+            if time::get_sys_ticks().wrapping_sub(start) >= ticks {
+                return false;
+            }
+            irq_guard = Some(DisableInterruptGuard::new());
+            compiler_fence(Ordering::SeqCst);
+            true
+        })?;
+        Some(SpinLockGuard {
+            mutex_guard,
+            irq_guard,
+        })
+    }
+}
+
 unsafe impl<T: ?Sized + Send> Send for SpinLock<T> {}
 unsafe impl<T: ?Sized + Sync> Sync for SpinLock<T> {}
 
@@ -16,11 +16,13 @@ extern crate alloc;
 use core::ffi::{c_size_t, c_ssize_t};
 
 use crate::{
-    arch, asynk, net, scheduler,
+    arch, asynk,
+    devices::{random, tty::termios::Termios},
+    net, scheduler,
     sync::atomic_wait as futex,
     thread::{self, Builder, Entry, Stack, Thread, ThreadNode},
     time,
-    vfs::syscalls as vfs_syscalls,
+    vfs::{epoll::EpollEvent, syscalls as vfs_syscalls},
 };
 use alloc::boxed::Box;
 use blueos_header::{
@@ -29,8 +31,8 @@ use blueos_header::{
 };
 use core::sync::atomic::AtomicUsize;
 use libc::{
-    addrinfo, c_char, c_int, c_ulong, c_void, clockid_t, mode_t, msghdr, off_t, sigset_t, size_t,
-    sockaddr, socklen_t, timespec, EINVAL,
+    addrinfo, c_char, c_int, c_uint, c_ulong, c_void, clockid_t, mode_t, msghdr, nfds_t, off_t,
+    pollfd, sigset_t, size_t, sockaddr, socklen_t, timespec, timeval, EINVAL,
 };
 
 #[repr(C)]
@@ -73,6 +75,16 @@ pub struct sigaction {
     pub sa_mask: sigset_t,
 }
 
+#[allow(non_camel_case_types)]
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct itimerval {
+    pub it_interval: timeval,
+    pub it_value: timeval,
+}
+
+pub const ITIMER_REAL: c_int = 0;
+
 // For every syscall number in NR, we have to define a module to
 // handle the syscall request.  `handle_context` serves as the
 // dispatcher if syscall is invoked via software interrupt.
@@ -115,7 +127,10 @@ macro_rules! define_syscall_handler {
             // FIXME: Rustc miscompiles if inlined.
             #[inline(never)]
             pub fn handle($($arg: $argty),*) -> $ret {
-                $body
+                fn imp($($arg: $argty),*) -> $ret $body
+                let ret = imp($($arg),*);
+                thread::signal::deliver_pending();
+                ret
             }
 
             pub fn handle_context(ctx: &Context) -> usize {
@@ -213,6 +228,10 @@ define_syscall_handler!(open(path: *const c_char, flags: c_int, mode: mode_t) ->
     vfs_syscalls::open(path, flags, mode)
 });
 
+define_syscall_handler!(openat(dirfd: c_int, path: *const c_char, flags: c_int, mode: mode_t) -> c_int {
+    vfs_syscalls::openat(dirfd, path, flags, mode)
+});
+
 define_syscall_handler!(
     close(fd: c_int) -> c_int {
         vfs_syscalls::close(fd)
@@ -224,6 +243,18 @@ define_syscall_handler!(
     }
 );
 
+define_syscall_handler!(
+    pread(fd: c_int, buf: *mut c_void, count: size_t, offset: off_t) -> isize {
+        vfs_syscalls::pread(fd, buf as *mut u8, count as usize, offset)
+    }
+);
+
+define_syscall_handler!(
+    pwrite(fd: c_int, buf: *const c_void, count: size_t, offset: off_t) -> isize {
+        vfs_syscalls::pwrite(fd, buf as *const u8, count as usize, offset)
+    }
+);
+
 define_syscall_handler!(
     lseek(fildes: c_int, offset: usize, whence: c_int) -> c_int {
         vfs_syscalls::lseek(fildes, offset as i64, whence) as c_int
@@ -273,21 +304,121 @@ define_syscall_handler!(
         vfs_syscalls::link(oldpath, newpath)
     }
 );
+define_syscall_handler!(
+    symlink(target: *const c_char, linkpath: *const c_char) -> c_int {
+        vfs_syscalls::symlink(target, linkpath)
+    }
+);
+define_syscall_handler!(
+    readlink(path: *const c_char, buf: *mut c_char, bufsiz: size_t) -> isize {
+        vfs_syscalls::readlink(path, buf, bufsiz as usize)
+    }
+);
+define_syscall_handler!(
+    fsync(fd: c_int) -> c_int {
+        vfs_syscalls::fsync(fd)
+    }
+);
+define_syscall_handler!(
+    fdatasync(fd: c_int) -> c_int {
+        vfs_syscalls::fdatasync(fd)
+    }
+);
+define_syscall_handler!(
+    tcgetattr(fd: c_int, termios: *mut Termios) -> c_int {
+        vfs_syscalls::tcgetattr(fd, termios)
+    }
+);
+define_syscall_handler!(
+    tcsetattr(fd: c_int, optional_actions: c_int, termios: *const Termios) -> c_int {
+        vfs_syscalls::tcsetattr(fd, optional_actions, termios)
+    }
+);
+define_syscall_handler!(
+    ioctl(fd: c_int, request: c_ulong, arg: usize) -> c_int {
+        vfs_syscalls::ioctl(fd, request as u32, arg)
+    }
+);
+define_syscall_handler!(
+    getrandom(buf: *mut c_void, buflen: size_t, flags: c_uint) -> c_ssize_t {
+        random::getrandom(buf as *mut u8, buflen, flags)
+    }
+);
 define_syscall_handler!(
     unlink(path: *const c_char) -> c_int {
         vfs_syscalls::unlink(path)
     }
 );
+define_syscall_handler!(
+    unlinkat(dirfd: c_int, path: *const c_char, flags: c_int) -> c_int {
+        vfs_syscalls::unlinkat(dirfd, path, flags)
+    }
+);
+define_syscall_handler!(
+    rename(oldpath: *const c_char, newpath: *const c_char) -> c_int {
+        vfs_syscalls::rename(oldpath, newpath)
+    }
+);
 define_syscall_handler!(
     fcntl(fildes: c_int, cmd: c_int, arg: usize) -> c_int {
         vfs_syscalls::fcntl(fildes, cmd, arg)
     }
 );
+define_syscall_handler!(
+    dup(fildes: c_int) -> c_int {
+        vfs_syscalls::dup(fildes)
+    }
+);
+define_syscall_handler!(
+    dup2(fildes: c_int, fildes2: c_int) -> c_int {
+        vfs_syscalls::dup2(fildes, fildes2)
+    }
+);
+define_syscall_handler!(
+    dup3(fildes: c_int, fildes2: c_int, flags: c_int) -> c_int {
+        vfs_syscalls::dup3(fildes, fildes2, flags)
+    }
+);
+define_syscall_handler!(
+    pipe(fildes: *mut c_int) -> c_int {
+        vfs_syscalls::pipe(fildes)
+    }
+);
+define_syscall_handler!(
+    pipe2(fildes: *mut c_int, flags: c_int) -> c_int {
+        vfs_syscalls::pipe2(fildes, flags)
+    }
+);
+define_syscall_handler!(
+    epoll_create(size: c_int) -> c_int {
+        vfs_syscalls::epoll_create(size)
+    }
+);
+define_syscall_handler!(
+    epoll_create1(flags: c_int) -> c_int {
+        vfs_syscalls::epoll_create1(flags)
+    }
+);
+define_syscall_handler!(
+    epoll_ctl(epfd: c_int, op: c_int, fd: c_int, event: *mut c_void) -> c_int {
+        vfs_syscalls::epoll_ctl(epfd, op, fd, event as *mut EpollEvent)
+    }
+);
+define_syscall_handler!(
+    epoll_wait(epfd: c_int, events: *mut c_void, maxevents: c_int, timeout: c_int) -> c_int {
+        vfs_syscalls::epoll_wait(epfd, events as *mut EpollEvent, maxevents, timeout)
+    }
+);
 define_syscall_handler!(
     stat(path: *const c_char, buf: *mut c_char) -> c_int {
         vfs_syscalls::stat(path, buf as *mut Stat) as c_int
     }
 );
+define_syscall_handler!(
+    fstatat(dirfd: c_int, path: *const c_char, buf: *mut c_char, flags: c_int) -> c_int {
+        vfs_syscalls::fstatat(dirfd, path, buf as *mut Stat, flags) as c_int
+    }
+);
 
 define_syscall_handler!(
     fstat(fd: c_int, buf: *mut c_char) -> c_int {
@@ -299,6 +430,11 @@ define_syscall_handler!(
         vfs_syscalls::mkdir(path, mode)
     }
 );
+define_syscall_handler!(
+    mkdirat(dirfd: c_int, path: *const c_char, mode: mode_t) -> c_int {
+        vfs_syscalls::mkdirat(dirfd, path, mode)
+    }
+);
 define_syscall_handler!(
     statfs(path: *const c_char, buf: *mut c_char) -> c_int {
         vfs_syscalls::statfs(path, buf as *mut StatFs) as c_int
@@ -353,29 +489,148 @@ define_syscall_handler!(
         vfs_syscalls::umount(target)
     }
 );
+// sigset_t is read as a little-endian bitmask, signal n at bit n - 1.
+fn sigset_to_bits(set: *const sigset_t) -> u64 {
+    let mut bits = 0u64;
+    let n = core::mem::size_of::<sigset_t>().min(core::mem::size_of::<u64>());
+    unsafe {
+        core::ptr::copy_nonoverlapping(set as *const u8, &mut bits as *mut u64 as *mut u8, n)
+    };
+    bits
+}
+
+fn bits_to_sigset(bits: u64, set: *mut sigset_t) {
+    let n = core::mem::size_of::<sigset_t>().min(core::mem::size_of::<u64>());
+    unsafe {
+        core::ptr::write_bytes(set, 0, 1);
+        core::ptr::copy_nonoverlapping(&bits as *const u64 as *const u8, set as *mut u8, n);
+    }
+}
+
 define_syscall_handler!(
-    signalaction(_signum: c_int, _act: *const c_void, _oact: *mut c_void) -> c_int {
-        // TODO: implement signalaction
+    signalaction(signum: c_int, act: *const c_void, oact: *mut c_void) -> c_int {
+        use crate::thread::signal::{SigAction, SIG_DFL};
+        let act = unsafe { (act as *const sigaction).as_ref() }.map(|act| SigAction {
+            handler: act.sa_handler.map_or(SIG_DFL, |f| f as usize),
+            flags: act.sa_flags as usize,
+            mask: sigset_to_bits(&act.sa_mask),
+        });
+        let old = match thread::signal::sigaction(signum, act) {
+            Ok(old) => old,
+            Err(e) => return e.to_errno(),
+        };
+        if let Some(oact) = unsafe { (oact as *mut sigaction).as_mut() } {
+            oact.sa_handler = (old.handler != SIG_DFL)
+                .then(|| unsafe { core::mem::transmute::<usize, extern "C" fn(c_int)>(old.handler) });
+            oact.sa_flags = old.flags as c_ulong;
+            oact.sa_restorer = None;
+            bits_to_sigset(old.mask, &mut oact.sa_mask);
+        }
         0
     }
 );
 define_syscall_handler!(
-    signaltstack(_ss: *const c_void, _old_ss: *mut c_void) -> c_int {
+    signaltstack(ss: *const c_void, old_ss: *mut c_void) -> c_int {
+        use crate::thread::signal::AltStack;
+        let ss = unsafe { (ss as *const sigaltstack).as_ref() }.map(|ss| AltStack {
+            sp: ss.ss_sp as usize,
+            flags: ss.ss_flags,
+            size: ss.ss_size,
+        });
+        let old = thread::signal::sigaltstack(ss);
+        if let Some(old_ss) = unsafe { (old_ss as *mut sigaltstack).as_mut() } {
+            old_ss.ss_sp = old.sp as *mut c_void;
+            old_ss.ss_flags = old.flags;
+            old_ss.ss_size = old.size;
+        }
         0
     }
 );
 define_syscall_handler!(
-    sigpending(_set: *mut libc::sigset_t) -> c_int {
+    sigpending(set: *mut libc::sigset_t) -> c_int {
+        if set.is_null() {
+            return -libc::EFAULT;
+        }
+        bits_to_sigset(thread::signal::sigpending(), set);
         0
     }
 );
 define_syscall_handler!(
-    sigprocmask(_how: c_int, _set: *const libc::sigset_t, _oldset: *mut libc::sigset_t) -> c_int {
-        0
+    sigprocmask(how: c_int, set: *const libc::sigset_t, oldset: *mut libc::sigset_t) -> c_int {
+        let set = (!set.is_null()).then(|| sigset_to_bits(set));
+        match thread::signal::sigprocmask(how, set) {
+            Ok(old) => {
+                if !oldset.is_null() {
+                    bits_to_sigset(old, oldset);
+                }
+                0
+            }
+            Err(e) => e.to_errno(),
+        }
     }
 );
 define_syscall_handler!(
-    sigqueueinfo(_pid: c_int, _sig: c_int, _info: *const c_void) -> c_int {
+    sigqueueinfo(pid: c_int, sig: c_int, _info: *const c_void) -> c_int {
+        if pid < 0 {
+            return -EINVAL;
+        }
+        let Some(t) = thread::signal::find_thread(pid as usize) else {
+            return -libc::ESRCH;
+        };
+        // Signal 0 only checks that the target exists.
+        if sig == 0 {
+            return 0;
+        }
+        thread::signal::send(&t, sig).map_or_else(|e| e.to_errno(), |_| 0)
+    }
+);
+fn timeval_to_ticks(tv: &timeval) -> Option<usize> {
+    if tv.tv_sec < 0 || !(0..1_000_000).contains(&tv.tv_usec) {
+        return None;
+    }
+    let ms = (tv.tv_sec as usize)
+        .saturating_mul(1000)
+        .saturating_add((tv.tv_usec as usize).div_ceil(1000));
+    Some(time::tick_from_millisecond(ms))
+}
+
+fn ticks_to_timeval(ticks: usize) -> timeval {
+    let ms = time::tick_to_millisecond(ticks);
+    let mut tv: timeval = unsafe { core::mem::zeroed() };
+    tv.tv_sec = (ms / 1000).try_into().unwrap_or(libc::time_t::MAX);
+    tv.tv_usec = ((ms % 1000) * 1000) as _;
+    tv
+}
+
+define_syscall_handler!(
+    alarm(seconds: c_uint) -> c_uint {
+        let ticks = time::tick_from_millisecond((seconds as usize).saturating_mul(1000));
+        let (remaining, _) = thread::signal::set_itimer(ticks, 0);
+        // A pending alarm never reports 0 seconds left.
+        time::tick_to_millisecond(remaining)
+            .div_ceil(1000)
+            .try_into()
+            .unwrap_or(c_uint::MAX)
+    }
+);
+define_syscall_handler!(
+    setitimer(which: c_int, new: *const c_void, old: *mut c_void) -> c_int {
+        if which != ITIMER_REAL {
+            return -EINVAL;
+        }
+        let Some(new) = (unsafe { (new as *const itimerval).as_ref() }) else {
+            return -libc::EFAULT;
+        };
+        let (Some(value), Some(interval)) =
+            (timeval_to_ticks(&new.it_value), timeval_to_ticks(&new.it_interval))
+        else {
+            return -EINVAL;
+        };
+        let (remaining, old_interval) = thread::signal::set_itimer(value, interval);
+        if let Some(old) = unsafe { (old as *mut itimerval).as_mut() } {
+            old.it_value = ticks_to_timeval(remaining);
+            old.it_interval = ticks_to_timeval(old_interval);
+        }
         0
     }
 );
@@ -390,6 +645,12 @@ define_syscall_handler!(
     }
 );
 
+define_syscall_handler!(
+    poll(fds: *mut pollfd, nfds: nfds_t, timeout: c_int) -> c_int {
+        vfs_syscalls::poll(fds, nfds, timeout)
+    }
+);
+
 // Socket syscall begin
 define_syscall_handler!(
     socket(domain: c_int, type_: c_int, protocol_: c_int) -> c_int {
@@ -480,6 +741,18 @@ define_syscall_handler!(
     }
 );
 
+define_syscall_handler!(
+    getsockname(sockfd: c_int, addr: *mut sockaddr, addrlen: *mut socklen_t) -> c_int {
+        net::syscalls::getsockname(sockfd, addr, addrlen)
+    }
+);
+
+define_syscall_handler!(
+    getpeername(sockfd: c_int, addr: *mut sockaddr, addrlen: *mut socklen_t) -> c_int {
+        net::syscalls::getpeername(sockfd, addr, addrlen)
+    }
+);
+
 define_syscall_handler!(
     sendmsg(sockfd: c_int, message: *const msghdr, flags: c_int) -> c_ssize_t {
         net::syscalls::sendmsg(sockfd, message, flags)
@@ -565,6 +838,35 @@ syscall_table! {
     (Recvmsg,recvmsg),
     (GetAddrinfo,getaddrinfo),
     (FreeAddrinfo,freeaddrinfo),
+    (Poll, poll),
+    (Rename, rename),
+    (GetSockName, getsockname),
+    (GetPeerName, getpeername),
+    (Pread, pread),
+    (Pwrite, pwrite),
+    (Dup, dup),
+    (Dup2, dup2),
+    (Dup3, dup3),
+    (Pipe, pipe),
+    (Pipe2, pipe2),
+    (EpollCreate, epoll_create),
+    (EpollCreate1, epoll_create1),
+    (EpollCtl, epoll_ctl),
+    (EpollWait, epoll_wait),
+    (Alarm, alarm),
+    (SetITimer, setitimer),
+    (Openat, openat),
+    (Mkdirat, mkdirat),
+    (Unlinkat, unlinkat),
+    (Fstatat, fstatat),
+    (Symlink, symlink),
+    (Readlink, readlink),
+    (Fsync, fsync),
+    (Fdatasync, fdatasync),
+    (Tcgetattr, tcgetattr),
+    (Tcsetattr, tcsetattr),
+    (Ioctl, ioctl),
+    (GetRandom, getrandom),
 }
 
 // Begin syscall modules.
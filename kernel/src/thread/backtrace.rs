@@ -0,0 +1,115 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{Thread, ThreadNode, READY, RUNNING, SUSPENDED};
+use crate::{
+    arch,
+    error::{code, Error},
+    scheduler,
+};
+
+pub use arch::MAX_BACKTRACE_ADDRESSES;
+
+/// Capture return addresses of `t`, innermost first. The current
+/// thread is unwound live, other threads only from their saved context
+/// while they are ready or suspended, to avoid racing a running one.
+/// Returns EBUSY if `t` is running on another core, or EAGAIN if it
+/// got scheduled during the walk.
+pub fn backtrace(
+    t: &ThreadNode,
+    addrs: &mut [usize; MAX_BACKTRACE_ADDRESSES],
+) -> Result<usize, Error> {
+    let stack = t.stack_base()..t.stack_base() + t.stack_size();
+    if Thread::id(t) == scheduler::current_thread_id() {
+        let fp = arch::current_frame_pointer();
+        return Ok(arch::backtrace::unwind(fp, stack, addrs));
+    }
+    let state = t.state();
+    if state != READY && state != SUSPENDED {
+        return Err(code::EBUSY);
+    }
+    let saved_sp = t.saved_sp();
+    if saved_sp < stack.start || saved_sp + core::mem::size_of::<arch::Context>() > stack.end {
+        return Err(code::EINVAL);
+    }
+    let ctx = unsafe { &*(saved_sp as *const arch::Context) };
+    addrs[0] = ctx.pc();
+    let n = 1 + arch::backtrace::unwind(ctx.frame_pointer(), stack, &mut addrs[1..]);
+    if t.state() == RUNNING || t.saved_sp() != saved_sp {
+        return Err(code::EAGAIN);
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thread::spawn;
+    use blueos_test_macro::test;
+    use core::sync::atomic::{AtomicBool, Ordering};
+
+    static STOP: AtomicBool = AtomicBool::new(false);
+
+    #[inline(never)]
+    fn level2() {
+        while !STOP.load(Ordering::Acquire) {
+            scheduler::suspend_me_for(1);
+        }
+        core::hint::black_box(());
+    }
+
+    #[inline(never)]
+    fn level1() {
+        level2();
+        core::hint::black_box(());
+    }
+
+    fn returns_into(f: fn(), addr: usize) -> bool {
+        let start = f as usize;
+        addr > start && addr < start + 256
+    }
+
+    #[test]
+    fn test_backtrace_suspended_thread() {
+        STOP.store(false, Ordering::Release);
+        let t = spawn(|| level1()).unwrap();
+        let mut addrs = [0; MAX_BACKTRACE_ADDRESSES];
+        let n = loop {
+            if t.state() == SUSPENDED {
+                if let Ok(n) = backtrace(&t, &mut addrs) {
+                    break n;
+                }
+            }
+            scheduler::yield_me();
+        };
+        STOP.store(true, Ordering::Release);
+        let addrs = &addrs[..n];
+        assert!(addrs.iter().all(|&addr| addr != 0));
+        // level2 returns into level1, and the scheduler into level2.
+        let caller = addrs
+            .iter()
+            .position(|&addr| returns_into(level1, addr))
+            .unwrap();
+        assert!(addrs[..caller]
+            .iter()
+            .any(|&addr| returns_into(level2, addr)));
+    }
+
+    #[test]
+    fn test_backtrace_current_thread() {
+        let mut addrs = [0; MAX_BACKTRACE_ADDRESSES];
+        let n = backtrace(&scheduler::current_thread(), &mut addrs).unwrap();
+        assert!(n > 0 && n <= MAX_BACKTRACE_ADDRESSES);
+    }
+}
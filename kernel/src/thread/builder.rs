@@ -17,7 +17,7 @@ use crate::{
     arch, config, debug, scheduler, static_arc, thread, trace,
     types::{ArcInner, ArcList, ArcListIterator, IlistHead as ListHead, Uint},
 };
-use alloc::boxed::Box;
+use alloc::{boxed::Box, string::String};
 use config::SYSTEM_THREAD_STACK_SIZE;
 use core::mem::MaybeUninit;
 use spin::{Mutex, MutexGuard};
@@ -82,6 +82,8 @@ pub struct Builder {
     stack: Option<Stack>,
     entry: Entry,
     priority: ThreadPriority,
+    stack_guard: bool,
+    name: Option<String>,
 }
 
 impl Builder {
@@ -90,6 +92,8 @@ impl Builder {
             stack: None,
             entry,
             priority: config::MAX_THREAD_PRIORITY / 2,
+            stack_guard: false,
+            name: None,
         }
     }
 
@@ -105,6 +109,20 @@ impl Builder {
         self
     }
 
+    #[inline]
+    pub fn set_name(mut self, name: &str) -> Self {
+        self.name = Some(String::from(name));
+        self
+    }
+
+    // Arm an MPU guard region at the low end of the stack if the arch
+    // supports it, so that overflow traps instead of corrupting memory.
+    #[inline]
+    pub fn set_stack_guard(mut self, enable: bool) -> Self {
+        self.stack_guard = enable;
+        self
+    }
+
     pub fn build(mut self) -> ThreadNode {
         let thread = ThreadNode::new(Thread::new(ThreadKind::Normal));
         let mut w = thread.lock();
@@ -114,14 +132,15 @@ impl Builder {
         );
         w.init(stack, self.entry);
         w.set_priority(self.priority);
+        if self.stack_guard {
+            w.enable_stack_guard();
+        }
+        if let Some(name) = self.name.as_deref() {
+            w.set_name(name);
+        }
         drop(w);
         GlobalQueueVisitor::add(thread.clone());
 
-        #[cfg(procfs)]
-        {
-            let _ = crate::vfs::trace_thread_create(thread.clone());
-        }
-
         thread
     }
 
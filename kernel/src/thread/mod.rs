@@ -23,10 +23,18 @@ use crate::{
 use alloc::boxed::Box;
 use core::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
 
+#[cfg(target_arch = "aarch64")]
+mod backtrace;
 mod builder;
 mod posix;
+pub mod signal;
+mod tls;
+#[cfg(target_arch = "aarch64")]
+pub use backtrace::{backtrace, MAX_BACKTRACE_ADDRESSES};
 pub use builder::*;
 use posix::*;
+pub use tls::{tls_alloc_key, tls_get, tls_set, TlsKey};
+use tls::{tls_slots_new, TlsSlots};
 
 pub type ThreadNode = Arc<Thread>;
 
@@ -87,6 +95,31 @@ impl Stack {
     }
 }
 
+// Unused stack is painted with this pattern so that the deepest point
+// ever reached can be found by scanning up from the stack base.
+const STACK_PAINT: usize = usize::from_ne_bytes([0xa5; core::mem::size_of::<usize>()]);
+// The lowest word of every stack holds this canary. It's checked each
+// time the thread is switched out.
+const STACK_CANARY: usize = 0x57ac_ca9a_57ac_ca9a_u64 as usize;
+
+#[derive(Debug)]
+pub struct StackOverflow {
+    base: usize,
+    size: usize,
+}
+
+impl core::fmt::Display for StackOverflow {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "Stack overflow detected: canary at 0x{:x} of stack [0x{:x}, 0x{:x}) is clobbered",
+            self.base,
+            self.base,
+            self.base + self.size
+        )
+    }
+}
+
 impl_simple_intrusive_adapter!(OffsetOfSchedNode, Thread, sched_node);
 impl_simple_intrusive_adapter!(OffsetOfGlobal, Thread, global);
 impl_simple_intrusive_adapter!(OffsetOfLock, Thread, lock);
@@ -146,7 +179,13 @@ pub struct Thread {
     // whole struct except those atomic fields.
     lock: ISpinLock<Thread, OffsetOfLock>,
     posix_compat: Option<PosixCompat>,
+    signals: signal::SignalState,
     stats: ThreadStats,
+    tls: TlsSlots,
+    // Base of the MPU guard region at the low end of the stack.
+    stack_guard: Option<usize>,
+    name: [u8; config::MAX_THREAD_NAME_LEN],
+    name_len: usize,
 }
 
 extern "C" fn run_simple_c(f: extern "C" fn()) {
@@ -200,6 +239,49 @@ impl Thread {
         self.stack.base() + self.stack.size() - self.saved_sp()
     }
 
+    // Peak stack usage since the thread was initialized.
+    pub fn stack_high_water(&self) -> usize {
+        let base = self.stack.base();
+        let words = self.stack.size() / core::mem::size_of::<usize>();
+        // Skip the canary word.
+        let untouched = (1..words)
+            .take_while(|&i| unsafe { *(base as *const usize).add(i) } == STACK_PAINT)
+            .count();
+        self.stack
+            .size()
+            .saturating_sub((untouched + 1) * core::mem::size_of::<usize>())
+    }
+
+    pub fn check_stack_canary(&self) -> Result<(), StackOverflow> {
+        let base = self.stack.base();
+        if self.stack.size() == 0 || unsafe { *(base as *const usize) } == STACK_CANARY {
+            return Ok(());
+        }
+        Err(StackOverflow {
+            base,
+            size: self.stack.size(),
+        })
+    }
+
+    #[inline]
+    pub fn stack_guard(&self) -> Option<usize> {
+        self.stack_guard
+    }
+
+    // Reserve the low end of the stack as a guard region, which traps
+    // on overflow. It's a no-op if the arch has no MPU, in which case
+    // the stack canary is the only protection.
+    pub(crate) fn enable_stack_guard(&mut self) -> &mut Self {
+        #[cfg(all(target_arch = "arm", not(armv6m)))]
+        if arch::mpu::is_present() {
+            let guard = arch::mpu::stack_guard_base(self.stack.base());
+            if guard + arch::mpu::STACK_GUARD_SIZE < self.saved_sp {
+                self.stack_guard = Some(guard);
+            }
+        }
+        self
+    }
+
     #[inline]
     pub fn stack_base(&self) -> usize {
         self.stack.base()
@@ -233,6 +315,30 @@ impl Thread {
         self.kind
     }
 
+    #[inline]
+    pub fn name(&self) -> &str {
+        // set_name_bytes only keeps valid UTF-8.
+        unsafe { core::str::from_utf8_unchecked(&self.name[..self.name_len]) }
+    }
+
+    #[inline]
+    pub fn set_name(&mut self, name: &str) -> &mut Self {
+        self.set_name_bytes(name.as_bytes())
+    }
+
+    // The name is truncated to MAX_THREAD_NAME_LEN bytes, and then to
+    // its longest valid UTF-8 prefix.
+    pub fn set_name_bytes(&mut self, name: &[u8]) -> &mut Self {
+        let name = &name[..core::cmp::min(name.len(), config::MAX_THREAD_NAME_LEN)];
+        let len = match core::str::from_utf8(name) {
+            Ok(_) => name.len(),
+            Err(e) => e.valid_up_to(),
+        };
+        self.name[..len].copy_from_slice(&name[..len]);
+        self.name_len = len;
+        self
+    }
+
     #[inline]
     pub fn kind_to_str(&self) -> &str {
         match self.kind {
@@ -311,7 +417,12 @@ impl Thread {
             priority: 0,
             preempt_count: AtomicUint::new(0),
             posix_compat: None,
+            signals: signal::SignalState::new(),
             stats: ThreadStats::new(),
+            tls: tls_slots_new(),
+            stack_guard: None,
+            name: [0u8; config::MAX_THREAD_NAME_LEN],
+            name_len: 0,
             timer: None,
             #[cfg(robin_scheduler)]
             robin_count: AtomicI32::new(0),
@@ -361,6 +472,14 @@ impl Thread {
         // TODO: Stack sanity check.
         self.saved_sp =
             self.stack.base() + self.stack.size() - core::mem::size_of::<arch::Context>();
+        let base = self.stack.base() as *mut usize;
+        let words = (self.saved_sp - self.stack.base()) / core::mem::size_of::<usize>();
+        for i in 0..words {
+            unsafe { base.add(i).write_volatile(STACK_PAINT) };
+        }
+        if words > 0 {
+            unsafe { base.write_volatile(STACK_CANARY) };
+        }
         let region = Region {
             base: self.saved_sp,
             size: core::mem::size_of::<arch::Context>(),
@@ -472,3 +591,83 @@ impl Drop for PreemptGuard {
 
 impl !Send for Thread {}
 unsafe impl Sync for Thread {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blueos_test_macro::test;
+    use core::sync::atomic::AtomicBool;
+
+    const FRAME_SIZE: usize = 128;
+    const DEPTH: usize = 8;
+
+    #[inline(never)]
+    fn recurse(depth: usize) -> usize {
+        let frame = core::hint::black_box([depth as u8; FRAME_SIZE]);
+        if depth == 0 {
+            return frame[0] as usize;
+        }
+        recurse(depth - 1) + frame[FRAME_SIZE - 1] as usize
+    }
+
+    static RECURSED: AtomicBool = AtomicBool::new(false);
+
+    #[test]
+    fn test_stack_high_water() {
+        spawn(|| {
+            let current = scheduler::current_thread();
+            let usage = current.stack_usage();
+            assert!(current.stack_high_water() >= usage);
+            recurse(DEPTH);
+            let high_water = current.stack_high_water();
+            assert!(high_water >= usage + DEPTH * FRAME_SIZE);
+            assert!(high_water <= current.stack_size());
+            RECURSED.store(true, Ordering::Release);
+        });
+        while !RECURSED.load(Ordering::Acquire) {
+            scheduler::yield_me();
+        }
+    }
+
+    extern "C" fn never_run() {}
+
+    #[test]
+    fn test_thread_name_truncated() {
+        let mut t = Thread::new(ThreadKind::Normal);
+        assert_eq!(t.name(), "");
+        t.set_name("worker");
+        assert_eq!(t.name(), "worker");
+        t.set_name("a_very_long_thread_name");
+        assert_eq!(t.name(), "a_very_long_thre");
+        // "é" straddles the limit and is dropped as a whole.
+        t.set_name("fifteen_bytes__é");
+        assert_eq!(t.name(), "fifteen_bytes__");
+        t.set_name_bytes(b"bad\xffname");
+        assert_eq!(t.name(), "bad");
+    }
+
+    #[test]
+    fn test_stack_canary_overflow() {
+        const WORDS: usize = 256;
+        // The lower half is the memory adjacent to the stack, which
+        // the overflow runs into.
+        let mut storage = alloc::vec![0usize; 2 * WORDS];
+        let base = unsafe { storage.as_mut_ptr().add(WORDS) };
+        let mut t = Thread::new(ThreadKind::Normal);
+        t.init(
+            Stack::Raw {
+                base: base as usize,
+                size: WORDS * core::mem::size_of::<usize>(),
+            },
+            Entry::C(never_run),
+        );
+        assert!(t.check_stack_canary().is_ok());
+        // A frame straddling the stack base, as a runaway recursion
+        // would push.
+        unsafe { core::ptr::write_bytes(base.sub(2), 0, 4) };
+        let err = t.check_stack_canary().unwrap_err();
+        let msg = alloc::format!("{}", err);
+        assert!(msg.starts_with("Stack overflow detected"));
+        assert!(msg.contains(&alloc::format!("0x{:x}", base as usize)));
+    }
+}
@@ -0,0 +1,306 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Per-thread signal state. Signals are only delivered synchronously:
+// a handler runs on the thread's own stack right before a syscall
+// returns. Delivery from the timer interrupt and the alternate stack
+// are not supported yet; the alternate stack is only recorded. A
+// signal raised by ITIMER_REAL is thus seen on the next syscall.
+
+extern crate alloc;
+use super::{GlobalQueueVisitor, Thread, ThreadNode};
+use crate::{
+    error::{code, Error},
+    scheduler,
+    time::{self, timer::Timer},
+    types::Arc,
+};
+use alloc::boxed::Box;
+use core::ffi::c_int;
+
+// Numbering follows the Linux ABI used by librs.
+pub const NSIG: usize = 64;
+pub const SIGKILL: c_int = 9;
+pub const SIGUSR1: c_int = 10;
+pub const SIGUSR2: c_int = 12;
+pub const SIGALRM: c_int = 14;
+pub const SIGSTOP: c_int = 19;
+
+pub const SIG_BLOCK: c_int = 0;
+pub const SIG_UNBLOCK: c_int = 1;
+pub const SIG_SETMASK: c_int = 2;
+
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+pub const SA_NODEFER: usize = 0x4000_0000;
+
+const UNBLOCKABLE: u64 = sigbit(SIGKILL) | sigbit(SIGSTOP);
+
+#[inline]
+const fn sigbit(sig: c_int) -> u64 {
+    1 << (sig - 1)
+}
+
+fn check_signo(sig: c_int) -> Result<(), Error> {
+    if sig <= 0 || sig as usize > NSIG {
+        return Err(code::EINVAL);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SigAction {
+    // SIG_DFL, SIG_IGN or the address of an `extern "C" fn(c_int)`.
+    pub handler: usize,
+    pub flags: usize,
+    pub mask: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AltStack {
+    pub sp: usize,
+    pub flags: c_int,
+    pub size: usize,
+}
+
+#[derive(Debug)]
+pub(crate) struct SignalState {
+    // Allocated on the first sigaction, most threads never install one.
+    actions: Option<Box<[SigAction; NSIG]>>,
+    pending: u64,
+    blocked: u64,
+    altstack: AltStack,
+    // ITIMER_REAL, raising SIGALRM. The interval is in ticks, 0 if the
+    // timer is oneshot.
+    itimer: Option<Arc<Timer>>,
+    itimer_interval: usize,
+}
+
+impl Default for SignalState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SignalState {
+    pub const fn new() -> Self {
+        Self {
+            actions: None,
+            pending: 0,
+            blocked: 0,
+            altstack: AltStack {
+                sp: 0,
+                flags: 0,
+                size: 0,
+            },
+            itimer: None,
+            itimer_interval: 0,
+        }
+    }
+
+    fn action(&self, sig: c_int) -> SigAction {
+        self.actions
+            .as_ref()
+            .map_or_else(SigAction::default, |a| a[sig as usize - 1])
+    }
+
+    // Lowest numbered pending signal that isn't blocked.
+    fn take_deliverable(&mut self) -> Option<c_int> {
+        let ready = self.pending & !self.blocked;
+        if ready == 0 {
+            return None;
+        }
+        let sig = ready.trailing_zeros() as c_int + 1;
+        self.pending &= !sigbit(sig);
+        Some(sig)
+    }
+}
+
+/// Install `act` for `sig` if given and return the previous action.
+pub fn sigaction(sig: c_int, act: Option<SigAction>) -> Result<SigAction, Error> {
+    check_signo(sig)?;
+    let t = scheduler::current_thread();
+    let mut w = t.lock();
+    let old = w.signals.action(sig);
+    if let Some(mut act) = act {
+        if sigbit(sig) & UNBLOCKABLE != 0 {
+            return Err(code::EINVAL);
+        }
+        act.mask &= !UNBLOCKABLE;
+        let actions = w
+            .signals
+            .actions
+            .get_or_insert_with(|| Box::new([SigAction::default(); NSIG]));
+        actions[sig as usize - 1] = act;
+        // Setting SIG_IGN discards a pending instance.
+        if act.handler == SIG_IGN {
+            w.signals.pending &= !sigbit(sig);
+        }
+    }
+    Ok(old)
+}
+
+/// Update the blocked mask of the current thread as sigprocmask(2)
+/// does and return the previous mask.
+pub fn sigprocmask(how: c_int, set: Option<u64>) -> Result<u64, Error> {
+    let t = scheduler::current_thread();
+    let mut w = t.lock();
+    let old = w.signals.blocked;
+    if let Some(set) = set {
+        let blocked = match how {
+            SIG_BLOCK => old | set,
+            SIG_UNBLOCK => old & !set,
+            SIG_SETMASK => set,
+            _ => return Err(code::EINVAL),
+        };
+        w.signals.blocked = blocked & !UNBLOCKABLE;
+    }
+    Ok(old)
+}
+
+pub fn sigpending() -> u64 {
+    let t = scheduler::current_thread();
+    let pending = t.lock().signals.pending;
+    pending
+}
+
+pub fn sigaltstack(ss: Option<AltStack>) -> AltStack {
+    let t = scheduler::current_thread();
+    let mut w = t.lock();
+    let old = w.signals.altstack;
+    if let Some(ss) = ss {
+        w.signals.altstack = ss;
+    }
+    old
+}
+
+/// Mark `sig` pending on `t`. Ignored signals are dropped right away.
+pub fn send(t: &ThreadNode, sig: c_int) -> Result<(), Error> {
+    check_signo(sig)?;
+    let mut w = t.lock();
+    if w.signals.action(sig).handler != SIG_IGN {
+        w.signals.pending |= sigbit(sig);
+    }
+    Ok(())
+}
+
+/// Arm ITIMER_REAL of the current thread to raise SIGALRM after `value`
+/// ticks and then every `interval` ticks. A zero `value` disarms it.
+/// Returns the remaining ticks and the interval of the replaced timer.
+pub fn set_itimer(value: usize, interval: usize) -> (usize, usize) {
+    let t = scheduler::current_thread();
+    let (old, old_interval) = {
+        let mut w = t.lock();
+        let interval = if value == 0 { 0 } else { interval };
+        let old_interval = core::mem::replace(&mut w.signals.itimer_interval, interval);
+        (w.signals.itimer.take(), old_interval)
+    };
+    // Stop the old timer with the thread unlocked, its callback takes
+    // the lock.
+    let remaining = old.map_or(0, |timer| {
+        let remaining = if timer.is_activated() {
+            // Due in this tick still counts as pending.
+            timer
+                .timeout_ticks()
+                .saturating_sub(time::get_sys_ticks())
+                .max(1)
+        } else {
+            0
+        };
+        timer.stop();
+        remaining
+    });
+    if value != 0 {
+        let th = t.clone();
+        let timer = Timer::new_hard_oneshot(value, Box::new(move || raise_alarm(&th)));
+        t.lock().signals.itimer = Some(timer.clone());
+        timer.start();
+    }
+    (remaining, old_interval)
+}
+
+// Runs in IRQ.
+fn raise_alarm(t: &ThreadNode) {
+    let _ = send(t, SIGALRM);
+    let (timer, interval) = {
+        let w = t.lock();
+        (w.signals.itimer.clone(), w.signals.itimer_interval)
+    };
+    if interval == 0 {
+        return;
+    }
+    if let Some(timer) = timer {
+        timer.start_new_interval(interval);
+    }
+}
+
+/// Stop ITIMER_REAL of a retiring thread, its callback holds the
+/// thread alive.
+pub(crate) fn disarm_itimer(t: &ThreadNode) {
+    let timer = t.lock().signals.itimer.take();
+    if let Some(timer) = timer {
+        timer.stop();
+    }
+}
+
+/// Look up a thread by the id returned from gettid. 0 stands for the
+/// current thread.
+pub fn find_thread(id: usize) -> Option<ThreadNode> {
+    let current = scheduler::current_thread();
+    if id == 0 || id == Thread::id(&current) {
+        return Some(current);
+    }
+    let mut visitor = GlobalQueueVisitor::new();
+    while let Some(t) = visitor.next() {
+        if Thread::id(&t) == id {
+            return Some(t);
+        }
+    }
+    None
+}
+
+/// Run the handlers of all deliverable signals of the current thread.
+/// While a handler runs, its sa_mask and, unless SA_NODEFER is set, the
+/// signal itself are blocked.
+pub fn deliver_pending() {
+    let t = scheduler::current_thread();
+    loop {
+        let (sig, handler, saved) = {
+            let mut w = t.lock();
+            let Some(sig) = w.signals.take_deliverable() else {
+                return;
+            };
+            let act = w.signals.action(sig);
+            match act.handler {
+                SIG_IGN => continue,
+                SIG_DFL => {
+                    log::warn!("Default action of signal {} is not supported", sig);
+                    continue;
+                }
+                _ => {}
+            }
+            let saved = w.signals.blocked;
+            let mut blocked = saved | act.mask;
+            if act.flags & SA_NODEFER == 0 {
+                blocked |= sigbit(sig);
+            }
+            w.signals.blocked = blocked & !UNBLOCKABLE;
+            (sig, act.handler, saved)
+        };
+        let handler: extern "C" fn(c_int) = unsafe { core::mem::transmute(handler) };
+        handler(sig);
+        t.lock().signals.blocked = saved;
+    }
+}
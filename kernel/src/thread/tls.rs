@@ -0,0 +1,96 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{
+    config::MAX_TLS_KEYS,
+    error::{code, Error},
+    scheduler,
+};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+pub type TlsKey = usize;
+
+// Keys are shared by all threads and never freed, so that a slot can't
+// be handed to another user while a thread still holds a stale value.
+static NEXT_KEY: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) type TlsSlots = [AtomicUsize; MAX_TLS_KEYS];
+
+pub(crate) const fn tls_slots_new() -> TlsSlots {
+    [const { AtomicUsize::new(0) }; MAX_TLS_KEYS]
+}
+
+/// Allocate a key whose slot reads 0 in every thread until it is set.
+/// Returns EAGAIN when all keys are in use.
+pub fn tls_alloc_key() -> Result<TlsKey, Error> {
+    NEXT_KEY
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |key| {
+            (key < MAX_TLS_KEYS).then_some(key + 1)
+        })
+        .map_err(|_| code::EAGAIN)
+}
+
+fn check_key(key: TlsKey) -> Result<(), Error> {
+    if key >= NEXT_KEY.load(Ordering::Relaxed) {
+        return Err(code::EINVAL);
+    }
+    Ok(())
+}
+
+pub fn tls_get(key: TlsKey) -> Result<usize, Error> {
+    check_key(key)?;
+    Ok(scheduler::current_thread().tls[key].load(Ordering::Relaxed))
+}
+
+pub fn tls_set(key: TlsKey, val: usize) -> Result<(), Error> {
+    check_key(key)?;
+    scheduler::current_thread().tls[key].store(val, Ordering::Relaxed);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::thread;
+    use blueos_test_macro::test;
+
+    static DONE: AtomicUsize = AtomicUsize::new(0);
+
+    #[test]
+    fn test_tls_independent_values() {
+        let key = tls_alloc_key().unwrap();
+        assert_eq!(tls_get(key), Ok(0));
+        for i in 1..=2 {
+            thread::spawn(move || {
+                assert_eq!(tls_get(key), Ok(0));
+                assert_eq!(tls_set(key, i), Ok(()));
+                for _ in 0..8 {
+                    scheduler::yield_me();
+                    assert_eq!(tls_get(key), Ok(i));
+                }
+                DONE.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        while DONE.load(Ordering::Relaxed) != 2 {
+            scheduler::yield_me();
+        }
+        assert_eq!(tls_get(key), Ok(0));
+    }
+
+    #[test]
+    fn test_tls_invalid_key() {
+        assert_eq!(tls_get(MAX_TLS_KEYS), Err(code::EINVAL));
+        assert_eq!(tls_set(MAX_TLS_KEYS, 1), Err(code::EINVAL));
+    }
+}
@@ -0,0 +1,94 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::systick;
+use spin::Once;
+
+const NS_PER_SEC: u128 = 1_000_000_000;
+
+/// A free-running monotonic counter that backs the kernel's notion of
+/// elapsed time. The tick interrupt is driven by `Systick`, while
+/// cycle accounting and high-resolution timestamps read through this
+/// trait, so a board can pick the most precise counter it has.
+pub trait Clocksource: Sync {
+    /// Raw counter value, counted from boot.
+    fn read_cycles(&self) -> u64;
+
+    /// Counter frequency in Hz. Returns 0 if the counter is not ready.
+    fn frequency(&self) -> u64;
+
+    fn cycles_to_ns(&self, cycles: u64) -> u64 {
+        let freq = self.frequency();
+        if freq == 0 {
+            return 0;
+        }
+        (cycles as u128 * NS_PER_SEC / freq as u128) as u64
+    }
+
+    fn now_ns(&self) -> u64 {
+        self.cycles_to_ns(self.read_cycles())
+    }
+
+    fn resolution_ns(&self) -> u64 {
+        let freq = self.frequency();
+        if freq == 0 {
+            return 0;
+        }
+        core::cmp::max(1, (NS_PER_SEC / freq as u128) as u64)
+    }
+}
+
+static CLOCKSOURCE: Once<&'static dyn Clocksource> = Once::new();
+
+/// Replace the arch default clocksource. It must be called before the
+/// first read of the clocksource, otherwise cycles accounted so far
+/// would be mixed with cycles of another counter. Returns false if a
+/// clocksource is already in use.
+pub fn register(cs: &'static dyn Clocksource) -> bool {
+    let mut registered = false;
+    CLOCKSOURCE.call_once(|| {
+        registered = true;
+        cs
+    });
+    registered
+}
+
+#[inline]
+pub fn current() -> &'static dyn Clocksource {
+    *CLOCKSOURCE.call_once(systick::default_clocksource)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blueos_test_macro::test;
+
+    #[test]
+    fn test_clocksource_resolution() {
+        let cs = current();
+        assert!(cs.frequency() > 0);
+        assert!(cs.resolution_ns() > 0);
+    }
+
+    #[test]
+    fn test_clocksource_monotonic() {
+        let cs = current();
+        let mut last = cs.now_ns();
+        for _ in 0..1000 {
+            let now = cs.now_ns();
+            assert!(now >= last);
+            last = now;
+        }
+    }
+}
@@ -12,11 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+pub(crate) mod clocksource;
 pub(crate) mod systick;
 pub(crate) mod timer;
 
-use crate::{arch, boards, scheduler, support::DisableInterruptGuard, thread::Thread};
+use crate::{arch, scheduler, support::DisableInterruptGuard, thread::Thread};
 use blueos_kconfig::TICKS_PER_SECOND;
+pub use clocksource::Clocksource;
 use systick::SYSTICK;
 
 pub const WAITING_FOREVER: usize = usize::MAX;
@@ -30,15 +32,15 @@ pub fn get_sys_ticks() -> usize {
 }
 
 pub fn get_sys_cycles() -> u64 {
-    SYSTICK.get_cycles()
+    clocksource::current().read_cycles()
 }
 
 pub(crate) fn get_cycles_to_duration(cycles: u64) -> core::time::Duration {
-    boards::get_cycles_to_duration(cycles)
+    core::time::Duration::from_nanos(clocksource::current().cycles_to_ns(cycles))
 }
 
 pub(crate) fn get_cycles_to_ms(cycles: u64) -> u64 {
-    boards::get_cycles_to_ms(cycles)
+    clocksource::current().cycles_to_ns(cycles) / 1_000_000
 }
 
 pub fn reset_systick() {
@@ -60,23 +62,24 @@ pub extern "C" fn handle_tick_increment() {
     }
 }
 
+// Saturates at usize::MAX for long durations.
 pub fn tick_from_millisecond(ms: usize) -> usize {
     #[cfg(has_fpu)]
     {
-        let ticks = TICKS_PER_SECOND * (ms / 1000);
-        ticks + (TICKS_PER_SECOND * (ms % 1000) + 999) / 1000
+        let ticks = TICKS_PER_SECOND.saturating_mul(ms / 1000);
+        ticks.saturating_add((TICKS_PER_SECOND * (ms % 1000) + 999) / 1000)
     }
     // use 1024 as 1000 to aviod use math library
     #[cfg(not(has_fpu))]
     {
-        let ticks = TICKS_PER_SECOND.wrapping_mul(ms >> 10);
+        let ticks = TICKS_PER_SECOND.saturating_mul(ms >> 10);
         let remainder = ms & 0x3FF;
-        ticks.wrapping_add((TICKS_PER_SECOND.wrapping_mul(remainder) + 1023) >> 10)
+        ticks.saturating_add((TICKS_PER_SECOND * remainder + 1023) >> 10)
     }
 }
 
 pub fn tick_to_millisecond(ticks: usize) -> usize {
-    ticks * (1000 / TICKS_PER_SECOND)
+    ticks.saturating_mul(1000 / TICKS_PER_SECOND)
 }
 
 pub fn tick_get_millisecond() -> usize {
@@ -17,7 +17,7 @@ use crate::{
         irq::{enable_irq_with_priority, register_handler, IrqHandler, Priority},
         registers::{
             cntfrq_el0::CNTFRQ_EL0, cntp_ctl_el0::CNTP_CTL_EL0, cntp_tval_el0::CNTP_TVAL_EL0,
-            cntpct_el0::CNTPCT_EL0,
+            cntvct_el0::CNTVCT_EL0,
         },
     },
     boards,
@@ -30,8 +30,9 @@ use tock_registers::interfaces::{Readable, Writeable};
 pub const SYSTICK_IRQ_NUM: IrqNumber = IrqNumber::new(30);
 static BOOT_CYCLE_COUNT: Once<u64> = Once::new();
 fn get_boot_cycle_count() -> u64 {
-    *BOOT_CYCLE_COUNT.call_once(|| CNTPCT_EL0.get())
+    *BOOT_CYCLE_COUNT.call_once(|| CNTVCT_EL0.get())
 }
+static GENERIC_TIMER: GenericTimer = GenericTimer {};
 pub struct SystickIrq {}
 
 impl IrqHandler for SystickIrq {
@@ -58,13 +59,27 @@ impl Systick {
         true
     }
 
-    pub fn get_cycles(&self) -> u64 {
-        let current = CNTPCT_EL0.get();
+    pub fn reset_counter(&self) {
+        CNTP_TVAL_EL0.set(self.get_step() as u64);
+    }
+}
+
+// The virtual count of the generic timer, it's shared by all cores
+// and keeps counting while the cores are in wfi.
+pub struct GenericTimer {}
+
+impl Clocksource for GenericTimer {
+    fn read_cycles(&self) -> u64 {
+        let current = CNTVCT_EL0.get();
         let boot_cycle_count = get_boot_cycle_count();
         current.saturating_sub(boot_cycle_count)
     }
 
-    pub fn reset_counter(&self) {
-        CNTP_TVAL_EL0.set(self.get_step() as u64);
+    fn frequency(&self) -> u64 {
+        CNTFRQ_EL0.get()
     }
 }
+
+pub(crate) fn default_clocksource() -> &'static dyn Clocksource {
+    &GENERIC_TIMER
+}
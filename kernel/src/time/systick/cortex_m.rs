@@ -13,8 +13,9 @@
 // limitations under the License.
 
 use crate::arch::irq::IRQ_PRIORITY_FOR_SCHEDULER;
+use blueos_kconfig::TICKS_PER_SECOND;
 use cortex_m::{
-    peripheral::{scb::SystemHandler, syst::SystClkSource, SYST},
+    peripheral::{scb::SystemHandler, syst::SystClkSource, SCB, SYST},
     Peripherals,
 };
 
@@ -48,12 +49,41 @@ impl Systick {
 
     pub fn get_cycles(&self) -> u64 {
         let step = self.get_step() as u64;
-        let current = step - SYST::get_current() as u64;
-        let ticks = self.get_tick() as u64;
-        ticks * step + current
+        loop {
+            let ticks = self.get_tick();
+            let before = SYST::get_current();
+            let pending = SCB::is_pendst_pending();
+            let after = SYST::get_current();
+            if ticks != self.get_tick() {
+                continue;
+            }
+            // The counter has wrapped but the tick interrupt has not
+            // been taken yet, account the elapsed period by hand to
+            // keep the result monotonic.
+            let ticks = if pending || after > before {
+                ticks as u64 + 1
+            } else {
+                ticks as u64
+            };
+            return ticks * step + (step - after as u64);
+        }
     }
 
     pub fn reset_counter(&self) {
         // no need to reset counter
     }
 }
+
+impl Clocksource for Systick {
+    fn read_cycles(&self) -> u64 {
+        self.get_cycles()
+    }
+
+    fn frequency(&self) -> u64 {
+        (self.get_step() * TICKS_PER_SECOND) as u64
+    }
+}
+
+pub(crate) fn default_clocksource() -> &'static dyn Clocksource {
+    &SYSTICK
+}
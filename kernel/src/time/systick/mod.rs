@@ -15,7 +15,7 @@
 use crate::{
     arch::{self, irq::IrqNumber},
     config, scheduler,
-    time::timer,
+    time::{clocksource::Clocksource, timer},
 };
 use core::{
     cell::UnsafeCell,
@@ -18,8 +18,9 @@ use spin::Once;
 pub const SYSTICK_IRQ_NUM: IrqNumber = IrqNumber::new(arch::TIMER_INT);
 static BOOT_CYCLE_COUNT: Once<u64> = Once::new();
 fn get_boot_cycle_count() -> u64 {
-    *BOOT_CYCLE_COUNT.call_once(|| boards::current_cycles() as u64)
+    *BOOT_CYCLE_COUNT.call_once(|| boards::current_ticks() as u64)
 }
+static MTIME: Mtime = Mtime {};
 
 impl Systick {
     pub fn init(&self, _sys_clock: u32, tick_per_second: u32) -> bool {
@@ -33,11 +34,25 @@ impl Systick {
         true
     }
 
-    pub fn get_cycles(&self) -> u64 {
-        boards::current_cycles() as u64
-    }
-
     pub fn reset_counter(&self) {
         boards::set_timeout_after(self.get_step());
     }
 }
+
+// mtime is what the `time` CSR shadows. Unlike the `cycle` CSR, it
+// runs at a fixed frequency shared by all harts.
+pub struct Mtime {}
+
+impl Clocksource for Mtime {
+    fn read_cycles(&self) -> u64 {
+        (boards::current_ticks() as u64).saturating_sub(get_boot_cycle_count())
+    }
+
+    fn frequency(&self) -> u64 {
+        boards::NUM_TICKS_PER_SECOND as u64
+    }
+}
+
+pub(crate) fn default_clocksource() -> &'static dyn Clocksource {
+    &MTIME
+}
@@ -22,13 +22,22 @@ use bitflags::bitflags;
 use core::{
     cmp, fmt,
     mem::MaybeUninit,
-    sync::atomic::{AtomicU32, Ordering},
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
 };
 use log::warn;
 use sync::spinlock::SpinLock;
 use thread::{Entry, SystemThreadStorage, Thread, ThreadKind, ThreadNode};
 
-const TIMER_WHEEL_SIZE: u32 = 32;
+// The wheel has WHEEL_LEVELS levels of WHEEL_SIZE slots. A slot of level
+// n covers WHEEL_SIZE^n ticks, timers are filed to the lowest level whose
+// span can hold them and cascade down as the wheel turns.
+const WHEEL_BITS: usize = 5;
+const WHEEL_SIZE: usize = 1 << WHEEL_BITS;
+const WHEEL_MASK: usize = WHEEL_SIZE - 1;
+const WHEEL_LEVELS: usize = 4;
+// Timers farther than this are parked at the top level and filed again
+// when their slot cascades.
+const WHEEL_MAX_DELTA: usize = (1 << (WHEEL_BITS * WHEEL_LEVELS)) - 1;
 
 static HARD_TIMER_WHEEL: TimerWheel = TimerWheel::const_new();
 #[cfg(soft_timer)]
@@ -88,7 +97,7 @@ fn wakeup_soft_timer_thread() {
 }
 
 struct TimerWheel {
-    wheel: SpinLock<[WheelTimerList; TIMER_WHEEL_SIZE as usize]>,
+    wheel: SpinLock<WheelInner>,
 }
 
 unsafe impl Sync for TimerWheel {}
@@ -96,46 +105,54 @@ unsafe impl Sync for TimerWheel {}
 impl TimerWheel {
     const fn const_new() -> Self {
         Self {
-            wheel: SpinLock::const_new(
-                [const { WheelTimerList::const_new() }; TIMER_WHEEL_SIZE as usize],
-            ),
+            wheel: SpinLock::const_new(WheelInner::const_new()),
         }
     }
 
     fn init(&self) {
-        let mut wheel = self.wheel.irqsave_lock();
-        for i in 0..wheel.len() {
-            let ok = wheel[i].init();
-            debug_assert!(ok);
-        }
+        self.wheel.irqsave_lock().init();
     }
 
     fn add_timer(&self, timer: Arc<Timer>, timeout_ticks: usize) {
-        let mut wheel = self.wheel.irqsave_lock();
-        let cursor = timeout_ticks & (TIMER_WHEEL_SIZE as usize - 1);
-        let it = wheel[cursor].iter();
-        for t in it {
-            if t.timeout_ticks() > timeout_ticks {
-                WheelTimerList::insert_before(
-                    unsafe { WheelTimerList::list_head_of_mut(&t) },
-                    timer,
-                );
-                return;
-            }
-        }
-        wheel[cursor].push_back(timer.clone());
+        #[cfg(soft_timer)]
+        let is_soft = timer.is_soft();
+        self.wheel.irqsave_lock().file(timer, timeout_ticks);
         #[cfg(soft_timer)]
         {
-            if timer.is_soft() {
+            if is_soft {
                 wakeup_soft_timer_thread();
             }
         }
     }
 
+    // Re-arm a periodic timer from the deadline it has just expired at, so
+    // that late callbacks don't accumulate drift. Periods which have been
+    // missed entirely are skipped rather than fired in a burst.
+    fn rearm(&self, timer: Arc<Timer>) {
+        let interval = timer.interval();
+        let mut wheel = self.wheel.irqsave_lock();
+        // Stopped or re-armed by its callback in the meantime.
+        if !timer.is_activated() || !timer.wheel_node.is_detached() {
+            return;
+        }
+        if interval == 0 {
+            timer
+                .flags
+                .fetch_and(!TimerFlags::ACTIVATED.bits(), Ordering::Relaxed);
+            return;
+        }
+        let mut timeout_ticks = timer.timeout_ticks().saturating_add(interval);
+        if timeout_ticks < wheel.current {
+            let missed = (wheel.current - timeout_ticks).div_ceil(interval);
+            timeout_ticks = timeout_ticks.saturating_add(missed * interval);
+        }
+        wheel.file(timer, timeout_ticks);
+    }
+
     fn remove_timer(&self, timer: &Arc<Timer>) {
-        let lock = self.wheel.irqsave_lock();
-        WheelTimerList::detach(timer);
-        drop(lock);
+        let mut wheel = self.wheel.irqsave_lock();
+        wheel.dequeue(timer);
+        drop(wheel);
         #[cfg(soft_timer)]
         {
             if timer.is_soft() {
@@ -145,44 +162,184 @@ impl TimerWheel {
     }
 
     fn next_timeout(&self) -> usize {
-        let mut next_timeout_tick = usize::MAX;
-        let wheel = self.wheel.irqsave_lock();
-        for i in 0..TIMER_WHEEL_SIZE as usize {
-            if let Some(timer) = wheel[i].iter().next() {
-                let timeout_ticks = timer.timeout_ticks();
-                if timeout_ticks < next_timeout_tick {
-                    next_timeout_tick = timeout_ticks;
+        self.wheel.irqsave_lock().next_timeout()
+    }
+
+    fn check_timer(&self, current_ticks: usize) -> bool {
+        let mut need_reschedule = false;
+        self.wheel.irqsave_lock().advance(current_ticks);
+        // Expired timers are popped one by one with the wheel unlocked, so
+        // that callbacks are free to arm timers, including themselves.
+        loop {
+            let Some(timer) = self.wheel.irqsave_lock().pop_expired() else {
+                break;
+            };
+            timer.run();
+            need_reschedule = true;
+            if timer.is_periodic() {
+                self.rearm(timer);
+            }
+        }
+        need_reschedule
+    }
+}
+
+struct WheelInner {
+    // The next tick to be processed.
+    current: usize,
+    // Arming sequence, keeps timers expiring at the same tick in FIFO order.
+    seq: usize,
+    pending: [usize; WHEEL_LEVELS],
+    levels: [[WheelTimerList; WHEEL_SIZE]; WHEEL_LEVELS],
+    // Timers which have expired but whose callbacks have not run yet.
+    expired: WheelTimerList,
+}
+
+impl WheelInner {
+    const fn const_new() -> Self {
+        Self {
+            current: 0,
+            seq: 0,
+            pending: [0; WHEEL_LEVELS],
+            levels: [const { [const { WheelTimerList::const_new() }; WHEEL_SIZE] }; WHEEL_LEVELS],
+            expired: WheelTimerList::const_new(),
+        }
+    }
+
+    fn init(&mut self) {
+        for level in self.levels.iter_mut() {
+            for list in level.iter_mut() {
+                let ok = list.init();
+                debug_assert!(ok);
+            }
+        }
+        let ok = self.expired.init();
+        debug_assert!(ok);
+    }
+
+    fn file(&mut self, timer: Arc<Timer>, timeout_ticks: usize) {
+        timer.timeout_ticks.store(timeout_ticks, Ordering::Relaxed);
+        timer.seq.store(self.seq, Ordering::Relaxed);
+        self.seq = self.seq.wrapping_add(1);
+        self.enqueue(timer, false);
+    }
+
+    // Timers armed directly always carry the latest seq and are appended,
+    // cascaded timers are inserted by seq to keep each slot in FIFO order.
+    fn enqueue(&mut self, timer: Arc<Timer>, in_order: bool) {
+        let timeout_ticks = timer.timeout_ticks();
+        let (level, index) = if timeout_ticks < self.current {
+            (0, self.current & WHEEL_MASK)
+        } else {
+            let delta = cmp::min(timeout_ticks - self.current, WHEEL_MAX_DELTA);
+            let mut level = 0;
+            while delta >= 1 << (WHEEL_BITS * (level + 1)) {
+                level += 1;
+            }
+            let index = ((self.current + delta) >> (WHEEL_BITS * level)) & WHEEL_MASK;
+            (level, index)
+        };
+        timer.level.store(level, Ordering::Relaxed);
+        self.pending[level] += 1;
+        let list = &mut self.levels[level][index];
+        if in_order {
+            let seq = timer.seq.load(Ordering::Relaxed);
+            for t in list.iter() {
+                if t.seq.load(Ordering::Relaxed).wrapping_sub(seq) as isize > 0 {
+                    WheelTimerList::insert_before(
+                        unsafe { WheelTimerList::list_head_of_mut(&t) },
+                        timer,
+                    );
+                    return;
                 }
             }
         }
-        next_timeout_tick
+        list.push_back(timer);
     }
 
-    fn check_timer(&self, current_ticks: usize) -> bool {
-        let mut need_reschedule = false;
-        let cursor = current_ticks & (TIMER_WHEEL_SIZE as usize - 1);
-        let mut task_list = WheelTimerList::new();
-        task_list.init();
-        {
-            let wheel = self.wheel.irqsave_lock();
-            let mut iter = wheel[cursor].iter();
-            for timer in &mut iter {
-                if timer.timeout_ticks() > current_ticks {
-                    break;
+    fn dequeue(&mut self, timer: &Arc<Timer>) {
+        let level = timer.level.load(Ordering::Relaxed);
+        if WheelTimerList::detach(timer) && level < WHEEL_LEVELS {
+            self.pending[level] -= 1;
+        }
+    }
+
+    // File the timers of the slot the wheel has just entered at `level`
+    // to lower levels. Returns the index of that slot.
+    fn cascade(&mut self, level: usize) -> usize {
+        let index = (self.current >> (WHEEL_BITS * level)) & WHEEL_MASK;
+        for timer in self.levels[level][index].iter() {
+            WheelTimerList::detach(&timer);
+            self.pending[level] -= 1;
+            self.enqueue(timer, true);
+        }
+        index
+    }
+
+    fn advance(&mut self, ticks: usize) {
+        while self.current <= ticks {
+            if self.pending.iter().all(|&n| n == 0) {
+                self.current = ticks + 1;
+                break;
+            }
+            let index = self.current & WHEEL_MASK;
+            if index == 0 {
+                let mut level = 1;
+                while level < WHEEL_LEVELS && self.cascade(level) == 0 {
+                    level += 1;
                 }
+            }
+            for timer in self.levels[0][index].iter() {
                 WheelTimerList::detach(&timer);
-                task_list.push_back(timer);
+                self.pending[0] -= 1;
+                timer.level.store(WHEEL_LEVELS, Ordering::Relaxed);
+                self.expired.push_back(timer);
+            }
+            self.current += 1;
+            if self.pending[0] != 0 {
+                continue;
+            }
+            // Nothing expires before the lowest populated level cascades,
+            // skip the empty ticks in between.
+            if let Some(level) = (1..WHEEL_LEVELS).find(|&l| self.pending[l] != 0) {
+                let span = 1 << (WHEEL_BITS * level);
+                let next = (self.current + span - 1) & !(span - 1);
+                self.current = cmp::min(next, ticks + 1);
             }
         }
+    }
 
-        while let Some(timer) = task_list.pop_front() {
-            timer.run();
-            need_reschedule = true;
-            if timer.is_periodic() {
-                timer.start();
+    fn pop_expired(&mut self) -> Option<Arc<Timer>> {
+        let timer = self.expired.iter().next()?;
+        WheelTimerList::detach(&timer);
+        Some(timer)
+    }
+
+    fn next_timeout(&self) -> usize {
+        let mut next_timeout_tick = usize::MAX;
+        for level in 0..WHEEL_LEVELS {
+            if self.pending[level] == 0 {
+                continue;
+            }
+            let shift = WHEEL_BITS * level;
+            let mut index = self.current >> shift;
+            // Unless the wheel is about to cascade it, the current slot of an
+            // upper level holds timers of the next round.
+            if self.current & ((1 << shift) - 1) != 0 {
+                index += 1;
+            }
+            for i in 0..WHEEL_SIZE {
+                let mut found = false;
+                for timer in self.levels[level][(index + i) & WHEEL_MASK].iter() {
+                    found = true;
+                    next_timeout_tick = cmp::min(next_timeout_tick, timer.timeout_ticks());
+                }
+                if found {
+                    break;
+                }
             }
         }
-        need_reschedule
+        next_timeout_tick
     }
 }
 
@@ -192,6 +349,7 @@ bitflags! {
         const SOFT_TIMER = 1 << 0;
         const PERIODIC = 1 << 1;
         const ACTIVATED = 1 << 2;
+        const RUNNING = 1 << 3;
     }
 }
 
@@ -202,22 +360,20 @@ type WheelTimerList = ArcList<Timer, OffsetOfWheelNode>;
 pub struct Timer {
     pub wheel_node: IlistHead<Timer, OffsetOfWheelNode>, // lock by TimerWheel
     flags: AtomicU32,
+    timeout_ticks: AtomicUsize,
+    seq: AtomicUsize,   // lock by TimerWheel
+    level: AtomicUsize, // lock by TimerWheel
     inner: SpinLock<Inner>,
 }
 
 struct Inner {
     interval: usize,
-    timeout_ticks: usize,
     callback: Option<Box<dyn Fn() + Send + Sync>>,
 }
 
 impl fmt::Debug for Inner {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "interval: {}, timeout_ticks: {}",
-            self.interval, self.timeout_ticks
-        )
+        write!(f, "interval: {}", self.interval)
     }
 }
 
@@ -244,20 +400,32 @@ impl Timer {
         Self::new(interval, TimerFlags::PERIODIC, callback)
     }
 
+    /// A periodic timer fires every `interval` ticks counted from the
+    /// deadline it was armed for, however late its callback ran.
+    pub fn new_periodic(interval: usize, callback: Box<dyn Fn() + Send + Sync>) -> Arc<Self> {
+        Self::new_hard_periodic(interval, callback)
+    }
+
     fn new(interval: usize, flags: TimerFlags, callback: Box<dyn Fn() + Send + Sync>) -> Arc<Self> {
         Arc::new(Self {
             wheel_node: IlistHead::const_new(),
             flags: AtomicU32::new(flags.bits()),
+            timeout_ticks: AtomicUsize::new(0),
+            seq: AtomicUsize::new(0),
+            level: AtomicUsize::new(WHEEL_LEVELS),
             inner: SpinLock::new(Inner {
                 interval,
-                timeout_ticks: 0,
                 callback: Some(callback),
             }),
         })
     }
 
     pub fn timeout_ticks(&self) -> usize {
-        self.inner.irqsave_lock().timeout_ticks
+        self.timeout_ticks.load(Ordering::Relaxed)
+    }
+
+    fn interval(&self) -> usize {
+        self.inner.irqsave_lock().interval
     }
 
     pub fn is_soft(&self) -> bool {
@@ -272,6 +440,10 @@ impl Timer {
         self.flags.load(Ordering::Relaxed) & TimerFlags::ACTIVATED.bits() != 0
     }
 
+    fn is_running(&self) -> bool {
+        self.flags.load(Ordering::Relaxed) & TimerFlags::RUNNING.bits() != 0
+    }
+
     pub fn set_callback(&self, callback: Box<dyn Fn() + Send + Sync>) {
         self.inner.irqsave_lock().callback = Some(callback);
     }
@@ -299,16 +471,21 @@ impl Timer {
         }
 
         let mut inner = self.inner.irqsave_lock();
-        if inner.callback.is_none() {
+        // A running callback is allowed to re-arm its own timer.
+        if inner.callback.is_none() && !self.is_running() {
             warn!("timer callback is None");
             return;
         }
         if inner.interval == 0 {
             // just run the callback and return
-            inner.callback.take().unwrap()();
+            let callback = inner.callback.take();
+            drop(inner);
+            if let Some(callback) = callback {
+                callback();
+            }
             return;
         }
-        inner.timeout_ticks = get_sys_ticks().saturating_add(inner.interval);
+        let timeout_ticks = get_sys_ticks().saturating_add(inner.interval);
         self.flags
             .fetch_or(TimerFlags::ACTIVATED.bits(), Ordering::Relaxed);
 
@@ -316,14 +493,14 @@ impl Timer {
         let timer = unsafe { WheelTimerList::make_arc_from(&self.wheel_node) };
         #[cfg(soft_timer)]
         if is_soft {
-            SOFT_TIMER_WHEEL.add_timer(timer, inner.timeout_ticks);
+            SOFT_TIMER_WHEEL.add_timer(timer, timeout_ticks);
         } else {
-            HARD_TIMER_WHEEL.add_timer(timer, inner.timeout_ticks);
+            HARD_TIMER_WHEEL.add_timer(timer, timeout_ticks);
         }
 
         #[cfg(not(soft_timer))]
         {
-            HARD_TIMER_WHEEL.add_timer(timer, inner.timeout_ticks);
+            HARD_TIMER_WHEEL.add_timer(timer, timeout_ticks);
         }
     }
 
@@ -351,7 +528,7 @@ impl Timer {
 
         let mut inner = self.inner.irqsave_lock();
         inner.interval = interval;
-        inner.timeout_ticks = get_sys_ticks().saturating_add(interval);
+        let timeout_ticks = get_sys_ticks().saturating_add(interval);
         self.flags
             .fetch_or(TimerFlags::ACTIVATED.bits(), Ordering::Relaxed);
 
@@ -359,14 +536,14 @@ impl Timer {
         let timer = unsafe { WheelTimerList::make_arc_from(&self.wheel_node) };
         #[cfg(soft_timer)]
         if is_soft {
-            SOFT_TIMER_WHEEL.add_timer(timer, inner.timeout_ticks);
+            SOFT_TIMER_WHEEL.add_timer(timer, timeout_ticks);
         } else {
-            HARD_TIMER_WHEEL.add_timer(timer, inner.timeout_ticks);
+            HARD_TIMER_WHEEL.add_timer(timer, timeout_ticks);
         }
 
         #[cfg(not(soft_timer))]
         {
-            HARD_TIMER_WHEEL.add_timer(timer, inner.timeout_ticks);
+            HARD_TIMER_WHEEL.add_timer(timer, timeout_ticks);
         }
     }
 
@@ -406,22 +583,22 @@ impl Timer {
                     .fetch_and(!TimerFlags::ACTIVATED.bits(), Ordering::Relaxed);
                 SOFT_TIMER_WHEEL.remove_timer(&timer);
             }
-            let mut inner = self.inner.irqsave_lock();
-            inner.timeout_ticks = get_sys_ticks().saturating_add(inner.interval);
+            let inner = self.inner.irqsave_lock();
+            let timeout_ticks = get_sys_ticks().saturating_add(inner.interval);
             self.flags
                 .fetch_or(TimerFlags::ACTIVATED.bits(), Ordering::Relaxed);
-            SOFT_TIMER_WHEEL.add_timer(timer, inner.timeout_ticks);
+            SOFT_TIMER_WHEEL.add_timer(timer, timeout_ticks);
         } else {
             if self.is_activated() {
                 self.flags
                     .fetch_and(!TimerFlags::ACTIVATED.bits(), Ordering::Relaxed);
                 HARD_TIMER_WHEEL.remove_timer(&timer);
             }
-            let mut inner = self.inner.irqsave_lock();
-            inner.timeout_ticks = get_sys_ticks().saturating_add(inner.interval);
+            let inner = self.inner.irqsave_lock();
+            let timeout_ticks = get_sys_ticks().saturating_add(inner.interval);
             self.flags
                 .fetch_or(TimerFlags::ACTIVATED.bits(), Ordering::Relaxed);
-            HARD_TIMER_WHEEL.add_timer(timer, inner.timeout_ticks);
+            HARD_TIMER_WHEEL.add_timer(timer, timeout_ticks);
         }
 
         #[cfg(not(soft_timer))]
@@ -431,27 +608,39 @@ impl Timer {
                     .fetch_and(!TimerFlags::ACTIVATED.bits(), Ordering::Relaxed);
                 HARD_TIMER_WHEEL.remove_timer(&timer);
             }
-            let mut inner = self.inner.irqsave_lock();
-            inner.timeout_ticks = get_sys_ticks().saturating_add(inner.interval);
+            let inner = self.inner.irqsave_lock();
+            let timeout_ticks = get_sys_ticks().saturating_add(inner.interval);
             self.flags
                 .fetch_or(TimerFlags::ACTIVATED.bits(), Ordering::Relaxed);
-            HARD_TIMER_WHEEL.add_timer(timer, inner.timeout_ticks);
+            HARD_TIMER_WHEEL.add_timer(timer, timeout_ticks);
         }
     }
 
     // this function can only used in check_timer and tests
     pub fn run(&self) {
-        if self.is_activated() {
+        if !self.is_activated() {
+            return;
+        }
+        // A periodic timer stays activated until it is stopped, the wheel
+        // re-arms it after the callback returns.
+        if !self.is_periodic() {
             self.flags
                 .fetch_and(!TimerFlags::ACTIVATED.bits(), Ordering::Relaxed);
+        }
+        self.flags
+            .fetch_or(TimerFlags::RUNNING.bits(), Ordering::Relaxed);
+        // The callback runs with the timer unlocked, so it can re-arm or
+        // stop the timer.
+        let callback = self.inner.irqsave_lock().callback.take();
+        if let Some(callback) = callback {
+            callback();
             let mut inner = self.inner.irqsave_lock();
-            if let Some(callback) = inner.callback.take() {
-                callback();
-                if self.is_periodic() {
-                    inner.callback = Some(callback);
-                }
+            if inner.callback.is_none() && (self.is_periodic() || self.is_activated()) {
+                inner.callback = Some(callback);
             }
         }
+        self.flags
+            .fetch_and(!TimerFlags::RUNNING.bits(), Ordering::Relaxed);
     }
 }
 
@@ -1097,4 +1286,214 @@ mod tests {
 
         timer1.stop();
     }
+
+    #[test]
+    fn test_timer_wheel_many_timers() {
+        const NUM_TIMERS: usize = 1024;
+        let mut timers = Vec::new();
+        let mut fired = Vec::new();
+
+        // Deadlines are staggered across the first three levels of the wheel.
+        for i in 0..NUM_TIMERS {
+            let fired_tick = Arc::new(AtomicUsize::new(0));
+            let t = fired_tick.clone();
+            let timer = Timer::new_hard_oneshot(
+                1 + (i * 7) % 1100,
+                Box::new(move || {
+                    t.store(get_sys_ticks(), Ordering::Relaxed);
+                }),
+            );
+            timer.start();
+            timers.push(timer);
+            fired.push(fired_tick);
+        }
+        scheduler::suspend_me_for(1101);
+
+        for (timer, fired_tick) in timers.iter().zip(fired.iter()) {
+            assert!(!timer.is_activated());
+            assert_eq!(fired_tick.load(Ordering::Relaxed), timer.timeout_ticks());
+        }
+    }
+
+    #[test]
+    fn test_timer_wheel_same_tick_order() {
+        let wheel = TimerWheel::const_new();
+        wheel.init();
+        let order = Arc::new(SpinLock::new(Vec::new()));
+        let arm = |id: usize, timeout_ticks: usize| {
+            let order = order.clone();
+            let timer = Timer::new_hard_oneshot(
+                1,
+                Box::new(move || {
+                    order.irqsave_lock().push(id);
+                }),
+            );
+            timer
+                .flags
+                .fetch_or(TimerFlags::ACTIVATED.bits(), Ordering::Relaxed);
+            wheel.add_timer(timer.clone(), timeout_ticks);
+            timer
+        };
+
+        // Timer 0 is filed to an upper level and cascades down after
+        // timer 1 has been filed to the lowest level directly.
+        let _t0 = arm(0, 100);
+        wheel.check_timer(69);
+        let _t1 = arm(1, 100);
+        let _t2 = arm(2, 99);
+        assert_eq!(wheel.next_timeout(), 99);
+
+        wheel.check_timer(98);
+        assert!(order.irqsave_lock().is_empty());
+        wheel.check_timer(99);
+        assert_eq!(*order.irqsave_lock(), [2]);
+        wheel.check_timer(100);
+        assert_eq!(*order.irqsave_lock(), [2, 0, 1]);
+        assert_eq!(wheel.next_timeout(), usize::MAX);
+    }
+
+    #[test]
+    fn test_timer_wheel_stress() {
+        const NUM_TIMERS: usize = 2048;
+        let wheel = TimerWheel::const_new();
+        wheel.init();
+        let now = Arc::new(AtomicUsize::new(0));
+        let total = Arc::new(AtomicUsize::new(0));
+        let new_counters = || Arc::new((0..NUM_TIMERS).map(|_| AtomicUsize::new(0)).collect());
+        let fired_at: Arc<Vec<AtomicUsize>> = new_counters();
+        let fire_count: Arc<Vec<AtomicUsize>> = new_counters();
+
+        // Deadlines are staggered over every level, the last ones beyond
+        // the span of the wheel are parked at the top level first.
+        let deadline = |i: usize| {
+            if i % 256 == 255 {
+                WHEEL_MAX_DELTA + 1 + i
+            } else {
+                1 + (i * 37) % 40000
+            }
+        };
+        let mut timers = Vec::new();
+        for i in 0..NUM_TIMERS {
+            let (now, total) = (now.clone(), total.clone());
+            let (fired_at, fire_count) = (fired_at.clone(), fire_count.clone());
+            let timer = Timer::new_hard_oneshot(
+                1,
+                Box::new(move || {
+                    fired_at[i].store(now.load(Ordering::Relaxed), Ordering::Relaxed);
+                    fire_count[i].fetch_add(1, Ordering::Relaxed);
+                    total.fetch_add(1, Ordering::Relaxed);
+                }),
+            );
+            timer
+                .flags
+                .fetch_or(TimerFlags::ACTIVATED.bits(), Ordering::Relaxed);
+            wheel.add_timer(timer.clone(), deadline(i));
+            timers.push(timer);
+        }
+
+        let mut deadlines: Vec<usize> = (0..NUM_TIMERS).map(deadline).collect();
+        deadlines.sort_unstable();
+        let mut ticks = deadlines.clone();
+        ticks.dedup();
+        for &tick in ticks.iter() {
+            // Nothing fires early.
+            now.store(tick - 1, Ordering::Relaxed);
+            wheel.check_timer(tick - 1);
+            let due = deadlines.partition_point(|&d| d < tick);
+            assert_eq!(total.load(Ordering::Relaxed), due);
+            assert_eq!(wheel.next_timeout(), tick);
+            now.store(tick, Ordering::Relaxed);
+            wheel.check_timer(tick);
+        }
+
+        assert_eq!(total.load(Ordering::Relaxed), NUM_TIMERS);
+        assert_eq!(wheel.next_timeout(), usize::MAX);
+        for i in 0..NUM_TIMERS {
+            assert_eq!(fire_count[i].load(Ordering::Relaxed), 1);
+            assert_eq!(fired_at[i].load(Ordering::Relaxed), deadline(i));
+        }
+    }
+
+    #[test]
+    fn test_timer_rearm_in_callback() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let timer = Timer::new_hard_oneshot(5, Box::new(|| {}));
+        let t = timer.clone();
+        let c = counter.clone();
+        // The callback holds the timer until it stops re-arming.
+        timer.set_callback(Box::new(move || {
+            if c.fetch_add(1, Ordering::Relaxed) < 2 {
+                t.start();
+            }
+        }));
+        timer.start();
+
+        scheduler::suspend_me_for(20);
+        assert_eq!(counter.load(Ordering::Relaxed), 3);
+        assert!(!timer.is_activated());
+    }
+
+    #[test]
+    fn test_periodic_timer_no_drift() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let callback = create_test_callback(counter.clone());
+        let timer = Timer::new_periodic(10, callback);
+
+        timer.start();
+        let first_timeout = timer.timeout_ticks();
+        scheduler::suspend_me_for(100);
+        timer.stop();
+
+        assert_eq!(counter.load(Ordering::Relaxed), 10);
+        assert_eq!(timer.timeout_ticks(), first_timeout + 100);
+    }
+
+    #[test]
+    fn test_periodic_timer_late_check() {
+        let wheel = TimerWheel::const_new();
+        wheel.init();
+        let counter = Arc::new(AtomicUsize::new(0));
+        let callback = create_test_callback(counter.clone());
+        let timer = Timer::new_periodic(10, callback);
+        timer
+            .flags
+            .fetch_or(TimerFlags::ACTIVATED.bits(), Ordering::Relaxed);
+        wheel.add_timer(timer.clone(), 10);
+
+        wheel.check_timer(15);
+        assert_eq!(counter.load(Ordering::Relaxed), 1);
+        assert_eq!(timer.timeout_ticks(), 20);
+
+        // The expiry at 20 is handled late and the one at 30 is missed,
+        // the timer keeps its phase.
+        wheel.check_timer(37);
+        assert_eq!(counter.load(Ordering::Relaxed), 2);
+        assert_eq!(timer.timeout_ticks(), 40);
+
+        timer
+            .flags
+            .fetch_and(!TimerFlags::ACTIVATED.bits(), Ordering::Relaxed);
+        wheel.remove_timer(&timer);
+        assert_eq!(wheel.next_timeout(), usize::MAX);
+    }
+
+    #[test]
+    fn test_periodic_timer_stop_in_callback() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let timer = Timer::new_periodic(5, Box::new(|| {}));
+        let t = timer.clone();
+        let c = counter.clone();
+        timer.set_callback(Box::new(move || {
+            if c.fetch_add(1, Ordering::Relaxed) == 2 {
+                t.stop();
+            }
+        }));
+        timer.start();
+
+        scheduler::suspend_me_for(30);
+        assert_eq!(counter.load(Ordering::Relaxed), 3);
+        assert!(!timer.is_activated());
+        // Drop the callback holding the timer.
+        timer.set_callback(Box::new(|| {}));
+    }
 }
@@ -16,9 +16,10 @@ pub use blueos_infra::{
     impl_simple_intrusive_adapter,
     intrusive::Adapter as IntrusiveAdapter,
     list::typed_ilist::ListHead as IlistHead,
+    spinarc::SpinArc,
     tinyarc::{
         TinyArc as Arc, TinyArcInner as ArcInner, TinyArcList as ArcList,
-        TinyArcListIterator as ArcListIterator,
+        TinyArcListIterator as ArcListIterator, TinyWeak as Weak,
     },
     tinyrwlock::{IRwLock, RwLock, RwLockReadGuard, RwLockWriteGuard},
 };
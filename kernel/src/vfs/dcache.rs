@@ -129,6 +129,24 @@ impl Dcache {
         Ok(child)
     }
 
+    pub fn symlink(&self, name: &str, target: &str) -> Result<Arc<Self>, Error> {
+        if self.inode.type_() != InodeFileType::Directory {
+            return Err(code::ENOTDIR);
+        }
+        let mut children = self.children.write();
+        if children.contains_key(name) {
+            return Err(code::EEXIST);
+        }
+
+        let inode = self.inode.symlink(name, target)?;
+        let name_str = String::from(name);
+        let child = Self::new(inode, name_str.clone(), self.get_weak_ref());
+        if child.is_dcacheable() {
+            children.insert(name_str, child.clone());
+        }
+        Ok(child)
+    }
+
     pub fn lookup(&self, name: &str) -> Result<Arc<Dcache>, Error> {
         if name.len() > NAME_MAX {
             return Err(code::ENAMETOOLONG);
@@ -355,6 +373,12 @@ impl Dcache {
             return Err(code::EINVAL);
         }
 
+        if ptr::addr_eq(self, Arc::as_ptr(new_dir)) && old_name == new_name {
+            return self.lookup(old_name).map(|_| ());
+        }
+        // Make sure the entry is cached
+        self.lookup(old_name)?;
+
         let mut children = self.children.write();
         let child = match children.get(old_name) {
             Some(child) => child.clone(),
@@ -368,25 +392,31 @@ impl Dcache {
             return Err(code::EBUSY);
         }
 
-        // rename in the same directory
-        if ptr::addr_eq(self, Arc::as_ptr(new_dir)) && old_name != new_name {
-            if children.contains_key(new_name) {
-                debug!("{} already exists", new_name);
-                return Err(code::EEXIST);
+        // An existing target is replaced by the filesystem
+        if ptr::addr_eq(self, Arc::as_ptr(new_dir)) {
+            if children.get(new_name).is_some_and(|c| c.is_mount_point()) {
+                debug!("{} is a mount point", new_name);
+                return Err(code::EBUSY);
             }
             self.inode.rename(old_name, &self.inode, new_name)?;
             children.remove(old_name);
+            children.remove(new_name);
+            child.set_name_and_parent(new_name, self.this.clone());
             if child.is_dcacheable() {
                 children.insert(String::from(new_name), child);
             }
         } else {
             let mut new_children = new_dir.children.write();
-            if new_children.contains_key(new_name) {
-                debug!("{} already exists", new_name);
-                return Err(code::EEXIST);
+            if new_children
+                .get(new_name)
+                .is_some_and(|c| c.is_mount_point())
+            {
+                debug!("{} is a mount point", new_name);
+                return Err(code::EBUSY);
             }
             self.inode.rename(old_name, &new_dir.inode, new_name)?;
             children.remove(old_name);
+            new_children.remove(new_name);
             child.set_name_and_parent(new_name, new_dir.this.clone());
             if child.is_dcacheable() {
                 new_children.insert(String::from(new_name), child);
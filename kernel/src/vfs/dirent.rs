@@ -133,6 +133,8 @@ impl Dirent {
 pub struct DirBufferReader<'a> {
     buf: &'a mut [u8],
     read_pos: usize,
+    // d_off of the last entry written.
+    last_off: Option<i64>,
     _marker: PhantomData<&'a mut [u8]>,
 }
 
@@ -141,6 +143,7 @@ impl<'a> DirBufferReader<'a> {
         Self {
             buf,
             read_pos: 0,
+            last_off: None,
             _marker: PhantomData,
         }
     }
@@ -170,6 +173,7 @@ impl<'a> DirBufferReader<'a> {
             .copy_from_slice(&name_bytes[..name_len]);
         self.buf[self.read_pos + Dirent::NAME_OFFSET + name_len] = 0;
         self.read_pos += dirent_size;
+        self.last_off = Some(off);
 
         Ok(())
     }
@@ -177,6 +181,14 @@ impl<'a> DirBufferReader<'a> {
     pub fn recv_len(&self) -> usize {
         self.read_pos
     }
+
+    // Directory offset to resume from, given the offset this read
+    // started at and the number of entries written. Filesystems may use
+    // cookies rather than indexes as d_off, so resume after the last
+    // entry's d_off.
+    pub fn next_offset(&self, offset: usize, count: usize) -> usize {
+        self.last_off.map_or(offset + count, |off| off as usize + 1)
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,442 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Readiness notification for epoll_wait().
+//!
+//! Each watched fd stays registered on its poll queues with a callback that
+//! moves it to the ready list, so a wait only visits the fds notified since
+//! the last one instead of polling the whole interest set. Level-triggered
+//! fds are put back on the ready list as long as they are still ready.
+//! An interest goes away with the file it was added for, so closing the fd
+//! drops it and the fd number may be added again once reused.
+
+use crate::{
+    error::{code, Error},
+    sync::{atomic_wait as futex, spinlock::SpinLock},
+    time::{self, WAITING_FOREVER},
+    vfs::{
+        file::{FileAttr, FileOps, OpenFlags},
+        poll::{PollEvents, PollQueue, PollTable},
+    },
+};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{
+    ffi::c_int,
+    sync::atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64, AtomicUsize, Ordering},
+};
+use spin::Mutex;
+
+pub const EPOLL_CTL_ADD: c_int = 1;
+pub const EPOLL_CTL_DEL: c_int = 2;
+pub const EPOLL_CTL_MOD: c_int = 3;
+// Same values as the POLL* ones
+pub const EPOLLIN: u32 = 0x001;
+pub const EPOLLPRI: u32 = 0x002;
+pub const EPOLLOUT: u32 = 0x004;
+pub const EPOLLERR: u32 = 0x008;
+pub const EPOLLHUP: u32 = 0x010;
+pub const EPOLLET: u32 = 1 << 31;
+
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EpollEvent {
+    pub events: u32,
+    pub data: u64,
+}
+
+struct EpollEntry {
+    // Not kept open by the interest
+    file: Weak<dyn FileOps>,
+    events: AtomicU32,
+    data: AtomicU64,
+    // Registration on the poll queues of the file, None once removed
+    table: Mutex<Option<PollTable>>,
+    // On the ready list
+    queued: AtomicBool,
+}
+
+impl EpollEntry {
+    fn events(&self) -> PollEvents {
+        PollEvents::from_bits_truncate(self.events.load(Ordering::Relaxed) as u16 as i16)
+    }
+
+    fn is_edge_triggered(&self) -> bool {
+        self.events.load(Ordering::Relaxed) & EPOLLET != 0
+    }
+
+    // The fd it was added for has been closed.
+    fn is_closed(&self) -> bool {
+        self.file.strong_count() == 0
+    }
+
+    fn poll(&self) -> PollEvents {
+        let mut table = self.table.lock();
+        let Some(table) = table.as_mut() else {
+            return PollEvents::empty();
+        };
+        let Some(file) = self.file.upgrade() else {
+            return PollEvents::empty();
+        };
+        let events = self.events();
+        file.poll(events, table) & (events | PollEvents::ALWAYS)
+    }
+
+    fn unregister(&self) {
+        drop(self.table.lock().take());
+    }
+}
+
+struct Epoll {
+    interests: Mutex<BTreeMap<c_int, Arc<EpollEntry>>>,
+    // Pushed to by the poll queue callbacks, which may run in IRQ
+    ready: SpinLock<VecDeque<Arc<EpollEntry>>>,
+    // Bumped when an entry is put on the ready list
+    seq: AtomicUsize,
+    // Waiters of the epoll fd itself
+    poll_queue: Arc<PollQueue>,
+}
+
+impl Epoll {
+    fn new() -> Self {
+        Self {
+            interests: Mutex::new(BTreeMap::new()),
+            ready: SpinLock::new(VecDeque::new()),
+            seq: AtomicUsize::new(0),
+            poll_queue: Arc::new(PollQueue::new()),
+        }
+    }
+
+    // Returns false if the entry is already on the ready list.
+    fn queue(&self, entry: &Arc<EpollEntry>) -> bool {
+        if entry.queued.swap(true, Ordering::AcqRel) {
+            return false;
+        }
+        self.ready.irqsave_lock().push_back(entry.clone());
+        true
+    }
+
+    fn mark_ready(&self, entry: &Arc<EpollEntry>) {
+        if self.queue(entry) {
+            self.seq.fetch_add(1, Ordering::Release);
+            let _ = futex::atomic_wake(&self.seq, usize::MAX);
+            self.poll_queue.notify();
+        }
+    }
+
+    fn add(
+        self: &Arc<Self>,
+        fd: c_int,
+        file: Arc<dyn FileOps>,
+        event: &EpollEvent,
+    ) -> Result<(), Error> {
+        let mut interests = self.interests.lock();
+        // Interests in closed fds are dropped here, the fd may have been reused
+        interests.retain(|_, entry| {
+            let closed = entry.is_closed();
+            if closed {
+                entry.unregister();
+            }
+            !closed
+        });
+        if interests.contains_key(&fd) {
+            return Err(code::EEXIST);
+        }
+        let entry = Arc::new(EpollEntry {
+            file: Arc::downgrade(&file),
+            events: AtomicU32::new(event.events),
+            data: AtomicU64::new(event.data),
+            table: Mutex::new(None),
+            queued: AtomicBool::new(false),
+        });
+        let epoll = Arc::downgrade(self);
+        let weak_entry = Arc::downgrade(&entry);
+        *entry.table.lock() = Some(PollTable::with_callback(Box::new(move || {
+            let Some(epoll) = epoll.upgrade() else {
+                return;
+            };
+            if let Some(entry) = weak_entry.upgrade() {
+                epoll.mark_ready(&entry);
+            }
+        })));
+        interests.insert(fd, entry.clone());
+        drop(interests);
+        // Registers on the poll queues of the file, which may block
+        if !entry.poll().is_empty() {
+            self.mark_ready(&entry);
+        }
+        Ok(())
+    }
+
+    // Returns the live interest in `fd`, dropping it if the fd was closed.
+    fn get(&self, fd: c_int) -> Result<Arc<EpollEntry>, Error> {
+        let mut interests = self.interests.lock();
+        let entry = interests.get(&fd).cloned().ok_or(code::ENOENT)?;
+        if entry.is_closed() {
+            interests.remove(&fd);
+            entry.unregister();
+            return Err(code::ENOENT);
+        }
+        Ok(entry)
+    }
+
+    fn modify(&self, fd: c_int, event: &EpollEvent) -> Result<(), Error> {
+        let entry = self.get(fd)?;
+        entry.events.store(event.events, Ordering::Relaxed);
+        entry.data.store(event.data, Ordering::Relaxed);
+        if !entry.poll().is_empty() {
+            self.mark_ready(&entry);
+        }
+        Ok(())
+    }
+
+    fn remove(&self, fd: c_int) -> Result<(), Error> {
+        let entry = self.interests.lock().remove(&fd).ok_or(code::ENOENT)?;
+        let closed = entry.is_closed();
+        // Unregistered from the poll queues, a stale ready entry is skipped
+        // by the next wait.
+        entry.unregister();
+        if closed {
+            return Err(code::ENOENT);
+        }
+        Ok(())
+    }
+
+    fn clear(&self) {
+        let interests = core::mem::take(&mut *self.interests.lock());
+        for entry in interests.values() {
+            entry.unregister();
+        }
+        self.ready.irqsave_lock().clear();
+    }
+
+    // Reports the entries of the ready list which are still ready.
+    fn collect(&self, events: &mut [EpollEvent]) -> usize {
+        let mut count = 0;
+        let mut still_ready = Vec::new();
+        // Entries queued in the meantime are left to the next call
+        let pending = self.ready.irqsave_lock().len();
+        for _ in 0..pending {
+            if count == events.len() {
+                break;
+            }
+            let Some(entry) = self.ready.irqsave_lock().pop_front() else {
+                break;
+            };
+            // Cleared before polling, so that a notification in between
+            // queues it again.
+            entry.queued.store(false, Ordering::Release);
+            let revents = entry.poll();
+            if revents.is_empty() {
+                continue;
+            }
+            events[count] = EpollEvent {
+                events: revents.bits() as u16 as u32,
+                data: entry.data.load(Ordering::Relaxed),
+            };
+            count += 1;
+            if !entry.is_edge_triggered() {
+                still_ready.push(entry);
+            }
+        }
+        for entry in still_ready.iter() {
+            self.queue(entry);
+        }
+        count
+    }
+
+    fn wait(&self, events: &mut [EpollEvent], timeout: i32) -> usize {
+        let deadline = match timeout {
+            t if t < 0 => None,
+            t => {
+                Some(time::get_sys_ticks().saturating_add(time::tick_from_millisecond(t as usize)))
+            }
+        };
+        loop {
+            // Sample before checking, so that a notification in between isn't lost.
+            let seq = self.seq.load(Ordering::Acquire);
+            let ready = self.collect(events);
+            if ready > 0 {
+                return ready;
+            }
+            let ticks = match deadline {
+                None => WAITING_FOREVER,
+                Some(deadline) => {
+                    let now = time::get_sys_ticks();
+                    if now >= deadline {
+                        return 0;
+                    }
+                    deadline - now
+                }
+            };
+            let timeout = if ticks == WAITING_FOREVER {
+                None
+            } else {
+                Some(ticks)
+            };
+            if futex::atomic_wait(&self.seq, seq, timeout) == Err(code::ETIMEDOUT) {
+                return 0;
+            }
+        }
+    }
+}
+
+/// An epoll instance
+pub struct EpollFile {
+    epoll: Arc<Epoll>,
+    open_flags: AtomicI32,
+}
+
+impl EpollFile {
+    pub fn new(flags: OpenFlags) -> Self {
+        Self {
+            epoll: Arc::new(Epoll::new()),
+            open_flags: AtomicI32::new(flags.bits()),
+        }
+    }
+
+    /// Add, modify or remove the interest in `fd`.
+    pub fn ctl(
+        &self,
+        op: c_int,
+        fd: c_int,
+        file: Arc<dyn FileOps>,
+        event: &EpollEvent,
+    ) -> Result<(), Error> {
+        match op {
+            EPOLL_CTL_ADD => self.epoll.add(fd, file, event),
+            EPOLL_CTL_MOD => self.epoll.modify(fd, event),
+            EPOLL_CTL_DEL => self.epoll.remove(fd),
+            _ => Err(code::EINVAL),
+        }
+    }
+
+    /// Wait until any watched fd is ready or `timeout` milliseconds elapse.
+    /// A negative timeout waits forever, zero returns immediately.
+    pub fn wait(&self, events: &mut [EpollEvent], timeout: i32) -> usize {
+        self.epoll.wait(events, timeout)
+    }
+}
+
+impl FileOps for EpollFile {
+    fn close(&self) -> Result<(), Error> {
+        // Unregister now, a callback running meanwhile must not be the one
+        // dropping the instance.
+        self.epoll.clear();
+        Ok(())
+    }
+
+    fn poll(&self, events: PollEvents, table: &mut PollTable) -> PollEvents {
+        table.register(&self.epoll.poll_queue);
+        if self.epoll.ready.irqsave_lock().is_empty() {
+            PollEvents::empty()
+        } else {
+            events & PollEvents::POLLIN
+        }
+    }
+
+    fn stat(&self) -> FileAttr {
+        FileAttr {
+            mode: 0o600,
+            nlinks: 1,
+            ..Default::default()
+        }
+    }
+
+    fn flags(&self) -> OpenFlags {
+        OpenFlags::from_bits_truncate(self.open_flags.load(Ordering::Relaxed))
+    }
+
+    fn set_flags(&self, flags: OpenFlags) {
+        self.open_flags.store(flags.bits(), Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::pipe;
+    use blueos_test_macro::test;
+
+    #[test]
+    fn test_epoll_level_triggered() {
+        let epoll = EpollFile::new(OpenFlags::empty());
+        let (reader, writer) = pipe::new_pipe(OpenFlags::O_NONBLOCK);
+        let event = EpollEvent {
+            events: EPOLLIN,
+            data: 7,
+        };
+        assert_eq!(epoll.ctl(EPOLL_CTL_ADD, 3, reader.clone(), &event), Ok(()));
+        assert_eq!(
+            epoll.ctl(EPOLL_CTL_ADD, 3, reader.clone(), &event),
+            Err(code::EEXIST)
+        );
+
+        let mut events = [EpollEvent::default(); 4];
+        assert_eq!(epoll.wait(&mut events, 0), 0);
+
+        assert_eq!(writer.write(b"abc"), Ok(3));
+        // Reported until it's drained
+        for _ in 0..2 {
+            assert_eq!(epoll.wait(&mut events, 0), 1);
+            assert_eq!(events[0].events, EPOLLIN);
+            assert_eq!(events[0].data, 7);
+        }
+        let mut buf = [0u8; 8];
+        assert_eq!(reader.read(&mut buf), Ok(3));
+        assert_eq!(epoll.wait(&mut events, 0), 0);
+
+        assert_eq!(epoll.ctl(EPOLL_CTL_DEL, 3, reader.clone(), &event), Ok(()));
+        assert_eq!(writer.write(b"abc"), Ok(3));
+        assert_eq!(epoll.wait(&mut events, 0), 0);
+        assert_eq!(
+            epoll.ctl(EPOLL_CTL_DEL, 3, reader, &event),
+            Err(code::ENOENT)
+        );
+        assert_eq!(epoll.close(), Ok(()));
+    }
+
+    #[test]
+    fn test_epoll_closed_fd() {
+        let epoll = EpollFile::new(OpenFlags::empty());
+        let (reader, writer) = pipe::new_pipe(OpenFlags::O_NONBLOCK);
+        let event = EpollEvent {
+            events: EPOLLIN,
+            data: 7,
+        };
+        assert_eq!(epoll.ctl(EPOLL_CTL_ADD, 3, reader.clone(), &event), Ok(()));
+        assert_eq!(writer.write(b"abc"), Ok(3));
+        // Closing the fd drops the interest, even though it's ready
+        drop(reader);
+        let mut events = [EpollEvent::default(); 4];
+        assert_eq!(epoll.wait(&mut events, 0), 0);
+
+        // The fd number is reused for another file
+        let (reader, writer) = pipe::new_pipe(OpenFlags::O_NONBLOCK);
+        assert_eq!(epoll.ctl(EPOLL_CTL_ADD, 3, reader.clone(), &event), Ok(()));
+        assert_eq!(writer.write(b"abc"), Ok(3));
+        assert_eq!(epoll.wait(&mut events, 0), 1);
+        assert_eq!(events[0].events, EPOLLIN);
+        drop(reader);
+        assert_eq!(
+            epoll.ctl(EPOLL_CTL_MOD, 3, writer.clone(), &event),
+            Err(code::ENOENT)
+        );
+        assert_eq!(epoll.close(), Ok(()));
+    }
+}
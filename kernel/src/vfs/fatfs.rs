@@ -32,6 +32,7 @@ use alloc::{
     sync::{Arc, Weak},
 };
 use core::{
+    ptr,
     sync::atomic::{AtomicBool, AtomicUsize, Ordering},
     time::Duration,
 };
@@ -271,13 +272,20 @@ impl FileSystem for FatFileSystem {
         if !self.check_mounted() {
             return Err(code::EINVAL);
         }
+        // Sectors cached by the block device would be lost otherwise
+        self.sync()?;
         self.is_mounted.store(false, Ordering::Relaxed);
         Ok(())
     }
 
     fn sync(&self) -> Result<(), Error> {
-        let (internal_fs, _) = get_internal_fs_with_guard(&self.device_name);
+        let (internal_fs, guard) = get_internal_fs_with_guard(&self.device_name);
         internal_fs.flush_fs_info()?;
+        drop(guard);
+        let device = DeviceManager::get()
+            .get_block_device(&self.device_name)
+            .ok_or(code::ENODEV)?;
+        device.sync()?;
         Ok(())
     }
 
@@ -312,14 +320,14 @@ enum FatFileData {
 }
 
 struct FatFile {
-    _parent: Weak<FatInode>,
+    parent: Weak<FatInode>,
     internal_file: InternalFsLock<File>,
 }
 
 impl FatFile {
     fn new(parent: &Weak<FatInode>, internal_file: InternalFsLock<File>) -> Self {
         Self {
-            _parent: parent.clone(),
+            parent: parent.clone(),
             internal_file,
         }
     }
@@ -435,6 +443,38 @@ impl FatInode {
     }
 }
 
+impl FatInode {
+    // Walk up the parents, the root is its own parent.
+    fn is_same_or_descendant_of(&self, ancestor: &Arc<FatInode>) -> bool {
+        let mut current = self.this.upgrade();
+        while let Some(inode) = current {
+            if Arc::ptr_eq(&inode, ancestor) {
+                return true;
+            }
+            let parent = inode
+                .inner
+                .read()
+                .as_dir()
+                .and_then(|dir| dir.parent.upgrade());
+            current = parent.filter(|parent| !Arc::ptr_eq(parent, &inode));
+        }
+        false
+    }
+}
+
+// Check if `inode` may replace `existing` in a rename.
+fn check_rename_target(inode: &InnerNode, existing: &InnerNode) -> Result<(), Error> {
+    match (
+        inode.attr.type_() == InodeFileType::Directory,
+        existing.as_dir(),
+    ) {
+        (true, None) => Err(code::ENOTDIR),
+        (false, Some(_)) => Err(code::EISDIR),
+        (true, Some(dir)) if !dir.children.is_empty() => Err(code::ENOTEMPTY),
+        _ => Ok(()),
+    }
+}
+
 struct InnerNode {
     attr: InodeAttr,
     data: FatFileData,
@@ -468,6 +508,35 @@ impl InnerNode {
             _ => None,
         }
     }
+
+    fn write_file_at(&mut self, offset: usize, buf: &[u8]) -> Result<usize, Error> {
+        let (write_size, new_size, extents) = {
+            let (file, _) = self.as_file_mut().unwrap().internal_file.get_mut();
+            let mut offset = offset;
+            let mut total_write_size = 0;
+            let expected_write_size = buf.len();
+            let mut buf = buf;
+            while expected_write_size > total_write_size {
+                file.seek(SeekFrom::Start(offset as u64))?;
+                let write_size = file.write(buf)?;
+                if write_size == 0 {
+                    break;
+                }
+                buf = &buf[write_size..];
+                offset += write_size;
+                total_write_size += write_size;
+            }
+            let new_size = file.size().unwrap() as usize;
+            let extents = file.extents().count();
+            (total_write_size, new_size, extents)
+        };
+        // update attr size
+        let block_size = self.attr.blk_size;
+        self.attr.size = new_size;
+        self.attr.blocks = self.attr.size.div_ceil(block_size);
+        debug_assert!(extents == self.attr.blocks);
+        Ok(write_size)
+    }
 }
 
 impl InodeOps for FatInode {
@@ -579,37 +648,20 @@ impl InodeOps for FatInode {
             error!("[FatInode] write_at: inode is not a file");
             return Err(code::ENOTSUP);
         }
-        let (write_size, new_size, extents) = {
-            let mut inner = self.inner.write();
-            let (file, _) = inner.as_file_mut().unwrap().internal_file.get_mut();
-            let mut offset = offset;
-            let mut total_write_size = 0;
-            let expected_write_size = buf.len();
-            let mut buf = buf;
-            while expected_write_size > total_write_size {
-                file.seek(SeekFrom::Start(offset as u64))?;
-                let write_size = file.write(buf)?;
-                if write_size == 0 {
-                    break;
-                }
-                buf = &buf[write_size..];
-                offset += write_size;
-                total_write_size += write_size;
-            }
-            let new_size = file.size().unwrap() as usize;
-            let extents = file.extents().count();
-            (total_write_size, new_size, extents)
-        };
-        {
-            // update attr size
-            let mut inner = self.inner.write();
-            let block_size = inner.attr.blk_size;
-            inner.attr.size = new_size;
-            inner.attr.blocks = inner.attr.size.div_ceil(block_size);
-            debug_assert!(extents == inner.attr.blocks);
-        }
+        self.inner.write().write_file_at(offset, buf)
+    }
 
-        Ok(write_size)
+    fn append(&self, buf: &[u8], _nonblock: bool) -> Result<(usize, usize), Error> {
+        if self.type_() != InodeFileType::Regular {
+            error!("[FatInode] append: inode is not a file");
+            return Err(code::ENOTSUP);
+        }
+        // Hold the lock across reading the size and writing, so that
+        // concurrent appenders never write at the same offset.
+        let mut inner = self.inner.write();
+        let offset = inner.attr.size;
+        let written = inner.write_file_at(offset, buf)?;
+        Ok((offset, written))
     }
 
     fn link(&self, _old: &Arc<dyn InodeOps>, _name: &str) -> Result<(), Error> {
@@ -667,6 +719,110 @@ impl InodeOps for FatInode {
         Ok(())
     }
 
+    fn rename(
+        &self,
+        old_name: &str,
+        target: &Arc<dyn InodeOps>,
+        new_name: &str,
+    ) -> Result<(), Error> {
+        if old_name == "." || old_name == ".." || new_name == "." || new_name == ".." {
+            return Err(code::EINVAL);
+        }
+        if new_name.len() > NAME_MAX {
+            return Err(code::ENAMETOOLONG);
+        }
+        let Some(new_dir) = target
+            .downcast_ref::<FatInode>()
+            .filter(|new_dir| Weak::ptr_eq(&self.fs, &new_dir.fs))
+        else {
+            debug!("[FatInode] rename: cannot rename across filesystems");
+            return Err(code::EXDEV);
+        };
+        let same_dir = ptr::eq(self, new_dir);
+        if same_dir && old_name == new_name {
+            return self.lookup(old_name).map(|_| ());
+        }
+
+        let inode = {
+            let inner = self.inner.read();
+            let dir = inner.as_dir().ok_or(code::ENOTDIR)?;
+            dir.find(old_name).ok_or(code::ENOENT)?
+        };
+        let is_dir = inode.type_() == InodeFileType::Directory;
+        if is_dir && new_dir.is_same_or_descendant_of(&inode) {
+            debug!("[FatInode] rename: cannot move a directory into itself");
+            return Err(code::EINVAL);
+        }
+
+        // Lock both parents in address order, so two renames in opposite
+        // directions don't deadlock.
+        let (mut src, mut dst) = if same_dir {
+            (self.inner.write(), None)
+        } else if ptr::addr_of!(*self) < ptr::addr_of!(*new_dir) {
+            let src = self.inner.write();
+            (src, Some(new_dir.inner.write()))
+        } else {
+            let dst = new_dir.inner.write();
+            (self.inner.write(), Some(dst))
+        };
+
+        let src_dir = src.as_dir().ok_or(code::ENOTDIR)?;
+        // The entry may have been changed before the locks are taken
+        if !src_dir
+            .find(old_name)
+            .is_some_and(|current| Arc::ptr_eq(&current, &inode))
+        {
+            return Err(code::ENOENT);
+        }
+        let dst_dir = match dst.as_ref() {
+            Some(dst) => dst.as_dir().ok_or(code::ENOTDIR)?,
+            None => src_dir,
+        };
+        let existing = dst_dir.find(new_name);
+        if let Some(existing) = existing.as_ref() {
+            if Arc::ptr_eq(existing, &inode) {
+                return Ok(());
+            }
+            if ptr::eq(Arc::as_ptr(existing), self) || ptr::eq(Arc::as_ptr(existing), new_dir) {
+                // An ancestor of the source, it can't be empty
+                return Err(code::ENOTEMPTY);
+            }
+            check_rename_target(&inode.inner.read(), &existing.inner.read())?;
+        }
+
+        {
+            let (src_internal, _guard) = src_dir.internal_dir.get();
+            // Both directories are guarded by the lock of the filesystem
+            let dst_internal = &dst_dir.internal_dir.content;
+            if existing.is_some() {
+                // fatfs doesn't replace an existing entry
+                dst_internal.remove(new_name)?;
+            }
+            src_internal.rename(old_name, dst_internal, new_name)?;
+            // The handle still refers to the old directory entry, reopen it
+            // so that size updates go to the new one.
+            let mut moved = inode.inner.write();
+            match &mut moved.data {
+                FatFileData::File(file) => {
+                    file.internal_file.content = dst_internal.open_file(new_name)?;
+                    file.parent = new_dir.this.clone();
+                }
+                FatFileData::Directory(dir) => {
+                    dir.internal_dir.content = dst_internal.open_dir(new_name)?;
+                    dir.parent = new_dir.this.clone();
+                }
+            }
+        }
+
+        src.as_dir_mut().unwrap().remove(old_name);
+        let dst_dir = match dst.as_mut() {
+            Some(dst) => dst.as_dir_mut().unwrap(),
+            None => src.as_dir_mut().unwrap(),
+        };
+        dst_dir.insert(new_name, &inode);
+        Ok(())
+    }
+
     fn getdents_at(&self, offset: usize, reader: &mut DirBufferReader) -> Result<usize, Error> {
         if self.type_() != InodeFileType::Directory {
             error!("[FatInode] getdents_at: not a directory");
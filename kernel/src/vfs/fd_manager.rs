@@ -29,6 +29,8 @@ pub const STDOUT_FILENO: c_int = 1;
 pub const STDERR_FILENO: c_int = 2;
 /// First available file descriptor
 pub const FIRST_FD: usize = 3;
+/// Upper bound of file descriptors chosen by the caller
+pub const MAX_FD: usize = 1024;
 
 /// File descriptor manager
 pub struct FdManager {
@@ -59,9 +61,16 @@ impl FdManager {
         Ok(())
     }
 
-    /// Allocate new file descriptor
+    /// Allocate new file descriptor, `-EMFILE` if the table is full
     pub fn alloc_fd(&mut self, file: Arc<dyn FileOps>) -> c_int {
-        let fd = self.next_fd;
+        let mut fd = self.next_fd;
+        if fd >= MAX_FD {
+            // Reuse one closed since the table filled up
+            match (FIRST_FD..MAX_FD).find(|&fd| self.fds[fd].is_none()) {
+                Some(free) => fd = free,
+                None => return -libc::EMFILE,
+            }
+        }
         self.fds[fd] = Some(file);
         self.update_next_fd(fd);
         fd as c_int
@@ -94,6 +103,33 @@ impl FdManager {
         Ok(new_fd as c_int)
     }
 
+    /// Duplicate file descriptor to `new_fd`, returns the file previously
+    /// open at `new_fd`, it's up to the caller to close it.
+    pub fn dup_fd_to(
+        &mut self,
+        fd: c_int,
+        new_fd: c_int,
+        close_on_exec: bool,
+    ) -> Result<Option<Arc<dyn FileOps>>, Error> {
+        let Some(file) = self.get_file_ops(fd) else {
+            return Err(code::EBADF);
+        };
+        if new_fd < 0 || new_fd as usize >= MAX_FD {
+            return Err(code::EBADF);
+        }
+
+        let new_fd = new_fd as usize;
+        if new_fd >= self.fds.len() {
+            self.fds.resize(new_fd + 1, None);
+        }
+        let file2 = file.dup(close_on_exec)?;
+        let old = self.fds[new_fd].replace(file2);
+        if new_fd == self.next_fd {
+            self.update_next_fd(new_fd);
+        }
+        Ok(old)
+    }
+
     /// Free file descriptor
     pub fn free_fd(&mut self, fd: c_int) -> Result<(), Error> {
         // close stdio is allowed
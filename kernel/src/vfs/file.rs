@@ -20,13 +20,14 @@ use crate::{
         fs::FileSystemInfo,
         inode::{InodeAttr, InodeNo},
         inode_mode::{mode_t, InodeFileType},
+        poll::{PollEvents, PollTable},
         utils::SeekFrom,
     },
 };
 use alloc::sync::Arc;
 use core::{
     any::Any,
-    sync::atomic::{AtomicI32, Ordering},
+    sync::atomic::{AtomicI32, AtomicUsize, Ordering},
     time::Duration,
 };
 use log::warn;
@@ -160,13 +161,24 @@ pub trait FileOps: Send + Sync + Any {
         warn!("seek is not implemented");
         Err(code::ESPIPE)
     }
+    /// Read at `offset` without moving the file offset.
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, Error> {
+        Err(code::ESPIPE)
+    }
+    /// Write at `offset` without moving the file offset.
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, Error> {
+        Err(code::ESPIPE)
+    }
     fn ioctl(&self, cmd: u32, arg: usize) -> Result<i32, Error> {
-        warn!("ioctl is not implemented");
-        Err(code::EINVAL)
+        Err(code::ENOTTY)
     }
     fn flush(&self) -> Result<(), Error> {
         Ok(())
     }
+    /// Write the file's data out to the backing device.
+    fn fsync(&self) -> Result<(), Error> {
+        Err(code::EINVAL)
+    }
     fn close(&self) -> Result<(), Error> {
         Ok(())
     }
@@ -178,17 +190,30 @@ pub trait FileOps: Send + Sync + Any {
         warn!("dup is not implemented");
         Err(code::EINVAL)
     }
+    /// Returns the ready events, registers on `table` to be woken up
+    /// when the readiness changes.
+    fn poll(&self, events: PollEvents, table: &mut PollTable) -> PollEvents {
+        events & (PollEvents::POLLIN | PollEvents::POLLOUT)
+    }
     fn stat(&self) -> FileAttr;
     fn flags(&self) -> OpenFlags;
     fn set_flags(&self, flags: OpenFlags);
 }
 
+// Shared by the files duplicated from the same open()
+#[derive(Debug)]
+struct OpenFileDescription {
+    offset: Mutex<usize>, // also lock for read/ write
+    // Duplicates not closed yet, the inode is closed with the last one
+    refs: AtomicUsize,
+}
+
 // system file hander
 #[derive(Debug)]
 pub struct File {
     dcache: Arc<Dcache>,
     open_flags: AtomicI32,
-    offset: Mutex<usize>, // also lock for read/ write
+    desc: Arc<OpenFileDescription>,
 }
 
 impl File {
@@ -212,7 +237,10 @@ impl File {
         Ok(Self {
             dcache,
             open_flags: AtomicI32::new(access_mode as i32 | flags.bits()),
-            offset: Mutex::new(0),
+            desc: Arc::new(OpenFileDescription {
+                offset: Mutex::new(0),
+                refs: AtomicUsize::new(1),
+            }),
         })
     }
 
@@ -240,13 +268,13 @@ impl File {
     }
 
     pub fn offset(&self) -> usize {
-        *self.offset.lock()
+        *self.desc.offset.lock()
     }
 
     pub fn getdents(&self, reader: &mut DirBufferReader) -> Result<usize, Error> {
-        let mut offset = self.offset.lock();
+        let mut offset = self.desc.offset.lock();
         let cnt = self.dcache.inode().getdents_at(*offset, reader)?;
-        *offset += cnt;
+        *offset = reader.next_offset(*offset, cnt);
         Ok(cnt)
     }
 
@@ -266,7 +294,7 @@ impl FileOps for File {
         if !self.access_mode().is_readable() {
             return Err(code::EACCES);
         }
-        let mut offset = self.offset.lock();
+        let mut offset = self.desc.offset.lock();
         // TODO: support O_DIRECT
         let ret = self
             .dcache
@@ -280,10 +308,13 @@ impl FileOps for File {
         if !self.access_mode().is_writable() {
             return Err(code::EACCES);
         }
-        let mut offset = self.offset.lock();
-        // offset is ignored if O_APPEND is set
+        let mut offset = self.desc.offset.lock();
+        // offset is ignored if O_APPEND is set, other open files of the same
+        // inode may append in the meantime, so let the inode pick the offset.
         if self.open_flags().contains(OpenFlags::O_APPEND) {
-            *offset = self.dcache.size();
+            let (append_offset, ret) = self.dcache.inode().append(buf, self.is_nonblock())?;
+            *offset = append_offset + ret;
+            return Ok(ret);
         }
         let ret = self
             .dcache
@@ -293,8 +324,24 @@ impl FileOps for File {
         Ok(ret)
     }
 
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, Error> {
+        if !self.access_mode().is_readable() {
+            return Err(code::EACCES);
+        }
+        self.dcache.inode().read_at(offset, buf, self.is_nonblock())
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> Result<usize, Error> {
+        if !self.access_mode().is_writable() {
+            return Err(code::EACCES);
+        }
+        self.dcache
+            .inode()
+            .write_at(offset, buf, self.is_nonblock())
+    }
+
     fn seek(&self, pos: SeekFrom) -> Result<usize, Error> {
-        let mut cur_offset = self.offset.lock();
+        let mut cur_offset = self.desc.offset.lock();
         let new_offset: isize = match pos {
             SeekFrom::Start(offset) => {
                 if offset > isize::MAX as u64 {
@@ -326,7 +373,14 @@ impl FileOps for File {
         self.dcache.inode().flush()
     }
 
+    fn fsync(&self) -> Result<(), Error> {
+        self.dcache.fsync()
+    }
+
     fn close(&self) -> Result<(), Error> {
+        if self.desc.refs.fetch_sub(1, Ordering::AcqRel) > 1 {
+            return Ok(());
+        }
         self.dcache.inode().close()
     }
 
@@ -338,17 +392,20 @@ impl FileOps for File {
     }
 
     fn dup(&self, close_on_exec: bool) -> Result<Arc<dyn FileOps>, Error> {
-        let flags = self.open_flags();
-        let flags = if close_on_exec {
-            flags | OpenFlags::O_CLOEXEC
-        } else {
-            flags
-        };
-        Ok(Arc::new(File::new(
-            self.dcache(),
-            self.access_mode(),
-            flags,
-        )?))
+        // FD_CLOEXEC isn't inherited from the original
+        let mut flags = self.open_flags();
+        flags.set(OpenFlags::O_CLOEXEC, close_on_exec);
+        // The offset is shared, only the flags are per file
+        self.desc.refs.fetch_add(1, Ordering::AcqRel);
+        Ok(Arc::new(File {
+            dcache: self.dcache(),
+            open_flags: AtomicI32::new(self.access_mode() as i32 | flags.bits()),
+            desc: self.desc.clone(),
+        }))
+    }
+
+    fn poll(&self, events: PollEvents, table: &mut PollTable) -> PollEvents {
+        self.dcache.inode().poll(events, table)
     }
 
     fn stat(&self) -> FileAttr {
@@ -20,6 +20,7 @@ use crate::{
         file::FileAttr,
         fs::FileSystem,
         inode_mode::{mode_t, InodeFileType, InodeMode},
+        poll::{PollEvents, PollTable},
     },
 };
 use alloc::{string::String, sync::Arc};
@@ -112,6 +113,15 @@ pub trait InodeOps: Any + Sync + Send {
         warn!("write_at is not implemented");
         Err(code::EINVAL)
     }
+    /// Write `buf` at the end of the file, returns the offset it was
+    /// written at and the number of bytes written. Filesystems that may be
+    /// written concurrently should override it to pick the offset and write
+    /// under the same lock.
+    fn append(&self, buf: &[u8], nonblock: bool) -> Result<(usize, usize), Error> {
+        let offset = self.size();
+        let written = self.write_at(offset, buf, nonblock)?;
+        Ok((offset, written))
+    }
     fn link(&self, old: &Arc<dyn InodeOps>, name: &str) -> Result<(), Error> {
         warn!("link is not implemented");
         Err(code::ENOTDIR)
@@ -131,7 +141,7 @@ pub trait InodeOps: Any + Sync + Send {
         new_name: &str,
     ) -> Result<(), Error> {
         warn!("rename is not implemented");
-        Err(code::ENOTDIR)
+        Err(code::EPERM)
     }
     fn getdents_at(&self, offset: usize, reader: &mut DirBufferReader) -> Result<usize, Error> {
         warn!("getdents_at is not implemented");
@@ -159,12 +169,23 @@ pub trait InodeOps: Any + Sync + Send {
         warn!("create_socket is not implemented");
         Err(code::EINVAL)
     }
+    fn symlink(&self, name: &str, target: &str) -> Result<Arc<dyn InodeOps>, Error> {
+        warn!("symlink is not implemented");
+        Err(code::EPERM)
+    }
+    fn readlink(&self) -> Result<String, Error> {
+        Err(code::EINVAL)
+    }
+    /// Called each time a file is opened on the inode, paired with
+    /// close() once the file is closed.
+    fn open(&self) -> Result<(), Error> {
+        Ok(())
+    }
     fn close(&self) -> Result<(), Error> {
         Ok(())
     }
     fn ioctl(&self, cmd: u32, arg: usize) -> Result<i32, Error> {
-        warn!("ioctl is not implemented");
-        Err(code::ENOTDIR)
+        Err(code::ENOTTY)
     }
     fn flush(&self) -> Result<(), Error> {
         Ok(())
@@ -183,6 +204,10 @@ pub trait InodeOps: Any + Sync + Send {
     fn is_dcacheable(&self) -> bool {
         true
     }
+    // Regular files never block
+    fn poll(&self, events: PollEvents, table: &mut PollTable) -> PollEvents {
+        events & (PollEvents::POLLIN | PollEvents::POLLOUT)
+    }
     fn fs(&self) -> Option<Arc<dyn FileSystem>>;
     fn ino(&self) -> InodeNo;
     fn type_(&self) -> InodeFileType;
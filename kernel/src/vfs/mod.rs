@@ -28,6 +28,7 @@ use log::{debug, error, warn};
 mod dcache;
 mod devfs;
 pub mod dirent;
+pub mod epoll;
 #[cfg(virtio)]
 mod fatfs;
 mod fd_manager;
@@ -37,10 +38,10 @@ mod inode;
 mod inode_mode;
 mod mount;
 mod path;
+mod pipe;
+pub(crate) mod poll;
 #[cfg(procfs)]
 mod procfs;
-#[cfg(procfs)]
-pub use procfs::{trace_thread_close, trace_thread_create};
 mod root;
 mod sockfs;
 pub mod syscalls;
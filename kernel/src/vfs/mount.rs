@@ -93,18 +93,23 @@ pub fn get_mount_manager() -> &'static MountManager {
 
 pub fn get_fs(fs_type: &str, device: &str) -> Option<Arc<dyn FileSystem>> {
     match fs_type {
-        "tmpfs" => Some(TmpFileSystem::new()),
+        "tmpfs" | "ramfs" => Some(TmpFileSystem::new()),
+        #[cfg(procfs)]
+        "procfs" => Some(ProcFileSystem::new()),
+        // The device is named either as is or by its node under /dev
         #[cfg(virtio)]
-        "fatfs" => match FatFileSystem::new(device) {
-            Ok(fs) => Some(fs),
-            Err(error) => {
-                error!(
-                    "Fail to init fat file system with device {}, {}",
-                    device, error
-                );
-                None
+        "fatfs" | "vfat" => {
+            match FatFileSystem::new(device.strip_prefix("/dev/").unwrap_or(device)) {
+                Ok(fs) => Some(fs),
+                Err(error) => {
+                    error!(
+                        "Fail to init fat file system with device {}, {}",
+                        device, error
+                    );
+                    None
+                }
             }
-        },
+        }
         _ => None,
     }
 }
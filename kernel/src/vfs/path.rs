@@ -20,6 +20,7 @@ use crate::{
         file::{AccessMode, File, OpenFlags},
         inode_mode::{mode_t, InodeFileType, InodeMode},
         root::get_root_dir,
+        utils::SYMLOOP_MAX,
     },
 };
 use alloc::{string::String, sync::Arc};
@@ -61,18 +62,31 @@ impl<'a> FilePath<'a> {
 }
 
 pub fn lookup_path(path: &str) -> Option<Arc<Dcache>> {
+    lookup_path_at(&get_working_dir(), path)
+}
+
+/// Same as `lookup_path`, but a relative `path` is resolved against `base`.
+pub fn lookup_path_at(base: &Arc<Dcache>, path: &str) -> Option<Arc<Dcache>> {
     match FilePath::new(path) {
         FilePath::Absolute(path) => lookup_in_dir(get_root_dir(), path.trim_start_matches('/')),
-        FilePath::Relative(path) => lookup_in_dir(&get_working_dir(), path),
+        FilePath::Relative(path) => lookup_in_dir(base, path),
     }
 }
 
 pub fn find_parent_and_name(path: &str) -> Option<(Arc<Dcache>, &str)> {
+    find_parent_and_name_at(&get_working_dir(), path)
+}
+
+/// Same as `find_parent_and_name`, but a relative `path` is resolved
+/// against `base`.
+pub fn find_parent_and_name_at<'a>(
+    base: &Arc<Dcache>,
+    path: &'a str,
+) -> Option<(Arc<Dcache>, &'a str)> {
     match FilePath::new(path) {
         FilePath::Relative(path) => {
             let (parent, name) = split_path(path)?;
-            let dir = get_working_dir();
-            let parent = lookup_in_dir(&dir, parent)?;
+            let parent = lookup_in_dir(base, parent)?;
             Some((parent, name))
         }
         FilePath::Absolute(path) => {
@@ -85,11 +99,21 @@ pub fn find_parent_and_name(path: &str) -> Option<(Arc<Dcache>, &str)> {
 }
 
 pub fn open_path(path: &str, flags: i32, mode: mode_t) -> Result<File, Error> {
-    // TODO: add support for symlink
+    open_path_at(&get_working_dir(), path, flags, mode)
+}
+
+/// Same as `open_path`, but a relative `path` is resolved against `base`.
+pub fn open_path_at(
+    base: &Arc<Dcache>,
+    path: &str,
+    flags: i32,
+    mode: mode_t,
+) -> Result<File, Error> {
     let open_flags = OpenFlags::from_bits_truncate(flags);
     let access_mode = AccessMode::from(flags);
-    let dcache = match lookup_path(path) {
-        Some(dcache) => {
+    let follow = !open_flags.contains(OpenFlags::O_NOFOLLOW);
+    let dcache = match resolve_path_at(base, path, follow) {
+        Ok(dcache) => {
             if open_flags.contains(OpenFlags::O_NOFOLLOW)
                 && dcache.type_() == InodeFileType::SymLink
             {
@@ -105,12 +129,12 @@ pub fn open_path(path: &str, flags: i32, mode: mode_t) -> Result<File, Error> {
             }
             dcache
         }
-        None => {
+        Err(code::ENOENT) => {
             if open_flags.contains(OpenFlags::O_CREAT) {
                 if open_flags.contains(OpenFlags::O_DIRECTORY) || path.ends_with('/') {
                     return Err(code::ENOTDIR);
                 }
-                let Some((parent, name)) = find_parent_and_name(path) else {
+                let Some((parent, name)) = find_parent_and_name_at(base, path) else {
                     return Err(code::ENOENT);
                 };
                 if !parent.mode().is_writable() {
@@ -122,14 +146,18 @@ pub fn open_path(path: &str, flags: i32, mode: mode_t) -> Result<File, Error> {
                 return Err(code::ENOENT);
             }
         }
+        Err(e) => return Err(e),
     };
     // resize to 0 if O_TRUNC is set
     if open_flags.contains(OpenFlags::O_TRUNC) && access_mode.is_writable() {
         dcache.inode().resize(0)?;
     }
 
-    let file = File::new(dcache, AccessMode::from(flags), open_flags)?;
-    Ok(file)
+    let inode = dcache.inode();
+    inode.open()?;
+    File::new(dcache, AccessMode::from(flags), open_flags).inspect_err(|_| {
+        let _ = inode.close();
+    })
 }
 
 /// Split path into parent directory and filename
@@ -267,32 +295,62 @@ pub fn is_valid_path(path: &str) -> bool {
 }
 
 fn lookup_in_dir(dir: &Arc<Dcache>, path: &str) -> Option<Arc<Dcache>> {
-    // TODO: add support for symlink
+    resolve_in_dir(dir, path, true, &mut 0).ok()
+}
+
+/// Resolve `path` like `lookup_path_at`, but report why it failed.
+/// Symlinks in the middle of the path are always followed, the last
+/// component only if `follow_last` is set.
+pub fn resolve_path_at(
+    base: &Arc<Dcache>,
+    path: &str,
+    follow_last: bool,
+) -> Result<Arc<Dcache>, Error> {
+    match FilePath::new(path) {
+        FilePath::Absolute(path) => resolve_in_dir(
+            get_root_dir(),
+            path.trim_start_matches('/'),
+            follow_last,
+            &mut 0,
+        ),
+        FilePath::Relative(path) => resolve_in_dir(base, path, follow_last, &mut 0),
+    }
+}
+
+// `links` counts the symlinks followed so far, across nested calls.
+fn resolve_in_dir(
+    dir: &Arc<Dcache>,
+    path: &str,
+    follow_last: bool,
+    links: &mut usize,
+) -> Result<Arc<Dcache>, Error> {
     let mut current = dir.clone();
-    let mut cur_path = path;
-
-    while !cur_path.is_empty() {
-        match cur_path.split_once('/') {
-            Some((next_name, next_path)) => {
-                let next_path = next_path.trim_start_matches('/');
-                let next = current.lookup(next_name).ok()?;
-                if next.type_() == InodeFileType::Directory {
-                    current = next;
-                    cur_path = next_path;
-                } else {
-                    // not a directory
-                    return None;
-                }
-            }
-            None => {
-                // end of path, just find in cur dir
-                current = current.lookup(cur_path).ok()?;
-                break;
+    // A trailing slash requires the last component to be a directory.
+    let must_be_dir = path.ends_with('/');
+    let mut components = path.split('/').filter(|name| !name.is_empty()).peekable();
+    while let Some(name) = components.next() {
+        let is_last = components.peek().is_none();
+        let mut next = current.lookup(name)?;
+        if next.type_() == InodeFileType::SymLink && (!is_last || follow_last || must_be_dir) {
+            *links += 1;
+            if *links > SYMLOOP_MAX {
+                return Err(code::ELOOP);
             }
-        };
+            let target = next.inode().readlink()?;
+            let (base, target) = match target.strip_prefix('/') {
+                Some(target) => (get_root_dir().clone(), target),
+                // Relative to the directory holding the link.
+                None => (current.clone(), target.as_str()),
+            };
+            next = resolve_in_dir(&base, target, true, links)?;
+        }
+        if (!is_last || must_be_dir) && next.type_() != InodeFileType::Directory {
+            return Err(code::ENOTDIR);
+        }
+        current = next;
     }
 
-    Some(current)
+    Ok(current)
 }
 
 #[cfg(test)]
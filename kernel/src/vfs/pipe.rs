@@ -0,0 +1,311 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Anonymous pipes.
+//!
+//! Both ends share a ring buffer. Readers block while it's empty and
+//! writers while it's full, each side sleeps on its own futex which is
+//! bumped by the other side whenever it makes progress or closes.
+
+use crate::{
+    error::{code, Error},
+    sync::atomic_wait as futex,
+    vfs::{
+        file::{FileAttr, FileOps, OpenFlags},
+        poll::{PollEvents, PollQueue, PollTable},
+    },
+};
+use alloc::sync::Arc;
+use blueos_infra::ringbuffer::BoxedRingBuffer;
+use core::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use spin::Mutex;
+
+/// Capacity of a pipe
+pub const PIPE_SIZE: usize = 4096;
+/// Writes up to this size are not interleaved with other writers
+pub const PIPE_BUF: usize = 512;
+
+struct Pipe {
+    rb: BoxedRingBuffer,
+    // The ring buffer allows one reader and one writer at a time
+    read_lock: Mutex<()>,
+    write_lock: Mutex<()>,
+    // Open files of each end
+    readers: AtomicUsize,
+    writers: AtomicUsize,
+    // Bumped when there may be something to read / room to write
+    readable: AtomicUsize,
+    writable: AtomicUsize,
+    poll_queue: Arc<PollQueue>,
+}
+
+impl Pipe {
+    fn new() -> Self {
+        Self {
+            rb: BoxedRingBuffer::new(PIPE_SIZE),
+            read_lock: Mutex::new(()),
+            write_lock: Mutex::new(()),
+            readers: AtomicUsize::new(1),
+            writers: AtomicUsize::new(1),
+            readable: AtomicUsize::new(0),
+            writable: AtomicUsize::new(0),
+            poll_queue: Arc::new(PollQueue::new()),
+        }
+    }
+
+    fn wake(&self, seq: &AtomicUsize) {
+        seq.fetch_add(1, Ordering::Release);
+        let _ = futex::atomic_wake(seq, usize::MAX);
+        self.poll_queue.notify();
+    }
+
+    fn try_read(&self, buf: &mut [u8]) -> usize {
+        let _guard = self.read_lock.lock();
+        // SAFETY: Readers are serialized by `read_lock`.
+        let mut reader = unsafe { self.rb.reader() };
+        let mut count = 0;
+        for slice in reader.pop_slices() {
+            let len = slice.len().min(buf.len() - count);
+            buf[count..count + len].copy_from_slice(&slice[..len]);
+            count += len;
+        }
+        reader.pop_done(count);
+        count
+    }
+
+    // Writes nothing if `buf` has to be written at once and doesn't fit.
+    fn try_write(&self, buf: &[u8], atomic: bool) -> usize {
+        let _guard = self.write_lock.lock();
+        // SAFETY: Writers are serialized by `write_lock`.
+        let mut writer = unsafe { self.rb.writer() };
+        let mut slices = writer.push_slices();
+        let free: usize = slices.iter().map(|s| s.len()).sum();
+        if atomic && free < buf.len() {
+            return 0;
+        }
+        let mut count = 0;
+        for slice in slices.iter_mut() {
+            let len = slice.len().min(buf.len() - count);
+            slice[..len].copy_from_slice(&buf[count..count + len]);
+            count += len;
+        }
+        writer.push_done(count);
+        count
+    }
+
+    fn read(&self, buf: &mut [u8], is_nonblocking: bool) -> Result<usize, Error> {
+        loop {
+            // Sample before checking, so that a wake up in between isn't lost.
+            let seq = self.readable.load(Ordering::Acquire);
+            let n = self.try_read(buf);
+            if n > 0 {
+                self.wake(&self.writable);
+                return Ok(n);
+            }
+            // EOF once all writers are gone and the buffer is drained. The
+            // last one may have written right before closing, so look again.
+            if self.writers.load(Ordering::Acquire) == 0 {
+                let n = self.try_read(buf);
+                if n > 0 {
+                    self.wake(&self.writable);
+                }
+                return Ok(n);
+            }
+            if is_nonblocking {
+                return Err(code::EAGAIN);
+            }
+            let _ = futex::atomic_wait(&self.readable, seq, None);
+        }
+    }
+
+    fn write(&self, buf: &[u8], is_nonblocking: bool) -> Result<usize, Error> {
+        let atomic = buf.len() <= PIPE_BUF;
+        let mut count = 0;
+        loop {
+            let seq = self.writable.load(Ordering::Acquire);
+            if self.readers.load(Ordering::Acquire) == 0 {
+                return if count > 0 {
+                    Ok(count)
+                } else {
+                    Err(code::EPIPE)
+                };
+            }
+            let n = self.try_write(&buf[count..], atomic);
+            if n > 0 {
+                count += n;
+                self.wake(&self.readable);
+            }
+            if count == buf.len() {
+                return Ok(count);
+            }
+            if is_nonblocking {
+                return if count > 0 {
+                    Ok(count)
+                } else {
+                    Err(code::EAGAIN)
+                };
+            }
+            let _ = futex::atomic_wait(&self.writable, seq, None);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum PipeEnd {
+    Read,
+    Write,
+}
+
+/// One end of a pipe
+pub struct PipeFile {
+    pipe: Arc<Pipe>,
+    end: PipeEnd,
+    open_flags: AtomicI32,
+}
+
+impl PipeFile {
+    fn new(pipe: Arc<Pipe>, end: PipeEnd, flags: OpenFlags) -> Self {
+        Self {
+            pipe,
+            end,
+            open_flags: AtomicI32::new(flags.bits()),
+        }
+    }
+
+    fn is_nonblock(&self) -> bool {
+        self.flags().contains(OpenFlags::O_NONBLOCK)
+    }
+
+    fn open_count(&self) -> &AtomicUsize {
+        match self.end {
+            PipeEnd::Read => &self.pipe.readers,
+            PipeEnd::Write => &self.pipe.writers,
+        }
+    }
+}
+
+impl FileOps for PipeFile {
+    fn read(&self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.end != PipeEnd::Read {
+            return Err(code::EBADF);
+        }
+        self.pipe.read(buf, self.is_nonblock())
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<usize, Error> {
+        if self.end != PipeEnd::Write {
+            return Err(code::EBADF);
+        }
+        self.pipe.write(buf, self.is_nonblock())
+    }
+
+    fn close(&self) -> Result<(), Error> {
+        if self.open_count().fetch_sub(1, Ordering::AcqRel) > 1 {
+            return Ok(());
+        }
+        // The last one of this end, wake up the other end to see EOF or EPIPE
+        match self.end {
+            PipeEnd::Read => self.pipe.wake(&self.pipe.writable),
+            PipeEnd::Write => self.pipe.wake(&self.pipe.readable),
+        }
+        Ok(())
+    }
+
+    fn dup(&self, close_on_exec: bool) -> Result<Arc<dyn FileOps>, Error> {
+        let mut flags = self.flags();
+        flags.set(OpenFlags::O_CLOEXEC, close_on_exec);
+        self.open_count().fetch_add(1, Ordering::AcqRel);
+        Ok(Arc::new(PipeFile::new(self.pipe.clone(), self.end, flags)))
+    }
+
+    fn poll(&self, events: PollEvents, table: &mut PollTable) -> PollEvents {
+        table.register(&self.pipe.poll_queue);
+        let mut revents = PollEvents::empty();
+        match self.end {
+            PipeEnd::Read => {
+                if !self.pipe.rb.is_empty() {
+                    revents |= PollEvents::POLLIN;
+                }
+                if self.pipe.writers.load(Ordering::Acquire) == 0 {
+                    revents |= PollEvents::POLLHUP;
+                }
+            }
+            PipeEnd::Write => {
+                if self.pipe.readers.load(Ordering::Acquire) == 0 {
+                    revents |= PollEvents::POLLERR;
+                } else if !self.pipe.rb.is_full() {
+                    revents |= PollEvents::POLLOUT;
+                }
+            }
+        }
+        revents
+    }
+
+    fn stat(&self) -> FileAttr {
+        FileAttr {
+            size: self.pipe.rb.capacity(),
+            blk_size: PIPE_SIZE,
+            mode: libc::S_IFIFO | 0o600,
+            nlinks: 1,
+            ..Default::default()
+        }
+    }
+
+    fn flags(&self) -> OpenFlags {
+        OpenFlags::from_bits_truncate(self.open_flags.load(Ordering::Relaxed))
+    }
+
+    fn set_flags(&self, flags: OpenFlags) {
+        self.open_flags.store(flags.bits(), Ordering::Relaxed);
+    }
+}
+
+/// Create a pipe, returns the read end and the write end.
+pub fn new_pipe(flags: OpenFlags) -> (Arc<PipeFile>, Arc<PipeFile>) {
+    let pipe = Arc::new(Pipe::new());
+    let reader = PipeFile::new(pipe.clone(), PipeEnd::Read, flags);
+    let writer = PipeFile::new(pipe, PipeEnd::Write, flags);
+    (Arc::new(reader), Arc::new(writer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blueos_test_macro::test;
+
+    #[test]
+    fn test_pipe_eof_and_epipe() {
+        let (reader, writer) = new_pipe(OpenFlags::O_NONBLOCK);
+        let mut buf = [0u8; 8];
+        assert_eq!(reader.read(&mut buf), Err(code::EAGAIN));
+        assert_eq!(writer.write(b"abc"), Ok(3));
+        assert_eq!(reader.read(&mut buf), Ok(3));
+        assert_eq!(&buf[..3], b"abc");
+
+        // Atomic writes don't go in partially
+        let big = [0u8; PIPE_SIZE];
+        assert_eq!(writer.write(&big[..PIPE_SIZE - 4]), Ok(PIPE_SIZE - 4));
+        assert_eq!(writer.write(&big[..8]), Err(code::EAGAIN));
+        assert_eq!(reader.read(&mut buf), Ok(8));
+
+        assert_eq!(writer.close(), Ok(()));
+        let mut rest = [0u8; PIPE_SIZE];
+        assert_eq!(reader.read(&mut rest), Ok(PIPE_SIZE - 12));
+        assert_eq!(reader.read(&mut rest), Ok(0));
+
+        let (reader, writer) = new_pipe(OpenFlags::empty());
+        assert_eq!(reader.close(), Ok(()));
+        assert_eq!(writer.write(b"abc"), Err(code::EPIPE));
+    }
+}
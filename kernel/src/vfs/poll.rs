@@ -0,0 +1,259 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Readiness notification for poll().
+//!
+//! Every pollable object owns a `PollQueue`. A polling thread registers its
+//! `PollWaiter` on the queue of each fd it watches, then sleeps on the waiter
+//! until any of the objects calls `PollQueue::notify`. A waiter may also
+//! carry a callback, which is how epoll learns which fds became ready.
+
+use crate::{
+    error::{code, Error},
+    sync::{atomic_wait as futex, spinlock::SpinLock},
+    time::{self, WAITING_FOREVER},
+    vfs::fd_manager::get_fd_manager,
+};
+use alloc::{boxed::Box, sync::Arc, vec::Vec};
+use bitflags::bitflags;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct PollEvents: i16 {
+        const POLLIN = libc::POLLIN;
+        const POLLPRI = libc::POLLPRI;
+        const POLLOUT = libc::POLLOUT;
+        const POLLERR = libc::POLLERR;
+        const POLLHUP = libc::POLLHUP;
+        const POLLNVAL = libc::POLLNVAL;
+    }
+}
+
+impl PollEvents {
+    // Reported even if they are not requested.
+    pub const ALWAYS: PollEvents = PollEvents::POLLERR
+        .union(PollEvents::POLLHUP)
+        .union(PollEvents::POLLNVAL);
+}
+
+pub struct PollWaiter {
+    futex: AtomicUsize,
+    // Run on every notification, it may be called in IRQ
+    callback: Option<Box<dyn Fn() + Send + Sync>>,
+}
+
+impl PollWaiter {
+    fn new(callback: Option<Box<dyn Fn() + Send + Sync>>) -> Self {
+        Self {
+            futex: AtomicUsize::new(0),
+            callback,
+        }
+    }
+
+    fn wake(&self) {
+        self.futex.fetch_add(1, Ordering::Release);
+        let _ = futex::atomic_wake(&self.futex, 1);
+        if let Some(callback) = &self.callback {
+            callback();
+        }
+    }
+}
+
+/// Waiters of a pollable object, it's safe to notify in IRQ.
+pub struct PollQueue {
+    waiters: SpinLock<Vec<Arc<PollWaiter>>>,
+}
+
+impl PollQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: SpinLock::new(Vec::new()),
+        }
+    }
+
+    /// Wake up all threads polling this object.
+    pub fn notify(&self) {
+        let waiters = self.waiters.irqsave_lock();
+        for waiter in waiters.iter() {
+            waiter.wake();
+        }
+    }
+
+    fn register(&self, waiter: &Arc<PollWaiter>) {
+        self.waiters.irqsave_lock().push(waiter.clone());
+    }
+
+    fn unregister(&self, waiter: &Arc<PollWaiter>) {
+        self.waiters
+            .irqsave_lock()
+            .retain(|w| !Arc::ptr_eq(w, waiter));
+    }
+}
+
+impl Default for PollQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Queues the current poll() is registered on, unregistered on drop.
+pub struct PollTable {
+    waiter: Arc<PollWaiter>,
+    queues: Vec<Arc<PollQueue>>,
+}
+
+impl PollTable {
+    fn new() -> Self {
+        Self {
+            waiter: Arc::new(PollWaiter::new(None)),
+            queues: Vec::new(),
+        }
+    }
+
+    /// A table which runs `callback` whenever any of its queues is
+    /// notified, until it's dropped. The callback must not register or
+    /// unregister on poll queues.
+    pub fn with_callback(callback: Box<dyn Fn() + Send + Sync>) -> Self {
+        Self {
+            waiter: Arc::new(PollWaiter::new(Some(callback))),
+            queues: Vec::new(),
+        }
+    }
+
+    pub fn register(&mut self, queue: &Arc<PollQueue>) {
+        if self.queues.iter().any(|q| Arc::ptr_eq(q, queue)) {
+            return;
+        }
+        queue.register(&self.waiter);
+        self.queues.push(queue.clone());
+    }
+
+    fn seq(&self) -> usize {
+        self.waiter.futex.load(Ordering::Acquire)
+    }
+
+    // Returns false if `ticks` elapsed without any notification since `seq`.
+    fn wait(&self, seq: usize, ticks: usize) -> bool {
+        let timeout = if ticks == WAITING_FOREVER {
+            None
+        } else {
+            Some(ticks)
+        };
+        futex::atomic_wait(&self.waiter.futex, seq, timeout) != Err(code::ETIMEDOUT)
+    }
+}
+
+impl Drop for PollTable {
+    fn drop(&mut self) {
+        for queue in self.queues.iter() {
+            queue.unregister(&self.waiter);
+        }
+    }
+}
+
+fn poll_once(fds: &mut [libc::pollfd], table: &mut PollTable) -> usize {
+    let mut ready = 0;
+    for pollfd in fds.iter_mut() {
+        pollfd.revents = 0;
+        // Negative fds are ignored
+        if pollfd.fd < 0 {
+            continue;
+        }
+        let file_ops = get_fd_manager().lock().get_file_ops(pollfd.fd);
+        let revents = match file_ops {
+            Some(file_ops) => {
+                let events = PollEvents::from_bits_truncate(pollfd.events);
+                file_ops.poll(events, table) & (events | PollEvents::ALWAYS)
+            }
+            None => PollEvents::POLLNVAL,
+        };
+        if !revents.is_empty() {
+            pollfd.revents = revents.bits();
+            ready += 1;
+        }
+    }
+    ready
+}
+
+/// Wait until any of `fds` is ready or `timeout` milliseconds elapse.
+/// A negative timeout waits forever, zero returns immediately.
+pub fn poll(fds: &mut [libc::pollfd], timeout: i32) -> Result<usize, Error> {
+    let deadline = match timeout {
+        t if t < 0 => None,
+        t => Some(time::get_sys_ticks().saturating_add(time::tick_from_millisecond(t as usize))),
+    };
+    let mut table = PollTable::new();
+    loop {
+        // Sample before checking, so that a notification in between isn't lost.
+        let seq = table.seq();
+        let ready = poll_once(fds, &mut table);
+        if ready > 0 {
+            return Ok(ready);
+        }
+        let ticks = match deadline {
+            None => WAITING_FOREVER,
+            Some(deadline) => {
+                let now = time::get_sys_ticks();
+                if now >= deadline {
+                    return Ok(0);
+                }
+                deadline - now
+            }
+        };
+        if !table.wait(seq, ticks) {
+            return Ok(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blueos_test_macro::test;
+
+    #[test]
+    fn test_poll_queue_notify() {
+        let queue = Arc::new(PollQueue::new());
+        let mut table = PollTable::new();
+        table.register(&queue);
+        table.register(&queue);
+        assert_eq!(queue.waiters.irqsave_lock().len(), 1);
+
+        let seq = table.seq();
+        queue.notify();
+        assert_ne!(table.seq(), seq);
+        // Already notified, doesn't block
+        assert!(table.wait(seq, 1));
+
+        drop(table);
+        assert!(queue.waiters.irqsave_lock().is_empty());
+    }
+
+    #[test]
+    fn test_poll_timeout() {
+        let mut fds = [libc::pollfd {
+            fd: 1 << 20,
+            events: libc::POLLIN,
+            revents: 0,
+        }];
+        // Bad fd is reported as ready
+        assert_eq!(poll(&mut fds, 10), Ok(1));
+        assert_eq!(fds[0].revents, libc::POLLNVAL);
+
+        fds[0].fd = -1;
+        assert_eq!(poll(&mut fds, 10), Ok(0));
+        assert_eq!(fds[0].revents, 0);
+    }
+}
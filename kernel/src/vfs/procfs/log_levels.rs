@@ -0,0 +1,56 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::ProcFileOps;
+use crate::{
+    error::{code, Error},
+    logger,
+};
+use alloc::{string::String, vec::Vec};
+
+pub(crate) struct LogLevels;
+
+impl ProcFileOps for LogLevels {
+    fn get_content(&self) -> Result<Vec<u8>, Error> {
+        let mut result = String::with_capacity(64);
+        logger::write_levels(&mut result).unwrap();
+        Ok(result.as_bytes().to_vec())
+    }
+
+    fn set_content(&self, content: Vec<u8>) -> Result<usize, Error> {
+        Ok(0)
+    }
+}
+
+// /proc/sys/loglevel, reads and sets the max log level.
+pub(crate) struct LogLevel;
+
+impl ProcFileOps for LogLevel {
+    fn get_content(&self) -> Result<Vec<u8>, Error> {
+        let mut result = String::from(logger::max_level().as_str());
+        result.make_ascii_lowercase();
+        result.push('\n');
+        Ok(result.into_bytes())
+    }
+
+    fn set_content(&self, content: Vec<u8>) -> Result<usize, Error> {
+        let level = core::str::from_utf8(&content).map_err(|_| code::EINVAL)?;
+        logger::set_max_level(level.trim().parse()?);
+        Ok(content.len())
+    }
+
+    fn is_writable(&self) -> bool {
+        true
+    }
+}
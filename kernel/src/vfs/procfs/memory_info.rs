@@ -34,6 +34,22 @@ impl ProcFileOps for MemoryInfo {
             meminfo.max_used / 1024
         )
         .unwrap();
+        #[cfg(allocator = "slab")]
+        for slab in allocator::slab_info() {
+            let free = format!("Slab{}Free:", slab.block_size);
+            let total = format!("Slab{}Total:", slab.block_size);
+            writeln!(result, "{:<14}{:>8}", free, slab.free).unwrap();
+            writeln!(result, "{:<14}{:>8}", total, slab.total).unwrap();
+        }
+        #[cfg(allocator = "slab")]
+        {
+            let stats = allocator::heap_stats();
+            for (slab, class) in allocator::slab_info().iter().zip(stats.slabs.iter()) {
+                let live = format!("Slab{}Live:", slab.block_size);
+                writeln!(result, "{:<14}{:>8}", live, class.live()).unwrap();
+            }
+            writeln!(result, "{:<14}{:>8}", "SysLive:", stats.system.live()).unwrap();
+        }
         Ok(result.as_bytes().to_vec())
     }
 
@@ -12,13 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod log_levels;
 mod memory_info;
 mod stat;
 mod task;
 
+use log_levels::{LogLevel, LogLevels};
 use memory_info::MemoryInfo;
 use stat::SystemStat;
-use task::ProcTaskFile;
+use task::{ProcTaskFile, ProcTaskStackFile};
 
 use crate::{
     devices::Device,
@@ -53,6 +55,10 @@ pub trait ProcFileOps: Send + Sync {
     fn get_content(&self) -> Result<Vec<u8>, Error>;
     // Set the file content when a write operation is performed on a proc inode.
     fn set_content(&self, content: Vec<u8>) -> Result<usize, Error>;
+    // Writable files are created with mode 0o644, others with 0o444.
+    fn is_writable(&self) -> bool {
+        false
+    }
 }
 
 struct DefaultProcFileOps;
@@ -106,7 +112,7 @@ impl ProcFileSystem {
                 },
                 this: weak_root.clone(),
                 parent: weak_root.clone(),
-                children: RwLock::new(BTreeMap::new()),
+                children: RwLock::new(ProcChildren::new()),
             }),
             next_inode_no: AtomicUsize::new(ROOT_INO + 1),
             is_mounted: AtomicBool::new(false),
@@ -123,10 +129,6 @@ impl ProcFileSystem {
         self.is_mounted.load(Ordering::Relaxed)
     }
 
-    pub fn is_mounted(&self) -> bool {
-        self.is_mounted.load(Ordering::Relaxed)
-    }
-
     pub fn init(&self) -> Result<(), Error> {
         if self.check_mounted() {
             warn!("proc can not be mounted twice");
@@ -136,17 +138,12 @@ impl ProcFileSystem {
 
         self.root.create_meminfo_file("meminfo")?;
         self.root.create_stat_file("stat")?;
+        let sys_dir = self.root.create_dir("sys", true)?;
+        let kernel_dir = sys_dir.create_dir("kernel", true)?;
+        kernel_dir.create_log_levels_file("log_levels")?;
+        sys_dir.create_log_level_file("loglevel")?;
 
-        // not support process yet, use thread info instead. and put all threads in /proc
-        let mut global_queue_visitor = GlobalQueueVisitor::new();
-        while let Some(thread) = global_queue_visitor.next() {
-            let id = Thread::id(&thread);
-            let id_str = id.to_string();
-            log::debug!("create_task_dir: /proc/{}", id_str);
-            let thread_dir = self.root.create_dir(id_str.as_str(), false)?;
-            let _ = thread_dir.create_task_file("status", thread.clone())?;
-        }
-
+        // Thread directories are created on demand, see ProcDir::lookup.
         Ok(())
     }
 }
@@ -183,6 +180,35 @@ impl FileSystem for ProcFileSystem {
     }
 }
 
+// not support process yet, use thread info instead. and put all threads in /proc
+fn find_thread(name: &str) -> Option<ThreadNode> {
+    let id = name.parse::<usize>().ok()?;
+    // Reject aliases like "+1" or "01".
+    if id.to_string() != name {
+        return None;
+    }
+    let mut global_queue_visitor = GlobalQueueVisitor::new();
+    while let Some(thread) = global_queue_visitor.next() {
+        if Thread::id(&thread) == id {
+            return Some(thread);
+        }
+    }
+    None
+}
+
+fn live_threads() -> Vec<ThreadNode> {
+    let mut threads = Vec::new();
+    let mut global_queue_visitor = GlobalQueueVisitor::new();
+    while let Some(thread) = global_queue_visitor.next() {
+        threads.push(thread);
+    }
+    threads
+}
+
+fn is_thread_dir_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit())
+}
+
 #[derive(Debug)]
 struct BaseNode {
     attr: RwLock<InodeAttr>,
@@ -226,12 +252,63 @@ impl BaseNode {
     }
 }
 
+// Offsets 0 and 1 are taken by "." and "..".
+const FIRST_COOKIE: usize = 2;
+
+// Children are also indexed by a cookie allocated on insertion, which
+// is used as the directory offset. So threads created or retired
+// between two getdents calls don't shift other entries.
+#[derive(Debug)]
+struct ProcChildren {
+    by_name: BTreeMap<String, (usize, Arc<dyn InodeOps>)>,
+    by_cookie: BTreeMap<usize, String>,
+    next_cookie: usize,
+}
+
+impl ProcChildren {
+    const fn new() -> Self {
+        Self {
+            by_name: BTreeMap::new(),
+            by_cookie: BTreeMap::new(),
+            next_cookie: FIRST_COOKIE,
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&Arc<dyn InodeOps>> {
+        self.by_name.get(name).map(|(_, inode)| inode)
+    }
+
+    fn insert(&mut self, name: &str, inode: Arc<dyn InodeOps>) {
+        if let Some(entry) = self.by_name.get_mut(name) {
+            entry.1 = inode;
+            return;
+        }
+        let cookie = self.next_cookie;
+        self.next_cookie += 1;
+        self.by_name.insert(String::from(name), (cookie, inode));
+        self.by_cookie.insert(cookie, String::from(name));
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Some((cookie, _)) = self.by_name.remove(name) {
+            self.by_cookie.remove(&cookie);
+        }
+    }
+
+    // Children whose cookie is not less than `offset`.
+    fn iter_from(&self, offset: usize) -> impl Iterator<Item = (usize, &str, &Arc<dyn InodeOps>)> {
+        self.by_cookie
+            .range(offset..)
+            .map(|(&cookie, name)| (cookie, name.as_str(), &self.by_name[name].1))
+    }
+}
+
 #[derive(Debug)]
 struct ProcDir {
     base: BaseNode,
     this: Weak<ProcDir>,
     parent: Weak<ProcDir>,
-    children: RwLock<BTreeMap<String, Arc<dyn InodeOps>>>,
+    children: RwLock<ProcChildren>,
 }
 
 impl ProcDir {
@@ -252,6 +329,36 @@ impl ProcDir {
         Ok(inode)
     }
 
+    pub fn create_task_stack_file(
+        &self,
+        name: &str,
+        thread: ThreadNode,
+    ) -> Result<Arc<dyn InodeOps>, Error> {
+        if name.len() > NAME_MAX {
+            return Err(code::ENAMETOOLONG);
+        }
+
+        let ino = self.base.fs.upgrade().unwrap().alloc_inode_no();
+        let inode = ProcFile::new(
+            ProcTaskStackFile::new(thread),
+            ino,
+            self.base.fs.clone(),
+            false,
+        ) as Arc<dyn InodeOps>;
+        self.insert(name, inode.clone());
+
+        Ok(inode)
+    }
+
+    pub fn create_thread_dir(&self, thread: &ThreadNode) -> Result<Arc<Self>, Error> {
+        let id_str = Thread::id(thread).to_string();
+        log::debug!("create_task_dir: /proc/{}", id_str);
+        let thread_dir = self.create_dir(id_str.as_str(), false)?;
+        let _ = thread_dir.create_task_file("status", thread.clone())?;
+        let _ = thread_dir.create_task_stack_file("stack", thread.clone())?;
+        Ok(thread_dir)
+    }
+
     pub fn create_meminfo_file(&self, name: &str) -> Result<Arc<dyn InodeOps>, Error> {
         if name.len() > NAME_MAX {
             return Err(code::ENAMETOOLONG);
@@ -274,6 +381,28 @@ impl ProcDir {
         Ok(inode)
     }
 
+    pub fn create_log_levels_file(&self, name: &str) -> Result<Arc<dyn InodeOps>, Error> {
+        if name.len() > NAME_MAX {
+            return Err(code::ENAMETOOLONG);
+        }
+        let ino = self.base.fs.upgrade().unwrap().alloc_inode_no();
+        let inode =
+            ProcFile::new(LogLevels {}, ino, self.base.fs.clone(), true) as Arc<dyn InodeOps>;
+        self.insert(name, inode.clone());
+        Ok(inode)
+    }
+
+    pub fn create_log_level_file(&self, name: &str) -> Result<Arc<dyn InodeOps>, Error> {
+        if name.len() > NAME_MAX {
+            return Err(code::ENAMETOOLONG);
+        }
+        let ino = self.base.fs.upgrade().unwrap().alloc_inode_no();
+        let inode =
+            ProcFile::new(LogLevel {}, ino, self.base.fs.clone(), true) as Arc<dyn InodeOps>;
+        self.insert(name, inode.clone());
+        Ok(inode)
+    }
+
     pub fn create_dir(&self, name: &str, is_dcacheable: bool) -> Result<Arc<Self>, Error> {
         if name.len() > NAME_MAX {
             return Err(code::ENAMETOOLONG);
@@ -294,19 +423,60 @@ impl ProcDir {
             },
             this: weak_inode.clone(),
             parent: self.this.clone(),
-            children: RwLock::new(BTreeMap::new()),
+            children: RwLock::new(ProcChildren::new()),
         });
         self.insert(name, inode.clone());
 
         Ok(inode)
     }
 
+    // The root is its own parent.
+    fn is_root(&self) -> bool {
+        Weak::ptr_eq(&self.this, &self.parent)
+    }
+
+    // Resolve /proc/<id> against the global thread queue, a cached dir of
+    // a retired thread is dropped.
+    fn lookup_thread_dir(&self, name: &str) -> Result<Arc<dyn InodeOps>, Error> {
+        let Some(thread) = find_thread(name) else {
+            self.remove(name);
+            return Err(code::ENOENT);
+        };
+        if let Some(inode) = self.find(name) {
+            return Ok(inode);
+        }
+        Ok(self.create_thread_dir(&thread)?)
+    }
+
+    // Bring thread dirs in line with the global thread queue before listing.
+    fn sync_thread_dirs(&self) -> Result<(), Error> {
+        let threads = live_threads();
+        let stale: Vec<String> = self
+            .children
+            .read()
+            .iter_from(FIRST_COOKIE)
+            .map(|(_, name, _)| name)
+            .filter(|name| is_thread_dir_name(name))
+            .filter(|name| !threads.iter().any(|t| Thread::id(t).to_string() == *name))
+            .map(String::from)
+            .collect();
+        for name in stale {
+            self.remove(name.as_str());
+        }
+        for thread in threads.iter() {
+            if self.find(Thread::id(thread).to_string().as_str()).is_none() {
+                self.create_thread_dir(thread)?;
+            }
+        }
+        Ok(())
+    }
+
     fn find(&self, name: &str) -> Option<Arc<dyn InodeOps>> {
         self.children.read().get(name).cloned()
     }
 
     fn insert(&self, name: &str, inode: Arc<dyn InodeOps>) {
-        self.children.write().insert(String::from(name), inode);
+        self.children.write().insert(name, inode);
     }
 
     fn remove(&self, name: &str) {
@@ -323,11 +493,17 @@ impl InodeOps for ProcDir {
         if name == ".." {
             return Ok(self.parent.upgrade().unwrap());
         }
+        if self.is_root() && is_thread_dir_name(name) {
+            return self.lookup_thread_dir(name);
+        }
         let inode = self.find(name).ok_or(code::ENOENT)?;
         Ok(inode.clone())
     }
 
     fn getdents_at(&self, offset: usize, reader: &mut DirBufferReader) -> Result<usize, Error> {
+        if self.is_root() {
+            self.sync_thread_dirs()?;
+        }
         let mut count = 0;
         let mut current_offset = offset;
         // Handle special entries (., ..)
@@ -353,12 +529,11 @@ impl InodeOps for ProcDir {
             current_offset += 1;
         }
 
-        let start_idx = current_offset.saturating_sub(2);
-        for (name, inode) in self.children.read().iter().skip(start_idx) {
-            match reader.write_node(inode.ino(), current_offset as i64, inode.type_(), name) {
+        let children = self.children.read();
+        for (cookie, name, inode) in children.iter_from(current_offset) {
+            match reader.write_node(inode.ino(), cookie as i64, inode.type_(), name) {
                 Ok(_) => {
                     count += 1;
-                    current_offset += 1;
                 }
                 Err(e) => {
                     if count == 0 {
@@ -433,12 +608,13 @@ impl<T: ProcFileOps> ProcFile<T> {
         fs: Weak<ProcFileSystem>,
         is_dcacheable: bool,
     ) -> Arc<Self> {
+        let mode = if file.is_writable() { 0o644 } else { 0o444 };
         Arc::new(Self {
             base: BaseNode {
                 attr: RwLock::new(InodeAttr::new(
                     inode_no,
                     InodeFileType::Regular,
-                    InodeMode::from(0o444),
+                    InodeMode::from(mode),
                     0,
                     0,
                     BLOCK_SIZE,
@@ -471,8 +647,12 @@ impl<T: ProcFileOps + 'static> InodeOps for ProcFile<T> {
         Ok(len)
     }
 
-    fn write_at(&self, _offset: usize, _buf: &[u8], _nonblock: bool) -> Result<usize, Error> {
-        Err(code::EPERM)
+    // Each write carries the whole new content, the offset is ignored.
+    fn write_at(&self, _offset: usize, buf: &[u8], _nonblock: bool) -> Result<usize, Error> {
+        if !self.inner.is_writable() {
+            return Err(code::EPERM);
+        }
+        self.inner.set_content(buf.to_vec())
     }
 
     fn resize(&self, _new_size: usize) -> Result<(), Error> {
@@ -497,30 +677,77 @@ impl<T: ProcFileOps + 'static> InodeOps for ProcFile<T> {
     }
 }
 
-// TODO: add observer to trace create and close when process is supported.
-pub fn trace_thread_create(thread: ThreadNode) -> Result<(), Error> {
-    let procfs = get_procfs();
-    if !procfs.is_mounted() {
-        return Err(code::EINVAL);
-    }
-    let root = procfs.root.clone();
-    let pid_dir = root.lookup("0")?;
-    let task_dir = pid_dir.lookup("task")?;
-    let task_dir = task_dir.downcast_ref::<ProcDir>().ok_or(code::EINVAL)?;
-    let thread_dir = task_dir.create_dir(Thread::id(&thread).to_string().as_str(), false)?;
-    let _ = thread_dir.create_task_file("status", thread.clone())?;
-    Ok(())
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{scheduler, thread::spawn, vfs::dirent::Dirent};
+    use blueos_test_macro::test;
+
+    static STOP: AtomicBool = AtomicBool::new(false);
+
+    #[repr(align(8))]
+    struct DirentBuf([u8; 320]);
+
+    // Read a few entries at a time, just like a getdents loop on an
+    // open directory.
+    fn read_some(dir: &ProcDir, offset: &mut usize, names: &mut Vec<String>) -> usize {
+        let mut buf = DirentBuf([0u8; 320]);
+        // Room for 2 short entries, while Dirent::name may look past it.
+        let mut reader = DirBufferReader::new(&mut buf.0[..64]);
+        let cnt = dir.getdents_at(*offset, &mut reader).unwrap();
+        *offset = reader.next_offset(*offset, cnt);
+        let len = reader.recv_len();
+        let mut pos = 0;
+        while pos < len {
+            let entry = unsafe { Dirent::from_buf_ref(&buf.0[pos..]) };
+            names.push(entry.name().unwrap().to_string_lossy().into_owned());
+            pos += entry.reclen() as usize;
+        }
+        cnt
+    }
 
-pub fn trace_thread_close(thread: ThreadNode) -> Result<(), Error> {
-    let procfs = get_procfs();
-    if !procfs.is_mounted() {
-        return Err(code::EINVAL);
-    }
-    let root = procfs.root.clone();
-    let pid_dir = root.lookup("0")?;
-    let task_dir = pid_dir.lookup("task")?;
-    let task_dir = task_dir.downcast_ref::<ProcDir>().ok_or(code::EINVAL)?;
-    task_dir.remove(Thread::id(&thread).to_string().as_str());
-    Ok(())
+    #[test]
+    fn test_getdents_stable_across_insert() {
+        let root = &get_procfs().root;
+        let dir = root.create_dir("getdents_test", false).unwrap();
+        for name in ["a", "c", "e"] {
+            dir.create_dir(name, false).unwrap();
+        }
+        let mut offset = 0;
+        let mut names = Vec::new();
+        read_some(&dir, &mut offset, &mut names);
+        read_some(&dir, &mut offset, &mut names);
+        assert!(names.iter().any(|n| n == "a"));
+        // Sorts before every existing child.
+        dir.create_dir("0", false).unwrap();
+        while read_some(&dir, &mut offset, &mut names) > 0 {}
+        for name in [".", "..", "a", "c", "e"] {
+            assert_eq!(names.iter().filter(|n| *n == name).count(), 1);
+        }
+        root.remove("getdents_test");
+    }
+
+    #[test]
+    fn test_lookup_thread_created_after_mount() {
+        // A private instance, the global one is mounted at /proc.
+        let procfs = ProcFileSystem::new();
+        procfs.init().unwrap();
+        STOP.store(false, Ordering::Release);
+        let t = spawn(|| {
+            while !STOP.load(Ordering::Acquire) {
+                scheduler::suspend_me_for(1);
+            }
+        })
+        .unwrap();
+        let id = Thread::id(&t).to_string();
+        let root = procfs.root_inode();
+        let thread_dir = root.lookup(id.as_str()).unwrap();
+        let status = thread_dir.lookup("status").unwrap();
+        let mut buf = [0u8; 256];
+        let n = status.read_at(0, &mut buf, false).unwrap();
+        let content = core::str::from_utf8(&buf[..n]).unwrap();
+        assert!(content.contains(id.as_str()));
+        assert!(root.lookup(alloc::format!("0{}", id).as_str()).is_err());
+        STOP.store(true, Ordering::Release);
+    }
 }
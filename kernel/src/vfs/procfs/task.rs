@@ -33,10 +33,23 @@ impl ProcTaskFile {
 impl ProcFileOps for ProcTaskFile {
     fn get_content(&self) -> Result<Vec<u8>, Error> {
         let mut result = String::with_capacity(64);
-        writeln!(result, "{:<9} {}", "Name:", self.thread.kind_to_str()).unwrap();
+        let name = match self.thread.name() {
+            "" => self.thread.kind_to_str(),
+            name => name,
+        };
+        writeln!(result, "{:<9} {}", "Name:", name).unwrap();
+        writeln!(result, "{:<9} {}", "Kind:", self.thread.kind_to_str()).unwrap();
         writeln!(result, "{:<9} {}", "State:", self.thread.state_to_str()).unwrap();
         writeln!(result, "{:<9} {}", "Tid:", Thread::id(&self.thread)).unwrap();
         writeln!(result, "{:<9} {}", "Priority:", self.thread.priority()).unwrap();
+        writeln!(result, "{:<9} {}", "StkSize:", self.thread.stack_size()).unwrap();
+        writeln!(
+            result,
+            "{:<9} {}",
+            "StkPeak:",
+            self.thread.stack_high_water()
+        )
+        .unwrap();
         Ok(result.as_bytes().to_vec())
     }
 
@@ -44,3 +57,46 @@ impl ProcFileOps for ProcTaskFile {
         Ok(0)
     }
 }
+
+// /proc/<id>/stack, layout and usage of the thread's stack, followed by
+// its return addresses, one per line, if the arch supports backtraces.
+pub struct ProcTaskStackFile {
+    thread: ThreadNode,
+}
+
+impl ProcTaskStackFile {
+    pub fn new(thread: ThreadNode) -> Self {
+        Self { thread }
+    }
+}
+
+impl ProcFileOps for ProcTaskStackFile {
+    fn get_content(&self) -> Result<Vec<u8>, Error> {
+        let mut result = String::with_capacity(128);
+        let saved_usage = if self.thread.validate_saved_sp() {
+            self.thread.saved_stack_usage()
+        } else {
+            0
+        };
+        writeln!(result, "{:<9} 0x{:x}", "Base:", self.thread.stack_base()).unwrap();
+        writeln!(result, "{:<9} {}", "Size:", self.thread.stack_size()).unwrap();
+        writeln!(result, "{:<9} 0x{:x}", "SavedSP:", self.thread.saved_sp()).unwrap();
+        writeln!(result, "{:<9} {}", "Usage:", saved_usage).unwrap();
+        writeln!(result, "{:<9} {}", "Peak:", self.thread.stack_high_water()).unwrap();
+        #[cfg(target_arch = "aarch64")]
+        {
+            let mut addrs = [0usize; crate::thread::MAX_BACKTRACE_ADDRESSES];
+            // A thread running on another core can't be unwound, it's
+            // reported without frames.
+            let n = crate::thread::backtrace(&self.thread, &mut addrs).unwrap_or(0);
+            for (i, addr) in addrs[..n].iter().enumerate() {
+                writeln!(result, "#{:<2} 0x{:016x}", i, addr).unwrap();
+            }
+        }
+        Ok(result.into_bytes())
+    }
+
+    fn set_content(&self, content: Vec<u8>) -> Result<usize, Error> {
+        Ok(0)
+    }
+}
@@ -21,11 +21,12 @@ use crate::{
         inode::InodeOps,
         inode_mode::InodeMode,
         path,
+        poll::{PollEvents, PollTable},
         utils::SeekFrom,
     },
 };
 use alloc::{boxed::Box, sync::Arc};
-use core::sync::atomic::{AtomicI32, Ordering};
+use core::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
 use log::{debug, warn};
 use spin::Mutex;
 
@@ -33,6 +34,9 @@ pub struct SocketFile {
     inode: Arc<dyn InodeOps>,
     socket: Mutex<Option<Arc<Connection>>>,
     open_flags: AtomicI32,
+    // Files sharing the connection, it's shut down when the last one
+    // is closed.
+    refs: Arc<AtomicUsize>,
 }
 
 impl SocketFile {
@@ -41,6 +45,7 @@ impl SocketFile {
             inode,
             socket: Mutex::new(None),
             open_flags: AtomicI32::new(flags.bits()),
+            refs: Arc::new(AtomicUsize::new(1)),
         }
     }
 
@@ -147,7 +152,7 @@ impl FileOps for SocketFile {
 
     fn ioctl(&self, cmd: u32, arg: usize) -> Result<i32, Error> {
         warn!("Illegal ioctl on socket, ioctl is not implemented");
-        Err(code::ERROR)
+        Err(code::ENOTTY)
     }
 
     fn flush(&self) -> Result<(), Error> {
@@ -155,6 +160,9 @@ impl FileOps for SocketFile {
     }
 
     fn close(&self) -> Result<(), Error> {
+        if self.refs.fetch_sub(1, Ordering::AcqRel) != 1 {
+            return Ok(());
+        }
         if let Some(socket) = self.socket() {
             match socket.shutdown() {
                 Ok(_) => Ok(()),
@@ -175,8 +183,23 @@ impl FileOps for SocketFile {
     }
 
     fn dup(&self, close_on_exec: bool) -> Result<Arc<dyn FileOps>, Error> {
-        warn!("Illegal dup on socket, dup is not implemented");
-        Err(code::EINVAL)
+        // FD_CLOEXEC isn't inherited from the original
+        let mut flags = self.flags();
+        flags.set(OpenFlags::O_CLOEXEC, close_on_exec);
+        self.refs.fetch_add(1, Ordering::AcqRel);
+        Ok(Arc::new(SocketFile {
+            inode: self.inode.clone(),
+            socket: Mutex::new(self.socket()),
+            open_flags: AtomicI32::new(flags.bits()),
+            refs: self.refs.clone(),
+        }))
+    }
+
+    fn poll(&self, events: PollEvents, table: &mut PollTable) -> PollEvents {
+        match self.socket() {
+            Some(socket) => socket.poll(events, table),
+            None => PollEvents::POLLNVAL,
+        }
     }
 
     fn stat(&self) -> FileAttr {
@@ -199,7 +222,7 @@ pub fn alloc_sock_fd(flags: i32) -> i32 {
         Ok(inode) => inode,
         Err(e) => {
             warn!("Failed to create socket inode: {:?}", e);
-            return -1;
+            return e.to_errno();
         }
     };
     let socket_file = Arc::new(SocketFile::new(socket_inode, flags.into()));
@@ -14,15 +14,17 @@
 
 //! C API for VFS operations  
 use crate::{
+    devices::tty::termios::{TCGETS, TCSAFLUSH, TCSANOW, TCSETS, TCSETSF, TCSETSW},
     error::code,
     vfs::{
         dcache::Dcache,
         dirent::DirBufferReader,
+        epoll::{self, EpollEvent, EpollFile},
         fd_manager::get_fd_manager,
         file::{File, FileAttr, FileOps, OpenFlags},
         fs::FileSystemInfo,
         inode_mode::{InodeFileType, InodeMode},
-        mount, path,
+        mount, path, pipe, poll,
         utils::SeekFrom,
     },
 };
@@ -36,6 +38,8 @@ use core::{
 use libc;
 use log::{debug, error, warn};
 
+pub use crate::devices::tty::termios::{CcIndex, Lflags, Termios, TCSADRAIN, TIOCSTI};
+
 pub fn mount(
     device_name: *const c_char,
     path: *const c_char,
@@ -130,6 +134,33 @@ pub fn umount(path: *const c_char) -> c_int {
 
 /// Open a file
 pub fn open(path: *const c_char, flags: c_int, mode: libc::mode_t) -> c_int {
+    openat(libc::AT_FDCWD, path, flags, mode)
+}
+
+// Directory a relative path passed to an *at call is resolved against.
+// dirfd is ignored for absolute paths.
+fn at_dir(dirfd: c_int, path: &str) -> Result<Arc<Dcache>, c_int> {
+    if dirfd == libc::AT_FDCWD || path.starts_with('/') {
+        return Ok(path::get_working_dir());
+    }
+    let file_ops = {
+        let fd_manager = get_fd_manager().lock();
+        match fd_manager.get_file_ops(dirfd) {
+            Some(ops) => ops,
+            None => return Err(-libc::EBADF),
+        }
+    };
+    let Some(file) = file_ops.downcast_ref::<File>() else {
+        return Err(-libc::ENOTDIR);
+    };
+    if file.type_() != InodeFileType::Directory {
+        return Err(-libc::ENOTDIR);
+    }
+    Ok(file.dcache())
+}
+
+/// Open a file relative to the directory `dirfd` refers to
+pub fn openat(dirfd: c_int, path: *const c_char, flags: c_int, mode: libc::mode_t) -> c_int {
     if path.is_null() {
         return -libc::EINVAL;
     }
@@ -139,22 +170,29 @@ pub fn open(path: *const c_char, flags: c_int, mode: libc::mode_t) -> c_int {
         Err(_) => return -libc::EINVAL,
     };
     debug!(
-        "[open] path = {}, flags = {}, mode = {:o}",
+        "[openat] dirfd = {}, path = {}, flags = {}, mode = {:o}",
+        dirfd,
         file_path,
         flags_to_string(flags),
         mode
     );
 
+    let base = match at_dir(dirfd, file_path) {
+        Ok(base) => base,
+        Err(e) => return e,
+    };
     let file = {
-        match path::open_path(file_path, flags, mode) {
+        match path::open_path_at(&base, file_path, flags, mode) {
             Ok(file) => Arc::new(file),
             Err(e) => return e.to_errno(),
         }
     };
 
-    let mut fd_manager = get_fd_manager().lock();
-    let fd = fd_manager.alloc_fd(file);
-    fd as i32
+    let fd = get_fd_manager().lock().alloc_fd(file.clone());
+    if fd < 0 {
+        let _ = file.close();
+    }
+    fd
 }
 
 pub fn creat(path: *const c_char, mode: libc::mode_t) -> c_int {
@@ -180,6 +218,80 @@ pub fn close(fd: i32) -> i32 {
     }
 }
 
+/// Duplicate a file descriptor to the lowest free one, sharing the offset
+pub fn dup(fd: i32) -> c_int {
+    let mut fd_manager = get_fd_manager().lock();
+    match fd_manager.dup_fd(fd, 0, false) {
+        Ok(new_fd) => new_fd,
+        Err(e) => e.to_errno(),
+    }
+}
+
+/// Duplicate a file descriptor to `new_fd`, closing the file open there
+pub fn dup2(fd: i32, new_fd: i32) -> c_int {
+    if fd == new_fd {
+        return if get_fd_manager().lock().is_valid_fd(fd) {
+            fd
+        } else {
+            -libc::EBADF
+        };
+    }
+    dup_to(fd, new_fd, false)
+}
+
+/// Same as dup2() but `new_fd` must differ and `O_CLOEXEC` may be set
+pub fn dup3(fd: i32, new_fd: i32, flags: c_int) -> c_int {
+    if fd == new_fd || flags & !libc::O_CLOEXEC != 0 {
+        return -libc::EINVAL;
+    }
+    dup_to(fd, new_fd, flags & libc::O_CLOEXEC != 0)
+}
+
+fn dup_to(fd: i32, new_fd: i32, close_on_exec: bool) -> c_int {
+    // Closed out of the lock, closing may block
+    let old = match get_fd_manager().lock().dup_fd_to(fd, new_fd, close_on_exec) {
+        Ok(old) => old,
+        Err(e) => return e.to_errno(),
+    };
+    if let Some(old) = old {
+        // Errors of the implicit close are ignored like Linux does
+        let _ = old.close();
+    }
+    new_fd
+}
+
+/// Create a pipe, `fds[0]` is the read end and `fds[1]` the write end
+pub fn pipe(fds: *mut c_int) -> c_int {
+    pipe2(fds, 0)
+}
+
+/// Same as pipe() but `O_NONBLOCK` and `O_CLOEXEC` may be set on both ends
+pub fn pipe2(fds: *mut c_int, flags: c_int) -> c_int {
+    if fds.is_null() {
+        return -libc::EFAULT;
+    }
+    if flags & !(libc::O_NONBLOCK | libc::O_CLOEXEC) != 0 {
+        return -libc::EINVAL;
+    }
+
+    let (reader, writer) = pipe::new_pipe(OpenFlags::from(flags));
+    let mut fd_manager = get_fd_manager().lock();
+    let read_fd = fd_manager.alloc_fd(reader);
+    if read_fd < 0 {
+        return read_fd;
+    }
+    let write_fd = fd_manager.alloc_fd(writer);
+    if write_fd < 0 {
+        let _ = fd_manager.free_fd(read_fd);
+        return write_fd;
+    }
+    unsafe {
+        *fds = read_fd;
+        *fds.add(1) = write_fd;
+    }
+    0
+}
+
 /// Read from a file
 pub fn read(fd: i32, buf: *mut u8, count: usize) -> isize {
     if buf.is_null() {
@@ -230,6 +342,62 @@ pub fn write(fd: i32, buf: *const u8, count: usize) -> isize {
     }
 }
 
+/// Read from a file at `offset`, the file offset is left unchanged
+pub fn pread(fd: i32, buf: *mut u8, count: usize, offset: libc::off_t) -> isize {
+    if buf.is_null() {
+        return -libc::EINVAL as isize;
+    }
+    if offset < 0 {
+        return -libc::EINVAL as isize;
+    }
+
+    let file_ops = {
+        let fd_manager = get_fd_manager().lock();
+        match fd_manager.get_file_ops(fd) {
+            Some(ops) => ops,
+            None => return -libc::EBADF as isize,
+        }
+    };
+
+    if count == 0 {
+        return 0;
+    }
+
+    let slice = unsafe { slice::from_raw_parts_mut(buf, count) };
+    match file_ops.read_at(offset as usize, slice) {
+        Ok(n) => n as isize,
+        Err(e) => e.to_errno() as isize,
+    }
+}
+
+/// Write to a file at `offset`, the file offset is left unchanged
+pub fn pwrite(fd: i32, buf: *const u8, count: usize, offset: libc::off_t) -> isize {
+    if buf.is_null() {
+        return -libc::EINVAL as isize;
+    }
+    if offset < 0 {
+        return -libc::EINVAL as isize;
+    }
+
+    let file_ops = {
+        let fd_manager = get_fd_manager().lock();
+        match fd_manager.get_file_ops(fd) {
+            Some(ops) => ops,
+            None => return -libc::EBADF as isize,
+        }
+    };
+
+    if count == 0 {
+        return 0;
+    }
+
+    let slice = unsafe { slice::from_raw_parts(buf, count) };
+    match file_ops.write_at(offset as usize, slice) {
+        Ok(n) => n as isize,
+        Err(e) => e.to_errno() as isize,
+    }
+}
+
 /// Seek in a file
 pub fn lseek(fd: i32, offset: i64, whence: i32) -> i64 {
     debug!(
@@ -300,6 +468,83 @@ pub fn ftruncate(fd: i32, length: libc::off_t) -> c_int {
     }
 }
 
+/// Write the data of `fd` out to its backing device. A no-op for files
+/// of in-memory filesystems.
+pub fn fsync(fd: i32) -> c_int {
+    let file_ops = {
+        let fd_manager = get_fd_manager().lock();
+        match fd_manager.get_file_ops(fd) {
+            Some(ops) => ops,
+            None => return -libc::EBADF,
+        }
+    };
+
+    match file_ops.fsync() {
+        Ok(_) => 0,
+        Err(e) => e.to_errno(),
+    }
+}
+
+/// Same as fsync(), metadata is never written apart from the data
+pub fn fdatasync(fd: i32) -> c_int {
+    fsync(fd)
+}
+
+/// Get the terminal attributes of `fd`
+pub fn tcgetattr(fd: i32, termios: *mut Termios) -> c_int {
+    if termios.is_null() {
+        return -libc::EINVAL;
+    }
+    tty_ioctl(fd, TCGETS, termios as usize)
+}
+
+/// Set the terminal attributes of `fd`, `optional_actions` tells when
+pub fn tcsetattr(fd: i32, optional_actions: c_int, termios: *const Termios) -> c_int {
+    if termios.is_null() {
+        return -libc::EINVAL;
+    }
+    let request = match optional_actions {
+        TCSANOW => TCSETS,
+        TCSADRAIN => TCSETSW,
+        TCSAFLUSH => TCSETSF,
+        _ => return -libc::EINVAL,
+    };
+    tty_ioctl(fd, request, termios as usize)
+}
+
+/// Send a device specific `request` to the file of `fd`, what `arg`
+/// means depends on the request.
+pub fn ioctl(fd: i32, request: u32, arg: usize) -> c_int {
+    let file_ops = {
+        let fd_manager = get_fd_manager().lock();
+        match fd_manager.get_file_ops(fd) {
+            Some(ops) => ops,
+            None => return -libc::EBADF,
+        }
+    };
+
+    match file_ops.ioctl(request, arg) {
+        Ok(ret) => ret,
+        Err(e) => e.to_errno(),
+    }
+}
+
+// Files which aren't terminals fail the termios requests with ENOTTY.
+fn tty_ioctl(fd: i32, request: u32, arg: usize) -> c_int {
+    let file_ops = {
+        let fd_manager = get_fd_manager().lock();
+        match fd_manager.get_file_ops(fd) {
+            Some(ops) => ops,
+            None => return -libc::EBADF,
+        }
+    };
+
+    match file_ops.ioctl(request, arg) {
+        Ok(_) => 0,
+        Err(e) => e.to_errno(),
+    }
+}
+
 pub fn fcntl(fd: i32, cmd: c_int, args: usize) -> c_int {
     debug!("fcntl: fd = {}, cmd = {}, args = {}", fd, cmd, args);
     const FD_CLOEXEC: c_int = 1;
@@ -422,35 +667,156 @@ pub fn link(old_path: *const c_char, new_path: *const c_char) -> c_int {
     }
 }
 
+/// Create a symbolic link at `link_path` holding `target`. The target
+/// isn't required to exist.
+pub fn symlink(target: *const c_char, link_path: *const c_char) -> c_int {
+    if target.is_null() || link_path.is_null() {
+        return -libc::EINVAL;
+    }
+
+    let target = match unsafe { CStr::from_ptr(target).to_str() } {
+        Ok(s) => s,
+        Err(_) => return -libc::EINVAL,
+    };
+    let link_path = match unsafe { CStr::from_ptr(link_path).to_str() } {
+        Ok(s) => s,
+        Err(_) => return -libc::EINVAL,
+    };
+    if target.is_empty() {
+        return -libc::ENOENT;
+    }
+    if link_path.ends_with('/') {
+        return -libc::EEXIST;
+    }
+
+    let Some((dir, name)) = path::find_parent_and_name(link_path) else {
+        return -libc::ENOENT;
+    };
+    match dir.symlink(name, target) {
+        Ok(_) => 0,
+        Err(e) => e.to_errno(),
+    }
+}
+
+/// Copy the target of the symbolic link `path` into `buf`, without a
+/// terminating NUL. The target is truncated if `buf` is too small.
+pub fn readlink(path: *const c_char, buf: *mut c_char, buf_len: usize) -> isize {
+    if path.is_null() || buf.is_null() || buf_len == 0 {
+        return -libc::EINVAL as isize;
+    }
+
+    let path_str = match unsafe { CStr::from_ptr(path).to_str() } {
+        Ok(s) => s,
+        Err(_) => return -libc::EINVAL as isize,
+    };
+    let dir_entry = match path::resolve_path_at(&path::get_working_dir(), path_str, false) {
+        Ok(entry) => entry,
+        Err(e) => return e.to_errno() as isize,
+    };
+    let target = match dir_entry.inode().readlink() {
+        Ok(target) => target,
+        Err(e) => return e.to_errno() as isize,
+    };
+
+    let len = target.len().min(buf_len);
+    unsafe {
+        copy_nonoverlapping(target.as_ptr(), buf as *mut u8, len);
+    }
+    len as isize
+}
+
+/// Rename a file or directory, an existing `new_path` is replaced
+pub fn rename(old_path: *const c_char, new_path: *const c_char) -> c_int {
+    if old_path.is_null() || new_path.is_null() {
+        return -libc::EINVAL;
+    }
+
+    let old_path = match unsafe { CStr::from_ptr(old_path).to_str() } {
+        Ok(s) => s,
+        Err(_) => return -libc::EINVAL,
+    };
+
+    let new_path = match unsafe { CStr::from_ptr(new_path).to_str() } {
+        Ok(s) => s,
+        Err(_) => return -libc::EINVAL,
+    };
+
+    let Some((old_dir, old_name)) = path::find_parent_and_name(old_path.trim_end_matches('/'))
+    else {
+        warn!("[rename] Invalid path: {}", old_path);
+        return -libc::ENOENT;
+    };
+    let Some((new_dir, new_name)) = path::find_parent_and_name(new_path.trim_end_matches('/'))
+    else {
+        warn!("[rename] Invalid path: {}", new_path);
+        return -libc::ENOENT;
+    };
+
+    debug!("[rename] {} -> {}", old_path, new_path);
+
+    match old_dir.rename(old_name, &new_dir, new_name) {
+        Ok(_) => 0,
+        Err(e) => e.to_errno(),
+    }
+}
+
 pub fn unlink(path: *const c_char) -> c_int {
+    unlinkat(libc::AT_FDCWD, path, 0)
+}
+
+/// Remove a file, or a directory with AT_REMOVEDIR, relative to the
+/// directory `dirfd` refers to
+pub fn unlinkat(dirfd: c_int, path: *const c_char, flags: c_int) -> c_int {
     if path.is_null() {
         return -libc::EINVAL;
     }
+    if flags & !libc::AT_REMOVEDIR != 0 {
+        return -libc::EINVAL;
+    }
 
     let file_path = match unsafe { CStr::from_ptr(path).to_str() } {
         Ok(s) => s,
         Err(_) => return -libc::EINVAL,
     };
+    let remove_dir = flags & libc::AT_REMOVEDIR != 0;
 
-    if file_path.ends_with('/') {
+    if remove_dir && file_path == "/" {
+        warn!("Cannot remove root directory");
+        return -libc::EBUSY;
+    }
+    if !remove_dir && file_path.ends_with('/') {
         warn!("[unlink] Cannot unlink a directory: {}", file_path);
         return -libc::EISDIR;
     }
 
-    let Some((dir, name)) = path::find_parent_and_name(file_path) else {
+    let base = match at_dir(dirfd, file_path) {
+        Ok(base) => base,
+        Err(e) => return e,
+    };
+    let Some((dir, name)) = path::find_parent_and_name_at(&base, file_path) else {
         warn!("[unlink] Invalid path: {}", file_path);
         return -libc::EINVAL;
     };
 
     debug!("[unlink] file_path = {}", file_path);
 
-    match dir.unlink(name) {
+    let result = if remove_dir {
+        dir.rmdir(name.trim_end_matches('/'))
+    } else {
+        dir.unlink(name)
+    };
+    match result {
         Ok(_) => 0,
         Err(e) => e.to_errno(),
     }
 }
 
 pub fn mkdir(path: *const c_char, mode: libc::mode_t) -> i32 {
+    mkdirat(libc::AT_FDCWD, path, mode)
+}
+
+/// Create a directory relative to the directory `dirfd` refers to
+pub fn mkdirat(dirfd: c_int, path: *const c_char, mode: libc::mode_t) -> i32 {
     if path.is_null() {
         return -libc::EINVAL;
     }
@@ -460,7 +826,11 @@ pub fn mkdir(path: *const c_char, mode: libc::mode_t) -> i32 {
         Err(_) => return -libc::EINVAL,
     };
 
-    let (dir, name) = match path::find_parent_and_name(file_path) {
+    let base = match at_dir(dirfd, file_path) {
+        Ok(base) => base,
+        Err(e) => return e,
+    };
+    let (dir, name) = match path::find_parent_and_name_at(&base, file_path) {
         Some((dir, name)) => (dir, name),
         None => return -libc::EINVAL,
     };
@@ -477,29 +847,7 @@ pub fn mkdir(path: *const c_char, mode: libc::mode_t) -> i32 {
 }
 
 pub fn rmdir(path: *const c_char) -> c_int {
-    if path.is_null() {
-        return -libc::EINVAL;
-    }
-
-    let file_path = match unsafe { CStr::from_ptr(path).to_str() } {
-        Ok(s) => s,
-        Err(_) => return -libc::EINVAL,
-    };
-
-    if file_path == "/" {
-        warn!("Cannot remove root directory");
-        return -libc::EBUSY;
-    }
-
-    let Some((dir, name)) = path::find_parent_and_name(file_path) else {
-        warn!("Invalid path: {}", file_path);
-        return -libc::EINVAL;
-    };
-
-    match dir.rmdir(name.trim_end_matches('/')) {
-        Ok(_) => 0,
-        Err(e) => e.to_errno(),
-    }
+    unlinkat(libc::AT_FDCWD, path, libc::AT_REMOVEDIR)
 }
 
 pub fn getdents(fd: i32, buf: *mut u8, buf_len: usize) -> c_int {
@@ -595,18 +943,33 @@ impl From<FileAttr> for Stat {
 crate::static_assert!(size_of::<Stat>() == size_of::<libc::stat>());
 
 pub fn stat(path: *const c_char, buf: *mut Stat) -> c_int {
+    fstatat(libc::AT_FDCWD, path, buf, 0)
+}
+
+/// Stat a path relative to the directory `dirfd` refers to. A symlink
+/// in the last component is not followed with AT_SYMLINK_NOFOLLOW.
+pub fn fstatat(dirfd: c_int, path: *const c_char, buf: *mut Stat, flags: c_int) -> c_int {
     if path.is_null() || buf.is_null() {
         return -libc::EINVAL;
     }
+    if flags & !libc::AT_SYMLINK_NOFOLLOW != 0 {
+        return -libc::EINVAL;
+    }
 
     let path_str = match unsafe { CStr::from_ptr(path).to_str() } {
         Ok(s) => s,
         Err(_) => return -libc::EINVAL,
     };
 
-    let dir_entry = match path::lookup_path(path_str) {
-        Some(entry) => entry,
-        None => return -libc::EINVAL,
+    let base = match at_dir(dirfd, path_str) {
+        Ok(base) => base,
+        Err(e) => return e,
+    };
+    let follow = flags & libc::AT_SYMLINK_NOFOLLOW == 0;
+    let dir_entry = match path::resolve_path_at(&base, path_str, follow) {
+        Ok(entry) => entry,
+        Err(code::ELOOP) => return -libc::ELOOP,
+        Err(_) => return -libc::EINVAL,
     };
     let file_attr = dir_entry.inode().file_attr();
 
@@ -761,6 +1124,88 @@ pub fn getcwd(buf: *mut c_char, len: usize) -> c_int {
     cwd_str_len as c_int
 }
 
+/// Wait for events on a set of file descriptors
+pub fn poll(fds: *mut libc::pollfd, nfds: libc::nfds_t, timeout: c_int) -> c_int {
+    if fds.is_null() && nfds != 0 {
+        return -libc::EFAULT;
+    }
+
+    let fds = if nfds == 0 {
+        &mut []
+    } else {
+        unsafe { slice::from_raw_parts_mut(fds, nfds as usize) }
+    };
+    match poll::poll(fds, timeout) {
+        Ok(n) => n as c_int,
+        Err(e) => e.to_errno(),
+    }
+}
+
+/// Create an epoll instance, `size` is only checked to be positive
+pub fn epoll_create(size: c_int) -> c_int {
+    if size <= 0 {
+        return -libc::EINVAL;
+    }
+    epoll_create1(0)
+}
+
+/// Create an epoll instance, `O_CLOEXEC` may be set
+pub fn epoll_create1(flags: c_int) -> c_int {
+    if flags & !libc::O_CLOEXEC != 0 {
+        return -libc::EINVAL;
+    }
+    let epoll = Arc::new(EpollFile::new(OpenFlags::from(flags)));
+    get_fd_manager().lock().alloc_fd(epoll)
+}
+
+/// Add, modify or remove the interest of the epoll instance `epfd` in `fd`
+pub fn epoll_ctl(epfd: c_int, op: c_int, fd: c_int, event: *mut EpollEvent) -> c_int {
+    let (epoll_file, file_ops) = {
+        let fd_manager = get_fd_manager().lock();
+        match (fd_manager.get_file_ops(epfd), fd_manager.get_file_ops(fd)) {
+            (Some(epoll_file), Some(file_ops)) => (epoll_file, file_ops),
+            _ => return -libc::EBADF,
+        }
+    };
+    let Some(epoll_file) = epoll_file.downcast_ref::<EpollFile>() else {
+        return -libc::EINVAL;
+    };
+    if epfd == fd {
+        return -libc::EINVAL;
+    }
+    // The event is ignored by EPOLL_CTL_DEL
+    let event = if event.is_null() {
+        if op != epoll::EPOLL_CTL_DEL {
+            return -libc::EFAULT;
+        }
+        EpollEvent::default()
+    } else {
+        unsafe { *event }
+    };
+    match epoll_file.ctl(op, fd, file_ops, &event) {
+        Ok(()) => 0,
+        Err(e) => e.to_errno(),
+    }
+}
+
+/// Wait for events on the epoll instance `epfd`
+pub fn epoll_wait(epfd: c_int, events: *mut EpollEvent, maxevents: c_int, timeout: c_int) -> c_int {
+    if maxevents <= 0 {
+        return -libc::EINVAL;
+    }
+    if events.is_null() {
+        return -libc::EFAULT;
+    }
+    let Some(epoll_file) = get_fd_manager().lock().get_file_ops(epfd) else {
+        return -libc::EBADF;
+    };
+    let Some(epoll_file) = epoll_file.downcast_ref::<EpollFile>() else {
+        return -libc::EINVAL;
+    };
+    let events = unsafe { slice::from_raw_parts_mut(events, maxevents as usize) };
+    epoll_file.wait(events, timeout) as c_int
+}
+
 /// Convert open flags to readable string for debugging
 fn flags_to_string(flags: c_int) -> String {
     let mut result = String::new();
@@ -808,8 +1253,13 @@ fn flags_to_string(flags: c_int) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::vfs::dirent::{Dirent, DirentType};
+    use crate::{
+        devices::{Device, DeviceClass, DeviceId},
+        vfs::dirent::{Dirent, DirentType},
+    };
     use blueos_test_macro::test;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    use embedded_io::ErrorKind;
     use libc;
 
     // Mock data for testing
@@ -990,6 +1440,17 @@ mod tests {
         assert_eq!(result, code::EBADF.to_errno());
     }
 
+    #[test]
+    fn test_dup_invalid() {
+        assert_eq!(dup(-1), code::EBADF.to_errno());
+        assert_eq!(dup2(-1, -1), code::EBADF.to_errno());
+        assert_eq!(dup2(0, -1), code::EBADF.to_errno());
+        assert_eq!(dup2(0, 1 << 20), code::EBADF.to_errno());
+        assert_eq!(dup2(0, 0), 0);
+        assert_eq!(dup3(0, 0, 0), code::EINVAL.to_errno());
+        assert_eq!(dup3(0, 5, libc::O_APPEND), code::EINVAL.to_errno());
+    }
+
     #[test]
     fn test_mount_invalid_params() {
         // Test with invalid target path
@@ -1291,4 +1752,54 @@ mod tests {
         let result = rmdir(TEST_DIR);
         assert_eq!(result, code::EOK.to_errno());
     }
+
+    struct SyncCounter {
+        syncs: AtomicUsize,
+    }
+
+    impl Device for SyncCounter {
+        fn name(&self) -> String {
+            String::from("syncdev")
+        }
+        fn class(&self) -> DeviceClass {
+            DeviceClass::Block
+        }
+        fn id(&self) -> DeviceId {
+            DeviceId::new(240, 0)
+        }
+        fn read(&self, _pos: u64, buf: &mut [u8], _is_blocking: bool) -> Result<usize, ErrorKind> {
+            Ok(buf.len())
+        }
+        fn write(&self, _pos: u64, buf: &[u8], _is_blocking: bool) -> Result<usize, ErrorKind> {
+            Ok(buf.len())
+        }
+        fn sync(&self) -> Result<(), ErrorKind> {
+            self.syncs.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_fsync_block_device() {
+        let dev = Arc::new(SyncCounter {
+            syncs: AtomicUsize::new(0),
+        });
+        path::lookup_path("/dev")
+            .unwrap()
+            .create_device("syncdev", InodeMode::from_bits_truncate(0o666), dev.clone())
+            .unwrap();
+
+        let fd = open(c"/dev/syncdev".as_ptr(), libc::O_RDWR, 0);
+        assert!(fd >= 0);
+        let data = [0xa5u8; 512];
+        assert_eq!(write(fd, data.as_ptr(), data.len()), data.len() as isize);
+        assert_eq!(fsync(fd), 0);
+        assert_eq!(dev.syncs.load(Ordering::Relaxed), 1);
+        assert_eq!(fdatasync(fd), 0);
+        assert_eq!(dev.syncs.load(Ordering::Relaxed), 2);
+        assert_eq!(close(fd), 0);
+        assert_eq!(fsync(fd), code::EBADF.to_errno());
+
+        assert_eq!(unlink(c"/dev/syncdev".as_ptr()), 0);
+    }
 }
@@ -22,6 +22,7 @@ use crate::{
         fs::{FileSystem, FileSystemInfo},
         inode::{InodeAttr, InodeNo, InodeOps},
         inode_mode::{InodeFileType, InodeMode},
+        poll::{PollEvents, PollTable},
         utils::NAME_MAX,
     },
 };
@@ -32,10 +33,12 @@ use alloc::{
     vec::Vec,
 };
 use core::{
+    ptr,
     sync::atomic::{AtomicBool, AtomicUsize, Ordering},
     time::Duration,
 };
 use delegate::delegate;
+use embedded_io::ErrorKind;
 use log::{debug, trace, warn};
 use spin::RwLock;
 
@@ -48,8 +51,7 @@ enum TmpFileData {
     Directory(TmpDir),
     File(Vec<u8>),
     Device(Arc<dyn Device>),
-    // TODO: support symlink
-    // SymLink(String),
+    SymLink(String),
     Socket(),
 }
 
@@ -226,6 +228,26 @@ impl TmpInode {
         })
     }
 
+    fn new_symlink(
+        fs: &Weak<TmpFileSystem>,
+        inode_no: InodeNo,
+        uid: u32,
+        gid: u32,
+        target: &str,
+    ) -> Arc<Self> {
+        let mode = InodeMode::from_bits_truncate(0o777);
+        let mut attr = InodeAttr::new(inode_no, InodeFileType::SymLink, mode, uid, gid, 0);
+        attr.size = target.len();
+        Arc::new_cyclic(|weak_inode| Self {
+            inner: RwLock::new(InnerNode {
+                attr,
+                data: TmpFileData::SymLink(String::from(target)),
+            }),
+            this: weak_inode.clone(),
+            fs: fs.clone(),
+        })
+    }
+
     fn new_socket(
         fs: &Weak<TmpFileSystem>,
         inode_no: InodeNo,
@@ -242,6 +264,42 @@ impl TmpInode {
             fs: fs.clone(),
         })
     }
+
+    fn find_child(&self, name: &str) -> Result<Arc<TmpInode>, Error> {
+        let inner = self.inner.read();
+        let dir = inner.as_dir().ok_or(code::ENOTDIR)?;
+        dir.find(name).ok_or(code::ENOENT)
+    }
+
+    // Walk up the parents, the root is its own parent.
+    fn is_same_or_descendant_of(&self, ancestor: &Arc<TmpInode>) -> bool {
+        let mut current = self.this.upgrade();
+        while let Some(inode) = current {
+            if Arc::ptr_eq(&inode, ancestor) {
+                return true;
+            }
+            let parent = inode
+                .inner
+                .read()
+                .as_dir()
+                .and_then(|dir| dir.parent.upgrade());
+            current = parent.filter(|parent| !Arc::ptr_eq(parent, &inode));
+        }
+        false
+    }
+}
+
+// Check if `inode` may replace `existing` in a rename.
+fn check_rename_target(inode: &InnerNode, existing: &InnerNode) -> Result<(), Error> {
+    match (
+        inode.attr.type_() == InodeFileType::Directory,
+        existing.as_dir(),
+    ) {
+        (true, None) => Err(code::ENOTDIR),
+        (false, Some(_)) => Err(code::EISDIR),
+        (true, Some(dir)) if !dir.children.is_empty() => Err(code::ENOTEMPTY),
+        _ => Ok(()),
+    }
 }
 
 #[derive(Debug)]
@@ -272,6 +330,14 @@ impl InnerNode {
         }
     }
 
+    // Files of an unregistered device stay open, but can't be used.
+    fn as_online_device(&self) -> Result<Option<&Arc<dyn Device>>, Error> {
+        match self.as_device() {
+            Some(device) if !device.is_online() => Err(code::ENODEV),
+            device => Ok(device),
+        }
+    }
+
     fn as_file(&self) -> Option<&Vec<u8>> {
         match &self.data {
             TmpFileData::File(file) => Some(file),
@@ -286,6 +352,26 @@ impl InnerNode {
         }
     }
 
+    fn write_file_at(&mut self, offset: usize, buf: &[u8]) -> Result<usize, Error> {
+        let write_end = offset + buf.len();
+        let file_size = self.attr.size;
+        let Some(data) = self.as_file_mut() else {
+            warn!("write_at: inode is not a file");
+            return Err(code::EISDIR);
+        };
+
+        let need_resize = write_end > file_size;
+        if need_resize {
+            data.resize(write_end, 0);
+        }
+        data[offset..write_end].copy_from_slice(buf);
+        if need_resize {
+            self.attr.size = write_end;
+        }
+
+        Ok(buf.len())
+    }
+
     fn inc_nlinks(&mut self) {
         self.attr.nlinks += 1;
     }
@@ -365,8 +451,6 @@ impl InodeOps for TmpInode {
             return Err(code::EEXIST);
         }
 
-        device.open()?;
-
         let ino = self.fs.upgrade().unwrap().alloc_inode_no();
         let inode = TmpInode::new_device(&self.fs, ino, mode, 0, 0, device);
         dir.insert(name, &inode);
@@ -375,6 +459,36 @@ impl InodeOps for TmpInode {
         Ok(inode)
     }
 
+    fn symlink(&self, name: &str, target: &str) -> Result<Arc<dyn InodeOps>, Error> {
+        assert!(self.type_() == InodeFileType::Directory);
+        if name.len() > NAME_MAX {
+            return Err(code::ENAMETOOLONG);
+        }
+        if name == "." || name == ".." {
+            return Err(code::EEXIST);
+        }
+
+        let mut inner = self.inner.write();
+        let dir = inner.as_dir_mut().unwrap();
+        if dir.find(name).is_some() {
+            return Err(code::EEXIST);
+        }
+
+        let ino = self.fs.upgrade().unwrap().alloc_inode_no();
+        let inode = TmpInode::new_symlink(&self.fs, ino, 0, 0, target);
+        dir.insert(name, &inode);
+        inner.inc_size();
+
+        Ok(inode)
+    }
+
+    fn readlink(&self) -> Result<String, Error> {
+        match &self.inner.read().data {
+            TmpFileData::SymLink(target) => Ok(target.clone()),
+            _ => Err(code::EINVAL),
+        }
+    }
+
     fn create_socket(&self, mode: InodeMode) -> Result<Arc<dyn InodeOps>, Error> {
         assert!(self.type_() == InodeFileType::Directory);
         let mut inner = self.inner.write();
@@ -385,6 +499,14 @@ impl InodeOps for TmpInode {
         Ok(inode)
     }
 
+    fn open(&self) -> Result<(), Error> {
+        let inner = self.inner.read();
+        if let Some(device) = inner.as_online_device()? {
+            device.open()?;
+        }
+        Ok(())
+    }
+
     fn close(&self) -> Result<(), Error> {
         let inner = self.inner.read();
         if let Some(device) = inner.as_device() {
@@ -393,9 +515,35 @@ impl InodeOps for TmpInode {
         Ok(())
     }
 
+    fn ioctl(&self, cmd: u32, arg: usize) -> Result<i32, Error> {
+        let inner = self.inner.read();
+        match inner.as_online_device()? {
+            Some(device) => match device.ioctl(cmd, arg) {
+                Ok(_) => Ok(0),
+                // The device doesn't know the request.
+                Err(ErrorKind::Unsupported) => Err(code::ENOTTY),
+                Err(e) => Err(Error::from(e)),
+            },
+            None => Err(code::ENOTTY),
+        }
+    }
+
+    // Data of regular files only lives in memory. Device nodes are
+    // synced through their device, unless it has nothing to sync.
+    fn fsync(&self) -> Result<(), Error> {
+        let inner = self.inner.read();
+        if let Some(device) = inner.as_online_device()? {
+            return match device.sync() {
+                Err(ErrorKind::Unsupported) => Ok(()),
+                r => r.map_err(Error::from),
+            };
+        }
+        Ok(())
+    }
+
     fn read_at(&self, offset: usize, buf: &mut [u8], nonblock: bool) -> Result<usize, Error> {
         let inner = self.inner.read();
-        if let Some(device) = inner.as_device() {
+        if let Some(device) = inner.as_online_device()? {
             return device
                 .read(offset as u64, buf, nonblock)
                 .map_err(Error::from);
@@ -415,34 +563,39 @@ impl InodeOps for TmpInode {
         Ok(read_size)
     }
 
+    fn poll(&self, events: PollEvents, table: &mut PollTable) -> PollEvents {
+        let inner = self.inner.read();
+        match inner.as_online_device() {
+            Ok(Some(device)) => device.poll(events, table),
+            Ok(None) => events & (PollEvents::POLLIN | PollEvents::POLLOUT),
+            Err(_) => PollEvents::POLLERR,
+        }
+    }
+
     fn write_at(&self, offset: usize, buf: &[u8], nonblock: bool) -> Result<usize, Error> {
         let mut inner = self.inner.write();
-        if let Some(device) = inner.as_device() {
+        if let Some(device) = inner.as_online_device()? {
             return device
                 .write(offset as u64, buf, nonblock)
                 .map_err(Error::from);
         }
 
-        let write_end = offset + buf.len();
-        let need_resize;
-        {
-            let file_size = inner.attr.size;
-            let Some(data) = inner.as_file_mut() else {
-                warn!("write_at: inode is not a file");
-                return Err(code::EISDIR);
-            };
+        inner.write_file_at(offset, buf)
+    }
 
-            need_resize = write_end > file_size;
-            if need_resize {
-                data.resize(write_end, 0);
-            }
-            data[offset..write_end].copy_from_slice(buf);
-        }
-        if need_resize {
-            inner.attr.size = write_end;
+    fn append(&self, buf: &[u8], nonblock: bool) -> Result<(usize, usize), Error> {
+        // Hold the lock across reading the size and writing, so that
+        // concurrent appenders never write at the same offset.
+        let mut inner = self.inner.write();
+        let offset = inner.attr.size;
+        if let Some(device) = inner.as_online_device()? {
+            let written = device
+                .write(offset as u64, buf, nonblock)
+                .map_err(Error::from)?;
+            return Ok((offset, written));
         }
-
-        Ok(buf.len())
+        let written = inner.write_file_at(offset, buf)?;
+        Ok((offset, written))
     }
 
     fn link(&self, old: &Arc<dyn InodeOps>, name: &str) -> Result<(), Error> {
@@ -542,6 +695,114 @@ impl InodeOps for TmpInode {
         Ok(())
     }
 
+    fn rename(
+        &self,
+        old_name: &str,
+        target: &Arc<dyn InodeOps>,
+        new_name: &str,
+    ) -> Result<(), Error> {
+        if old_name == "." || old_name == ".." || new_name == "." || new_name == ".." {
+            return Err(code::EINVAL);
+        }
+        if new_name.len() > NAME_MAX {
+            return Err(code::ENAMETOOLONG);
+        }
+        let Some(new_dir) = target
+            .downcast_ref::<TmpInode>()
+            .filter(|new_dir| Weak::ptr_eq(&self.fs, &new_dir.fs))
+        else {
+            debug!("rename: cannot rename across filesystems");
+            return Err(code::EXDEV);
+        };
+        let same_dir = ptr::eq(self, new_dir);
+        if same_dir && old_name == new_name {
+            return self.find_child(old_name).map(|_| ());
+        }
+
+        let inode = self.find_child(old_name)?;
+        let is_dir = inode.type_() == InodeFileType::Directory;
+        if is_dir && new_dir.is_same_or_descendant_of(&inode) {
+            debug!("rename: cannot move a directory into itself");
+            return Err(code::EINVAL);
+        }
+
+        // Lock both parents in address order, so two renames in opposite
+        // directions don't deadlock.
+        let (mut src, mut dst) = if same_dir {
+            (self.inner.write(), None)
+        } else if ptr::addr_of!(*self) < ptr::addr_of!(*new_dir) {
+            let src = self.inner.write();
+            (src, Some(new_dir.inner.write()))
+        } else {
+            let dst = new_dir.inner.write();
+            (self.inner.write(), Some(dst))
+        };
+
+        let Some(src_dir) = src.as_dir() else {
+            return Err(code::ENOTDIR);
+        };
+        // The entry may have been changed before the locks are taken
+        if !src_dir
+            .find(old_name)
+            .is_some_and(|current| Arc::ptr_eq(&current, &inode))
+        {
+            return Err(code::ENOENT);
+        }
+        let dst_dir = match dst.as_ref() {
+            Some(dst) => dst.as_dir().ok_or(code::ENOTDIR)?,
+            None => src_dir,
+        };
+        let existing = dst_dir.find(new_name);
+
+        // Replace the target atomically, all checks are done before any change
+        let mut existing_inner = None;
+        if let Some(existing) = existing.as_ref() {
+            if Arc::ptr_eq(existing, &inode) {
+                // Both names refer to the same file
+                return Ok(());
+            }
+            if ptr::eq(Arc::as_ptr(existing), self) || ptr::eq(Arc::as_ptr(existing), new_dir) {
+                // An ancestor of the source, it can't be empty
+                return Err(code::ENOTEMPTY);
+            }
+            let guard = existing.inner.write();
+            check_rename_target(&inode.inner.read(), &guard)?;
+            existing_inner = Some(guard);
+        }
+
+        {
+            let dst_inner = match dst.as_mut() {
+                Some(dst) => &mut **dst,
+                None => &mut *src,
+            };
+            if let Some(mut existing_inner) = existing_inner {
+                if existing_inner.as_dir().is_some() {
+                    dst_inner.dec_nlinks();
+                    existing_inner.dec_nlinks();
+                }
+                existing_inner.dec_nlinks();
+                dst_inner.dec_size();
+            }
+            let dir = dst_inner.as_dir_mut().unwrap();
+            dir.remove(new_name);
+            dir.insert(new_name, &inode);
+            dst_inner.inc_size();
+            if is_dir && !same_dir {
+                dst_inner.inc_nlinks();
+            }
+        }
+
+        src.as_dir_mut().unwrap().remove(old_name);
+        src.dec_size();
+        if is_dir && !same_dir {
+            src.dec_nlinks();
+            if let Some(dir) = inode.inner.write().as_dir_mut() {
+                dir.parent = new_dir.this.clone();
+            }
+        }
+        Ok(())
+    }
+
     fn getdents_at(&self, offset: usize, reader: &mut DirBufferReader) -> Result<usize, Error> {
         let inner = self.inner.read();
         let Some(dir) = inner.as_dir() else {
@@ -25,3 +25,6 @@ pub enum SeekFrom {
 
 /// Maximum bytes in a file name
 pub const NAME_MAX: usize = 255;
+
+/// Maximum number of symbolic links followed while resolving a path
+pub const SYMLOOP_MAX: usize = 40;
@@ -28,6 +28,7 @@ mod test_futex;
 /// Unstable rust custom test framework test file hierarchy.
 /// Since there is no cargo framework, we manually set it up.
 mod test_semaphore;
+mod test_signal;
 mod test_vfs;
 
 /// Unstable rust custom test framework test runner
@@ -99,6 +99,7 @@ pub fn start_test_thread_with_cleanup(
     );
 
     let t = ThreadBuilder::new(Entry::Closure(worker))
+        .set_name(thread_name)
         .set_stack(Stack::Raw {
             base: thread_stack_base,
             size: stack_size,
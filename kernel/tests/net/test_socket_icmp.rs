@@ -19,6 +19,7 @@ use blueos::{
     scheduler,
     sync::atomic_wait as futex,
     thread::Builder as ThreadBuilder,
+    vfs,
 };
 use blueos_test_macro::test;
 use core::{
@@ -33,6 +34,8 @@ use semihosting::println;
 use crate::net::{net_utils, net_utils::NetTestArgs};
 
 static ICMP_THREAD_FINISH: AtomicUsize = AtomicUsize::new(0);
+static ICMP_PING_THREAD_FINISH: AtomicUsize = AtomicUsize::new(0);
+
 fn icmp_thread(args: Arc<NetTestArgs>) {
     println!("Thread enter:[icmp_thread]");
 
@@ -181,8 +184,8 @@ fn icmp_thread(args: Arc<NetTestArgs>) {
         false
     });
 
-    let shutdown_result = net::syscalls::shutdown(sock_fd, 0);
-    println!("Socket[{}] shutdown result {}", sock_fd, shutdown_result);
+    let close_result = vfs::syscalls::close(sock_fd);
+    println!("Socket[{}] close result {}", sock_fd, close_result);
 
     assert!(recv_bytes > 0, "Test icmp socket fail.");
     println!("Thread exit:[icmp_thread]");
@@ -279,3 +282,106 @@ fn test_icmp_ipv6_non_blocking() {
 
     let _ = futex::atomic_wait(&ICMP_THREAD_FINISH, 0, None);
 }
+
+fn icmpv4_echo_request(ident: u16, seq_no: u16, payload: &[u8]) -> vec::Vec<u8> {
+    let mut packet = vec![8u8, 0, 0, 0];
+    packet.extend_from_slice(&ident.to_be_bytes());
+    packet.extend_from_slice(&seq_no.to_be_bytes());
+    packet.extend_from_slice(payload);
+    let mut sum: u32 = packet
+        .chunks(2)
+        .map(|c| u32::from(u16::from_be_bytes([c[0], *c.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    packet[2..4].copy_from_slice(&(!(sum as u16)).to_be_bytes());
+    packet
+}
+
+fn ping_socket(ident: u16) -> i32 {
+    let sock_fd = net::syscalls::socket(AF_INET, libc::SOCK_RAW, libc::IPPROTO_ICMP);
+    assert!(sock_fd >= 0, "Fail to create icmp socket.");
+    let packet = icmpv4_echo_request(ident, 1, b"ping");
+    let remote = net_utils::create_ipv4_sockaddr("127.0.0.1", 0);
+    let send_bytes = net::syscalls::sendto(
+        sock_fd,
+        packet.as_ptr() as *const c_void,
+        packet.len(),
+        0,
+        &remote as *const _ as *const libc::sockaddr,
+        mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+    );
+    assert_eq!(send_bytes, packet.len() as isize);
+    sock_fd
+}
+
+// Returns false if nothing arrives in `timeout` milliseconds
+fn wait_echo_reply(sock_fd: i32, ident: u16, timeout: i32) -> bool {
+    let mut fds = [libc::pollfd {
+        fd: sock_fd,
+        events: libc::POLLIN,
+        revents: 0,
+    }];
+    loop {
+        if vfs::syscalls::poll(fds.as_mut_ptr(), 1, timeout) == 0 {
+            return false;
+        }
+        let mut buffer = [0u8; 64];
+        let mut addr: libc::sockaddr_in = unsafe { mem::zeroed() };
+        let mut addr_len = mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+        let recv_bytes = net::syscalls::recvfrom(
+            sock_fd,
+            buffer.as_mut_ptr() as *mut c_void,
+            buffer.len(),
+            0,
+            &mut addr as *mut _ as *mut libc::sockaddr,
+            &mut addr_len,
+        );
+        assert_eq!(recv_bytes, 12);
+        assert_eq!(u16::from_be_bytes([buffer[4], buffer[5]]), ident);
+        assert_eq!(
+            addr.sin_addr.s_addr,
+            net_utils::create_ipv4_sockaddr("127.0.0.1", 0)
+                .sin_addr
+                .s_addr
+        );
+        // The echo request to a local address is delivered as well
+        if buffer[0] == 0 {
+            assert_eq!(u16::from_be_bytes([buffer[6], buffer[7]]), 1);
+            assert_eq!(&buffer[8..12], b"ping");
+            return true;
+        }
+    }
+}
+
+fn icmp_ping_thread() {
+    println!("Thread enter:[icmp_ping_thread]");
+    let sock_a = ping_socket(0x5151);
+    assert!(wait_echo_reply(sock_a, 0x5151, 1000));
+
+    // The reply for another identifier is not delivered to sock_a
+    let sock_b = ping_socket(0x5252);
+    assert!(wait_echo_reply(sock_b, 0x5252, 1000));
+    assert!(!wait_echo_reply(sock_a, 0x5151, 100));
+
+    assert!(vfs::syscalls::close(sock_a) == 0);
+    assert!(vfs::syscalls::close(sock_b) == 0);
+    println!("Thread exit:[icmp_ping_thread]");
+}
+
+#[test]
+fn test_icmp_ping_sendto() {
+    ICMP_PING_THREAD_FINISH.store(0, Ordering::Release);
+
+    net_utils::start_test_thread_with_cleanup(
+        "icmp_ping_thread",
+        Box::new(icmp_ping_thread),
+        Some(Box::new(|| {
+            ICMP_PING_THREAD_FINISH.store(1, Ordering::Release);
+            let _ = futex::atomic_wake(&ICMP_PING_THREAD_FINISH, 1);
+        })),
+    );
+
+    let _ = futex::atomic_wait(&ICMP_PING_THREAD_FINISH, 0, None);
+}
@@ -19,6 +19,7 @@ use blueos::{
     scheduler,
     sync::atomic_wait as futex,
     thread::Builder as ThreadBuilder,
+    time, vfs,
 };
 use blueos_test_macro::test;
 use core::{
@@ -35,6 +36,7 @@ use crate::net::{net_utils, net_utils::NetTestArgs};
 
 static TCP_SERVER_THREAD_FINISH: AtomicUsize = AtomicUsize::new(0);
 static TCP_CLIENT_THREAD_FINISH: AtomicUsize = AtomicUsize::new(0);
+static TCP_RCVTIMEO_THREAD_FINISH: AtomicUsize = AtomicUsize::new(0);
 
 fn tcp_server_thread(args: Arc<NetTestArgs>) {
     println!("Thread enter:[tcp_server_thread]");
@@ -44,6 +46,17 @@ fn tcp_server_thread(args: Arc<NetTestArgs>) {
         net::syscalls::socket(args.domain.into(), libc::SOCK_STREAM | args.type_flag(), 0);
     assert!(sock_fd >= 0, "Fail to create tcp server socket.");
 
+    // Each test rebinds the listen port while the last one is still in TIME_WAIT
+    let reuse: libc::c_int = 1;
+    let setsockopt_result = net::syscalls::setsockopt(
+        sock_fd,
+        libc::SOL_SOCKET,
+        libc::SO_REUSEADDR,
+        &reuse as *const _ as *const c_void,
+        mem::size_of::<libc::c_int>() as libc::socklen_t,
+    );
+    assert!(setsockopt_result == 0, "Failed to set SO_REUSEADDR.");
+
     // Bind socket
     let listen_ip = "127.0.0.1"; // Replace with actual IP address
     let listen_port = 1234;
@@ -136,12 +149,9 @@ fn tcp_server_thread(args: Arc<NetTestArgs>) {
         "Failed to receive data or EOF."
     );
 
-    let shutdown_result = net::syscalls::shutdown(sock_fd, 0);
-    println!("Socket[{}] shutdown result {}", sock_fd, shutdown_result);
-    assert!(
-        shutdown_result == 0,
-        "Failed to shutdown tcp server socket."
-    );
+    let close_result = vfs::syscalls::close(sock_fd);
+    println!("Socket[{}] close result {}", sock_fd, close_result);
+    assert!(close_result == 0, "Failed to close tcp server socket.");
 
     TCP_SERVER_THREAD_FINISH.store(1, Ordering::Release);
     let _ = futex::atomic_wake(&TCP_SERVER_THREAD_FINISH, 1);
@@ -183,7 +193,11 @@ fn tcp_client_thread(args: Arc<NetTestArgs>) {
         }
     };
     println!("Socket[{}] connect result {}", sock_fd, connect_result);
-    assert!(connect_result == 0, "Failed to connect through tcp socket.");
+    // Completed in the background if the socket is non-blocking
+    assert!(
+        connect_result == 0 || (args.is_nonblocking && connect_result == -libc::EINPROGRESS),
+        "Failed to connect through tcp socket."
+    );
 
     let message = "Hello From Posix TCP client";
     let bytes = message.as_bytes();
@@ -211,12 +225,9 @@ fn tcp_client_thread(args: Arc<NetTestArgs>) {
         false
     });
 
-    // Call shutdown to send EOF (i.e., close the write side of the socket)
-    let shutdown_result = net::syscalls::shutdown(sock_fd, 0);
-    assert!(
-        shutdown_result == 0,
-        "Failed to shutdown tcp client socket."
-    );
+    // Close to send EOF
+    let close_result = vfs::syscalls::close(sock_fd);
+    assert!(close_result == 0, "Failed to close tcp client socket.");
 
     let _ = futex::atomic_wait(&TCP_SERVER_THREAD_FINISH, 0, None);
 
@@ -304,3 +315,764 @@ fn test_tcp_ipv6_non_blocking() {
 
     let _ = futex::atomic_wait(&TCP_CLIENT_THREAD_FINISH, 0, None);
 }
+
+fn tcp_rcvtimeo_thread() {
+    println!("Thread enter:[tcp_rcvtimeo_thread]");
+
+    let listen_ip = "127.0.0.1";
+    let listen_port = 1240;
+    let addr_ipv4 = net_utils::create_ipv4_sockaddr(listen_ip, listen_port);
+
+    // Server never sends anything
+    let server_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM, 0);
+    assert!(server_fd >= 0, "Fail to create tcp server socket.");
+    let bind_result = net::syscalls::bind(
+        server_fd,
+        &addr_ipv4 as *const _ as *const libc::sockaddr,
+        mem::size_of::<libc::sockaddr>() as libc::socklen_t,
+    );
+    assert!(bind_result == 0, "Failed to bind on tcp server socket.");
+    assert!(net::syscalls::listen(server_fd, 0) == 0);
+
+    let client_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM, 0);
+    assert!(client_fd >= 0, "Fail to create tcp client socket.");
+    let connect_result = net::syscalls::connect(
+        client_fd,
+        &addr_ipv4 as *const _ as *const libc::sockaddr,
+        mem::size_of::<libc::sockaddr>() as libc::socklen_t,
+    );
+    assert!(connect_result == 0, "Failed to connect through tcp socket.");
+
+    let timeout = libc::timeval {
+        tv_sec: 0,
+        tv_usec: 100_000,
+    };
+    let setsockopt_result = net::syscalls::setsockopt(
+        client_fd,
+        libc::SOL_SOCKET,
+        libc::SO_RCVTIMEO,
+        &timeout as *const _ as *const c_void,
+        mem::size_of::<libc::timeval>() as libc::socklen_t,
+    );
+    assert!(setsockopt_result == 0, "Failed to set SO_RCVTIMEO.");
+
+    let mut buffer = vec![0u8; 64];
+    let bytes_received = net::syscalls::recv(
+        client_fd,
+        buffer.as_mut_ptr() as *mut c_void,
+        buffer.len(),
+        0,
+    );
+    println!("Socket[{}] recv result {}", client_fd, bytes_received);
+    assert_eq!(bytes_received, -libc::EAGAIN as isize);
+
+    assert!(vfs::syscalls::close(client_fd) == 0);
+    assert!(vfs::syscalls::close(server_fd) == 0);
+    println!("Thread exit:[tcp_rcvtimeo_thread]");
+}
+
+#[test]
+fn test_tcp_recv_timeout() {
+    TCP_RCVTIMEO_THREAD_FINISH.store(0, Ordering::Release);
+
+    net_utils::start_test_thread_with_cleanup(
+        "tcp_rcvtimeo_thread",
+        Box::new(tcp_rcvtimeo_thread),
+        Some(Box::new(|| {
+            TCP_RCVTIMEO_THREAD_FINISH.store(1, Ordering::Release);
+            let _ = futex::atomic_wake(&TCP_RCVTIMEO_THREAD_FINISH, 1);
+        })),
+    );
+
+    let _ = futex::atomic_wait(&TCP_RCVTIMEO_THREAD_FINISH, 0, None);
+}
+
+#[test]
+fn test_tcp_reuseaddr() {
+    let addr_ipv4 = net_utils::create_ipv4_sockaddr("127.0.0.1", 1241);
+    let bind = |sock_fd| {
+        net::syscalls::bind(
+            sock_fd,
+            &addr_ipv4 as *const _ as *const libc::sockaddr,
+            mem::size_of::<libc::sockaddr>() as libc::socklen_t,
+        )
+    };
+
+    let first_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM, 0);
+    assert!(first_fd >= 0, "Fail to create tcp socket.");
+    assert!(bind(first_fd) == 0, "Failed to bind on tcp socket.");
+    assert!(net::syscalls::listen(first_fd, 0) == 0);
+    assert!(vfs::syscalls::close(first_fd) == 0);
+
+    // The port is in TIME_WAIT
+    let second_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM, 0);
+    assert!(second_fd >= 0, "Fail to create tcp socket.");
+    assert_eq!(bind(second_fd), -libc::EADDRINUSE);
+
+    let reuse: libc::c_int = 1;
+    let setsockopt_result = net::syscalls::setsockopt(
+        second_fd,
+        libc::SOL_SOCKET,
+        libc::SO_REUSEADDR,
+        &reuse as *const _ as *const c_void,
+        mem::size_of::<libc::c_int>() as libc::socklen_t,
+    );
+    assert!(setsockopt_result == 0, "Failed to set SO_REUSEADDR.");
+
+    let mut value: libc::c_int = 0;
+    let mut value_len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let getsockopt_result = net::syscalls::getsockopt(
+        second_fd,
+        libc::SOL_SOCKET,
+        libc::SO_REUSEADDR,
+        &mut value as *mut _ as *mut c_void,
+        &mut value_len,
+    );
+    assert!(getsockopt_result == 0, "Failed to get SO_REUSEADDR.");
+    assert_eq!(value, 1);
+
+    assert!(bind(second_fd) == 0, "Failed to rebind with SO_REUSEADDR.");
+    assert!(vfs::syscalls::close(second_fd) == 0);
+}
+
+#[test]
+fn test_tcp_getsockname() {
+    let sock_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM, 0);
+    assert!(sock_fd >= 0, "Fail to create tcp socket.");
+    let addr_ipv4 = net_utils::create_ipv4_sockaddr("127.0.0.1", 1242);
+    let bind_result = net::syscalls::bind(
+        sock_fd,
+        &addr_ipv4 as *const _ as *const libc::sockaddr,
+        mem::size_of::<libc::sockaddr>() as libc::socklen_t,
+    );
+    assert!(bind_result == 0, "Failed to bind on tcp socket.");
+
+    let mut addr: libc::sockaddr_in = unsafe { mem::zeroed() };
+    let mut addr_len = mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+    let result = net::syscalls::getsockname(
+        sock_fd,
+        &mut addr as *mut _ as *mut libc::sockaddr,
+        &mut addr_len,
+    );
+    assert_eq!(result, 0);
+    assert_eq!(u16::from_be(addr.sin_port), 1242);
+    assert_eq!(addr.sin_addr.s_addr, addr_ipv4.sin_addr.s_addr);
+
+    let result = net::syscalls::getpeername(
+        sock_fd,
+        &mut addr as *mut _ as *mut libc::sockaddr,
+        &mut addr_len,
+    );
+    assert_eq!(result, -libc::ENOTCONN);
+
+    assert!(vfs::syscalls::close(sock_fd) == 0);
+}
+
+fn get_tcp_nodelay(sock_fd: i32) -> i32 {
+    let mut value: libc::c_int = -1;
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let result = net::syscalls::getsockopt(
+        sock_fd,
+        libc::IPPROTO_TCP,
+        libc::TCP_NODELAY,
+        &mut value as *mut _ as *mut c_void,
+        &mut len,
+    );
+    assert_eq!(result, 0);
+    value
+}
+
+fn set_tcp_nodelay(sock_fd: i32, value: libc::c_int) -> i32 {
+    net::syscalls::setsockopt(
+        sock_fd,
+        libc::IPPROTO_TCP,
+        libc::TCP_NODELAY,
+        &value as *const _ as *const c_void,
+        mem::size_of::<libc::c_int>() as libc::socklen_t,
+    )
+}
+
+#[test]
+fn test_tcp_nodelay() {
+    let sock_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM, 0);
+    assert!(sock_fd >= 0, "Fail to create tcp socket.");
+    // Nagle is enabled by default
+    assert_eq!(get_tcp_nodelay(sock_fd), 0);
+
+    // Before the socket is bound
+    assert_eq!(set_tcp_nodelay(sock_fd, 1), 0);
+    assert_eq!(get_tcp_nodelay(sock_fd), 1);
+
+    let addr_ipv4 = net_utils::create_ipv4_sockaddr("127.0.0.1", 1243);
+    let bind_result = net::syscalls::bind(
+        sock_fd,
+        &addr_ipv4 as *const _ as *const libc::sockaddr,
+        mem::size_of::<libc::sockaddr>() as libc::socklen_t,
+    );
+    assert!(bind_result == 0, "Failed to bind on tcp socket.");
+    assert_eq!(get_tcp_nodelay(sock_fd), 1);
+    assert_eq!(set_tcp_nodelay(sock_fd, 0), 0);
+    assert_eq!(get_tcp_nodelay(sock_fd), 0);
+    assert!(vfs::syscalls::close(sock_fd) == 0);
+
+    // Not a stream socket
+    let sock_fd = net::syscalls::socket(AF_INET, libc::SOCK_DGRAM, 0);
+    assert!(sock_fd >= 0, "Fail to create udp socket.");
+    assert_eq!(set_tcp_nodelay(sock_fd, 1), -libc::ENOPROTOOPT);
+    assert!(vfs::syscalls::close(sock_fd) == 0);
+}
+
+fn get_int_option(sock_fd: i32, level: libc::c_int, option_name: libc::c_int) -> i32 {
+    let mut value: libc::c_int = -1;
+    let mut len = mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let result = net::syscalls::getsockopt(
+        sock_fd,
+        level,
+        option_name,
+        &mut value as *mut _ as *mut c_void,
+        &mut len,
+    );
+    assert_eq!(result, 0);
+    value
+}
+
+fn set_int_option(
+    sock_fd: i32,
+    level: libc::c_int,
+    option_name: libc::c_int,
+    value: libc::c_int,
+) -> i32 {
+    net::syscalls::setsockopt(
+        sock_fd,
+        level,
+        option_name,
+        &value as *const _ as *const c_void,
+        mem::size_of::<libc::c_int>() as libc::socklen_t,
+    )
+}
+
+#[test]
+fn test_tcp_keepalive() {
+    let sock_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM, 0);
+    assert!(sock_fd >= 0, "Fail to create tcp socket.");
+    assert_eq!(
+        get_int_option(sock_fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE),
+        0
+    );
+    assert_eq!(
+        get_int_option(sock_fd, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE),
+        7200
+    );
+    assert_eq!(
+        get_int_option(sock_fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL),
+        75
+    );
+
+    // Stored until the socket is bound
+    assert_eq!(
+        set_int_option(sock_fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1),
+        0
+    );
+    assert_eq!(
+        set_int_option(sock_fd, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE, 60),
+        0
+    );
+    assert_eq!(
+        set_int_option(sock_fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL, 10),
+        0
+    );
+    assert_eq!(
+        set_int_option(sock_fd, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE, 0),
+        -libc::EINVAL
+    );
+    assert_eq!(
+        get_int_option(sock_fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE),
+        1
+    );
+    assert_eq!(
+        get_int_option(sock_fd, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE),
+        60
+    );
+    assert_eq!(
+        get_int_option(sock_fd, libc::IPPROTO_TCP, libc::TCP_KEEPINTVL),
+        10
+    );
+
+    let addr_ipv4 = net_utils::create_ipv4_sockaddr("127.0.0.1", 1244);
+    let bind_result = net::syscalls::bind(
+        sock_fd,
+        &addr_ipv4 as *const _ as *const libc::sockaddr,
+        mem::size_of::<libc::sockaddr>() as libc::socklen_t,
+    );
+    assert!(bind_result == 0, "Failed to bind on tcp socket.");
+    assert_eq!(
+        set_int_option(sock_fd, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE, 30),
+        0
+    );
+    assert_eq!(
+        get_int_option(sock_fd, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE),
+        30
+    );
+    assert_eq!(
+        set_int_option(sock_fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 0),
+        0
+    );
+    assert_eq!(
+        get_int_option(sock_fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE),
+        0
+    );
+    assert!(vfs::syscalls::close(sock_fd) == 0);
+
+    // Only kept by other sockets
+    let sock_fd = net::syscalls::socket(AF_INET, libc::SOCK_DGRAM, 0);
+    assert!(sock_fd >= 0, "Fail to create udp socket.");
+    assert_eq!(
+        set_int_option(sock_fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE, 1),
+        0
+    );
+    assert_eq!(
+        get_int_option(sock_fd, libc::SOL_SOCKET, libc::SO_KEEPALIVE),
+        1
+    );
+    assert_eq!(
+        set_int_option(sock_fd, libc::IPPROTO_TCP, libc::TCP_KEEPIDLE, 60),
+        -libc::ENOPROTOOPT
+    );
+    assert!(vfs::syscalls::close(sock_fd) == 0);
+}
+
+static TCP_CONNECT_THREAD_FINISH: AtomicUsize = AtomicUsize::new(0);
+
+fn tcp_connect_to(sock_fd: i32, port: u16) -> i32 {
+    let addr_ipv4 = net_utils::create_ipv4_sockaddr("127.0.0.1", port);
+    net::syscalls::connect(
+        sock_fd,
+        &addr_ipv4 as *const _ as *const libc::sockaddr,
+        mem::size_of::<libc::sockaddr>() as libc::socklen_t,
+    )
+}
+
+fn tcp_listen_on(port: u16) -> i32 {
+    let server_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM, 0);
+    assert!(server_fd >= 0, "Fail to create tcp server socket.");
+    let addr_ipv4 = net_utils::create_ipv4_sockaddr("127.0.0.1", port);
+    let bind_result = net::syscalls::bind(
+        server_fd,
+        &addr_ipv4 as *const _ as *const libc::sockaddr,
+        mem::size_of::<libc::sockaddr>() as libc::socklen_t,
+    );
+    assert!(bind_result == 0, "Failed to bind on tcp server socket.");
+    assert!(net::syscalls::listen(server_fd, 0) == 0);
+    server_fd
+}
+
+fn poll_one(sock_fd: i32, events: i16) -> i16 {
+    let mut fds = [libc::pollfd {
+        fd: sock_fd,
+        events,
+        revents: 0,
+    }];
+    assert_eq!(vfs::syscalls::poll(fds.as_mut_ptr(), 1, 1000), 1);
+    fds[0].revents
+}
+
+fn tcp_connect_thread() {
+    println!("Thread enter:[tcp_connect_thread]");
+
+    // Blocking connect returns once connected
+    let server_fd = tcp_listen_on(1245);
+    let client_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM, 0);
+    assert!(client_fd >= 0, "Fail to create tcp client socket.");
+    assert_eq!(tcp_connect_to(client_fd, 1245), 0);
+    assert_eq!(
+        get_int_option(client_fd, libc::SOL_SOCKET, libc::SO_ERROR),
+        0
+    );
+    assert_eq!(tcp_connect_to(client_fd, 1245), -libc::EISCONN);
+    assert!(vfs::syscalls::close(client_fd) == 0);
+    assert!(vfs::syscalls::close(server_fd) == 0);
+
+    // Non-blocking connect completes in the background
+    let server_fd = tcp_listen_on(1246);
+    let client_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM | libc::SO_NONBLOCK, 0);
+    assert!(client_fd >= 0, "Fail to create tcp client socket.");
+    assert_eq!(tcp_connect_to(client_fd, 1246), -libc::EINPROGRESS);
+    assert!(poll_one(client_fd, libc::POLLOUT) & libc::POLLOUT != 0);
+    assert_eq!(
+        get_int_option(client_fd, libc::SOL_SOCKET, libc::SO_ERROR),
+        0
+    );
+    // Reports the completion once, like Linux
+    assert_eq!(tcp_connect_to(client_fd, 1246), 0);
+    assert_eq!(tcp_connect_to(client_fd, 1246), -libc::EISCONN);
+    assert!(vfs::syscalls::close(client_fd) == 0);
+    assert!(vfs::syscalls::close(server_fd) == 0);
+
+    // Nobody is listening
+    let client_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM | libc::SO_NONBLOCK, 0);
+    assert!(client_fd >= 0, "Fail to create tcp client socket.");
+    assert_eq!(tcp_connect_to(client_fd, 1247), -libc::EINPROGRESS);
+    assert!(poll_one(client_fd, libc::POLLOUT) & libc::POLLERR != 0);
+    assert_eq!(
+        get_int_option(client_fd, libc::SOL_SOCKET, libc::SO_ERROR),
+        libc::ECONNREFUSED
+    );
+    // The error is cleared once read
+    assert_eq!(
+        get_int_option(client_fd, libc::SOL_SOCKET, libc::SO_ERROR),
+        0
+    );
+    assert!(vfs::syscalls::close(client_fd) == 0);
+
+    println!("Thread exit:[tcp_connect_thread]");
+}
+
+#[test]
+fn test_tcp_connect() {
+    TCP_CONNECT_THREAD_FINISH.store(0, Ordering::Release);
+
+    net_utils::start_test_thread_with_cleanup(
+        "tcp_connect_thread",
+        Box::new(tcp_connect_thread),
+        Some(Box::new(|| {
+            TCP_CONNECT_THREAD_FINISH.store(1, Ordering::Release);
+            let _ = futex::atomic_wake(&TCP_CONNECT_THREAD_FINISH, 1);
+        })),
+    );
+
+    let _ = futex::atomic_wait(&TCP_CONNECT_THREAD_FINISH, 0, None);
+}
+
+static TCP_POLL_THREAD_FINISH: AtomicUsize = AtomicUsize::new(0);
+
+fn tcp_poll_thread() {
+    println!("Thread enter:[tcp_poll_thread]");
+
+    // The listening socket receives the accepted stream
+    let server_fd = tcp_listen_on(1248);
+    let client_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM, 0);
+    assert!(client_fd >= 0, "Fail to create tcp client socket.");
+    assert_eq!(tcp_connect_to(client_fd, 1248), 0);
+
+    // Nothing to read yet, returns immediately
+    let mut fds = [libc::pollfd {
+        fd: server_fd,
+        events: libc::POLLIN,
+        revents: 0,
+    }];
+    assert_eq!(vfs::syscalls::poll(fds.as_mut_ptr(), 1, 0), 0);
+    assert_eq!(fds[0].revents, 0);
+
+    // Writable right after connect
+    assert!(poll_one(client_fd, libc::POLLOUT) & libc::POLLOUT != 0);
+
+    let message = b"ping";
+    assert_eq!(
+        net::syscalls::send(
+            client_fd,
+            message.as_ptr() as *const c_void,
+            message.len(),
+            0
+        ),
+        message.len() as isize
+    );
+    assert!(poll_one(server_fd, libc::POLLIN) & libc::POLLIN != 0);
+
+    let mut buffer = vec![0u8; 16];
+    let bytes_received = net::syscalls::recv(
+        server_fd,
+        buffer.as_mut_ptr() as *mut c_void,
+        buffer.len(),
+        0,
+    );
+    assert_eq!(bytes_received, message.len() as isize);
+    assert_eq!(&buffer[..message.len()], message);
+
+    assert!(vfs::syscalls::close(client_fd) == 0);
+    assert!(vfs::syscalls::close(server_fd) == 0);
+    println!("Thread exit:[tcp_poll_thread]");
+}
+
+#[test]
+fn test_tcp_poll() {
+    TCP_POLL_THREAD_FINISH.store(0, Ordering::Release);
+
+    net_utils::start_test_thread_with_cleanup(
+        "tcp_poll_thread",
+        Box::new(tcp_poll_thread),
+        Some(Box::new(|| {
+            TCP_POLL_THREAD_FINISH.store(1, Ordering::Release);
+            let _ = futex::atomic_wake(&TCP_POLL_THREAD_FINISH, 1);
+        })),
+    );
+
+    let _ = futex::atomic_wait(&TCP_POLL_THREAD_FINISH, 0, None);
+}
+
+static TCP_LOOPBACK_THREAD_FINISH: AtomicUsize = AtomicUsize::new(0);
+
+fn get_name(
+    sock_fd: i32,
+    f: fn(i32, *mut libc::sockaddr, *mut libc::socklen_t) -> i32,
+) -> libc::sockaddr_in {
+    let mut addr: libc::sockaddr_in = unsafe { mem::zeroed() };
+    let mut addr_len = mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+    assert_eq!(
+        f(
+            sock_fd,
+            &mut addr as *mut _ as *mut libc::sockaddr,
+            &mut addr_len
+        ),
+        0
+    );
+    addr
+}
+
+fn tcp_echo(from_fd: i32, to_fd: i32, message: &[u8]) {
+    assert_eq!(
+        net::syscalls::send(from_fd, message.as_ptr() as *const c_void, message.len(), 0),
+        message.len() as isize
+    );
+    let mut buffer = vec![0u8; 16];
+    let bytes_received =
+        net::syscalls::recv(to_fd, buffer.as_mut_ptr() as *mut c_void, buffer.len(), 0);
+    assert_eq!(bytes_received, message.len() as isize);
+    assert_eq!(&buffer[..message.len()], message);
+}
+
+fn tcp_loopback_thread() {
+    println!("Thread enter:[tcp_loopback_thread]");
+
+    let server_fd = tcp_listen_on(1249);
+    let client_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM, 0);
+    assert!(client_fd >= 0, "Fail to create tcp client socket.");
+    assert_eq!(tcp_connect_to(client_fd, 1249), 0);
+
+    // Both ends live on 127.0.0.1 and see each other
+    let loopback = net_utils::create_ipv4_sockaddr("127.0.0.1", 0)
+        .sin_addr
+        .s_addr;
+    let client_name = get_name(client_fd, net::syscalls::getsockname);
+    let client_peer = get_name(client_fd, net::syscalls::getpeername);
+    let server_peer = get_name(server_fd, net::syscalls::getpeername);
+    assert_eq!(client_name.sin_addr.s_addr, loopback);
+    assert_eq!(client_peer.sin_addr.s_addr, loopback);
+    assert_eq!(u16::from_be(client_peer.sin_port), 1249);
+    assert_eq!(server_peer.sin_addr.s_addr, loopback);
+    assert_eq!(server_peer.sin_port, client_name.sin_port);
+
+    tcp_echo(client_fd, server_fd, b"ping");
+    tcp_echo(server_fd, client_fd, b"pong");
+
+    assert!(vfs::syscalls::close(client_fd) == 0);
+    assert!(vfs::syscalls::close(server_fd) == 0);
+    println!("Thread exit:[tcp_loopback_thread]");
+}
+
+#[test]
+fn test_tcp_loopback() {
+    TCP_LOOPBACK_THREAD_FINISH.store(0, Ordering::Release);
+
+    net_utils::start_test_thread_with_cleanup(
+        "tcp_loopback_thread",
+        Box::new(tcp_loopback_thread),
+        Some(Box::new(|| {
+            TCP_LOOPBACK_THREAD_FINISH.store(1, Ordering::Release);
+            let _ = futex::atomic_wake(&TCP_LOOPBACK_THREAD_FINISH, 1);
+        })),
+    );
+
+    let _ = futex::atomic_wait(&TCP_LOOPBACK_THREAD_FINISH, 0, None);
+}
+
+static TCP_HALF_CLOSE_THREAD_FINISH: AtomicUsize = AtomicUsize::new(0);
+
+fn tcp_half_close_thread() {
+    println!("Thread enter:[tcp_half_close_thread]");
+
+    let idle_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM, 0);
+    assert!(idle_fd >= 0, "Fail to create tcp socket.");
+    assert_eq!(
+        net::syscalls::shutdown(idle_fd, libc::SHUT_WR),
+        -libc::ENOTCONN
+    );
+    assert_eq!(net::syscalls::shutdown(idle_fd, 42), -libc::EINVAL);
+    assert!(vfs::syscalls::close(idle_fd) == 0);
+
+    let server_fd = tcp_listen_on(1260);
+    let client_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM, 0);
+    assert!(client_fd >= 0, "Fail to create tcp client socket.");
+    assert_eq!(tcp_connect_to(client_fd, 1260), 0);
+
+    // Queued data still goes out before the FIN
+    let message = b"last words";
+    assert_eq!(
+        net::syscalls::send(
+            client_fd,
+            message.as_ptr() as *const c_void,
+            message.len(),
+            0
+        ),
+        message.len() as isize
+    );
+    assert_eq!(net::syscalls::shutdown(client_fd, libc::SHUT_WR), 0);
+    assert_eq!(
+        net::syscalls::send(
+            client_fd,
+            message.as_ptr() as *const c_void,
+            message.len(),
+            0
+        ),
+        -libc::EPIPE as isize
+    );
+
+    // The peer drains the data, then sees EOF
+    let mut received = vec::Vec::new();
+    let mut buffer = [0u8; 4];
+    loop {
+        let n = net::syscalls::recv(
+            server_fd,
+            buffer.as_mut_ptr() as *mut c_void,
+            buffer.len(),
+            0,
+        );
+        assert!(n >= 0);
+        if n == 0 {
+            break;
+        }
+        received.extend_from_slice(&buffer[..n as usize]);
+    }
+    assert_eq!(&received[..], message);
+
+    // Reading goes on until the peer closes too
+    tcp_echo(server_fd, client_fd, b"pong");
+
+    // Nothing is read after SHUT_RD
+    assert_eq!(net::syscalls::shutdown(client_fd, libc::SHUT_RD), 0);
+    assert_eq!(
+        net::syscalls::send(
+            server_fd,
+            message.as_ptr() as *const c_void,
+            message.len(),
+            0
+        ),
+        message.len() as isize
+    );
+    assert_eq!(
+        net::syscalls::recv(
+            client_fd,
+            buffer.as_mut_ptr() as *mut c_void,
+            buffer.len(),
+            0
+        ),
+        0
+    );
+
+    assert!(vfs::syscalls::close(client_fd) == 0);
+    assert!(vfs::syscalls::close(server_fd) == 0);
+    println!("Thread exit:[tcp_half_close_thread]");
+}
+
+#[test]
+fn test_tcp_half_close() {
+    TCP_HALF_CLOSE_THREAD_FINISH.store(0, Ordering::Release);
+
+    net_utils::start_test_thread_with_cleanup(
+        "tcp_half_close_thread",
+        Box::new(tcp_half_close_thread),
+        Some(Box::new(|| {
+            TCP_HALF_CLOSE_THREAD_FINISH.store(1, Ordering::Release);
+            let _ = futex::atomic_wake(&TCP_HALF_CLOSE_THREAD_FINISH, 1);
+        })),
+    );
+
+    let _ = futex::atomic_wait(&TCP_HALF_CLOSE_THREAD_FINISH, 0, None);
+}
+
+static TCP_NODELAY_LOOPBACK_THREAD_FINISH: AtomicUsize = AtomicUsize::new(0);
+
+// Ticks taken by rounds of two small writes answered by the peer. With
+// Nagle, the second write waits for the first to be acked, which the peer
+// delays as it has nothing to send yet.
+fn tcp_write_write_read_ticks(port: u16, no_delay: libc::c_int) -> usize {
+    const ROUNDS: usize = 10;
+    let server_fd = tcp_listen_on(port);
+    let client_fd = net::syscalls::socket(AF_INET, libc::SOCK_STREAM, 0);
+    assert!(client_fd >= 0, "Fail to create tcp client socket.");
+    // Set before connecting, applied once the connection is established
+    assert_eq!(set_tcp_nodelay(client_fd, no_delay), 0);
+    assert_eq!(tcp_connect_to(client_fd, port), 0);
+    assert_eq!(get_tcp_nodelay(client_fd), no_delay);
+
+    let mut buffer = [0u8; 2];
+    let start = time::get_sys_ticks();
+    for _ in 0..ROUNDS {
+        for byte in b"ab" {
+            assert_eq!(
+                net::syscalls::send(client_fd, byte as *const u8 as *const c_void, 1, 0),
+                1
+            );
+        }
+        let mut received = 0;
+        while received < buffer.len() {
+            let n = net::syscalls::recv(
+                server_fd,
+                buffer[received..].as_mut_ptr() as *mut c_void,
+                buffer.len() - received,
+                0,
+            );
+            assert!(n > 0);
+            received += n as usize;
+        }
+        assert_eq!(&buffer, b"ab");
+        assert_eq!(
+            net::syscalls::send(server_fd, buffer.as_ptr() as *const c_void, 1, 0),
+            1
+        );
+        assert_eq!(
+            net::syscalls::recv(client_fd, buffer.as_mut_ptr() as *mut c_void, 1, 0),
+            1
+        );
+    }
+    let elapsed = time::get_sys_ticks() - start;
+
+    assert!(vfs::syscalls::close(client_fd) == 0);
+    assert!(vfs::syscalls::close(server_fd) == 0);
+    elapsed
+}
+
+fn tcp_nodelay_loopback_thread() {
+    println!("Thread enter:[tcp_nodelay_loopback_thread]");
+
+    // Small writes back to back don't wait for the previous one to be acked
+    let nagle = tcp_write_write_read_ticks(1261, 0);
+    let no_delay = tcp_write_write_read_ticks(1262, 1);
+    println!(
+        "write-write-read ticks: nagle {}, nodelay {}",
+        nagle, no_delay
+    );
+    assert!(
+        no_delay * 2 < nagle,
+        "Small writes are not sent promptly: {} vs {} ticks",
+        no_delay,
+        nagle
+    );
+
+    println!("Thread exit:[tcp_nodelay_loopback_thread]");
+}
+
+#[test]
+fn test_tcp_nodelay_loopback() {
+    TCP_NODELAY_LOOPBACK_THREAD_FINISH.store(0, Ordering::Release);
+
+    net_utils::start_test_thread_with_cleanup(
+        "tcp_nodelay_loopback_thread",
+        Box::new(tcp_nodelay_loopback_thread),
+        Some(Box::new(|| {
+            TCP_NODELAY_LOOPBACK_THREAD_FINISH.store(1, Ordering::Release);
+            let _ = futex::atomic_wake(&TCP_NODELAY_LOOPBACK_THREAD_FINISH, 1);
+        })),
+    );
+
+    let _ = futex::atomic_wait(&TCP_NODELAY_LOOPBACK_THREAD_FINISH, 0, None);
+}
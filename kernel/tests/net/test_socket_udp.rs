@@ -19,6 +19,10 @@ use blueos::{
     scheduler,
     sync::atomic_wait as futex,
     thread::Builder as ThreadBuilder,
+    vfs::{
+        self,
+        epoll::{EpollEvent, EPOLLIN, EPOLL_CTL_ADD},
+    },
 };
 use blueos_test_macro::test;
 use core::{
@@ -34,6 +38,10 @@ use crate::net::{net_utils, net_utils::NetTestArgs};
 
 static UDP_SERVER_THREAD_FINISH: AtomicUsize = AtomicUsize::new(0);
 static UDP_CLIENT_THREAD_FINISH: AtomicUsize = AtomicUsize::new(0);
+static UDP_POLL_SENDER_FINISH: AtomicUsize = AtomicUsize::new(0);
+static UDP_POLL_THREAD_FINISH: AtomicUsize = AtomicUsize::new(0);
+static UDP_PIPE_POLL_WRITER_FINISH: AtomicUsize = AtomicUsize::new(0);
+static UDP_MULTICAST_THREAD_FINISH: AtomicUsize = AtomicUsize::new(0);
 
 fn udp_server_thread(args: Arc<NetTestArgs>) {
     println!("Thread enter:[udp_server_thread]");
@@ -130,12 +138,9 @@ fn udp_server_thread(args: Arc<NetTestArgs>) {
         false
     });
 
-    let shutdown_result = net::syscalls::shutdown(sock_fd, 0);
-    println!("Socket[{}] shutdown result {}", sock_fd, shutdown_result);
-    assert!(
-        shutdown_result == 0,
-        "Failed to shutdown udp server socket."
-    );
+    let close_result = vfs::syscalls::close(sock_fd);
+    println!("Socket[{}] close result {}", sock_fd, close_result);
+    assert!(close_result == 0, "Failed to close udp server socket.");
 
     UDP_SERVER_THREAD_FINISH.store(1, Ordering::Release);
     let _ = futex::atomic_wake(&UDP_SERVER_THREAD_FINISH, 1);
@@ -235,11 +240,8 @@ fn udp_client_thread(args: Arc<NetTestArgs>) {
 
     let _ = futex::atomic_wait(&UDP_SERVER_THREAD_FINISH, 0, None);
     // Warning!!! Shutdown after server thread exit, or server may not able to recv data from client
-    let shutdown_result = net::syscalls::shutdown(sock_fd, 0);
-    assert!(
-        shutdown_result == 0,
-        "Failed to shutdown udp client socket."
-    );
+    let close_result = vfs::syscalls::close(sock_fd);
+    assert!(close_result == 0, "Failed to close udp client socket.");
 
     println!("Thread exit:[udp_client_thread]");
 }
@@ -324,3 +326,476 @@ fn test_udp_ipv6_non_blocking() {
 
     let _ = futex::atomic_wait(&UDP_CLIENT_THREAD_FINISH, 0, None);
 }
+
+fn bind_udp_socket(port: u16) -> i32 {
+    let sock_fd = net::syscalls::socket(AF_INET, libc::SOCK_DGRAM, 0);
+    assert!(sock_fd >= 0, "Fail to create udp socket fd.");
+    let addr_ipv4 = net_utils::create_ipv4_sockaddr("127.0.0.1", port);
+    let bind_result = net::syscalls::bind(
+        sock_fd,
+        &addr_ipv4 as *const _ as *const libc::sockaddr,
+        mem::size_of::<libc::sockaddr>() as libc::socklen_t,
+    );
+    assert!(bind_result == 0, "Failed to bind udp socket.");
+    sock_fd
+}
+
+fn udp_poll_sender_thread() {
+    println!("Thread enter:[udp_poll_sender_thread]");
+    let sock_fd = net::syscalls::socket(AF_INET, libc::SOCK_DGRAM, 0);
+    assert!(sock_fd >= 0, "Fail to create udp client socket fd.");
+
+    // Let the receiver block in poll() first
+    let delay = AtomicUsize::new(0);
+    let _ = futex::atomic_wait(&delay, 0, Some(20));
+
+    let message = "Hello poll";
+    let remote_endpoint = net_utils::create_ipv4_sockaddr("127.0.0.1", 1250);
+    let bytes_sent = net::syscalls::sendto(
+        sock_fd,
+        message.as_ptr() as *const c_void,
+        message.len(),
+        0,
+        &remote_endpoint as *const _ as *const libc::sockaddr,
+        mem::size_of::<libc::sockaddr>() as libc::socklen_t,
+    );
+    assert!(bytes_sent > 0, "Test udp client send fail.");
+
+    let _ = futex::atomic_wait(&UDP_POLL_THREAD_FINISH, 0, None);
+    assert!(vfs::syscalls::close(sock_fd) == 0);
+    println!("Thread exit:[udp_poll_sender_thread]");
+}
+
+fn udp_poll_thread() {
+    println!("Thread enter:[udp_poll_thread]");
+    let ready_fd = bind_udp_socket(1250);
+    let idle_fd = bind_udp_socket(1251);
+
+    let mut fds = [
+        libc::pollfd {
+            fd: ready_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: idle_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    // Nothing to read yet
+    assert_eq!(vfs::syscalls::poll(fds.as_mut_ptr(), 2, 0), 0);
+
+    net_utils::start_test_thread_with_cleanup(
+        "udp_poll_sender_thread",
+        Box::new(udp_poll_sender_thread),
+        Some(Box::new(|| {
+            UDP_POLL_SENDER_FINISH.store(1, Ordering::Release);
+            let _ = futex::atomic_wake(&UDP_POLL_SENDER_FINISH, 1);
+        })),
+    );
+
+    let ready = vfs::syscalls::poll(fds.as_mut_ptr(), 2, 5000);
+    println!("poll result {}", ready);
+    assert_eq!(ready, 1);
+    assert_eq!(fds[0].revents, libc::POLLIN);
+    assert_eq!(fds[1].revents, 0);
+
+    let mut buffer = vec![0u8; 64];
+    let bytes_received = net::syscalls::recvfrom(
+        ready_fd,
+        buffer.as_mut_ptr() as *mut c_void,
+        buffer.len(),
+        0,
+        core::ptr::null_mut(),
+        core::ptr::null_mut(),
+    );
+    assert_eq!(bytes_received, "Hello poll".len() as isize);
+
+    UDP_POLL_THREAD_FINISH.store(1, Ordering::Release);
+    let _ = futex::atomic_wake(&UDP_POLL_THREAD_FINISH, 1);
+    let _ = futex::atomic_wait(&UDP_POLL_SENDER_FINISH, 0, None);
+
+    assert!(vfs::syscalls::close(ready_fd) == 0);
+    assert!(vfs::syscalls::close(idle_fd) == 0);
+    println!("Thread exit:[udp_poll_thread]");
+}
+
+#[test]
+fn test_udp_poll() {
+    UDP_POLL_SENDER_FINISH.store(0, Ordering::Release);
+    UDP_POLL_THREAD_FINISH.store(0, Ordering::Release);
+
+    net_utils::start_test_thread("udp_poll_thread", Box::new(udp_poll_thread));
+
+    let _ = futex::atomic_wait(&UDP_POLL_SENDER_FINISH, 0, None);
+}
+
+// Sockets and pipes are polled together, a write to the pipe wakes the
+// poller up while the socket stays idle.
+#[test]
+fn test_udp_pipe_poll() {
+    UDP_PIPE_POLL_WRITER_FINISH.store(0, Ordering::Release);
+    let sock_fd = bind_udp_socket(1264);
+    let mut pipe_fds = [0; 2];
+    assert_eq!(vfs::syscalls::pipe(pipe_fds.as_mut_ptr()), 0);
+
+    let mut fds = [
+        libc::pollfd {
+            fd: sock_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: pipe_fds[0],
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    assert_eq!(vfs::syscalls::poll(fds.as_mut_ptr(), 2, 0), 0);
+
+    let write_fd = pipe_fds[1];
+    net_utils::start_test_thread_with_cleanup(
+        "udp_pipe_poll_writer_thread",
+        Box::new(move || {
+            // Let the poller block first
+            let delay = AtomicUsize::new(0);
+            let _ = futex::atomic_wait(&delay, 0, Some(20));
+            assert_eq!(vfs::syscalls::write(write_fd, b"x".as_ptr(), 1), 1);
+        }),
+        Some(Box::new(|| {
+            UDP_PIPE_POLL_WRITER_FINISH.store(1, Ordering::Release);
+            let _ = futex::atomic_wake(&UDP_PIPE_POLL_WRITER_FINISH, 1);
+        })),
+    );
+
+    assert_eq!(vfs::syscalls::poll(fds.as_mut_ptr(), 2, 5000), 1);
+    assert_eq!(fds[0].revents, 0);
+    assert_eq!(fds[1].revents, libc::POLLIN);
+    let _ = futex::atomic_wait(&UDP_PIPE_POLL_WRITER_FINISH, 0, None);
+
+    let mut buffer = [0u8; 4];
+    assert_eq!(
+        vfs::syscalls::read(pipe_fds[0], buffer.as_mut_ptr(), buffer.len()),
+        1
+    );
+    assert!(vfs::syscalls::close(pipe_fds[0]) == 0);
+    assert!(vfs::syscalls::close(pipe_fds[1]) == 0);
+    assert!(vfs::syscalls::close(sock_fd) == 0);
+}
+
+#[test]
+fn test_udp_getsockname() {
+    let sock_fd = bind_udp_socket(1252);
+
+    let mut addr: libc::sockaddr_in = unsafe { mem::zeroed() };
+    let mut addr_len = mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+    let result = net::syscalls::getsockname(
+        sock_fd,
+        &mut addr as *mut _ as *mut libc::sockaddr,
+        &mut addr_len,
+    );
+    assert_eq!(result, 0);
+    assert_eq!(addr.sin_family, AF_INET as libc::sa_family_t);
+    assert_eq!(u16::from_be(addr.sin_port), 1252);
+    assert_eq!(addr_len as usize, mem::size_of::<libc::sockaddr_in>());
+
+    // Not connected
+    let result = net::syscalls::getpeername(
+        sock_fd,
+        &mut addr as *mut _ as *mut libc::sockaddr,
+        &mut addr_len,
+    );
+    assert_eq!(result, -libc::ENOTCONN);
+
+    // Truncated to the buffer, the full length is still reported
+    let mut port = [0u8; 4];
+    let mut addr_len = port.len() as libc::socklen_t;
+    let result = net::syscalls::getsockname(
+        sock_fd,
+        port.as_mut_ptr() as *mut libc::sockaddr,
+        &mut addr_len,
+    );
+    assert_eq!(result, 0);
+    assert_eq!(u16::from_be_bytes([port[2], port[3]]), 1252);
+    assert_eq!(addr_len as usize, mem::size_of::<libc::sockaddr_in>());
+
+    assert!(vfs::syscalls::close(sock_fd) == 0);
+
+    // An unbound socket is named by the unspecified address
+    let sock_fd = net::syscalls::socket(AF_INET, libc::SOCK_DGRAM, 0);
+    assert!(sock_fd >= 0, "Fail to create udp socket.");
+    let mut addr: libc::sockaddr_in = unsafe { mem::zeroed() };
+    let mut addr_len = mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+    let result = net::syscalls::getsockname(
+        sock_fd,
+        &mut addr as *mut _ as *mut libc::sockaddr,
+        &mut addr_len,
+    );
+    assert_eq!(result, 0);
+    assert_eq!(addr.sin_family, AF_INET as libc::sa_family_t);
+    assert_eq!(addr.sin_port, 0);
+    assert_eq!(addr.sin_addr.s_addr, 0);
+
+    assert!(vfs::syscalls::close(sock_fd) == 0);
+}
+
+fn set_recv_timeout(sock_fd: i32, tv_usec: libc::suseconds_t) {
+    let timeout = libc::timeval { tv_sec: 0, tv_usec };
+    let setsockopt_result = net::syscalls::setsockopt(
+        sock_fd,
+        libc::SOL_SOCKET,
+        libc::SO_RCVTIMEO,
+        &timeout as *const _ as *const c_void,
+        mem::size_of::<libc::timeval>() as libc::socklen_t,
+    );
+    assert!(setsockopt_result == 0, "Failed to set SO_RCVTIMEO.");
+}
+
+fn get_recv_timeout(sock_fd: i32) -> libc::timeval {
+    let mut timeout: libc::timeval = unsafe { mem::zeroed() };
+    let mut len = mem::size_of::<libc::timeval>() as libc::socklen_t;
+    let getsockopt_result = net::syscalls::getsockopt(
+        sock_fd,
+        libc::SOL_SOCKET,
+        libc::SO_RCVTIMEO,
+        &mut timeout as *mut _ as *mut c_void,
+        &mut len,
+    );
+    assert!(getsockopt_result == 0, "Failed to get SO_RCVTIMEO.");
+    timeout
+}
+
+#[test]
+fn test_udp_recv_timeout() {
+    let sock_fd = bind_udp_socket(1255);
+
+    set_recv_timeout(sock_fd, 100_000);
+    let timeout = get_recv_timeout(sock_fd);
+    assert_eq!((timeout.tv_sec, timeout.tv_usec), (0, 100_000));
+
+    // Nobody sends anything
+    let mut buffer = vec![0u8; 64];
+    let mut addr: libc::sockaddr_in = unsafe { mem::zeroed() };
+    let mut addr_len = mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+    let bytes_received = net::syscalls::recvfrom(
+        sock_fd,
+        buffer.as_mut_ptr() as *mut c_void,
+        buffer.len(),
+        0,
+        &mut addr as *mut _ as *mut libc::sockaddr,
+        &mut addr_len,
+    );
+    assert_eq!(bytes_received, -libc::EAGAIN as isize);
+
+    // Zero blocks indefinitely again
+    set_recv_timeout(sock_fd, 0);
+    let timeout = get_recv_timeout(sock_fd);
+    assert_eq!((timeout.tv_sec, timeout.tv_usec), (0, 0));
+
+    assert!(vfs::syscalls::close(sock_fd) == 0);
+}
+
+fn set_membership(sock_fd: i32, option_name: i32, group: &str) -> i32 {
+    let group = net_utils::create_ipv4_sockaddr(group, 0);
+    let interface = net_utils::create_ipv4_sockaddr("127.0.0.1", 0);
+    let mreq = libc::ip_mreq {
+        imr_multiaddr: group.sin_addr,
+        imr_interface: interface.sin_addr,
+    };
+    net::syscalls::setsockopt(
+        sock_fd,
+        libc::IPPROTO_IP,
+        option_name,
+        &mreq as *const _ as *const c_void,
+        mem::size_of::<libc::ip_mreq>() as libc::socklen_t,
+    )
+}
+
+fn send_to_group(sock_fd: i32, port: u16, message: &'static str) {
+    let group = net_utils::create_ipv4_sockaddr("239.1.2.3", port);
+    let bytes_sent = net::syscalls::sendto(
+        sock_fd,
+        message.as_ptr() as *const c_void,
+        message.len(),
+        0,
+        &group as *const _ as *const libc::sockaddr,
+        mem::size_of::<libc::sockaddr>() as libc::socklen_t,
+    );
+    assert_eq!(bytes_sent, message.len() as isize);
+}
+
+fn udp_multicast_thread() {
+    println!("Thread enter:[udp_multicast_thread]");
+    let receiver_fd = bind_udp_socket(1253);
+    let sender_fd = bind_udp_socket(1254);
+    let mut fds = [libc::pollfd {
+        fd: receiver_fd,
+        events: libc::POLLIN,
+        revents: 0,
+    }];
+
+    // Only multicast addresses can be joined
+    assert_eq!(
+        set_membership(receiver_fd, libc::IP_ADD_MEMBERSHIP, "127.0.0.2"),
+        -libc::EINVAL
+    );
+    assert_eq!(
+        set_membership(receiver_fd, libc::IP_ADD_MEMBERSHIP, "239.1.2.3"),
+        0
+    );
+    assert_eq!(
+        set_membership(receiver_fd, libc::IP_ADD_MEMBERSHIP, "239.1.2.3"),
+        -libc::EADDRINUSE
+    );
+
+    send_to_group(sender_fd, 1253, "Hello group");
+    assert_eq!(vfs::syscalls::poll(fds.as_mut_ptr(), 1, 1000), 1);
+    let mut buffer = vec![0u8; 64];
+    let bytes_received = net::syscalls::recvfrom(
+        receiver_fd,
+        buffer.as_mut_ptr() as *mut c_void,
+        buffer.len(),
+        0,
+        core::ptr::null_mut(),
+        core::ptr::null_mut(),
+    );
+    assert_eq!(&buffer[..bytes_received as usize], b"Hello group");
+
+    // Not delivered after leaving the group
+    assert_eq!(
+        set_membership(receiver_fd, libc::IP_DROP_MEMBERSHIP, "239.1.2.3"),
+        0
+    );
+    assert_eq!(
+        set_membership(receiver_fd, libc::IP_DROP_MEMBERSHIP, "239.1.2.3"),
+        -libc::EADDRNOTAVAIL
+    );
+    send_to_group(sender_fd, 1253, "Hello again");
+    assert_eq!(vfs::syscalls::poll(fds.as_mut_ptr(), 1, 200), 0);
+
+    // The interface stays in the group while any socket is a member
+    let member_fd = bind_udp_socket(1256);
+    assert_eq!(
+        set_membership(receiver_fd, libc::IP_ADD_MEMBERSHIP, "239.1.2.3"),
+        0
+    );
+    assert_eq!(
+        set_membership(member_fd, libc::IP_ADD_MEMBERSHIP, "239.1.2.3"),
+        0
+    );
+    assert_eq!(
+        set_membership(receiver_fd, libc::IP_DROP_MEMBERSHIP, "239.1.2.3"),
+        0
+    );
+    send_to_group(sender_fd, 1256, "Still a member");
+    fds[0].fd = member_fd;
+    assert_eq!(vfs::syscalls::poll(fds.as_mut_ptr(), 1, 1000), 1);
+    let bytes_received = net::syscalls::recvfrom(
+        member_fd,
+        buffer.as_mut_ptr() as *mut c_void,
+        buffer.len(),
+        0,
+        core::ptr::null_mut(),
+        core::ptr::null_mut(),
+    );
+    assert_eq!(&buffer[..bytes_received as usize], b"Still a member");
+
+    assert!(vfs::syscalls::close(member_fd) == 0);
+    assert!(vfs::syscalls::close(receiver_fd) == 0);
+    assert!(vfs::syscalls::close(sender_fd) == 0);
+    println!("Thread exit:[udp_multicast_thread]");
+}
+
+#[test]
+fn test_udp_multicast() {
+    UDP_MULTICAST_THREAD_FINISH.store(0, Ordering::Release);
+
+    net_utils::start_test_thread_with_cleanup(
+        "udp_multicast_thread",
+        Box::new(udp_multicast_thread),
+        Some(Box::new(|| {
+            UDP_MULTICAST_THREAD_FINISH.store(1, Ordering::Release);
+            let _ = futex::atomic_wake(&UDP_MULTICAST_THREAD_FINISH, 1);
+        })),
+    );
+
+    let _ = futex::atomic_wait(&UDP_MULTICAST_THREAD_FINISH, 0, None);
+}
+
+const EPOLL_SOCKETS: usize = 64;
+
+#[test]
+fn test_udp_epoll() {
+    let epfd = vfs::syscalls::epoll_create1(0);
+    assert!(epfd >= 0);
+
+    // Every eighth socket is bound to be written to, the rest stay idle
+    let readable_ports = [1257u16, 1258, 1259];
+    let mut sock_fds = vec::Vec::new();
+    for i in 0..EPOLL_SOCKETS {
+        let sock_fd = match readable_ports.get(i / 8).filter(|_| i % 8 == 0) {
+            Some(&port) => bind_udp_socket(port),
+            None => net::syscalls::socket(AF_INET, libc::SOCK_DGRAM, 0),
+        };
+        assert!(sock_fd >= 0, "Fail to create udp socket fd.");
+        let mut event = EpollEvent {
+            events: EPOLLIN,
+            data: sock_fd as u64,
+        };
+        assert_eq!(
+            vfs::syscalls::epoll_ctl(epfd, EPOLL_CTL_ADD, sock_fd, &mut event),
+            0
+        );
+        sock_fds.push(sock_fd);
+    }
+
+    let mut events = [EpollEvent::default(); EPOLL_SOCKETS];
+    assert_eq!(
+        vfs::syscalls::epoll_wait(epfd, events.as_mut_ptr(), events.len() as i32, 0),
+        0
+    );
+
+    let sender_fd = net::syscalls::socket(AF_INET, libc::SOCK_DGRAM, 0);
+    assert!(sender_fd >= 0, "Fail to create udp client socket fd.");
+    let message = "Hello epoll";
+    for port in readable_ports {
+        let remote_endpoint = net_utils::create_ipv4_sockaddr("127.0.0.1", port);
+        let bytes_sent = net::syscalls::sendto(
+            sender_fd,
+            message.as_ptr() as *const c_void,
+            message.len(),
+            0,
+            &remote_endpoint as *const _ as *const libc::sockaddr,
+            mem::size_of::<libc::sockaddr>() as libc::socklen_t,
+        );
+        assert_eq!(bytes_sent, message.len() as isize);
+    }
+
+    // The datagrams may be delivered across several polls of the stack,
+    // wait until every socket has reported readiness, up to a second.
+    let mut ready_fds: vec::Vec<i32> = vec::Vec::new();
+    for _ in 0..10 {
+        if ready_fds.len() == readable_ports.len() {
+            break;
+        }
+        let ready = vfs::syscalls::epoll_wait(epfd, events.as_mut_ptr(), events.len() as i32, 100);
+        assert!(ready >= 0);
+        for event in &events[..ready as usize] {
+            assert_eq!(event.events, EPOLLIN);
+            if !ready_fds.contains(&(event.data as i32)) {
+                ready_fds.push(event.data as i32);
+            }
+        }
+    }
+    ready_fds.sort();
+    let mut expected = [sock_fds[0], sock_fds[8], sock_fds[16]];
+    expected.sort();
+    assert_eq!(ready_fds, expected);
+
+    assert_eq!(vfs::syscalls::close(epfd), 0);
+    assert!(vfs::syscalls::close(sender_fd) == 0);
+    for sock_fd in sock_fds {
+        assert!(vfs::syscalls::close(sock_fd) == 0);
+    }
+}
@@ -60,6 +60,7 @@ use blueos::{
     scheduler,
     sync::atomic_wait as futex,
     thread::{Builder as ThreadBuilder, Entry, Stack},
+    vfs,
 };
 use blueos_test_macro::test;
 use core::{
@@ -169,11 +170,11 @@ fn virtio_net_client(args: Arc<NetTestArgs>) {
         });
     }
 
-    let shutdown_result = net::syscalls::shutdown(sock_fd, 0);
-    println!("Socket[{}] shutdown result {}", sock_fd, shutdown_result);
+    let close_result = vfs::syscalls::close(sock_fd);
+    println!("Socket[{}] close result {}", sock_fd, close_result);
     assert!(
-        shutdown_result == 0,
-        "Failed to shutdown virtio-net tcp client socket."
+        close_result == 0,
+        "Failed to close virtio-net tcp client socket."
     );
 
     println!("Thread exit:[virtio_net_client]");
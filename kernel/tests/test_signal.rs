@@ -0,0 +1,164 @@
+// Copyright (c) 2025 vivo Mobile Communication Co., Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use blueos::{
+    asynk,
+    syscalls::{
+        alarm, itimerval, nop, setitimer, sigaction, signalaction, sigpending, sigprocmask,
+        sigqueueinfo, ITIMER_REAL,
+    },
+    thread::signal::{SIGALRM, SIGUSR1, SIG_BLOCK, SIG_UNBLOCK},
+};
+use blueos_test_macro::test;
+use core::{
+    ffi::{c_int, c_void},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn on_sigusr1(sig: c_int) {
+    assert_eq!(sig, SIGUSR1);
+    HANDLED.fetch_add(1, Ordering::Relaxed);
+}
+
+fn install_handler() {
+    install(SIGUSR1, on_sigusr1);
+}
+
+fn install(sig: c_int, handler: extern "C" fn(c_int)) {
+    let mut act: sigaction = unsafe { core::mem::zeroed() };
+    act.sa_handler = Some(handler);
+    let ret = signalaction::handle(
+        sig,
+        &act as *const sigaction as *const c_void,
+        core::ptr::null_mut(),
+    );
+    assert_eq!(ret, 0);
+}
+
+// Signal n is bit n - 1 of the set.
+fn sigset(bits: u64) -> libc::sigset_t {
+    let mut set: libc::sigset_t = unsafe { core::mem::zeroed() };
+    let n = core::mem::size_of::<libc::sigset_t>().min(core::mem::size_of::<u64>());
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            &bits as *const u64 as *const u8,
+            &mut set as *mut libc::sigset_t as *mut u8,
+            n,
+        )
+    };
+    set
+}
+
+fn sigset_bits(set: &libc::sigset_t) -> u64 {
+    let mut bits = 0u64;
+    let n = core::mem::size_of::<libc::sigset_t>().min(core::mem::size_of::<u64>());
+    unsafe {
+        core::ptr::copy_nonoverlapping(
+            set as *const libc::sigset_t as *const u8,
+            &mut bits as *mut u64 as *mut u8,
+            n,
+        )
+    };
+    bits
+}
+
+#[test]
+fn test_sigusr1_handler_runs_once() {
+    HANDLED.store(0, Ordering::Relaxed);
+    install_handler();
+    let set = sigset(1 << (SIGUSR1 - 1));
+    assert_eq!(
+        sigprocmask::handle(SIG_BLOCK, &set, core::ptr::null_mut()),
+        0
+    );
+    assert_eq!(sigqueueinfo::handle(0, SIGUSR1, core::ptr::null()), 0);
+    assert_eq!(HANDLED.load(Ordering::Relaxed), 0);
+    let mut pending = sigset(0);
+    assert_eq!(sigpending::handle(&mut pending), 0);
+    assert_eq!(sigset_bits(&pending), 1 << (SIGUSR1 - 1));
+    // Unblocking delivers it on the way out of sigprocmask.
+    assert_eq!(
+        sigprocmask::handle(SIG_UNBLOCK, &set, core::ptr::null_mut()),
+        0
+    );
+    assert_eq!(HANDLED.load(Ordering::Relaxed), 1);
+}
+
+static ALARMS: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn on_sigalrm(sig: c_int) {
+    assert_eq!(sig, SIGALRM);
+    ALARMS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[test]
+fn test_alarm_fires_sigalrm() {
+    ALARMS.store(0, Ordering::Relaxed);
+    install(SIGALRM, on_sigalrm);
+    assert_eq!(alarm::handle(1), 0);
+    asynk::block_on(asynk::sleep(Duration::from_millis(900)));
+    // Signals are delivered on syscall return.
+    nop::handle();
+    assert_eq!(ALARMS.load(Ordering::Relaxed), 0);
+    let mut waited = 0;
+    while ALARMS.load(Ordering::Relaxed) == 0 && waited < 600 {
+        asynk::block_on(asynk::sleep(Duration::from_millis(10)));
+        nop::handle();
+        waited += 10;
+    }
+    assert_eq!(ALARMS.load(Ordering::Relaxed), 1);
+    assert_eq!(alarm::handle(0), 0);
+}
+
+#[test]
+fn test_alarm_replaces_pending_alarm() {
+    ALARMS.store(0, Ordering::Relaxed);
+    install(SIGALRM, on_sigalrm);
+    assert_eq!(alarm::handle(5), 0);
+    assert_eq!(alarm::handle(1), 5);
+    assert_eq!(alarm::handle(0), 1);
+    asynk::block_on(asynk::sleep(Duration::from_millis(1100)));
+    nop::handle();
+    assert_eq!(ALARMS.load(Ordering::Relaxed), 0);
+}
+
+#[test]
+fn test_setitimer_saturates() {
+    let mut new: itimerval = unsafe { core::mem::zeroed() };
+    new.it_value.tv_sec = libc::time_t::MAX;
+    assert_eq!(
+        setitimer::handle(
+            ITIMER_REAL,
+            &new as *const _ as *const c_void,
+            core::ptr::null_mut()
+        ),
+        0
+    );
+    // Saturated rather than wrapped to a short or zero timeout.
+    let mut old: itimerval = unsafe { core::mem::zeroed() };
+    let off: itimerval = unsafe { core::mem::zeroed() };
+    assert_eq!(
+        setitimer::handle(
+            ITIMER_REAL,
+            &off as *const _ as *const c_void,
+            &mut old as *mut _ as *mut c_void
+        ),
+        0
+    );
+    assert!(old.it_value.tv_sec > 1_000_000);
+    assert_eq!(alarm::handle(0), 0);
+}
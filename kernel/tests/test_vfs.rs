@@ -14,16 +14,17 @@
 
 #![allow(dead_code)]
 use crate::net::net_utils;
-use alloc::{boxed::Box, ffi::CString, format, string::String, vec};
+use alloc::{boxed::Box, ffi::CString, format, string::String, vec, vec::Vec};
 use blueos::{
     allocator,
     error::{
-        code::{EEXIST, ENOENT, ENOTEMPTY},
+        code::{EEXIST, EINVAL, EISDIR, ELOOP, ENOENT, ENOTEMPTY},
         Error,
     },
     net, scheduler,
     sync::atomic_wait as futex,
-    thread::{Builder as ThreadBuilder, Entry, Stack},
+    thread::{Builder as ThreadBuilder, Entry, Stack, Thread},
+    time,
     vfs::{
         dirent::{Dirent, DirentType},
         syscalls::*,
@@ -37,7 +38,10 @@ use core::{
     mem,
     sync::atomic::{AtomicUsize, Ordering},
 };
-use libc::{AF_INET, ENOSYS, O_CREAT, O_DIRECTORY, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, SEEK_SET};
+use libc::{
+    AF_INET, ENOSYS, O_APPEND, O_CREAT, O_DIRECTORY, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, SEEK_END,
+    SEEK_SET,
+};
 use semihosting::println;
 
 #[test]
@@ -438,6 +442,814 @@ fn test_std_fds() {
     }
 }
 
+fn write_file(path: &CStr, data: &[u8]) {
+    let fd = open(path.as_ptr(), O_CREAT | O_RDWR | O_TRUNC, 0o644);
+    assert!(fd >= 0);
+    assert_eq!(write(fd, data.as_ptr(), data.len()), data.len() as isize);
+    close(fd);
+}
+
+fn read_file(path: &CStr, buf: &mut [u8]) -> isize {
+    let fd = open(path.as_ptr(), O_RDONLY, 0o644);
+    if fd < 0 {
+        return fd as isize;
+    }
+    let read_size = read(fd, buf.as_mut_ptr(), buf.len());
+    close(fd);
+    read_size
+}
+
+#[test]
+fn test_rename() {
+    let mode: libc::mode_t = 0o755;
+    let mut buf = [0u8; 16];
+    assert_eq!(mkdir(c"/rename".as_ptr(), mode), 0);
+
+    // Same directory
+    write_file(c"/rename/a", b"a");
+    assert_eq!(rename(c"/rename/a".as_ptr(), c"/rename/b".as_ptr()), 0);
+    assert!(read_file(c"/rename/a", &mut buf) < 0);
+    assert_eq!(read_file(c"/rename/b", &mut buf), 1);
+    assert_eq!(buf[0], b'a');
+
+    // Across directories
+    assert_eq!(mkdir(c"/rename/d".as_ptr(), mode), 0);
+    assert_eq!(rename(c"/rename/b".as_ptr(), c"/rename/d/c".as_ptr()), 0);
+    assert!(read_file(c"/rename/b", &mut buf) < 0);
+    assert_eq!(read_file(c"/rename/d/c", &mut buf), 1);
+
+    // Replace an existing file
+    write_file(c"/rename/e", b"ee");
+    assert_eq!(rename(c"/rename/d/c".as_ptr(), c"/rename/e".as_ptr()), 0);
+    assert!(read_file(c"/rename/d/c", &mut buf) < 0);
+    assert_eq!(read_file(c"/rename/e", &mut buf), 1);
+    assert_eq!(buf[0], b'a');
+
+    // Directories
+    assert_eq!(mkdir(c"/rename/x".as_ptr(), mode), 0);
+    assert_eq!(mkdir(c"/rename/y".as_ptr(), mode), 0);
+    write_file(c"/rename/y/f", b"f");
+    assert_eq!(
+        rename(c"/rename/x".as_ptr(), c"/rename/y".as_ptr()),
+        ENOTEMPTY.to_errno()
+    );
+    assert_eq!(
+        rename(c"/rename/e".as_ptr(), c"/rename/x".as_ptr()),
+        EISDIR.to_errno()
+    );
+    // /rename/d is empty now
+    assert_eq!(rename(c"/rename/x".as_ptr(), c"/rename/d".as_ptr()), 0);
+    assert_eq!(rmdir(c"/rename/x".as_ptr()), ENOENT.to_errno());
+    assert_eq!(
+        rename(c"/rename/y".as_ptr(), c"/rename/y/z".as_ptr()),
+        EINVAL.to_errno()
+    );
+    assert_eq!(rename(c"/rename/y".as_ptr(), c"/rename/d/y".as_ptr()), 0);
+    assert_eq!(read_file(c"/rename/d/y/f", &mut buf), 1);
+
+    assert_eq!(unlink(c"/rename/d/y/f".as_ptr()), 0);
+    assert_eq!(rmdir(c"/rename/d/y".as_ptr()), 0);
+    assert_eq!(rmdir(c"/rename/d".as_ptr()), 0);
+    assert_eq!(unlink(c"/rename/e".as_ptr()), 0);
+    assert_eq!(rmdir(c"/rename".as_ptr()), 0);
+}
+
+#[test]
+fn test_at_syscalls() {
+    let mut buf = [0u8; 16];
+    assert_eq!(mkdir(c"/at".as_ptr(), 0o755), 0);
+    write_file(c"/at/f", b"hello");
+
+    let dirfd = open(c"/at".as_ptr(), O_RDONLY | O_DIRECTORY, 0);
+    assert!(dirfd >= 0);
+    let fd = openat(dirfd, c"f".as_ptr(), O_RDONLY, 0);
+    assert!(fd >= 0);
+    assert_eq!(read(fd, buf.as_mut_ptr(), buf.len()), 5);
+    assert_eq!(&buf[..5], b"hello");
+    // A regular file can't serve as dirfd.
+    assert_eq!(openat(fd, c"f".as_ptr(), O_RDONLY, 0), -libc::ENOTDIR);
+    close(fd);
+    // dirfd is ignored for absolute paths.
+    let fd = openat(-1, c"/at/f".as_ptr(), O_RDONLY, 0);
+    assert!(fd >= 0);
+    close(fd);
+    assert_eq!(openat(-1, c"f".as_ptr(), O_RDONLY, 0), -libc::EBADF);
+
+    assert_eq!(mkdirat(dirfd, c"sub".as_ptr(), 0o755), 0);
+    let mut st: Stat = unsafe { mem::zeroed() };
+    assert_eq!(
+        fstatat(dirfd, c"sub".as_ptr(), &mut st, libc::AT_SYMLINK_NOFOLLOW),
+        0
+    );
+    assert_eq!(st.st_mode & libc::S_IFMT, libc::S_IFDIR);
+    assert_eq!(fstatat(dirfd, c"f".as_ptr(), &mut st, 0), 0);
+    assert_eq!(st.st_size, 5);
+
+    assert_eq!(unlinkat(dirfd, c"sub".as_ptr(), libc::AT_REMOVEDIR), 0);
+    assert_eq!(unlinkat(dirfd, c"f".as_ptr(), 0), 0);
+    assert!(read_file(c"/at/f", &mut buf) < 0);
+    close(dirfd);
+    assert_eq!(rmdir(c"/at".as_ptr()), 0);
+}
+
+#[test]
+fn test_symlink() {
+    let mut buf = [0u8; 16];
+    assert_eq!(mkdir(c"/sl".as_ptr(), 0o755), 0);
+    write_file(c"/sl/target", b"data");
+
+    assert_eq!(symlink(c"/sl/target".as_ptr(), c"/sl/abs".as_ptr()), 0);
+    assert_eq!(symlink(c"target".as_ptr(), c"/sl/rel".as_ptr()), 0);
+    assert_eq!(
+        symlink(c"target".as_ptr(), c"/sl/rel".as_ptr()),
+        EEXIST.to_errno()
+    );
+    assert_eq!(read_file(c"/sl/abs", &mut buf), 4);
+    assert_eq!(&buf[..4], b"data");
+    assert_eq!(read_file(c"/sl/rel", &mut buf), 4);
+    assert_eq!(&buf[..4], b"data");
+
+    let mut target = [0 as c_char; 32];
+    assert_eq!(
+        readlink(c"/sl/abs".as_ptr(), target.as_mut_ptr(), target.len()),
+        10
+    );
+    assert_eq!(
+        unsafe { core::slice::from_raw_parts(target.as_ptr() as *const u8, 10) },
+        b"/sl/target"
+    );
+    // Not a symlink.
+    assert_eq!(
+        readlink(c"/sl/target".as_ptr(), target.as_mut_ptr(), target.len()),
+        EINVAL.to_errno() as isize
+    );
+
+    let mut st: Stat = unsafe { mem::zeroed() };
+    assert_eq!(
+        fstatat(
+            libc::AT_FDCWD,
+            c"/sl/abs".as_ptr(),
+            &mut st,
+            libc::AT_SYMLINK_NOFOLLOW
+        ),
+        0
+    );
+    assert_eq!(st.st_mode & libc::S_IFMT, libc::S_IFLNK);
+    assert_eq!(stat(c"/sl/abs".as_ptr(), &mut st), 0);
+    assert_eq!(st.st_mode & libc::S_IFMT, libc::S_IFREG);
+
+    // A link to itself never resolves.
+    assert_eq!(symlink(c"/sl/loop".as_ptr(), c"/sl/loop".as_ptr()), 0);
+    assert_eq!(read_file(c"/sl/loop", &mut buf), ELOOP.to_errno() as isize);
+
+    for link in [c"/sl/abs", c"/sl/rel", c"/sl/loop", c"/sl/target"] {
+        assert_eq!(unlink(link.as_ptr()), 0);
+    }
+    assert_eq!(rmdir(c"/sl".as_ptr()), 0);
+}
+
+#[test]
+fn test_fsync() {
+    // Files of tmpfs only live in memory, syncing them is a no-op.
+    write_file(c"/fsync", b"data");
+    let fd = open(c"/fsync".as_ptr(), O_RDWR, 0);
+    assert!(fd >= 0);
+    assert_eq!(fsync(fd), 0);
+    assert_eq!(fdatasync(fd), 0);
+    assert_eq!(close(fd), 0);
+    assert_eq!(unlink(c"/fsync".as_ptr()), 0);
+
+    // Pipes can't be synced.
+    let mut fds = [0 as c_int; 2];
+    assert_eq!(pipe(fds.as_mut_ptr()), 0);
+    assert_eq!(fsync(fds[0]), EINVAL.to_errno());
+    assert_eq!(fdatasync(fds[1]), EINVAL.to_errno());
+    assert_eq!(close(fds[0]), 0);
+    assert_eq!(close(fds[1]), 0);
+
+    assert_eq!(fsync(fds[0]), -libc::EBADF);
+}
+
+#[test]
+fn test_memory_devices() {
+    let mut buf = [0xffu8; 16];
+    assert_eq!(read_file(c"/dev/null", &mut buf), 0);
+    assert_eq!(read_file(c"/dev/zero", &mut buf), 16);
+    assert!(buf.iter().all(|&b| b == 0));
+    buf.fill(0xff);
+    assert_eq!(read_file(c"/dev/full", &mut buf), 16);
+    assert!(buf.iter().all(|&b| b == 0));
+
+    for (dev, expected) in [
+        (c"/dev/null", 16),
+        (c"/dev/zero", 16),
+        (c"/dev/full", -libc::ENOSPC as isize),
+    ] {
+        let fd = open(dev.as_ptr(), O_WRONLY, 0);
+        assert!(fd >= 0);
+        assert_eq!(write(fd, buf.as_ptr(), buf.len()), expected);
+        assert_eq!(close(fd), 0);
+    }
+
+    for dev in [c"/dev/random", c"/dev/urandom"] {
+        let mut other = [0u8; 16];
+        assert_eq!(read_file(dev, &mut buf), 16);
+        assert_eq!(read_file(dev, &mut other), 16);
+        assert!(buf.iter().any(|&b| b != buf[0]));
+        assert_ne!(buf, other);
+    }
+}
+
+#[test]
+fn test_tcgetattr_not_a_tty() {
+    let mut termios: Termios = unsafe { mem::zeroed() };
+    write_file(c"/notty", b"data");
+    let fd = open(c"/notty".as_ptr(), O_RDWR, 0);
+    assert!(fd >= 0);
+    assert_eq!(tcgetattr(fd, &mut termios), -libc::ENOTTY);
+    assert_eq!(tcsetattr(fd, 0, &termios), -libc::ENOTTY);
+    assert_eq!(tcgetattr(fd, core::ptr::null_mut()), EINVAL.to_errno());
+    assert_eq!(close(fd), 0);
+    assert_eq!(unlink(c"/notty".as_ptr()), 0);
+    assert_eq!(tcgetattr(fd, &mut termios), -libc::EBADF);
+}
+
+#[test]
+fn test_ioctl_not_a_device() {
+    let mut value: libc::c_ulong = 0;
+    write_file(c"/noioctl", b"data");
+    let fd = open(c"/noioctl".as_ptr(), O_RDWR, 0);
+    assert!(fd >= 0);
+    assert_eq!(
+        ioctl(fd, 0x1260, &mut value as *mut libc::c_ulong as usize),
+        -libc::ENOTTY
+    );
+    assert_eq!(close(fd), 0);
+    assert_eq!(unlink(c"/noioctl".as_ptr()), 0);
+    assert_eq!(ioctl(fd, 0x1260, 0), -libc::EBADF);
+}
+
+#[cfg(virtio)]
+#[test]
+fn test_ioctl_block_capacity() {
+    use blueos::devices::{
+        block::{BLKGETSIZE, BLKGETSIZE64, VIRTUAL_STORAGE_NAME},
+        DeviceManager,
+    };
+
+    let block = DeviceManager::get()
+        .get_block_device(VIRTUAL_STORAGE_NAME)
+        .unwrap();
+    let capacity = block.capacity().unwrap();
+    let sector_size = block.sector_size().unwrap() as u64;
+    let mut sectors: libc::c_ulong = 0;
+    let mut size = 0u64;
+    let fd = open(c"/dev/virt-storage".as_ptr(), O_RDWR, 0);
+    assert!(fd >= 0);
+    assert_eq!(
+        ioctl(fd, BLKGETSIZE, &mut sectors as *mut libc::c_ulong as usize),
+        0
+    );
+    assert_eq!(sectors as u64 * 512, capacity * sector_size);
+    assert_eq!(ioctl(fd, BLKGETSIZE64, &mut size as *mut u64 as usize), 0);
+    assert_eq!(size, capacity * sector_size);
+    // Unknown requests aren't for block devices either.
+    assert_eq!(ioctl(fd, 0x5401, 0), -libc::ENOTTY);
+    assert_eq!(close(fd), 0);
+}
+
+// The console is a tty on boards other than qemu_riscv64.
+#[cfg(not(target_arch = "riscv64"))]
+#[test]
+fn test_console_termios() {
+    let mut termios: Termios = unsafe { mem::zeroed() };
+    let fd = open(c"/dev/console".as_ptr(), O_RDWR, 0);
+    assert!(fd >= 0);
+    assert_eq!(tcgetattr(fd, &mut termios), 0);
+    assert!(termios.lflag.contains(Lflags::ICANON | Lflags::ECHO));
+
+    let saved = termios;
+    termios.lflag.remove(Lflags::ECHO);
+    assert_eq!(tcsetattr(fd, 0, &termios), 0);
+    let mut current: Termios = unsafe { mem::zeroed() };
+    assert_eq!(tcgetattr(fd, &mut current), 0);
+    assert!(!current.lflag.contains(Lflags::ECHO));
+    assert_eq!(tcsetattr(fd, 3, &saved), EINVAL.to_errno());
+    assert_eq!(tcsetattr(fd, 0, &saved), 0);
+    assert_eq!(close(fd), 0);
+}
+
+#[cfg(not(target_arch = "riscv64"))]
+#[test]
+fn test_console_raw_read() {
+    let mut termios: Termios = unsafe { mem::zeroed() };
+    let mut buf = [0u8; 1];
+    let fd = open(c"/dev/console".as_ptr(), O_RDWR, 0);
+    assert!(fd >= 0);
+    assert_eq!(tcgetattr(fd, &mut termios), 0);
+    let saved = termios;
+
+    // Neither VMIN nor VTIME, reads return what's pending without waiting.
+    termios.lflag.remove(Lflags::ICANON | Lflags::ECHO);
+    termios.cc[CcIndex::Vmin as usize] = 0;
+    termios.cc[CcIndex::Vtime as usize] = 0;
+    assert_eq!(tcsetattr(fd, 0, &termios), 0);
+    let start = time::get_sys_ticks();
+    assert_eq!(read(fd, buf.as_mut_ptr(), 1), 0);
+    assert!(time::get_sys_ticks() - start < time::tick_from_millisecond(50));
+    let ch = b'x';
+    assert_eq!(ioctl(fd, TIOCSTI, &ch as *const u8 as usize), 0);
+    assert_eq!(read(fd, buf.as_mut_ptr(), 1), 1);
+    assert_eq!(buf[0], b'x');
+
+    // VTIME alone bounds the wait for the first byte to 100ms.
+    termios.cc[CcIndex::Vtime as usize] = 1;
+    assert_eq!(tcsetattr(fd, 0, &termios), 0);
+    let start = time::get_sys_ticks();
+    assert_eq!(read(fd, buf.as_mut_ptr(), 1), 0);
+    let elapsed = time::get_sys_ticks() - start;
+    assert!(elapsed >= time::tick_from_millisecond(100));
+    assert!(elapsed < time::tick_from_millisecond(300));
+    // A pending byte is returned without waiting.
+    assert_eq!(ioctl(fd, TIOCSTI, &ch as *const u8 as usize), 0);
+    let start = time::get_sys_ticks();
+    assert_eq!(read(fd, buf.as_mut_ptr(), 1), 1);
+    assert!(time::get_sys_ticks() - start < time::tick_from_millisecond(50));
+
+    // Restored once what's written is sent.
+    let message = b"draining\n";
+    assert_eq!(
+        write(fd, message.as_ptr(), message.len()),
+        message.len() as isize
+    );
+    assert_eq!(tcsetattr(fd, TCSADRAIN, &saved), 0);
+    assert_eq!(close(fd), 0);
+}
+
+#[cfg(virtio)]
+#[test]
+fn test_fatfs_fsync() {
+    let mut buf = [0u8; 16];
+    let fd = open(c"/fat/fsync".as_ptr(), O_CREAT | O_RDWR | O_TRUNC, 0o644);
+    assert!(fd >= 0);
+    assert_eq!(write(fd, b"synced".as_ptr(), 6), 6);
+    assert_eq!(fsync(fd), 0);
+    assert_eq!(close(fd), 0);
+    assert_eq!(read_file(c"/fat/fsync", &mut buf), 6);
+    assert_eq!(&buf[..6], b"synced");
+    assert_eq!(unlink(c"/fat/fsync".as_ptr()), 0);
+}
+
+#[cfg(virtio)]
+#[test]
+fn test_fatfs_rename() {
+    let mode: libc::mode_t = 0o755;
+    let mut buf = [0u8; 16];
+    assert_eq!(mkdir(c"/fat/rename".as_ptr(), mode), 0);
+    assert_eq!(mkdir(c"/fat/rename/d".as_ptr(), mode), 0);
+
+    write_file(c"/fat/rename/a", b"abc");
+    assert_eq!(
+        rename(c"/fat/rename/a".as_ptr(), c"/fat/rename/b".as_ptr()),
+        0
+    );
+    assert!(read_file(c"/fat/rename/a", &mut buf) < 0);
+    assert_eq!(read_file(c"/fat/rename/b", &mut buf), 3);
+    assert_eq!(&buf[..3], b"abc");
+
+    // Across directories, replacing an existing file
+    write_file(c"/fat/rename/d/c", b"x");
+    assert_eq!(
+        rename(c"/fat/rename/b".as_ptr(), c"/fat/rename/d/c".as_ptr()),
+        0
+    );
+    assert!(read_file(c"/fat/rename/b", &mut buf) < 0);
+    assert_eq!(read_file(c"/fat/rename/d/c", &mut buf), 3);
+    assert_eq!(&buf[..3], b"abc");
+
+    // The renamed file is still writable
+    write_file(c"/fat/rename/d/c", b"abcdef");
+    assert_eq!(read_file(c"/fat/rename/d/c", &mut buf), 6);
+
+    assert_eq!(mkdir(c"/fat/rename/e".as_ptr(), mode), 0);
+    assert_eq!(
+        rename(c"/fat/rename/e".as_ptr(), c"/fat/rename/d".as_ptr()),
+        ENOTEMPTY.to_errno()
+    );
+    assert_eq!(
+        rename(c"/fat/rename/d".as_ptr(), c"/fat/rename/e".as_ptr()),
+        0
+    );
+    assert_eq!(read_file(c"/fat/rename/e/c", &mut buf), 6);
+
+    assert_eq!(unlink(c"/fat/rename/e/c".as_ptr()), 0);
+    assert_eq!(rmdir(c"/fat/rename/e".as_ptr()), 0);
+    assert_eq!(rmdir(c"/fat/rename".as_ptr()), 0);
+}
+
+#[test]
+fn test_ramfs_mount() {
+    let mode: libc::mode_t = 0o644;
+    let mount_path = c"/ram".as_ptr() as *const c_char;
+    assert_eq!(mkdir(mount_path, mode), 0);
+    assert_eq!(
+        mount(
+            core::ptr::null(),
+            mount_path,
+            c"ramfs".as_ptr() as *const c_char,
+            0,
+            core::ptr::null(),
+        ),
+        0
+    );
+
+    // Write past EOF, the hole reads back as zeros
+    let file_path = c"/ram/sparse.txt".as_ptr() as *const c_char;
+    let fd = open(file_path, O_CREAT | O_RDWR, mode);
+    assert!(fd >= 0);
+    let test_data = b"data";
+    assert_eq!(lseek(fd, 8, SEEK_SET), 8);
+    assert_eq!(write(fd, test_data.as_ptr(), test_data.len()), 4);
+    assert_eq!(lseek(fd, 0, SEEK_END), 12);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    let mut read_buf = [0xffu8; 16];
+    assert_eq!(read(fd, read_buf.as_mut_ptr(), read_buf.len()), 12);
+    assert_eq!(&read_buf[..8], &[0u8; 8]);
+    assert_eq!(&read_buf[8..12], test_data);
+
+    // Truncate
+    assert_eq!(ftruncate(fd, 2), 0);
+    assert_eq!(lseek(fd, 0, SEEK_END), 2);
+    close(fd);
+
+    // The data survives through a hard link
+    let link_path = c"/ram/link.txt".as_ptr() as *const c_char;
+    assert_eq!(link(file_path, link_path), 0);
+    assert_eq!(unlink(file_path), 0);
+    assert!(open(file_path, O_RDONLY, mode) < 0);
+    let fd = open(link_path, O_RDONLY, mode);
+    assert!(fd >= 0);
+    assert_eq!(read(fd, read_buf.as_mut_ptr(), read_buf.len()), 2);
+    close(fd);
+    assert_eq!(unlink(link_path), 0);
+
+    let dir_path = c"/ram/dir".as_ptr() as *const c_char;
+    assert_eq!(mkdir(dir_path, mode), 0);
+    assert_eq!(rmdir(dir_path), 0);
+
+    assert_eq!(umount(mount_path), 0);
+    assert_eq!(rmdir(mount_path), 0);
+}
+
+// Names of the entries of the directory at `path`.
+fn list_directory(path: &CStr) -> Vec<String> {
+    let fd = open(path.as_ptr(), O_RDONLY, 0o755);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 256];
+    let len = getdents(fd, buf.as_mut_ptr(), buf.len());
+    close(fd);
+    assert!(len >= 0);
+    let mut names = Vec::new();
+    let mut next_entry = 0;
+    while next_entry < len as usize {
+        let entry = unsafe { Dirent::from_buf_ref(&buf[next_entry..]) };
+        names.push(String::from(entry.name().unwrap().to_str().unwrap()));
+        next_entry += entry.reclen() as usize;
+    }
+    names
+}
+
+#[test]
+fn test_tmpfs_mount() {
+    let mode: libc::mode_t = 0o755;
+    let mount_path = c"/tmp_mnt".as_ptr();
+    assert_eq!(mkdir(mount_path, mode), 0);
+    assert_eq!(
+        mount(
+            core::ptr::null(),
+            mount_path,
+            c"tmpfs".as_ptr(),
+            0,
+            core::ptr::null(),
+        ),
+        0
+    );
+
+    let mut buf = [0u8; 16];
+    write_file(c"/tmp_mnt/file.txt", b"scratch");
+    assert_eq!(read_file(c"/tmp_mnt/file.txt", &mut buf), 7);
+    assert_eq!(&buf[..7], b"scratch");
+    assert_eq!(mkdir(c"/tmp_mnt/dir".as_ptr(), mode), 0);
+
+    let mut names = list_directory(c"/tmp_mnt");
+    names.sort();
+    assert_eq!(names, [".", "..", "dir", "file.txt"]);
+
+    assert_eq!(unlink(c"/tmp_mnt/file.txt".as_ptr()), 0);
+    assert_eq!(rmdir(c"/tmp_mnt/dir".as_ptr()), 0);
+    assert_eq!(list_directory(c"/tmp_mnt"), [".", ".."]);
+
+    assert_eq!(umount(mount_path), 0);
+    assert_eq!(rmdir(mount_path), 0);
+}
+
+#[test]
+fn test_pread_pwrite() {
+    let path = c"/pread.txt";
+    write_file(path, b"0123456789");
+    let fd = open(path.as_ptr(), O_RDWR, 0o644);
+    assert!(fd >= 0);
+
+    let mut buf = [0u8; 4];
+    assert_eq!(read(fd, buf.as_mut_ptr(), 2), 2);
+    assert_eq!(&buf[..2], b"01");
+
+    // Positioned I/O leaves the file offset alone
+    assert_eq!(pread(fd, buf.as_mut_ptr(), 4, 6), 4);
+    assert_eq!(&buf, b"6789");
+    assert_eq!(pwrite(fd, b"ab".as_ptr(), 2, 8), 2);
+    assert_eq!(pwrite(fd, b"xy".as_ptr(), 2, 12), 2);
+    assert_eq!(lseek(fd, 0, libc::SEEK_CUR), 2);
+
+    assert_eq!(read(fd, buf.as_mut_ptr(), 2), 2);
+    assert_eq!(&buf[..2], b"23");
+    assert_eq!(write(fd, b"CD".as_ptr(), 2), 2);
+    assert_eq!(lseek(fd, 0, libc::SEEK_CUR), 6);
+
+    // The hole left by pwrite past EOF reads as zeros
+    let mut content = [0xffu8; 16];
+    assert_eq!(pread(fd, content.as_mut_ptr(), content.len(), 0), 14);
+    assert_eq!(&content[..14], b"0123CD67ab\0\0xy");
+    assert_eq!(pread(fd, buf.as_mut_ptr(), buf.len(), 14), 0);
+    assert_eq!(lseek(fd, 0, libc::SEEK_CUR), 6);
+
+    assert_eq!(
+        pread(fd, buf.as_mut_ptr(), buf.len(), -1),
+        -libc::EINVAL as isize
+    );
+    assert_eq!(
+        pwrite(fd, buf.as_ptr(), buf.len(), -1),
+        -libc::EINVAL as isize
+    );
+    close(fd);
+
+    // Not readable through a write only file
+    let fd = open(path.as_ptr(), O_WRONLY, 0o644);
+    assert!(fd >= 0);
+    assert!(pread(fd, buf.as_mut_ptr(), buf.len(), 0) < 0);
+    close(fd);
+    assert_eq!(unlink(path.as_ptr()), 0);
+}
+
+#[test]
+fn test_dup2_redirect_stdout() {
+    let path = c"/dup_stdout.txt";
+    write_file(path, b"");
+    let fd = open(path.as_ptr(), O_WRONLY, 0o644);
+    assert!(fd >= 0);
+
+    let saved = dup(1);
+    assert!(saved >= 0);
+    assert_eq!(dup2(fd, 1), 1);
+    assert_eq!(write(1, b"redirected".as_ptr(), 10), 10);
+    // The offset is shared with the original
+    assert_eq!(lseek(fd, 0, libc::SEEK_CUR), 10);
+    assert_eq!(dup2(saved, 1), 1);
+    assert_eq!(close(saved), 0);
+
+    let copy = dup3(fd, saved, libc::O_CLOEXEC);
+    assert_eq!(copy, saved);
+    assert_eq!(fcntl(copy, libc::F_GETFD, 0), 1);
+    assert_eq!(fcntl(fd, libc::F_GETFD, 0), 0);
+    assert_eq!(write(copy, b"!".as_ptr(), 1), 1);
+    assert_eq!(close(copy), 0);
+    // Still open after closing a duplicate
+    assert_eq!(lseek(fd, 0, libc::SEEK_CUR), 11);
+    assert_eq!(close(fd), 0);
+
+    let mut buf = [0u8; 16];
+    assert_eq!(read_file(path, &mut buf), 11);
+    assert_eq!(&buf[..11], b"redirected!");
+    assert_eq!(unlink(path.as_ptr()), 0);
+}
+
+// Threads started by spawn_counted() bump `done` once they finish,
+// join_counted() waits until `count` of them have.
+fn spawn_counted(done: &'static AtomicUsize, f: impl FnOnce() + 'static) {
+    ThreadBuilder::new(Entry::Closure(Box::new(move || {
+        f();
+        done.fetch_add(1, Ordering::Release);
+        let _ = futex::atomic_wake(done, 1);
+    })))
+    .start();
+}
+
+fn join_counted(done: &AtomicUsize, count: usize) {
+    loop {
+        let finished = done.load(Ordering::Acquire);
+        if finished >= count {
+            break;
+        }
+        let _ = futex::atomic_wait(done, finished, None);
+    }
+}
+
+const PWRITE_ROUNDS: usize = 32;
+const PWRITE_SLOT_LEN: usize = 16;
+static PWRITERS_DONE: AtomicUsize = AtomicUsize::new(0);
+
+// Fill and check the slot of `tag` through the shared fd.
+fn pwrite_slot(fd: c_int, slot: usize, tag: u8) {
+    let offset = (slot * PWRITE_SLOT_LEN) as libc::off_t;
+    let data = [tag; PWRITE_SLOT_LEN];
+    let mut buf = [0u8; PWRITE_SLOT_LEN];
+    for _ in 0..PWRITE_ROUNDS {
+        assert_eq!(
+            pwrite(fd, data.as_ptr(), data.len(), offset),
+            PWRITE_SLOT_LEN as isize
+        );
+        scheduler::yield_me();
+        assert_eq!(
+            pread(fd, buf.as_mut_ptr(), buf.len(), offset),
+            PWRITE_SLOT_LEN as isize
+        );
+        assert_eq!(buf, data);
+    }
+}
+
+#[test]
+fn test_pread_pwrite_threads() {
+    let path = c"/pread_threads.txt";
+    write_file(path, b"");
+    let fd = open(path.as_ptr(), O_RDWR, 0o644);
+    assert!(fd >= 0);
+    assert_eq!(lseek(fd, 3, SEEK_SET), 3);
+
+    PWRITERS_DONE.store(0, Ordering::Release);
+    for (slot, tag) in [(0, b'a'), (1, b'b')] {
+        spawn_counted(&PWRITERS_DONE, move || pwrite_slot(fd, slot, tag));
+    }
+    join_counted(&PWRITERS_DONE, 2);
+
+    // Neither thread moved the shared cursor
+    assert_eq!(lseek(fd, 0, libc::SEEK_CUR), 3);
+    let mut content = [0u8; 2 * PWRITE_SLOT_LEN];
+    assert_eq!(
+        pread(fd, content.as_mut_ptr(), content.len(), 0),
+        content.len() as isize
+    );
+    assert!(content[..PWRITE_SLOT_LEN].iter().all(|&b| b == b'a'));
+    assert!(content[PWRITE_SLOT_LEN..].iter().all(|&b| b == b'b'));
+    close(fd);
+    assert_eq!(unlink(path.as_ptr()), 0);
+}
+
+const PIPE_DATA_LEN: usize = 10 * 1024;
+static PIPE_WRITER_DONE: AtomicUsize = AtomicUsize::new(0);
+
+fn pipe_pattern(i: usize) -> u8 {
+    (i * 7 % 251) as u8
+}
+
+#[test]
+fn test_pipe_threads() {
+    let mut fds = [-1; 2];
+    assert_eq!(pipe(fds.as_mut_ptr()), 0);
+    let [read_fd, write_fd] = fds;
+
+    // More than the pipe holds, so the writer has to wait for the reader
+    PIPE_WRITER_DONE.store(0, Ordering::Release);
+    spawn_counted(&PIPE_WRITER_DONE, move || {
+        let data: Vec<u8> = (0..PIPE_DATA_LEN).map(pipe_pattern).collect();
+        assert_eq!(
+            write(write_fd, data.as_ptr(), data.len()),
+            PIPE_DATA_LEN as isize
+        );
+        assert_eq!(close(write_fd), 0);
+    });
+
+    let mut received = Vec::with_capacity(PIPE_DATA_LEN);
+    let mut chunk = [0u8; 100];
+    loop {
+        let n = read(read_fd, chunk.as_mut_ptr(), chunk.len());
+        assert!(n >= 0);
+        if n == 0 {
+            break;
+        }
+        received.extend_from_slice(&chunk[..n as usize]);
+    }
+    join_counted(&PIPE_WRITER_DONE, 1);
+
+    // EOF only after every byte came through in order
+    assert_eq!(received.len(), PIPE_DATA_LEN);
+    assert!(received
+        .iter()
+        .enumerate()
+        .all(|(i, &b)| b == pipe_pattern(i)));
+    assert_eq!(close(read_fd), 0);
+}
+
+#[test]
+fn test_pipe_no_reader() {
+    let mut fds = [-1; 2];
+    assert_eq!(pipe2(fds.as_mut_ptr(), libc::O_NONBLOCK), 0);
+    let [read_fd, write_fd] = fds;
+
+    let mut buf = [0u8; 4];
+    assert_eq!(
+        read(read_fd, buf.as_mut_ptr(), buf.len()),
+        -libc::EAGAIN as isize
+    );
+    assert_eq!(close(read_fd), 0);
+    assert_eq!(
+        write(write_fd, buf.as_ptr(), buf.len()),
+        -libc::EPIPE as isize
+    );
+    assert_eq!(close(write_fd), 0);
+
+    assert_eq!(pipe2(fds.as_mut_ptr(), libc::O_TRUNC), -libc::EINVAL);
+}
+
+const APPEND_RECORDS: usize = 64;
+const APPEND_RECORD_LEN: usize = 8;
+static APPEND_WRITERS_DONE: AtomicUsize = AtomicUsize::new(0);
+
+fn append_records(path: &CStr, tag: u8) {
+    let fd = open(path.as_ptr(), O_WRONLY | O_APPEND, 0o644);
+    assert!(fd >= 0);
+    let record = [tag; APPEND_RECORD_LEN];
+    for _ in 0..APPEND_RECORDS {
+        assert_eq!(
+            write(fd, record.as_ptr(), record.len()),
+            APPEND_RECORD_LEN as isize
+        );
+        scheduler::yield_me();
+    }
+    close(fd);
+}
+
+// Append from two threads and check every record landed once.
+fn concurrent_append(path: &'static CStr) {
+    write_file(path, b"");
+    APPEND_WRITERS_DONE.store(0, Ordering::Release);
+    for tag in [b'a', b'b'] {
+        spawn_counted(&APPEND_WRITERS_DONE, move || append_records(path, tag));
+    }
+    join_counted(&APPEND_WRITERS_DONE, 2);
+
+    // No record is overwritten or torn
+    let total = 2 * APPEND_RECORDS * APPEND_RECORD_LEN;
+    let mut buf = vec![0u8; total + 1];
+    assert_eq!(read_file(path, &mut buf), total as isize);
+    let mut counts = [0usize; 2];
+    for record in buf[..total].chunks(APPEND_RECORD_LEN) {
+        assert!(record.iter().all(|&b| b == record[0]));
+        counts[(record[0] - b'a') as usize] += 1;
+    }
+    assert_eq!(counts, [APPEND_RECORDS; 2]);
+}
+
+#[test]
+fn test_ramfs_append() {
+    let mode: libc::mode_t = 0o755;
+    let mount_path = c"/append".as_ptr();
+    assert_eq!(mkdir(mount_path, mode), 0);
+    assert_eq!(
+        mount(
+            core::ptr::null(),
+            mount_path,
+            c"ramfs".as_ptr(),
+            0,
+            core::ptr::null(),
+        ),
+        0
+    );
+    write_file(c"/append/log.txt", b"");
+
+    // Offset is ignored by an O_APPEND write
+    let fd = open(c"/append/log.txt".as_ptr(), O_RDWR | O_APPEND, 0o644);
+    assert!(fd >= 0);
+    assert_eq!(write(fd, b"head".as_ptr(), 4), 4);
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    assert_eq!(write(fd, b"tail".as_ptr(), 4), 4);
+    assert_eq!(lseek(fd, 0, libc::SEEK_CUR), 8);
+    assert_eq!(ftruncate(fd, 0), 0);
+    close(fd);
+
+    concurrent_append(c"/append/log.txt");
+
+    assert_eq!(unlink(c"/append/log.txt".as_ptr()), 0);
+    assert_eq!(umount(mount_path), 0);
+    assert_eq!(rmdir(mount_path), 0);
+}
+
+#[cfg(virtio)]
+#[test]
+fn test_fatfs_append() {
+    concurrent_append(c"/fat/log.txt");
+    assert_eq!(unlink(c"/fat/log.txt".as_ptr()), 0);
+}
+
 #[cfg(virtio)]
 #[test]
 fn test_fatfs_mount_unmount() {
@@ -502,6 +1314,36 @@ fn test_fatfs_mount_unmount() {
     close(fd);
 }
 
+#[cfg(virtio)]
+#[test]
+fn test_vfat_remount() {
+    let mount_path = c"/fat".as_ptr();
+    let mount_vfat = || {
+        mount(
+            c"/dev/virt-storage".as_ptr(),
+            mount_path,
+            c"vfat".as_ptr(),
+            0,
+            core::ptr::null(),
+        )
+    };
+    assert_eq!(umount(mount_path), 0);
+    assert_eq!(mount_vfat(), 0);
+
+    let test_data = b"persisted through remount";
+    write_file(c"/fat/vfat.txt", test_data);
+    assert_eq!(umount(mount_path), 0);
+    assert_eq!(mount_vfat(), 0);
+
+    let mut buf = [0u8; 64];
+    assert_eq!(
+        read_file(c"/fat/vfat.txt", &mut buf),
+        test_data.len() as isize
+    );
+    assert_eq!(&buf[..test_data.len()], test_data);
+    assert_eq!(unlink(c"/fat/vfat.txt".as_ptr()), 0);
+}
+
 #[cfg(procfs)]
 #[test]
 fn test_procfs_posix() {
@@ -564,7 +1406,9 @@ fn test_procfs_posix() {
         let name = entry.name().unwrap().to_string_lossy();
         let mut dir_full_path = String::with_capacity(name.len() + 1 + path_str.len());
         write!(dir_full_path, "{}/{}", path_str, name);
-        if entry.type_() == DirentType::Dir && name.as_ref() != "." && name.as_ref() != ".." {
+        // Only thread directories carry a status file.
+        let is_thread_dir = name.chars().all(|c| c.is_ascii_digit());
+        if entry.type_() == DirentType::Dir && is_thread_dir {
             let status_path = format!("{}/status\0", dir_full_path);
             let status_path_str = status_path.as_ptr() as *const c_char;
             let fd = open(status_path_str, O_RDONLY, 0o444);
@@ -585,6 +1429,141 @@ fn test_procfs_posix() {
         next_entry += entry.reclen() as usize;
     }
     close(fd);
+
+    // 4. Test: read /proc/sys/kernel/log_levels
+    let path = c"/proc/sys/kernel/log_levels".as_ptr() as *const c_char;
+    let fd = open(path, O_RDONLY, 0o444);
+    assert!(fd >= 0, "[VFS Test proc posix] Failed to open log_levels");
+    let read_size = read_fd_content("/proc/sys/kernel/log_levels", fd);
+    assert!(
+        read_size > 0,
+        "[VFS Test proc posix] Failed to read log_levels"
+    );
+    close(fd);
+}
+
+#[cfg(all(procfs, allocator = "slab"))]
+#[test]
+fn test_procfs_meminfo_slab() {
+    let fd = open(c"/proc/meminfo".as_ptr() as *const c_char, O_RDONLY, 0o444);
+    assert!(
+        fd >= 0,
+        "[VFS Test proc posix] Failed to open /proc/meminfo"
+    );
+    let mut buf = [0u8; 1024];
+    let size = read(fd, buf.as_mut_ptr(), buf.len());
+    close(fd);
+    assert!(
+        size > 0,
+        "[VFS Test proc posix] Failed to read /proc/meminfo"
+    );
+    let content = String::from_utf8_lossy(&buf[..size as usize]);
+    let field = |name: &str| -> usize {
+        let line = content.lines().find(|l| l.starts_with(name)).unwrap();
+        line[name.len()..].trim().parse().unwrap()
+    };
+    // Each class is made of 2 pages
+    for block_size in [16, 32, 64, 128, 256, 512, 1024] {
+        let free = field(&format!("Slab{}Free:", block_size));
+        let total = field(&format!("Slab{}Total:", block_size));
+        assert_eq!(total, 2 * 4096 / block_size);
+        assert!(free <= total);
+    }
+}
+
+#[cfg(procfs)]
+#[test]
+fn test_procfs_loglevel() {
+    let path = c"/proc/sys/loglevel".as_ptr() as *const c_char;
+    let fd = open(path, O_RDWR, 0o644);
+    assert!(fd >= 0, "[VFS Test proc posix] Failed to open loglevel");
+    let mut origin = [0u8; 16];
+    let origin_len = read(fd, origin.as_mut_ptr(), origin.len());
+    assert!(
+        origin_len > 0,
+        "[VFS Test proc posix] Failed to read loglevel"
+    );
+
+    let level = b"error\n";
+    assert_eq!(write(fd, level.as_ptr(), level.len()), level.len() as isize);
+    lseek(fd, 0, SEEK_SET);
+    let mut buf = [0u8; 16];
+    let len = read(fd, buf.as_mut_ptr(), buf.len());
+    assert_eq!(&buf[..len as usize], level);
+
+    let bad = b"verbose";
+    assert_eq!(write(fd, bad.as_ptr(), bad.len()), -libc::EINVAL as isize);
+
+    // Restore the original level.
+    assert_eq!(write(fd, origin.as_ptr(), origin_len as usize), origin_len);
+    close(fd);
+}
+
+#[cfg(procfs)]
+static NAMED_THREAD_EXIT: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(procfs)]
+#[test]
+fn test_procfs_thread_name() {
+    let t = ThreadBuilder::new(Entry::Closure(Box::new(|| {
+        while NAMED_THREAD_EXIT.load(Ordering::Acquire) == 0 {
+            scheduler::yield_me();
+        }
+    })))
+    .set_name("procfs_named_thread")
+    .start();
+    assert_eq!(t.name(), "procfs_named_thr");
+
+    let path = format!("/proc/{}/status\0", Thread::id(&t));
+    let fd = open(path.as_ptr() as *const c_char, O_RDONLY, 0o444);
+    assert!(fd >= 0, "[VFS Test proc posix] Failed to open {}", path);
+    let mut buf = [0u8; 256];
+    let size = read(fd, buf.as_mut_ptr(), buf.len());
+    close(fd);
+    assert!(size > 0, "[VFS Test proc posix] Failed to read {}", path);
+    let content = String::from_utf8_lossy(&buf[..size as usize]);
+    assert!(content.lines().any(|l| l == "Name:     procfs_named_thr"));
+    NAMED_THREAD_EXIT.store(1, Ordering::Release);
+}
+
+#[cfg(procfs)]
+static STACK_THREAD_EXIT: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(procfs)]
+#[test]
+fn test_procfs_thread_stack() {
+    let t = ThreadBuilder::new(Entry::Closure(Box::new(|| {
+        while STACK_THREAD_EXIT.load(Ordering::Acquire) == 0 {
+            scheduler::yield_me();
+        }
+    })))
+    .start();
+
+    let path = format!("/proc/{}/stack\0", Thread::id(&t));
+    let fd = open(path.as_ptr() as *const c_char, O_RDONLY, 0o444);
+    assert!(fd >= 0, "[VFS Test proc posix] Failed to open {}", path);
+    let mut buf = [0u8; 1024];
+    let size = read(fd, buf.as_mut_ptr(), buf.len());
+    close(fd);
+    assert!(size > 0, "[VFS Test proc posix] Failed to read {}", path);
+    let content = String::from_utf8_lossy(&buf[..size as usize]);
+    let field = |name: &str| -> usize {
+        let line = content.lines().find(|l| l.starts_with(name)).unwrap();
+        let value = line[name.len()..].trim();
+        match value.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16).unwrap(),
+            None => value.parse().unwrap(),
+        }
+    };
+    let (base, size) = (field("Base:"), field("Size:"));
+    assert_eq!(base, t.stack_base());
+    assert_eq!(size, t.stack_size());
+    let saved_sp = field("SavedSP:");
+    assert!(saved_sp > base && saved_sp <= base + size);
+    let (usage, peak) = (field("Usage:"), field("Peak:"));
+    assert_eq!(usage, base + size - saved_sp);
+    assert!(usage <= peak && peak <= size);
+    STACK_THREAD_EXIT.store(1, Ordering::Release);
 }
 
 fn read_fd_content(path_str: &str, fd: i32) -> usize {
@@ -679,8 +1658,19 @@ fn tcp_socket_file_thread() {
     );
     println!("Data verified successfully (server -> client)");
 
+    // === test 3: the connection outlives the original fd of a dup ===
+    let dup_fd = dup(client_fd);
+    assert!(dup_fd >= 0, "Client dup failed");
+    assert_eq!(close(client_fd), 0);
+    let write_size = write(dup_fd, test_data.as_ptr(), test_data.len());
+    assert_eq!(write_size, test_data.len() as isize, "Dup send failed");
+    let read_size = read(server_fd, read_buf.as_mut_ptr(), read_buf.len());
+    assert_eq!(read_size, test_data.len() as isize, "Server read failed");
+    assert_eq!(&read_buf[..test_data.len()], test_data);
+    println!("Data verified successfully (dup -> server)");
+
     close(server_fd);
-    close(client_fd);
+    close(dup_fd);
 }
 
 fn create_connected_sockets() -> (i32, i32) {
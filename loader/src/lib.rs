@@ -15,52 +15,184 @@
 #![no_std]
 #![feature(c_size_t)]
 
+extern crate alloc;
+
 mod memory_mapper;
-use goblin::elf::Elf;
-use librs::string::memcpy;
-pub use memory_mapper::MemoryMapper;
+use alloc::vec::Vec;
+use blueos::{
+    thread::{self, ThreadNode},
+    vfs::syscalls as vfs_syscalls,
+};
+use core::ffi::c_int;
+use goblin::{
+    container::Ctx,
+    elf::{
+        dynamic::{DT_NULL, DT_REL, DT_RELA, DT_RELASZ, DT_RELSZ},
+        header::{self, Header},
+        program_header::{self, ProgramHeader, PT_DYNAMIC, PT_LOAD},
+        reloc, Elf,
+    },
+};
+use librs::string::{memcpy, memset};
+pub use memory_mapper::{MemoryMapper, SegmentPerms};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    // The buffer is not a well-formed ELF file.
+    ParseFailed,
+    // A segment or relocation lies outside of the file or the image.
+    SegmentOutOfBounds,
+    // The ELF type, class, machine or relocation type is not supported.
+    UnsupportedType,
+    AllocFailed,
+    // The entry point is not inside any loadable segment.
+    BadEntry,
+    // Reading the ELF file failed.
+    ReadFailed,
+}
 
-pub type Result = core::result::Result<(), &'static str>;
+impl core::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let msg = match self {
+            Self::ParseFailed => "unable to parse the ELF file",
+            Self::SegmentOutOfBounds => "segment out of bounds",
+            Self::UnsupportedType => "unsupported ELF type",
+            Self::AllocFailed => "unable to allocate memory for segments",
+            Self::BadEntry => "entry point out of loadable segments",
+            Self::ReadFailed => "unable to read the ELF file",
+        };
+        f.write_str(msg)
+    }
+}
+
+impl LoadError {
+    /// The errno a libc wrapper reports the error as.
+    pub fn to_errno(self) -> c_int {
+        match self {
+            Self::AllocFailed => libc::ENOMEM,
+            Self::ReadFailed => libc::EIO,
+            _ => libc::ENOEXEC,
+        }
+    }
+}
 
-fn build_memory_layout(binary: &Elf, mapper: &mut MemoryMapper) -> Result {
-    for ph in &binary.program_headers {
+pub type Result = core::result::Result<(), LoadError>;
+
+#[cfg(target_arch = "aarch64")]
+const NATIVE_MACHINE: u16 = header::EM_AARCH64;
+#[cfg(target_arch = "arm")]
+const NATIVE_MACHINE: u16 = header::EM_ARM;
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+const NATIVE_MACHINE: u16 = header::EM_RISCV;
+#[cfg(target_arch = "x86_64")]
+const NATIVE_MACHINE: u16 = header::EM_X86_64;
+
+#[cfg(target_pointer_width = "64")]
+const NATIVE_CLASS: u8 = header::ELFCLASS64;
+#[cfg(target_pointer_width = "32")]
+const NATIVE_CLASS: u8 = header::ELFCLASS32;
+#[cfg(target_pointer_width = "64")]
+const NATIVE_PHENTSIZE: usize = program_header::program_header64::SIZEOF_PHDR;
+#[cfg(target_pointer_width = "32")]
+const NATIVE_PHENTSIZE: usize = program_header::program_header32::SIZEOF_PHDR;
+
+fn build_memory_layout(
+    header: &Header,
+    program_headers: &[ProgramHeader],
+    mapper: &mut MemoryMapper,
+) -> Result {
+    match header.e_type {
+        header::ET_EXEC | header::ET_DYN => {}
+        _ => return Err(LoadError::UnsupportedType),
+    }
+    if header.e_machine != NATIVE_MACHINE || header.e_ident[header::EI_CLASS] != NATIVE_CLASS {
+        return Err(LoadError::UnsupportedType);
+    }
+    for ph in program_headers {
         match ph.p_type {
-            goblin::elf::program_header::PT_LOAD => {
-                // We're assuming loadable segments are compact.
+            PT_LOAD => {
+                if ph.p_filesz > ph.p_memsz {
+                    return Err(LoadError::SegmentOutOfBounds);
+                }
+                let end = ph
+                    .p_vaddr
+                    .checked_add(ph.p_memsz)
+                    .and_then(|end| usize::try_from(end).ok())
+                    .ok_or(LoadError::SegmentOutOfBounds)?;
+                // The image spans from the lowest segment start to the
+                // highest segment end, gaps in between are left zeroed.
                 mapper
                     .update_start(ph.p_vaddr as usize)
-                    .update_end((ph.p_vaddr + ph.p_memsz) as usize);
+                    .update_end(end)
+                    .set_segment_perms(ph.p_vaddr as usize, ph.p_memsz as usize, ph.p_flags);
             }
             _ => continue,
         }
     }
-    mapper.set_entry(binary.entry as usize);
+    if mapper.start() >= mapper.end() {
+        return Err(LoadError::SegmentOutOfBounds);
+    }
+    let entry = header.e_entry as usize;
+    if mapper.segment_perms(entry).is_none() {
+        return Err(LoadError::BadEntry);
+    }
+    mapper.set_entry(entry);
     Ok(())
 }
 
-fn allocate_memory_for_segments(_binary: &Elf, mapper: &mut MemoryMapper) -> Result {
-    mapper.allocate_memory();
+fn allocate_memory_for_segments(mapper: &mut MemoryMapper) -> Result {
+    mapper.allocate_memory().ok_or(LoadError::AllocFailed)?;
     Ok(())
 }
 
-fn copy_content_to_memory(buffer: &[u8], binary: &Elf, mapper: &mut MemoryMapper) -> Result {
-    // FIXME: We are assuming if filesize < memsize, (memsize -
-    // filesize) bits are .bss. I need to read more about ELF spec to
-    // find out exceptions. Currently, it just works.
+// Where the segment's file content goes in the image. Layout is
+// already checked in build_memory_layout.
+fn segment_memory<'a>(ph: &ProgramHeader, mapper: &'a mut MemoryMapper) -> &'a mut [u8] {
+    let base = mapper.real_start_mut().unwrap();
+    unsafe {
+        core::slice::from_raw_parts_mut(
+            base.add(ph.p_vaddr as usize - mapper.start()),
+            ph.p_filesz as usize,
+        )
+    }
+}
+
+// Memory past the file content of a segment, up to p_memsz, is its
+// .bss and reads as zero.
+fn zero_segment_tail(ph: &ProgramHeader, mapper: &mut MemoryMapper) {
     let base = mapper.real_start_mut().unwrap();
-    for ph in &binary.program_headers {
+    unsafe {
+        memset(
+            base.add((ph.p_vaddr + ph.p_filesz) as usize - mapper.start())
+                as *mut core::ffi::c_void,
+            0,
+            (ph.p_memsz - ph.p_filesz) as core::ffi::c_size_t,
+        )
+    };
+}
+
+fn copy_content_to_memory(
+    buffer: &[u8],
+    program_headers: &[ProgramHeader],
+    mapper: &mut MemoryMapper,
+) -> Result {
+    for ph in program_headers {
         match ph.p_type {
-            goblin::elf::program_header::PT_LOAD => {
-                let src =
-                    buffer[ph.p_offset as usize..(ph.p_offset + ph.p_filesz) as usize].as_ptr();
-                let dst = unsafe { base.add(ph.p_vaddr as usize - mapper.start()) };
+            PT_LOAD => {
+                let src = usize::try_from(ph.p_offset)
+                    .ok()
+                    .zip(usize::try_from(ph.p_filesz).ok())
+                    .and_then(|(offset, size)| buffer.get(offset..offset.checked_add(size)?))
+                    .ok_or(LoadError::SegmentOutOfBounds)?;
+                let dst = segment_memory(ph, mapper);
                 unsafe {
                     memcpy(
-                        dst as *mut core::ffi::c_void,
-                        src as *const core::ffi::c_void,
-                        ph.p_filesz as core::ffi::c_size_t,
+                        dst.as_mut_ptr() as *mut core::ffi::c_void,
+                        src.as_ptr() as *const core::ffi::c_void,
+                        src.len() as core::ffi::c_size_t,
                     )
                 };
+                zero_segment_tail(ph, mapper);
             }
             _ => continue,
         }
@@ -68,12 +200,215 @@ fn copy_content_to_memory(buffer: &[u8], binary: &Elf, mapper: &mut MemoryMapper
     Ok(())
 }
 
-// FIXME: We should use lseek to parse ELF files to achieve low footprint.
+fn relative_reloc_type(machine: u16) -> Option<usize> {
+    let ty = match machine {
+        header::EM_X86_64 => reloc::R_X86_64_RELATIVE,
+        header::EM_AARCH64 => reloc::R_AARCH64_RELATIVE,
+        header::EM_ARM => reloc::R_ARM_RELATIVE,
+        header::EM_RISCV => reloc::R_RISCV_RELATIVE,
+        _ => return None,
+    };
+    Some(ty as usize)
+}
+
+const WORD: usize = core::mem::size_of::<usize>();
+
+// Pointer to the word at `vaddr` in the image.
+fn image_word(mapper: &MemoryMapper, vaddr: usize) -> core::result::Result<*mut usize, LoadError> {
+    let offset = vaddr.wrapping_sub(mapper.start());
+    match offset.checked_add(WORD) {
+        Some(end) if end <= mapper.total_size() => {}
+        _ => return Err(LoadError::SegmentOutOfBounds),
+    }
+    Ok(unsafe { mapper.real_start_mut().unwrap().add(offset) as *mut usize })
+}
+
+fn read_image_word(mapper: &MemoryMapper, vaddr: usize) -> core::result::Result<usize, LoadError> {
+    Ok(unsafe { image_word(mapper, vaddr)?.read_unaligned() })
+}
+
+// End of the `size` bytes at `vaddr`, which must lie in the image.
+fn image_range_end(
+    mapper: &MemoryMapper,
+    vaddr: usize,
+    size: usize,
+) -> core::result::Result<usize, LoadError> {
+    match vaddr.checked_add(size) {
+        Some(end) if vaddr >= mapper.start() && end <= mapper.end() => Ok(end),
+        _ => Err(LoadError::SegmentOutOfBounds),
+    }
+}
+
+// Position independent executables carry R_*_RELATIVE relocations in
+// PT_DYNAMIC, which are fixed up by the difference between the address
+// the image is linked at and the address it is loaded at. The dynamic
+// section and relocation tables are read from the loaded image, since
+// they are part of loadable segments. We only load images of the
+// native word size, so their entries are made of native words.
+fn apply_relocations(
+    header: &Header,
+    program_headers: &[ProgramHeader],
+    mapper: &mut MemoryMapper,
+) -> Result {
+    let Some(dynamic) = program_headers.iter().find(|ph| ph.p_type == PT_DYNAMIC) else {
+        return Ok(());
+    };
+    let (mut rela, mut relasz, mut rel, mut relsz) = (0, 0, 0, 0);
+    let mut vaddr = usize::try_from(dynamic.p_vaddr).map_err(|_| LoadError::SegmentOutOfBounds)?;
+    let memsz = usize::try_from(dynamic.p_memsz).map_err(|_| LoadError::SegmentOutOfBounds)?;
+    let dynamic_end = image_range_end(mapper, vaddr, memsz)?;
+    // Entries are (d_tag, d_val), the section ends with DT_NULL.
+    while dynamic_end - vaddr >= 2 * WORD {
+        let tag = read_image_word(mapper, vaddr)? as u64;
+        let val = read_image_word(mapper, vaddr + WORD)?;
+        match tag {
+            DT_NULL => break,
+            DT_RELA => rela = val,
+            DT_RELASZ => relasz = val,
+            DT_REL => rel = val,
+            DT_RELSZ => relsz = val,
+            _ => {}
+        }
+        vaddr += 2 * WORD;
+    }
+    if relasz == 0 && relsz == 0 {
+        return Ok(());
+    }
+    let Some(relative) = relative_reloc_type(header.e_machine) else {
+        return Err(LoadError::UnsupportedType);
+    };
+    // Entries are then at most at the end of the tables, nothing below
+    // overflows.
+    if relasz != 0 {
+        image_range_end(mapper, rela, relasz)?;
+    }
+    if relsz != 0 {
+        image_range_end(mapper, rel, relsz)?;
+    }
+    let bias = (mapper.real_start().unwrap() as usize).wrapping_sub(mapper.start());
+    // Low bits of r_info hold the relocation type.
+    let type_mask = if WORD == 8 { 0xffff_ffff } else { 0xff };
+    // RELA entries are (r_offset, r_info, r_addend), REL ones keep the
+    // addend in the place being relocated.
+    let relas = (0..relasz / (3 * WORD)).map(|i| (rela + i * 3 * WORD, true));
+    let rels = (0..relsz / (2 * WORD)).map(|i| (rel + i * 2 * WORD, false));
+    for (entry, is_rela) in relas.chain(rels) {
+        let r_offset = read_image_word(mapper, entry)?;
+        if read_image_word(mapper, entry + WORD)? & type_mask != relative {
+            return Err(LoadError::UnsupportedType);
+        }
+        let dst = image_word(mapper, r_offset)?;
+        let addend = if is_rela {
+            read_image_word(mapper, entry + 2 * WORD)?
+        } else {
+            unsafe { dst.read_unaligned() }
+        };
+        unsafe { dst.write_unaligned(addend.wrapping_add(bias)) };
+    }
+    Ok(())
+}
+
 pub fn load_elf(buffer: &[u8], mapper: &mut MemoryMapper) -> Result {
-    let Ok(binary) = goblin::elf::Elf::parse(buffer) else {
-        return Err("Unable to parse the buffer");
+    let Ok(binary) = Elf::parse(buffer) else {
+        return Err(LoadError::ParseFailed);
     };
-    build_memory_layout(&binary, mapper)?;
-    allocate_memory_for_segments(&binary, mapper)?;
-    copy_content_to_memory(buffer, &binary, mapper)
+    build_memory_layout(&binary.header, &binary.program_headers, mapper)?;
+    allocate_memory_for_segments(mapper)?;
+    copy_content_to_memory(buffer, &binary.program_headers, mapper)?;
+    apply_relocations(&binary.header, &binary.program_headers, mapper)
+}
+
+// Fill `buf` with the file content at `offset`, returns the number of
+// bytes read, which is less than `buf.len()` only at the end of file.
+fn read_at(fd: c_int, offset: u64, buf: &mut [u8]) -> core::result::Result<usize, LoadError> {
+    let offset = i64::try_from(offset).map_err(|_| LoadError::SegmentOutOfBounds)?;
+    if vfs_syscalls::lseek(fd, offset, libc::SEEK_SET) != offset {
+        return Err(LoadError::ReadFailed);
+    }
+    let mut total = 0;
+    while total < buf.len() {
+        let n = vfs_syscalls::read(fd, buf[total..].as_mut_ptr(), buf.len() - total);
+        if n < 0 {
+            return Err(LoadError::ReadFailed);
+        }
+        if n == 0 {
+            break;
+        }
+        total += n as usize;
+    }
+    Ok(total)
+}
+
+/// Load the ELF file opened as `fd`. Only the ELF header and program
+/// headers are buffered, segments are read from the file right into
+/// the image.
+pub fn load_elf_from_fd(fd: c_int, mapper: &mut MemoryMapper) -> Result {
+    let mut buf = [0u8; header::header64::SIZEOF_EHDR];
+    let len = read_at(fd, 0, &mut buf)?;
+    let header = Elf::parse_header(&buf[..len]).map_err(|_| LoadError::ParseFailed)?;
+    // The dynamic section is read as native words.
+    if header.e_ident[header::EI_CLASS] != NATIVE_CLASS {
+        return Err(LoadError::UnsupportedType);
+    }
+    let container = header.container().map_err(|_| LoadError::ParseFailed)?;
+    let endian = header.endianness().map_err(|_| LoadError::ParseFailed)?;
+    let ctx = Ctx::new(container, endian);
+
+    // The table size comes from the file, don't let it abort the kernel.
+    if header.e_phentsize as usize != NATIVE_PHENTSIZE {
+        return Err(LoadError::ParseFailed);
+    }
+    let phnum = header.e_phnum as usize;
+    let size = phnum
+        .checked_mul(NATIVE_PHENTSIZE)
+        .ok_or(LoadError::ParseFailed)?;
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(size)
+        .map_err(|_| LoadError::AllocFailed)?;
+    buf.resize(size, 0u8);
+    if read_at(fd, header.e_phoff, &mut buf)? != buf.len() {
+        return Err(LoadError::ParseFailed);
+    }
+    let program_headers =
+        ProgramHeader::parse(&buf, 0, phnum, ctx).map_err(|_| LoadError::ParseFailed)?;
+    drop(buf);
+
+    build_memory_layout(&header, &program_headers, mapper)?;
+    allocate_memory_for_segments(mapper)?;
+    for ph in program_headers.iter().filter(|ph| ph.p_type == PT_LOAD) {
+        let dst = segment_memory(ph, mapper);
+        if read_at(fd, ph.p_offset, dst)? != dst.len() {
+            return Err(LoadError::SegmentOutOfBounds);
+        }
+        zero_segment_tail(ph, mapper);
+    }
+    apply_relocations(&header, &program_headers, mapper)
+}
+
+// Run the loaded image's entry point in a new thread, which owns the
+// image and frees it once the entry returns.
+fn spawn_image(mapper: MemoryMapper) -> core::result::Result<ThreadNode, LoadError> {
+    let entry = mapper.real_entry().ok_or(LoadError::BadEntry)?;
+    let entry = unsafe { core::mem::transmute::<*const u8, extern "C" fn()>(entry) };
+    thread::spawn(move || {
+        entry();
+        drop(mapper);
+    })
+    .ok_or(LoadError::AllocFailed)
+}
+
+/// Load `buffer` and run it in a new thread, like posix_spawn. There is
+/// no process isolation: the image shares the address space, the heap
+/// and the file descriptors with everything else.
+pub fn spawn_elf(buffer: &[u8]) -> core::result::Result<ThreadNode, LoadError> {
+    let mut mapper = MemoryMapper::new();
+    load_elf(buffer, &mut mapper)?;
+    spawn_image(mapper)
+}
+
+/// Same as `spawn_elf`, but the ELF file is read from `fd`.
+pub fn spawn_elf_from_fd(fd: c_int) -> core::result::Result<ThreadNode, LoadError> {
+    let mut mapper = MemoryMapper::new();
+    load_elf_from_fd(fd, &mut mapper)?;
+    spawn_image(mapper)
 }
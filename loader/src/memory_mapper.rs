@@ -13,7 +13,39 @@
 // limitations under the License.
 
 extern crate alloc;
-use alloc::{sync::Arc, vec};
+use alloc::{sync::Arc, vec::Vec};
+use goblin::elf::program_header::{PF_R, PF_W, PF_X};
+
+// Permissions of a loaded segment, in the image's link address space.
+// `flags` takes the ELF p_flags bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentPerms {
+    pub start: usize,
+    pub size: usize,
+    pub flags: u32,
+}
+
+impl SegmentPerms {
+    #[inline]
+    pub fn is_readable(&self) -> bool {
+        self.flags & PF_R != 0
+    }
+
+    #[inline]
+    pub fn is_writable(&self) -> bool {
+        self.flags & PF_W != 0
+    }
+
+    #[inline]
+    pub fn is_executable(&self) -> bool {
+        self.flags & PF_X != 0
+    }
+
+    #[inline]
+    pub fn contains(&self, addr: usize) -> bool {
+        addr >= self.start && addr - self.start < self.size
+    }
+}
 
 #[derive(Debug)]
 pub struct MemoryMapper {
@@ -22,6 +54,7 @@ pub struct MemoryMapper {
     end: usize,
     mem: Option<Arc<[u8]>>,
     align: usize,
+    segments: Vec<SegmentPerms>,
 }
 
 impl MemoryMapper {
@@ -36,6 +69,7 @@ impl MemoryMapper {
             align: 4096,
             #[cfg(not(target_arch = "aarch64"))]
             align: core::mem::size_of::<usize>(),
+            segments: Vec::new(),
         }
     }
 
@@ -77,12 +111,22 @@ impl MemoryMapper {
     }
 
     #[inline]
-    pub fn allocate_memory(&mut self) -> Arc<[u8]> {
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    // Returns None if the image is too large to be allocated.
+    #[inline]
+    pub fn allocate_memory(&mut self) -> Option<Arc<[u8]>> {
         // FIXME: We are not using paging yet, so alignment(usually
         // 4096) specified in program header is not applied here.
-        let mem: Arc<[u8]> = Arc::from(vec![0u8; self.total_size() + self.align]);
+        let size = self.total_size().checked_add(self.align)?;
+        let mut buf = Vec::new();
+        buf.try_reserve_exact(size).ok()?;
+        buf.resize(size, 0u8);
+        let mem: Arc<[u8]> = Arc::from(buf);
         self.mem = Some(mem.clone());
-        mem
+        Some(mem)
     }
 
     #[inline]
@@ -113,4 +157,20 @@ impl MemoryMapper {
     pub fn memory(&self) -> Option<Arc<[u8]>> {
         self.mem.clone()
     }
+
+    // Record permissions of [start, start + size). They are only kept for
+    // lookups, nothing enforces them on the loaded image yet.
+    pub fn set_segment_perms(&mut self, start: usize, size: usize, flags: u32) -> &mut Self {
+        self.segments.push(SegmentPerms { start, size, flags });
+        self
+    }
+
+    #[inline]
+    pub fn segments(&self) -> &[SegmentPerms] {
+        &self.segments
+    }
+
+    pub fn segment_perms(&self, addr: usize) -> Option<&SegmentPerms> {
+        self.segments.iter().find(|s| s.contains(addr))
+    }
 }
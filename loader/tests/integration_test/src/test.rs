@@ -13,6 +13,7 @@
 
 extern crate alloc;
 extern crate rsrt;
+use blueos::vfs::syscalls as vfs_syscalls;
 // Import it just for the global allocator.
 use blueos_loader as loader;
 use libc::{c_char, pthread_t};
@@ -51,10 +52,395 @@ mod test_everyting {
         f();
     }
 
-    // FIXME: We should use FS's lseek API to get lower footprint.
-    // TODO: Use semihosting's seek API to parse the ELF file.
+    // Copy the ELF file to tmpfs chunk by chunk, then load it from the
+    // fd, so the whole file is never buffered.
     #[test]
-    fn test_seek_and_parse_elf() {}
+    fn test_seek_and_parse_elf() {
+        let path =
+            unsafe { core::ffi::CStr::from_ptr(EVERYTHING_ELF_PATH as *const core::ffi::c_char) };
+        let mut f = semihosting::fs::File::open(path).unwrap();
+        let tmp_path = c"/no_std_app.elf";
+        let fd = vfs_syscalls::open(tmp_path.as_ptr(), libc::O_CREAT | libc::O_RDWR, 0o755);
+        assert!(fd >= 0);
+        let mut tmp = [0u8; 256];
+        loop {
+            let size = f.read(&mut tmp).unwrap();
+            if size == 0 {
+                break;
+            }
+            assert_eq!(vfs_syscalls::write(fd, tmp.as_ptr(), size), size as isize);
+        }
+        let mut mapper = loader::MemoryMapper::new();
+        loader::load_elf_from_fd(fd, &mut mapper).unwrap();
+        vfs_syscalls::close(fd);
+        vfs_syscalls::unlink(tmp_path.as_ptr());
+        let f =
+            unsafe { core::mem::transmute::<*const u8, fn() -> ()>(mapper.real_entry().unwrap()) };
+        f();
+    }
+
+    // Runs the ELF file like test_load_elf_and_run, which is too large
+    // in debug mode.
+    #[cfg(not(debug_assertions))]
+    #[test]
+    fn test_spawn_elf() {
+        use blueos::{scheduler, thread};
+
+        let path =
+            unsafe { core::ffi::CStr::from_ptr(EVERYTHING_ELF_PATH as *const core::ffi::c_char) };
+        let mut f = semihosting::fs::File::open(path).unwrap();
+        let tmp_path = c"/spawn_app.elf";
+        let fd = vfs_syscalls::open(tmp_path.as_ptr(), libc::O_CREAT | libc::O_RDWR, 0o755);
+        assert!(fd >= 0);
+        let mut tmp = [0u8; 256];
+        loop {
+            let size = f.read(&mut tmp).unwrap();
+            if size == 0 {
+                break;
+            }
+            assert_eq!(vfs_syscalls::write(fd, tmp.as_ptr(), size), size as isize);
+        }
+        let t = loader::spawn_elf_from_fd(fd).unwrap();
+        vfs_syscalls::close(fd);
+        vfs_syscalls::unlink(tmp_path.as_ptr());
+        while t.state() != thread::RETIRED {
+            scheduler::yield_me();
+        }
+    }
+
+    #[test]
+    fn test_spawn_elf_error_to_errno() {
+        let err = loader::spawn_elf(b"not an elf").unwrap_err();
+        assert_eq!(err, loader::LoadError::ParseFailed);
+        assert_eq!(err.to_errno(), libc::ENOEXEC);
+    }
+}
+
+mod test_relocation {
+    use super::*;
+    use alloc::vec::Vec;
+    use blueos_test_macro::test;
+
+    #[cfg(target_arch = "aarch64")]
+    const MACHINE: (u16, u32) = (183, 1027);
+    #[cfg(target_arch = "arm")]
+    const MACHINE: (u16, u32) = (40, 23);
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+    const MACHINE: (u16, u32) = (243, 3);
+    #[cfg(target_arch = "x86_64")]
+    const MACHINE: (u16, u32) = (62, 8);
+
+    // Layout of the tiny PIE image, linked at address 0.
+    const PHOFF: usize = 0x40;
+    const DYNAMIC: usize = 0x100;
+    const FUNC: usize = 0x140;
+    const SLOT: usize = 0x160;
+    const RELOCS: usize = 0x180;
+    const IMAGE_SIZE: usize = 0x200;
+
+    fn put(image: &mut [u8], offset: usize, bytes: &[u8]) {
+        image[offset..offset + bytes.len()].copy_from_slice(bytes);
+    }
+
+    // Native sized word, ELF32 on 32-bit targets and ELF64 on 64-bit ones.
+    fn put_word(image: &mut [u8], offset: usize, val: usize) {
+        put(image, offset, &val.to_le_bytes());
+    }
+
+    fn put_phdr(image: &mut [u8], offset: usize, p_type: u32, start: usize, size: usize) {
+        put_segment(image, offset, p_type, start, start, size, size);
+    }
+
+    // Program header of a segment whose file content at `file_offset` is
+    // loaded at `vaddr`.
+    fn put_segment(
+        image: &mut [u8],
+        offset: usize,
+        p_type: u32,
+        file_offset: usize,
+        vaddr: usize,
+        filesz: usize,
+        memsz: usize,
+    ) {
+        let w = core::mem::size_of::<usize>();
+        put(image, offset, &p_type.to_le_bytes());
+        let flags = 7u32.to_le_bytes();
+        let fields = [file_offset, vaddr, vaddr, filesz, memsz];
+        if w == 8 {
+            put(image, offset + 4, &flags);
+            for (i, val) in fields.into_iter().chain([8]).enumerate() {
+                put_word(image, offset + 8 + i * w, val);
+            }
+        } else {
+            for (i, val) in fields.into_iter().enumerate() {
+                put_word(image, offset + 4 + i * w, val);
+            }
+            put(image, offset + 24, &flags);
+            put_word(image, offset + 28, 4);
+        }
+    }
+
+    // ELF header of an ET_DYN image with `phnum` program headers at PHOFF.
+    fn put_ehdr(image: &mut [u8], entry: usize, phnum: u16) -> usize {
+        let w = core::mem::size_of::<usize>();
+        put(image, 0, &[0x7f, b'E', b'L', b'F', w as u8 / 4, 1, 1]);
+        put(image, 16, &3u16.to_le_bytes());
+        put(image, 18, &MACHINE.0.to_le_bytes());
+        put(image, 20, &1u32.to_le_bytes());
+        put_word(image, 24, entry);
+        put_word(image, 24 + w, PHOFF);
+        let (ehsize, phentsize) = if w == 8 { (64u16, 56u16) } else { (52, 32) };
+        put(image, 28 + 3 * w, &ehsize.to_le_bytes());
+        put(image, 30 + 3 * w, &phentsize.to_le_bytes());
+        put(image, 32 + 3 * w, &phnum.to_le_bytes());
+        phentsize as usize
+    }
+
+    // Build an ET_DYN image with a single R_*_RELATIVE relocation which
+    // makes SLOT point to FUNC. 64-bit targets use DT_RELA, 32-bit ones
+    // use DT_REL with the addend stored in SLOT.
+    fn build_pie() -> Vec<u8> {
+        let w = core::mem::size_of::<usize>();
+        let is_64 = w == 8;
+        let mut image = alloc::vec![0u8; IMAGE_SIZE];
+        let phentsize = put_ehdr(&mut image, FUNC, 2);
+        put_phdr(&mut image, PHOFF, 1, 0, IMAGE_SIZE);
+        put_phdr(&mut image, PHOFF + phentsize, 2, DYNAMIC, 8 * w);
+        let (tag, tag_size, tag_ent, reloc_size) = if is_64 {
+            (7, 8, 9, 3 * w)
+        } else {
+            (17, 18, 19, 2 * w)
+        };
+        for (i, val) in [tag, RELOCS, tag_size, reloc_size, tag_ent, reloc_size]
+            .into_iter()
+            .enumerate()
+        {
+            put_word(&mut image, DYNAMIC + i * w, val);
+        }
+        put_word(&mut image, RELOCS, SLOT);
+        put_word(&mut image, RELOCS + w, MACHINE.1 as usize);
+        if is_64 {
+            put_word(&mut image, RELOCS + 2 * w, FUNC);
+        } else {
+            put_word(&mut image, SLOT, FUNC);
+        }
+        image
+    }
+
+    #[test]
+    fn test_load_pie_relative_relocation() {
+        let image = build_pie();
+        let mut mapper = loader::MemoryMapper::new();
+        loader::load_elf(image.as_slice(), &mut mapper).unwrap();
+        let base = mapper.real_start().unwrap();
+        let slot = unsafe { (base.add(SLOT) as *const usize).read_unaligned() };
+        assert_eq!(slot, base as usize + FUNC);
+        assert_eq!(mapper.real_entry().unwrap() as usize, slot);
+    }
+
+    #[test]
+    fn test_load_segment_perms() {
+        let mut image = build_pie();
+        // Make the PT_LOAD segment R+X.
+        let flags = if core::mem::size_of::<usize>() == 8 {
+            PHOFF + 4
+        } else {
+            PHOFF + 24
+        };
+        put(&mut image, flags, &5u32.to_le_bytes());
+        let mut mapper = loader::MemoryMapper::new();
+        loader::load_elf(image.as_slice(), &mut mapper).unwrap();
+        assert_eq!(mapper.segments().len(), 1);
+        let perms = mapper.segment_perms(FUNC).unwrap();
+        assert_eq!((perms.start, perms.size), (0, IMAGE_SIZE));
+        assert!(perms.is_readable());
+        assert!(!perms.is_writable());
+        assert!(perms.is_executable());
+        assert!(mapper.segment_perms(IMAGE_SIZE).is_none());
+    }
+
+    // The second segment is loaded at 0x300, leaving a gap after the
+    // first one, and has 0x40 bytes of .bss past its file content.
+    fn build_sparse(entry: usize) -> Vec<u8> {
+        let mut image = alloc::vec![0u8; IMAGE_SIZE];
+        let phentsize = put_ehdr(&mut image, entry, 2);
+        put_segment(&mut image, PHOFF, 1, 0, 0, 0x180, 0x180);
+        put_segment(&mut image, PHOFF + phentsize, 1, 0x180, 0x300, 0x40, 0x80);
+        image[0x180..0x1c0].fill(0xaa);
+        // Not part of any segment, must not leak into the .bss.
+        image[0x1c0..].fill(0x55);
+        image
+    }
+
+    // Write `image` to tmpfs and load it from there.
+    fn load_from_fd(image: &[u8], mapper: &mut loader::MemoryMapper) -> loader::Result {
+        let path = c"/loader_image.elf";
+        let fd = vfs_syscalls::open(
+            path.as_ptr(),
+            libc::O_CREAT | libc::O_RDWR | libc::O_TRUNC,
+            0o644,
+        );
+        assert!(fd >= 0);
+        assert_eq!(
+            vfs_syscalls::write(fd, image.as_ptr(), image.len()),
+            image.len() as isize
+        );
+        let res = loader::load_elf_from_fd(fd, mapper);
+        vfs_syscalls::close(fd);
+        vfs_syscalls::unlink(path.as_ptr());
+        res
+    }
+
+    fn image_memory(mapper: &loader::MemoryMapper) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(mapper.real_start().unwrap(), mapper.total_size()) }
+    }
+
+    #[test]
+    fn test_load_sparse_segments() {
+        let image = build_sparse(FUNC);
+        let mut mapper = loader::MemoryMapper::new();
+        loader::load_elf(image.as_slice(), &mut mapper).unwrap();
+        assert_eq!((mapper.start(), mapper.end()), (0, 0x380));
+        let mem = image_memory(&mapper);
+        assert_eq!(&mem[..0x180], &image[..0x180]);
+        assert!(mem[0x180..0x300].iter().all(|&b| b == 0));
+        assert!(mem[0x300..0x340].iter().all(|&b| b == 0xaa));
+        assert!(mem[0x340..0x380].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_load_from_fd_matches_buffer() {
+        let image = build_sparse(FUNC);
+        let mut from_buf = loader::MemoryMapper::new();
+        loader::load_elf(image.as_slice(), &mut from_buf).unwrap();
+        let mut from_fd = loader::MemoryMapper::new();
+        load_from_fd(image.as_slice(), &mut from_fd).unwrap();
+        assert_eq!(
+            (from_fd.start(), from_fd.end(), from_fd.entry()),
+            (from_buf.start(), from_buf.end(), from_buf.entry())
+        );
+        assert_eq!(image_memory(&from_fd), image_memory(&from_buf));
+
+        // Relocated words differ by the load addresses only.
+        let image = build_pie();
+        let mut from_fd = loader::MemoryMapper::new();
+        load_from_fd(image.as_slice(), &mut from_fd).unwrap();
+        let base = from_fd.real_start().unwrap();
+        let slot = unsafe { (base.add(SLOT) as *const usize).read_unaligned() };
+        assert_eq!(slot, base as usize + FUNC);
+        assert_eq!(
+            load_from_fd(&image[..PHOFF], &mut loader::MemoryMapper::new()),
+            Err(loader::LoadError::ParseFailed)
+        );
+    }
+
+    #[test]
+    fn test_load_bad_entry() {
+        let mut image = build_pie();
+        put_word(&mut image, 24, IMAGE_SIZE);
+        let mut mapper = loader::MemoryMapper::new();
+        assert_eq!(
+            loader::load_elf(image.as_slice(), &mut mapper),
+            Err(loader::LoadError::BadEntry)
+        );
+
+        // Inside the image, but in the gap between two segments.
+        let image = build_sparse(0x200);
+        let mut mapper = loader::MemoryMapper::new();
+        assert_eq!(
+            loader::load_elf(image.as_slice(), &mut mapper),
+            Err(loader::LoadError::BadEntry)
+        );
+    }
+
+    #[test]
+    fn test_load_foreign_elf() {
+        // Some other machine.
+        let mut image = build_pie();
+        put(&mut image, 18, &(MACHINE.0 + 1).to_le_bytes());
+        let mut mapper = loader::MemoryMapper::new();
+        assert_eq!(
+            loader::load_elf(image.as_slice(), &mut mapper),
+            Err(loader::LoadError::UnsupportedType)
+        );
+    }
+
+    #[test]
+    fn test_load_foreign_class_from_fd() {
+        let mut image = build_pie();
+        // ELFCLASS32 on 64-bit targets and the other way around.
+        image[4] = 3 - image[4];
+        let mut mapper = loader::MemoryMapper::new();
+        assert_eq!(
+            load_from_fd(&image, &mut mapper),
+            Err(loader::LoadError::UnsupportedType)
+        );
+    }
+
+    #[test]
+    fn test_load_bad_phentsize_from_fd() {
+        let mut image = build_pie();
+        let offset = if core::mem::size_of::<usize>() == 8 {
+            54
+        } else {
+            42
+        };
+        image[offset..offset + 2].copy_from_slice(&u16::MAX.to_ne_bytes());
+        let mut mapper = loader::MemoryMapper::new();
+        assert_eq!(
+            load_from_fd(&image, &mut mapper),
+            Err(loader::LoadError::ParseFailed)
+        );
+    }
+
+    #[test]
+    fn test_load_bad_dynamic() {
+        let w = core::mem::size_of::<usize>();
+        // The relocation table runs past the image, its end overflows.
+        let mut image = build_pie();
+        put_word(&mut image, DYNAMIC + 3 * w, usize::MAX - RELOCS + 1);
+        let mut mapper = loader::MemoryMapper::new();
+        assert_eq!(
+            loader::load_elf(image.as_slice(), &mut mapper),
+            Err(loader::LoadError::SegmentOutOfBounds)
+        );
+
+        // So does PT_DYNAMIC.
+        let mut image = build_pie();
+        let phentsize = if w == 8 { 56 } else { 32 };
+        let memsz = PHOFF + phentsize + if w == 8 { 40 } else { 20 };
+        put_word(&mut image, memsz, usize::MAX);
+        let mut mapper = loader::MemoryMapper::new();
+        assert_eq!(
+            loader::load_elf(image.as_slice(), &mut mapper),
+            Err(loader::LoadError::SegmentOutOfBounds)
+        );
+    }
+
+    #[test]
+    fn test_load_truncated_elf() {
+        let image = build_pie();
+        let mut mapper = loader::MemoryMapper::new();
+        assert_eq!(
+            loader::load_elf(&image[..PHOFF], &mut mapper),
+            Err(loader::LoadError::ParseFailed)
+        );
+    }
+
+    #[test]
+    fn test_load_segment_beyond_file() {
+        let mut image = build_pie();
+        let w = core::mem::size_of::<usize>();
+        // Enlarge p_filesz and p_memsz of the PT_LOAD segment.
+        let filesz = if w == 8 { PHOFF + 32 } else { PHOFF + 16 };
+        put_word(&mut image, filesz, 2 * IMAGE_SIZE);
+        put_word(&mut image, filesz + w, 2 * IMAGE_SIZE);
+        let mut mapper = loader::MemoryMapper::new();
+        assert_eq!(
+            loader::load_elf(image.as_slice(), &mut mapper),
+            Err(loader::LoadError::SegmentOutOfBounds)
+        );
+    }
 }
 
 #[no_mangle]
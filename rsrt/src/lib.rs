@@ -39,6 +39,7 @@ static GLOBAL: PosixAllocator = PosixAllocator;
 #[cfg(not(feature = "std"))]
 #[panic_handler]
 fn oops(info: &core::panic::PanicInfo) -> ! {
+    blueos::console::SEMIHOSTING_CONSOLE.flush_on_panic();
     #[cfg(test)]
     {
         semihosting::println!("{}", info);